@@ -1,3 +1,18 @@
+// Scales the post processed image to the back buffer. fs_main samples it bilinearly, the
+// fsr entry points do an edge adaptive upscale (EASU) and a sharpening (RCAS) pass after
+// AMD FidelityFX Super Resolution 1.
+
+// Matches BlitParams in blit_to_backbuffer.rs
+struct BlitParams
+{
+    // exp2(-sharpness in stops), 1 is the sharpest.
+    sharpness: f32,
+    // The back buffer has a linear format, so the sRGB encoding is done here.
+    encode_srgb: u32,
+    _padding0: f32,
+    _padding1: f32,
+};
+
 struct VertexOutput
 {
     @builtin(position) clip_position: vec4<f32>,
@@ -8,6 +23,8 @@ struct VertexOutput
 var t_diffuse: texture_2d<f32>;
 @group(0)@binding(1)
 var s_diffuse: sampler;
+@group(0) @binding(2)
+var<uniform> params: BlitParams;
 
 @vertex
 fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput
@@ -23,7 +40,194 @@ fn vs_main(@builtin(vertex_index) in_vertex_index: u32) -> VertexOutput
     return out;
 }
 
+fn encode_output(color: vec4<f32>) -> vec4<f32>
+{
+    if(params.encode_srgb == 0u)
+    {
+        return color;
+    }
+    let c = clamp(color.rgb, vec3<f32>(0.0), vec3<f32>(1.0));
+    let low = c * 12.92;
+    let high = 1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return vec4<f32>(select(high, low, c <= vec3<f32>(0.0031308)), color.a);
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    return encode_output(textureSample(t_diffuse, s_diffuse, in.tex_coords));
+}
+
+fn load_clamped(pixel: vec2<i32>) -> vec3<f32>
+{
+    let size = vec2<i32>(textureDimensions(t_diffuse));
+    return textureLoad(t_diffuse, clamp(pixel, vec2<i32>(0), size - 1), 0).rgb;
+}
+
+fn luma2(color: vec3<f32>) -> f32
+{
+    return color.b * 0.5 + (color.r * 0.5 + color.g);
+}
+
+struct EasuAccumulator
+{
+    dir: vec2<f32>,
+    len: f32,
+};
+
+// Direction and edge length from the luma around one of the four texels nearest the pixel,
+// a is above, b left, c the texel, d right and e below.
+fn easu_set(acc: ptr<function, EasuAccumulator>, w: f32, a: f32, b: f32, c: f32, d: f32, e: f32)
+{
+    let dir_x = d - b;
+    var len_x = clamp(abs(dir_x) / max(max(abs(d - c), abs(c - b)), 1e-5), 0.0, 1.0);
+    len_x *= len_x;
+    let dir_y = e - a;
+    var len_y = clamp(abs(dir_y) / max(max(abs(e - c), abs(c - a)), 1e-5), 0.0, 1.0);
+    len_y *= len_y;
+    (*acc).dir += vec2<f32>(dir_x, dir_y) * w;
+    (*acc).len += (len_x + len_y) * w;
+}
+
+struct EasuKernel
+{
+    dir: vec2<f32>,
+    len2: vec2<f32>,
+    lob: f32,
+    clp: f32,
+};
+
+struct EasuSum
+{
+    color: vec3<f32>,
+    weight: f32,
+};
+
+// Approximate lanczos2 weight of a tap, stretched along the edge direction.
+fn easu_tap(sum: ptr<function, EasuSum>, offset: vec2<f32>, kernel: EasuKernel, color: vec3<f32>)
+{
+    var v = vec2<f32>(
+        offset.x * kernel.dir.x + offset.y * kernel.dir.y,
+        offset.x * -kernel.dir.y + offset.y * kernel.dir.x);
+    v *= kernel.len2;
+    let d2 = min(dot(v, v), kernel.clp);
+    var wb = 2.0 / 5.0 * d2 - 1.0;
+    var wa = kernel.lob * d2 - 1.0;
+    wb *= wb;
+    wa *= wa;
+    wb = 25.0 / 16.0 * wb - (25.0 / 16.0 - 1.0);
+    let w = wb * wa;
+    (*sum).color += color * w;
+    (*sum).weight += w;
+}
+
+@fragment
+fn fs_easu(in: VertexOutput) -> @location(0) vec4<f32>
+{
+    // Texel position in the input, fp is the top left of the 2x2 nearest texels.
+    let position = in.tex_coords * vec2<f32>(textureDimensions(t_diffuse)) - 0.5;
+    let fp = floor(position);
+    let pp = position - fp;
+    let ip = vec2<i32>(fp);
+
+    //    b c
+    //  e f g h
+    //  i j k l
+    //    n o
+    let b = load_clamped(ip + vec2<i32>(0, -1));
+    let c = load_clamped(ip + vec2<i32>(1, -1));
+    let e = load_clamped(ip + vec2<i32>(-1, 0));
+    let f = load_clamped(ip);
+    let g = load_clamped(ip + vec2<i32>(1, 0));
+    let h = load_clamped(ip + vec2<i32>(2, 0));
+    let i = load_clamped(ip + vec2<i32>(-1, 1));
+    let j = load_clamped(ip + vec2<i32>(0, 1));
+    let k = load_clamped(ip + vec2<i32>(1, 1));
+    let l = load_clamped(ip + vec2<i32>(2, 1));
+    let n = load_clamped(ip + vec2<i32>(0, 2));
+    let o = load_clamped(ip + vec2<i32>(1, 2));
+
+    let bl = luma2(b);
+    let cl = luma2(c);
+    let el = luma2(e);
+    let fl = luma2(f);
+    let gl = luma2(g);
+    let hl = luma2(h);
+    let il = luma2(i);
+    let jl = luma2(j);
+    let kl = luma2(k);
+    let ll = luma2(l);
+    let nl = luma2(n);
+    let ol = luma2(o);
+
+    var acc = EasuAccumulator(vec2<f32>(0.0), 0.0);
+    easu_set(&acc, (1.0 - pp.x) * (1.0 - pp.y), bl, el, fl, gl, jl);
+    easu_set(&acc, pp.x * (1.0 - pp.y), cl, fl, gl, hl, kl);
+    easu_set(&acc, (1.0 - pp.x) * pp.y, fl, il, jl, kl, nl);
+    easu_set(&acc, pp.x * pp.y, gl, jl, kl, ll, ol);
+
+    var dir = acc.dir;
+    let dir_r = dot(dir, dir);
+    if(dir_r < 1.0 / 32768.0)
+    {
+        dir = vec2<f32>(1.0, 0.0);
+    }
+    else
+    {
+        dir *= inverseSqrt(dir_r);
+    }
+    var len = acc.len * 0.5;
+    len *= len;
+    // 1 along the axes, sqrt(2) on diagonals.
+    let stretch = dot(dir, dir) / max(abs(dir.x), abs(dir.y));
+    let lob = 0.5 + ((1.0 / 4.0 - 0.04) - 0.5) * len;
+    let kernel = EasuKernel(
+        dir,
+        vec2<f32>(1.0 + (stretch - 1.0) * len, 1.0 - 0.5 * len),
+        lob,
+        1.0 / lob);
+
+    var sum = EasuSum(vec3<f32>(0.0), 0.0);
+    easu_tap(&sum, vec2<f32>(0.0, -1.0) - pp, kernel, b);
+    easu_tap(&sum, vec2<f32>(1.0, -1.0) - pp, kernel, c);
+    easu_tap(&sum, vec2<f32>(-1.0, 1.0) - pp, kernel, i);
+    easu_tap(&sum, vec2<f32>(0.0, 1.0) - pp, kernel, j);
+    easu_tap(&sum, vec2<f32>(0.0, 0.0) - pp, kernel, f);
+    easu_tap(&sum, vec2<f32>(-1.0, 0.0) - pp, kernel, e);
+    easu_tap(&sum, vec2<f32>(1.0, 1.0) - pp, kernel, k);
+    easu_tap(&sum, vec2<f32>(2.0, 1.0) - pp, kernel, l);
+    easu_tap(&sum, vec2<f32>(2.0, 0.0) - pp, kernel, h);
+    easu_tap(&sum, vec2<f32>(1.0, 0.0) - pp, kernel, g);
+    easu_tap(&sum, vec2<f32>(1.0, 2.0) - pp, kernel, o);
+    easu_tap(&sum, vec2<f32>(0.0, 2.0) - pp, kernel, n);
+
+    // Clamping to the nearest texels removes the ringing of the negative lobes.
+    let lo = min(min(f, g), min(j, k));
+    let hi = max(max(f, g), max(j, k));
+    let color = clamp(sum.color / sum.weight, lo, hi);
+    return vec4<f32>(color, 1.0);
+}
+
+@fragment
+fn fs_rcas(in: VertexOutput) -> @location(0) vec4<f32>
+{
+    //   b
+    // d e f
+    //   h
+    let pixel = vec2<i32>(floor(in.clip_position.xy));
+    let b = load_clamped(pixel + vec2<i32>(0, -1));
+    let d = load_clamped(pixel + vec2<i32>(-1, 0));
+    let e = load_clamped(pixel);
+    let f = load_clamped(pixel + vec2<i32>(1, 0));
+    let h = load_clamped(pixel + vec2<i32>(0, 1));
+
+    let mn4 = min(min(b, d), min(f, h));
+    let mx4 = max(max(b, d), max(f, h));
+    // Largest negative lobe that doesn't push the result out of 0..1.
+    let hit_min = mn4 / max(4.0 * mx4, vec3<f32>(1e-5));
+    let hit_max = (1.0 - mx4) / min(4.0 * mn4 - 4.0, vec3<f32>(-1e-5));
+    let lobe_rgb = max(-hit_min, hit_max);
+    let limit = 0.25 - 1.0 / 16.0;
+    let lobe = max(-limit, min(max(lobe_rgb.r, max(lobe_rgb.g, lobe_rgb.b)), 0.0)) * params.sharpness;
+    let color = (lobe * (b + d + f + h) + e) / (4.0 * lobe + 1.0);
+    return encode_output(vec4<f32>(color, 1.0));
 }
@@ -0,0 +1,131 @@
+// Bloom on the hdr scene before tonemapping. The bright parts are downsampled through the
+// mips of one texture, then upsampled and summed through the mips of another, and the
+// result is added on top of the scene.
+
+// Matches BloomParams in bloom_system.rs
+struct BloomParams
+{
+    threshold: f32,
+    knee: f32,
+    intensity: f32,
+    _padding: f32,
+};
+
+// Down and upsampling read textureInput and write textureOutput, which are different mips.
+// Upsampling adds textureBloom, the downsampled mip of the same size, to the result.
+// Composite reads the scene from textureInput and the upsampled bloom from textureBloom.
+@group(0) @binding(0) var textureInput: texture_2d<f32>;
+@group(0) @binding(1) var textureOutput: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(2) var linearSampler: sampler;
+@group(0) @binding(3) var<uniform> params: BloomParams;
+@group(0) @binding(4) var textureBloom: texture_2d<f32>;
+
+fn output_uv(pixel: vec2<u32>) -> vec2<f32>
+{
+    return (vec2<f32>(pixel) + 0.5) / vec2<f32>(textureDimensions(textureOutput));
+}
+
+fn outside(pixel: vec2<u32>) -> bool
+{
+    let size = textureDimensions(textureOutput);
+    return pixel.x >= size.x || pixel.y >= size.y;
+}
+
+fn sample_input(uv: vec2<f32>) -> vec3<f32>
+{
+    return textureSampleLevel(textureInput, linearSampler, uv, 0.0).rgb;
+}
+
+// 13 tap downsample filter, bilinear fetches covering a 4x4 texel area of the input.
+fn downsample(uv: vec2<f32>) -> vec3<f32>
+{
+    let texel = 1.0 / vec2<f32>(textureDimensions(textureInput));
+    let a = sample_input(uv + texel * vec2<f32>(-2.0, -2.0));
+    let b = sample_input(uv + texel * vec2<f32>(0.0, -2.0));
+    let c = sample_input(uv + texel * vec2<f32>(2.0, -2.0));
+    let d = sample_input(uv + texel * vec2<f32>(-2.0, 0.0));
+    let e = sample_input(uv);
+    let f = sample_input(uv + texel * vec2<f32>(2.0, 0.0));
+    let g = sample_input(uv + texel * vec2<f32>(-2.0, 2.0));
+    let h = sample_input(uv + texel * vec2<f32>(0.0, 2.0));
+    let i = sample_input(uv + texel * vec2<f32>(2.0, 2.0));
+    let j = sample_input(uv + texel * vec2<f32>(-1.0, -1.0));
+    let k = sample_input(uv + texel * vec2<f32>(1.0, -1.0));
+    let l = sample_input(uv + texel * vec2<f32>(-1.0, 1.0));
+    let m = sample_input(uv + texel * vec2<f32>(1.0, 1.0));
+    return e * 0.125
+        + (a + c + g + i) * 0.03125
+        + (b + d + f + h) * 0.0625
+        + (j + k + l + m) * 0.125;
+}
+
+// Soft threshold, the knee blends the cut off instead of a hard step.
+fn threshold(color: vec3<f32>) -> vec3<f32>
+{
+    let brightness = max(color.r, max(color.g, color.b));
+    let knee = max(params.knee, 0.0001);
+    var soft = clamp(brightness - params.threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee);
+    let contribution = max(soft, brightness - params.threshold) / max(brightness, 0.0001);
+    return color * contribution;
+}
+
+@compute
+@workgroup_size(8, 8)
+fn prefilter_main(@builtin(global_invocation_id) global_id: vec3<u32>)
+{
+    if(outside(global_id.xy))
+    {
+        return;
+    }
+    let color = threshold(downsample(output_uv(global_id.xy)));
+    textureStore(textureOutput, global_id.xy, vec4<f32>(color, 1.0));
+}
+
+@compute
+@workgroup_size(8, 8)
+fn downsample_main(@builtin(global_invocation_id) global_id: vec3<u32>)
+{
+    if(outside(global_id.xy))
+    {
+        return;
+    }
+    textureStore(textureOutput, global_id.xy, vec4<f32>(downsample(output_uv(global_id.xy)), 1.0));
+}
+
+@compute
+@workgroup_size(8, 8)
+fn upsample_main(@builtin(global_invocation_id) global_id: vec3<u32>)
+{
+    if(outside(global_id.xy))
+    {
+        return;
+    }
+    // 3x3 tent filter over the smaller mip.
+    let uv = output_uv(global_id.xy);
+    let texel = 1.0 / vec2<f32>(textureDimensions(textureInput));
+    var color = sample_input(uv) * 4.0;
+    color += (sample_input(uv + texel * vec2<f32>(-1.0, 0.0))
+        + sample_input(uv + texel * vec2<f32>(1.0, 0.0))
+        + sample_input(uv + texel * vec2<f32>(0.0, -1.0))
+        + sample_input(uv + texel * vec2<f32>(0.0, 1.0))) * 2.0;
+    color += sample_input(uv + texel * vec2<f32>(-1.0, -1.0))
+        + sample_input(uv + texel * vec2<f32>(1.0, -1.0))
+        + sample_input(uv + texel * vec2<f32>(-1.0, 1.0))
+        + sample_input(uv + texel * vec2<f32>(1.0, 1.0));
+    color = color / 16.0 + textureLoad(textureBloom, global_id.xy, 0).rgb;
+    textureStore(textureOutput, global_id.xy, vec4<f32>(color, 1.0));
+}
+
+@compute
+@workgroup_size(8, 8)
+fn composite_main(@builtin(global_invocation_id) global_id: vec3<u32>)
+{
+    if(outside(global_id.xy))
+    {
+        return;
+    }
+    let scene = textureLoad(textureInput, global_id.xy, 0);
+    let bloom = textureSampleLevel(textureBloom, linearSampler, output_uv(global_id.xy), 0.0).rgb;
+    textureStore(textureOutput, global_id.xy, vec4<f32>(scene.rgb + bloom * params.intensity, scene.a));
+}
@@ -0,0 +1,101 @@
+// Applies the ambient occlusion and fog to the hdr scene. Either can be half resolution,
+// they are upsampled with bilinear weights scaled down where the depth differs, so they
+// don't bleed over edges.
+
+// Matches CompositeParams in composite_system.rs
+struct CompositeParams
+{
+    apply_ao: u32,
+    apply_fog: u32,
+    depth_sharpness: f32,
+    _padding: f32,
+};
+
+@group(0) @binding(0) var textureInput: texture_2d<f32>;
+@group(0) @binding(1) var textureOutput: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(2) var textureDepth: texture_2d<f32>;
+@group(0) @binding(3) var textureAo: texture_2d<f32>;
+@group(0) @binding(4) var textureFog: texture_2d<f32>;
+@group(0) @binding(5) var<uniform> params: CompositeParams;
+
+// Weights of the 2x2 low resolution texels around a full resolution pixel.
+struct Upsample
+{
+    texels: array<vec2<u32>, 4>,
+    weights: vec4<f32>,
+};
+
+fn bilateral_weights(pixel: vec2<u32>, low_size: vec2<u32>) -> Upsample
+{
+    let full_size = textureDimensions(textureDepth);
+    let depth = textureLoad(textureDepth, pixel, 0).r;
+    let position = (vec2<f32>(pixel) + 0.5) * vec2<f32>(low_size) / vec2<f32>(full_size) - 0.5;
+    let base = floor(position);
+    let f = position - base;
+
+    var upsample: Upsample;
+    var bilinear = vec4<f32>((1.0 - f.x) * (1.0 - f.y), f.x * (1.0 - f.y), (1.0 - f.x) * f.y, f.x * f.y);
+    // Arrays are vars, naga only allows constant indices into lets.
+    var offsets = array<vec2<f32>, 4>(vec2<f32>(0.0, 0.0), vec2<f32>(1.0, 0.0), vec2<f32>(0.0, 1.0), vec2<f32>(1.0, 1.0));
+    var total = 0.0;
+    var closest = 0u;
+    var closest_difference = 1e30;
+    for(var i = 0u; i < 4u; i = i + 1u)
+    {
+        let texel = vec2<u32>(clamp(base + offsets[i], vec2<f32>(0.0), vec2<f32>(low_size - 1u)));
+        // The texel was computed from this full resolution depth, see full_resolution_pixel.
+        let texel_pixel = min(vec2<u32>((vec2<f32>(texel) + 0.5) * vec2<f32>(full_size) / vec2<f32>(low_size)), full_size - 1u);
+        let difference = abs(textureLoad(textureDepth, texel_pixel, 0).r - depth) / max(depth, 0.0001);
+        upsample.texels[i] = texel;
+        upsample.weights[i] = bilinear[i] * exp(-difference * params.depth_sharpness);
+        total += upsample.weights[i];
+        if(difference < closest_difference)
+        {
+            closest_difference = difference;
+            closest = i;
+        }
+    }
+    // Every texel is on another surface, take the closest one in depth.
+    if(total < 0.0001)
+    {
+        upsample.weights = vec4<f32>(0.0);
+        upsample.weights[closest] = 1.0;
+        return upsample;
+    }
+    upsample.weights = upsample.weights / total;
+    return upsample;
+}
+
+@compute
+@workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>)
+{
+    let size = textureDimensions(textureOutput);
+    if(global_id.x >= size.x || global_id.y >= size.y)
+    {
+        return;
+    }
+    var color = textureLoad(textureInput, global_id.xy, 0);
+
+    if(params.apply_ao != 0u)
+    {
+        var upsample = bilateral_weights(global_id.xy, textureDimensions(textureAo));
+        var ao = 0.0;
+        for(var i = 0u; i < 4u; i = i + 1u)
+        {
+            ao += textureLoad(textureAo, upsample.texels[i], 0).r * upsample.weights[i];
+        }
+        color = vec4<f32>(color.rgb * ao, color.a);
+    }
+    if(params.apply_fog != 0u)
+    {
+        var upsample = bilateral_weights(global_id.xy, textureDimensions(textureFog));
+        var fog = vec4<f32>(0.0);
+        for(var i = 0u; i < 4u; i = i + 1u)
+        {
+            fog += textureLoad(textureFog, upsample.texels[i], 0) * upsample.weights[i];
+        }
+        color = vec4<f32>(color.rgb * fog.a + fog.rgb, color.a);
+    }
+    textureStore(textureOutput, global_id.xy, color);
+}
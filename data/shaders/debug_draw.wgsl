@@ -0,0 +1,55 @@
+// Draws the debug lines over the post processed image. Depth tested lines are compared
+// against the linear depth of the scene, so they work the same with and without MSAA.
+
+#include "shared/camera.wgsl"
+#include "shared/view.wgsl"
+
+struct VertexInput
+{
+    @location(0) position: vec3<f32>,
+    @location(1) depth_test: u32,
+    @location(2) color: vec4<f32>,
+};
+
+struct VertexOutput
+{
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+    @location(1) view_depth: f32,
+    @location(2) @interpolate(flat) depth_test: u32,
+};
+
+// Relative depth difference still drawn in front, keeps lines on surfaces visible.
+const DEPTH_BIAS: f32 = 0.01;
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+@group(0) @binding(1)
+var<uniform> view_params: ViewParams;
+@group(0) @binding(2)
+var linear_depth: texture_2d<f32>;
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput
+{
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(in.position, 1.0);
+    out.color = in.color;
+    out.view_depth = dot(in.position - view_params.position.xyz, view_params.forward.xyz);
+    out.depth_test = in.depth_test;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32>
+{
+    if (in.depth_test != 0u)
+    {
+        let scene_depth = textureLoad(linear_depth, vec2<i32>(in.clip_position.xy), 0).r;
+        if (in.view_depth > scene_depth * (1.0 + DEPTH_BIAS))
+        {
+            discard;
+        }
+    }
+    return in.color;
+}
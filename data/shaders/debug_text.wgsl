@@ -0,0 +1,88 @@
+// Draws the debug overlay text over the back buffer, one instanced quad per character from
+// an 8x8 bitmap font atlas, with a one font pixel drop shadow.
+
+// Matches DebugTextParams in debug_text_system.rs
+struct DebugTextParams
+{
+    inv_screen_size: vec2<f32>,
+    // Screen pixels per font pixel.
+    scale: f32,
+    _padding: f32,
+};
+
+struct GlyphInstance
+{
+    // Top left corner in screen pixels.
+    @location(0) position: vec2<f32>,
+    // Column and row of the glyph in the atlas.
+    @location(1) glyph: u32,
+    @location(2) color: vec4<f32>,
+};
+
+struct VertexOutput
+{
+    @builtin(position) clip_position: vec4<f32>,
+    // Font pixels from the glyph's top left, the shadow extends one pixel past the glyph.
+    @location(0) font_position: vec2<f32>,
+    @location(1) @interpolate(flat) glyph: u32,
+    @location(2) color: vec4<f32>,
+};
+
+const GLYPH_SIZE: i32 = 8;
+const ATLAS_COLUMNS: u32 = 16u;
+
+@group(0) @binding(0)
+var font_atlas: texture_2d<f32>;
+@group(0) @binding(1)
+var<uniform> params: DebugTextParams;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32, instance: GlyphInstance) -> VertexOutput
+{
+    // Arrays are vars, naga only allows constant indices into lets.
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+    );
+    let font_position = corners[vertex_index] * f32(GLYPH_SIZE + 1);
+    let screen_position = instance.position + font_position * params.scale;
+    let ndc = screen_position * params.inv_screen_size * 2.0 - 1.0;
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc.x, -ndc.y, 0.0, 1.0);
+    out.font_position = font_position;
+    out.glyph = instance.glyph;
+    out.color = instance.color;
+    return out;
+}
+
+fn glyph_coverage(glyph: u32, pixel: vec2<i32>) -> f32
+{
+    if (any(pixel < vec2<i32>(0)) || any(pixel >= vec2<i32>(GLYPH_SIZE)))
+    {
+        return 0.0;
+    }
+    let cell = vec2<i32>(i32(glyph % ATLAS_COLUMNS), i32(glyph / ATLAS_COLUMNS));
+    return textureLoad(font_atlas, cell * GLYPH_SIZE + pixel, 0).r;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32>
+{
+    let pixel = vec2<i32>(floor(in.font_position));
+    let coverage = glyph_coverage(in.glyph, pixel);
+    let shadow = glyph_coverage(in.glyph, pixel - vec2<i32>(1));
+    if (coverage == 0.0 && shadow == 0.0)
+    {
+        discard;
+    }
+    if (coverage > 0.0)
+    {
+        return in.color;
+    }
+    return vec4<f32>(0.0, 0.0, 0.0, in.color.a);
+}
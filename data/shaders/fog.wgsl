@@ -0,0 +1,62 @@
+// Distance and height fog from the linear depth. Writes the inscattered fog color to rgb
+// and the transmittance of the scene behind it to a, the composite pass applies it.
+
+#include "shared/view.wgsl"
+
+// Matches FogParams in fog_system.rs
+struct FogParams
+{
+    color: vec4<f32>,
+    distance_density: f32,
+    start_distance: f32,
+    height_density: f32,
+    height_falloff: f32,
+    base_height: f32,
+    _padding0: f32,
+    _padding1: f32,
+    _padding2: f32,
+};
+
+@group(0) @binding(0) var textureDepth: texture_2d<f32>;
+@group(0) @binding(1) var textureOutput: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(2) var<uniform> view_params: ViewParams;
+@group(0) @binding(3) var<uniform> params: FogParams;
+
+// Integral of density * exp(-falloff * (height - base)) along the ray, the density only
+// depends on the height so it integrates analytically.
+fn height_fog(start: vec3<f32>, ray: vec3<f32>) -> f32
+{
+    let falloff = max(params.height_falloff, 0.0001);
+    let start_density = params.height_density * exp(-falloff * (start.y - params.base_height));
+    let height_change = falloff * ray.y;
+    var factor = 1.0;
+    if(abs(height_change) > 0.0001)
+    {
+        factor = (1.0 - exp(-height_change)) / height_change;
+    }
+    return start_density * length(ray) * factor;
+}
+
+@compute
+@workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>)
+{
+    let size = textureDimensions(textureOutput);
+    if(global_id.x >= size.x || global_id.y >= size.y)
+    {
+        return;
+    }
+    let full_size = textureDimensions(textureDepth);
+    let pixel = full_resolution_pixel(global_id.xy, size, full_size);
+    let uv = (vec2<f32>(pixel) + 0.5) / vec2<f32>(full_size);
+    let position = view_position(view_params, uv, textureLoad(textureDepth, pixel, 0).r);
+
+    let distance = length(position);
+    let fog_distance = max(distance - params.start_distance, 0.0);
+    let direction = normalize(view_to_world(view_params, position) - view_params.position.xyz);
+    let start = view_params.position.xyz + direction * (distance - fog_distance);
+
+    let optical_depth = params.distance_density * fog_distance + height_fog(start, direction * fog_distance);
+    let transmittance = exp(-optical_depth);
+    textureStore(textureOutput, global_id.xy, vec4<f32>(params.color.rgb * (1.0 - transmittance), transmittance));
+}
@@ -0,0 +1,25 @@
+// Converts the scene depth buffer into linear view depth, read by the ssao, fog and
+// composite passes. With MSAA the first sample is used.
+
+#include "shared/view.wgsl"
+
+#ifdef MULTISAMPLED
+@group(0) @binding(0) var textureDepth: texture_depth_multisampled_2d;
+#else
+@group(0) @binding(0) var textureDepth: texture_depth_2d;
+#endif
+@group(0) @binding(1) var textureOutput: texture_storage_2d<r32float, write>;
+@group(0) @binding(2) var<uniform> view_params: ViewParams;
+
+@compute
+@workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>)
+{
+    let size = textureDimensions(textureOutput);
+    if(global_id.x >= size.x || global_id.y >= size.y)
+    {
+        return;
+    }
+    let depth = textureLoad(textureDepth, global_id.xy, 0);
+    textureStore(textureOutput, global_id.xy, vec4<f32>(linearize_depth(view_params, depth), 0.0, 0.0, 0.0));
+}
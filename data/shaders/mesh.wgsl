@@ -0,0 +1,55 @@
+// Draws the MeshData instances of the frame. The vertices are pulled from the mesh buffers
+// through the instance's model location, so every model is drawn without vertex buffers.
+
+#include "shared/camera.wgsl"
+#include "shared/mesh.wgsl"
+
+struct VertexOutput
+{
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+// Direction towards the light in world space.
+const LIGHT_DIRECTION: vec3<f32> = vec3<f32>(0.36, 0.8, 0.48);
+const AMBIENT: f32 = 0.3;
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+@group(0) @binding(1)
+var<storage, read> vertices: array<MeshVertex>;
+@group(0) @binding(2)
+var<storage, read> indices: array<u32>;
+@group(0) @binding(3)
+var<storage, read> instance_models: array<MeshModelLocation>;
+@group(0) @binding(4)
+var<storage, read> instance_transforms: array<GpuOutInstanceMatrices>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32, @builtin(instance_index) instance_index: u32) -> VertexOutput
+{
+    let model = instance_models[instance_index];
+    let index = indices[model.indices_start_index + vertex_index];
+    let vertex = vertices[model.vertices_start_index + index];
+    // The rows of the affine transform.
+    let transform = instance_transforms[instance_index];
+    let position = vec4<f32>(vertex.position.xyz, 1.0);
+    let world_position = vec3<f32>(dot(transform.v0, position), dot(transform.v1, position), dot(transform.v2, position));
+    // Right for rotations and uniform scales.
+    let normal = vec4<f32>(vertex.normal.xyz, 0.0);
+    let world_normal = vec3<f32>(dot(transform.v0, normal), dot(transform.v1, normal), dot(transform.v2, normal));
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.normal = world_normal;
+    out.color = vertex.color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32>
+{
+    let diffuse = max(dot(normalize(in.normal), normalize(LIGHT_DIRECTION)), 0.0);
+    return vec4<f32>(in.color.rgb * (AMBIENT + (1.0 - AMBIENT) * diffuse), in.color.a);
+}
@@ -0,0 +1,44 @@
+// Writes the instance under the cursor into a 1x1 id target. The camera's view projection is
+// zoomed so the picked pixel covers the whole target. Zero means nothing was hit.
+
+#include "shared/camera.wgsl"
+#include "shared/mesh.wgsl"
+
+struct VertexOutput
+{
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) @interpolate(flat) id: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+@group(0) @binding(1)
+var<storage, read> vertices: array<MeshVertex>;
+@group(0) @binding(2)
+var<storage, read> indices: array<u32>;
+@group(0) @binding(3)
+var<storage, read> instance_models: array<MeshModelLocation>;
+@group(0) @binding(4)
+var<storage, read> instance_transforms: array<GpuOutInstanceMatrices>;
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32, @builtin(instance_index) instance_index: u32) -> VertexOutput
+{
+    let model = instance_models[instance_index];
+    let index = indices[model.indices_start_index + vertex_index];
+    let vertex = vertices[model.vertices_start_index + index];
+    let transform = instance_transforms[instance_index];
+    let position = vec4<f32>(vertex.position.xyz, 1.0);
+    let world_position = vec3<f32>(dot(transform.v0, position), dot(transform.v1, position), dot(transform.v2, position));
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.id = instance_index + 1u;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) u32
+{
+    return in.id;
+}
@@ -0,0 +1,144 @@
+// Post effects run after tonemapping. Every entry point reads textureInput and writes the
+// whole textureOutput, the renderer swaps the two between effects.
+
+// Matches PostProcessParams in post_process_system.rs
+struct PostProcessParams
+{
+    inv_size: vec2<f32>,
+    fxaa_edge_threshold: f32,
+    fxaa_edge_threshold_min: f32,
+    fxaa_span_max: f32,
+    lut_strength: f32,
+    lut_size: f32,
+    vignette_intensity: f32,
+    vignette_radius: f32,
+    vignette_smoothness: f32,
+    chromatic_aberration: f32,
+    _padding: f32,
+};
+
+@group(0) @binding(0) var textureInput: texture_2d<f32>;
+@group(0) @binding(1) var textureOutput: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(2) var linearSampler: sampler;
+@group(0) @binding(3) var<uniform> params: PostProcessParams;
+@group(0) @binding(4) var colorLut: texture_3d<f32>;
+
+fn pixel_uv(pixel: vec2<u32>) -> vec2<f32>
+{
+    return (vec2<f32>(pixel) + 0.5) * params.inv_size;
+}
+
+fn sample_input(uv: vec2<f32>) -> vec4<f32>
+{
+    return textureSampleLevel(textureInput, linearSampler, uv, 0.0);
+}
+
+fn outside(pixel: vec2<u32>) -> bool
+{
+    let size = textureDimensions(textureOutput);
+    return pixel.x >= size.x || pixel.y >= size.y;
+}
+
+// Perceptual luma for edge detection, the input is linear.
+fn luma(color: vec3<f32>) -> f32
+{
+    return sqrt(dot(color, vec3<f32>(0.299, 0.587, 0.114)));
+}
+
+@compute
+@workgroup_size(8, 8)
+fn fxaa_main(@builtin(global_invocation_id) global_id: vec3<u32>)
+{
+    if(outside(global_id.xy))
+    {
+        return;
+    }
+    let uv = pixel_uv(global_id.xy);
+    let center = textureLoad(textureInput, global_id.xy, 0);
+
+    let luma_nw = luma(sample_input(uv + vec2<f32>(-1.0, -1.0) * params.inv_size).rgb);
+    let luma_ne = luma(sample_input(uv + vec2<f32>(1.0, -1.0) * params.inv_size).rgb);
+    let luma_sw = luma(sample_input(uv + vec2<f32>(-1.0, 1.0) * params.inv_size).rgb);
+    let luma_se = luma(sample_input(uv + vec2<f32>(1.0, 1.0) * params.inv_size).rgb);
+    let luma_m = luma(center.rgb);
+
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+    if(luma_max - luma_min < max(params.fxaa_edge_threshold_min, luma_max * params.fxaa_edge_threshold))
+    {
+        textureStore(textureOutput, global_id.xy, center);
+        return;
+    }
+
+    var dir = vec2<f32>(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se));
+    let dir_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * (1.0 / 8.0), 1.0 / 128.0);
+    let inv_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+    dir = clamp(dir * inv_dir_min, vec2<f32>(-params.fxaa_span_max), vec2<f32>(params.fxaa_span_max)) * params.inv_size;
+
+    let color_a = 0.5 * (
+        sample_input(uv + dir * (1.0 / 3.0 - 0.5)).rgb +
+        sample_input(uv + dir * (2.0 / 3.0 - 0.5)).rgb);
+    let color_b = color_a * 0.5 + 0.25 * (
+        sample_input(uv + dir * -0.5).rgb +
+        sample_input(uv + dir * 0.5).rgb);
+    let luma_b = luma(color_b);
+
+    var color = color_b;
+    if(luma_b < luma_min || luma_b > luma_max)
+    {
+        color = color_a;
+    }
+    textureStore(textureOutput, global_id.xy, vec4<f32>(color, center.a));
+}
+
+@compute
+@workgroup_size(8, 8)
+fn color_grading_main(@builtin(global_invocation_id) global_id: vec3<u32>)
+{
+    if(outside(global_id.xy))
+    {
+        return;
+    }
+    let color = textureLoad(textureInput, global_id.xy, 0);
+    // The lut is authored for display encoded colors.
+    let encoded = pow(clamp(color.rgb, vec3<f32>(0.0), vec3<f32>(1.0)), vec3<f32>(1.0 / 2.2));
+    let scale = (params.lut_size - 1.0) / params.lut_size;
+    let offset = 0.5 / params.lut_size;
+    let graded = textureSampleLevel(colorLut, linearSampler, encoded * scale + offset, 0.0).rgb;
+    let linear = pow(graded, vec3<f32>(2.2));
+    textureStore(textureOutput, global_id.xy, vec4<f32>(mix(color.rgb, linear, params.lut_strength), color.a));
+}
+
+@compute
+@workgroup_size(8, 8)
+fn vignette_main(@builtin(global_invocation_id) global_id: vec3<u32>)
+{
+    if(outside(global_id.xy))
+    {
+        return;
+    }
+    let color = textureLoad(textureInput, global_id.xy, 0);
+    let distance = length(pixel_uv(global_id.xy) - 0.5) * 2.0;
+    let darken = params.vignette_intensity *
+        smoothstep(params.vignette_radius, params.vignette_radius + params.vignette_smoothness, distance);
+    textureStore(textureOutput, global_id.xy, vec4<f32>(color.rgb * (1.0 - darken), color.a));
+}
+
+@compute
+@workgroup_size(8, 8)
+fn chromatic_aberration_main(@builtin(global_invocation_id) global_id: vec3<u32>)
+{
+    if(outside(global_id.xy))
+    {
+        return;
+    }
+    let uv = pixel_uv(global_id.xy);
+    // Offset in pixels at the screen edges, growing from zero at the center.
+    let offset = (uv - 0.5) * 2.0 * params.chromatic_aberration * params.inv_size;
+    let center = textureLoad(textureInput, global_id.xy, 0);
+    let r = sample_input(uv + offset).r;
+    let b = sample_input(uv - offset).b;
+    textureStore(textureOutput, global_id.xy, vec4<f32>(r, center.g, b, center.a));
+}
@@ -0,0 +1,5 @@
+// Matches CameraUniform in triangle_system_camera_vertices.rs
+struct CameraUniform
+{
+    view_proj: mat4x4<f32>,
+};
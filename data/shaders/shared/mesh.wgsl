@@ -0,0 +1,31 @@
+// Matches common::MeshVertex
+struct MeshVertex
+{
+    position: vec4<f32>,
+    normal: vec4<f32>,
+    color: vec4<f32>,
+};
+
+// Matches common::MeshModelLocation
+struct MeshModelLocation
+{
+    vertices_start_index: u32,
+    vertices_count: u32,
+    indices_start_index: u32,
+    indices_count: u32,
+};
+
+// Matches common::GpuOutInstanceMatrices
+struct GpuOutInstanceMatrices
+{
+    v0: vec4<f32>,
+    v1: vec4<f32>,
+    v2: vec4<f32>,
+};
+
+// Matches common::GpuOutInstanceBounds
+struct GpuOutInstanceBounds
+{
+    center: vec3<f32>,
+    radius: f32,
+};
@@ -0,0 +1,51 @@
+// Camera parameters for reconstructing positions from the depth buffer in compute passes.
+// View space here has x right, y up and z forward, so z is the linear depth.
+
+// Matches ViewParams in depth_system.rs
+struct ViewParams
+{
+    right: vec4<f32>,
+    up: vec4<f32>,
+    forward: vec4<f32>,
+    position: vec4<f32>,
+    near: f32,
+    far: f32,
+    tan_half_fov_y: f32,
+    aspect: f32,
+};
+
+// Depth buffer value to distance along the view direction, for a 0..1 depth range projection.
+fn linearize_depth(view_params: ViewParams, depth: f32) -> f32
+{
+    return view_params.near * view_params.far /
+        (view_params.far - depth * (view_params.far - view_params.near));
+}
+
+fn view_position(view_params: ViewParams, uv: vec2<f32>, linear_depth: f32) -> vec3<f32>
+{
+    let ndc = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    let half_size = vec2<f32>(view_params.tan_half_fov_y * view_params.aspect, view_params.tan_half_fov_y);
+    return vec3<f32>(ndc * half_size * linear_depth, linear_depth);
+}
+
+fn view_to_uv(view_params: ViewParams, position: vec3<f32>) -> vec2<f32>
+{
+    let half_size = vec2<f32>(view_params.tan_half_fov_y * view_params.aspect, view_params.tan_half_fov_y);
+    let ndc = position.xy / (half_size * max(position.z, 0.0001));
+    return vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+}
+
+fn view_to_world(view_params: ViewParams, position: vec3<f32>) -> vec3<f32>
+{
+    return view_params.position.xyz
+        + view_params.right.xyz * position.x
+        + view_params.up.xyz * position.y
+        + view_params.forward.xyz * position.z;
+}
+
+// Full resolution pixel a pixel of a smaller, e.g. half resolution, texture is computed for.
+fn full_resolution_pixel(pixel: vec2<u32>, low_size: vec2<u32>, full_size: vec2<u32>) -> vec2<u32>
+{
+    let scaled = vec2<u32>((vec2<f32>(pixel) + 0.5) * vec2<f32>(full_size) / vec2<f32>(low_size));
+    return min(scaled, full_size - 1u);
+}
@@ -0,0 +1,115 @@
+// Screen space ambient occlusion from the linear depth. Runs at the size of the ao texture,
+// which can be half the screen size, the composite pass upsamples it.
+
+#include "shared/view.wgsl"
+
+// Matches SsaoParams in ssao_system.rs
+struct SsaoParams
+{
+    radius: f32,
+    bias: f32,
+    intensity: f32,
+    sample_count: u32,
+};
+
+@group(0) @binding(0) var textureDepth: texture_2d<f32>;
+@group(0) @binding(1) var textureOutput: texture_storage_2d<r32float, write>;
+@group(0) @binding(2) var<uniform> view_params: ViewParams;
+@group(0) @binding(3) var<uniform> params: SsaoParams;
+
+const GOLDEN_ANGLE: f32 = 2.39996323;
+const TWO_PI: f32 = 6.28318531;
+
+fn load_depth(pixel: vec2<i32>) -> f32
+{
+    let size = vec2<i32>(textureDimensions(textureDepth));
+    return textureLoad(textureDepth, clamp(pixel, vec2<i32>(0), size - 1), 0).r;
+}
+
+fn position_at(pixel: vec2<i32>) -> vec3<f32>
+{
+    let uv = (vec2<f32>(pixel) + 0.5) / vec2<f32>(textureDimensions(textureDepth));
+    return view_position(view_params, uv, load_depth(pixel));
+}
+
+// Of the two neighbours on an axis, the one closer in depth is on the same surface more often.
+fn closer_difference(center: vec3<f32>, pixel: vec2<i32>, step: vec2<i32>) -> vec3<f32>
+{
+    let after = position_at(pixel + step) - center;
+    let before = center - position_at(pixel - step);
+    if(abs(after.z) < abs(before.z))
+    {
+        return after;
+    }
+    return before;
+}
+
+fn interleaved_gradient_noise(pixel: vec2<f32>) -> f32
+{
+    return fract(52.9829189 * fract(dot(pixel, vec2<f32>(0.06711056, 0.00583715))));
+}
+
+@compute
+@workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>)
+{
+    let size = textureDimensions(textureOutput);
+    if(global_id.x >= size.x || global_id.y >= size.y)
+    {
+        return;
+    }
+    let full_size = textureDimensions(textureDepth);
+    let pixel = vec2<i32>(full_resolution_pixel(global_id.xy, size, full_size));
+    let center = position_at(pixel);
+    // Nothing was drawn here.
+    if(center.z >= view_params.far * 0.999)
+    {
+        textureStore(textureOutput, global_id.xy, vec4<f32>(1.0, 0.0, 0.0, 0.0));
+        return;
+    }
+
+    var normal = normalize(cross(
+        closer_difference(center, pixel, vec2<i32>(0, 1)),
+        closer_difference(center, pixel, vec2<i32>(1, 0))));
+    if(dot(normal, center) > 0.0)
+    {
+        normal = -normal;
+    }
+    var helper = vec3<f32>(0.0, 1.0, 0.0);
+    if(abs(normal.y) > 0.9)
+    {
+        helper = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    let tangent = normalize(cross(helper, normal));
+    let bitangent = cross(normal, tangent);
+
+    // Rotates the sample pattern per pixel, the upsampling blurs the noise away.
+    let rotation = interleaved_gradient_noise(vec2<f32>(global_id.xy)) * TWO_PI;
+    let sample_count = max(params.sample_count, 1u);
+    var occlusion = 0.0;
+    for(var i = 0u; i < sample_count; i = i + 1u)
+    {
+        // Cosine weighted golden angle spiral over the hemisphere, denser near the center.
+        let t = (f32(i) + 0.5) / f32(sample_count);
+        let angle = f32(i) * GOLDEN_ANGLE + rotation;
+        let spread = sqrt(t);
+        let direction = tangent * (cos(angle) * spread)
+            + bitangent * (sin(angle) * spread)
+            + normal * sqrt(1.0 - t);
+        let sample_position = center + direction * (params.radius * mix(0.1, 1.0, t * t));
+
+        let uv = view_to_uv(view_params, sample_position);
+        if(any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)))
+        {
+            continue;
+        }
+        let sample_depth = load_depth(vec2<i32>(uv * vec2<f32>(full_size)));
+        if(sample_depth < sample_position.z - params.bias)
+        {
+            // Ignores occluders far in front, they are not near the surface.
+            occlusion += smoothstep(0.0, 1.0, params.radius / abs(center.z - sample_depth));
+        }
+    }
+    let ao = pow(max(1.0 - occlusion / f32(sample_count), 0.0), params.intensity);
+    textureStore(textureOutput, global_id.xy, vec4<f32>(ao, 0.0, 0.0, 0.0));
+}
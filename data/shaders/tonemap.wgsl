@@ -0,0 +1,171 @@
+// Tonemaps the HDR scene into the other post process texture. With auto exposure a luminance
+// histogram of the scene is built first, and averaged into the adapted luminance.
+
+const HISTOGRAM_BINS: u32 = 256u;
+
+// Matches TonemapParams in tonemap_system.rs
+struct TonemapParams
+{
+    exposure: f32,
+    tonemapper: u32,
+    auto_exposure: u32,
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    dt: f32,
+    adaptation_rate: f32,
+    pixel_count: u32,
+};
+
+struct AutoExposure
+{
+    adapted_luminance: f32,
+};
+
+@group(0) @binding(0) var textureInput: texture_2d<f32>;
+@group(0) @binding(1) var textureOutput: texture_storage_2d<rgba16float, write>;
+@group(0) @binding(2) var<uniform> params: TonemapParams;
+@group(0) @binding(3) var<storage, read_write> histogram: array<atomic<u32>, 256>;
+@group(0) @binding(4) var<storage, read_write> auto_exposure: AutoExposure;
+
+var<workgroup> histogram_shared: array<atomic<u32>, 256>;
+var<workgroup> weighted_shared: array<f32, 256>;
+
+fn luminance(color: vec3<f32>) -> f32
+{
+    return dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+}
+
+// Bin 0 is reserved for black pixels, so they don't drag the average down.
+fn luminance_bin(lum: f32) -> u32
+{
+    if(lum < 0.0001)
+    {
+        return 0u;
+    }
+    let log_lum = clamp((log2(lum) - params.min_log_luminance) / params.log_luminance_range, 0.0, 1.0);
+    return u32(log_lum * 254.0 + 1.0);
+}
+
+@compute
+@workgroup_size(16, 16)
+fn histogram_main(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32)
+{
+    atomicStore(&histogram_shared[local_index], 0u);
+    workgroupBarrier();
+
+    let size = textureDimensions(textureInput);
+    if(global_id.x < size.x && global_id.y < size.y)
+    {
+        let color = textureLoad(textureInput, global_id.xy, 0).rgb;
+        atomicAdd(&histogram_shared[luminance_bin(luminance(color))], 1u);
+    }
+    workgroupBarrier();
+
+    atomicAdd(&histogram[local_index], atomicLoad(&histogram_shared[local_index]));
+}
+
+@compute
+@workgroup_size(256)
+fn average_main(@builtin(local_invocation_index) local_index: u32)
+{
+    let count = atomicLoad(&histogram[local_index]);
+    weighted_shared[local_index] = f32(count) * f32(local_index);
+    // Cleared for the next frame.
+    atomicStore(&histogram[local_index], 0u);
+    workgroupBarrier();
+
+    for(var stride = HISTOGRAM_BINS / 2u; stride > 0u; stride = stride / 2u)
+    {
+        if(local_index < stride)
+        {
+            weighted_shared[local_index] += weighted_shared[local_index + stride];
+        }
+        workgroupBarrier();
+    }
+
+    if(local_index == 0u)
+    {
+        let non_black = max(f32(params.pixel_count) - f32(count), 1.0);
+        let log_average = weighted_shared[0] / non_black - 1.0;
+        let target_luminance = exp2(log_average / 254.0 * params.log_luminance_range + params.min_log_luminance);
+        let previous = auto_exposure.adapted_luminance;
+        let blend = 1.0 - exp(-params.dt * params.adaptation_rate);
+        auto_exposure.adapted_luminance = previous + (target_luminance - previous) * blend;
+    }
+}
+
+fn tonemap_aces(x: vec3<f32>) -> vec3<f32>
+{
+    // Narkowicz fit of the ACES filmic curve.
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp((x * (a * x + b)) / (x * (c * x + d) + e), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn tonemap_reinhard(x: vec3<f32>) -> vec3<f32>
+{
+    return x / (1.0 + luminance(x));
+}
+
+fn agx_contrast(x: vec3<f32>) -> vec3<f32>
+{
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232;
+}
+
+fn tonemap_agx(x: vec3<f32>) -> vec3<f32>
+{
+    let agx_mat = mat3x3<f32>(
+        0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+        0.0784335999999992, 0.878468636469772, 0.0784336,
+        0.0792237451477643, 0.0791661274605434, 0.879142973793104);
+    let agx_mat_inv = mat3x3<f32>(
+        1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+        -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+        -0.0990297440797205, -0.0989611768448433, 1.15107367264116);
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    var color = agx_mat * x;
+    color = clamp(log2(max(color, vec3<f32>(1e-10))), vec3<f32>(min_ev), vec3<f32>(max_ev));
+    color = (color - min_ev) / (max_ev - min_ev);
+    color = agx_contrast(color);
+    color = agx_mat_inv * color;
+    // The curve outputs display encoded values, back to linear for the srgb back buffer.
+    return pow(max(color, vec3<f32>(0.0)), vec3<f32>(2.2));
+}
+
+@compute
+@workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) global_id: vec3<u32>)
+{
+    let size = textureDimensions(textureInput);
+    if(global_id.x >= size.x || global_id.y >= size.y)
+    {
+        return;
+    }
+
+    var exposure = params.exposure;
+    if(params.auto_exposure != 0u)
+    {
+        exposure = exposure * 0.18 / max(auto_exposure.adapted_luminance, 0.0001);
+    }
+
+    let color = textureLoad(textureInput, global_id.xy, 0);
+    let hdr = color.rgb * exposure;
+    var mapped: vec3<f32>;
+    // Same order as TonemapOperator.
+    switch params.tonemapper
+    {
+        case 1u: { mapped = tonemap_reinhard(hdr); }
+        case 2u: { mapped = tonemap_agx(hdr); }
+        default: { mapped = tonemap_aces(hdr); }
+    }
+    textureStore(textureOutput, global_id.xy, vec4<f32>(mapped, color.a));
+}
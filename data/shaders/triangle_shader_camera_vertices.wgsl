@@ -1,8 +1,6 @@
+#include "shared/camera.wgsl"
+
 // Vertex shader
-struct CameraUniform
-{
-    view_proj: mat4x4<f32>,
-};
 @group(0) @binding(0)
 var<uniform> camera: CameraUniform;
 
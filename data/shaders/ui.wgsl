@@ -0,0 +1,75 @@
+// Draws the ui meshes over the back buffer. The vertex colors and the textures are sRGB with
+// premultiplied alpha, they are blended in sRGB like the ui library expects.
+
+// Matches UiParams in ui_system.rs
+struct UiParams
+{
+    // The back buffer size in ui points.
+    screen_size: vec2<f32>,
+    // The back buffer has an sRGB format, so the output is decoded here and encoded on store.
+    decode_srgb: u32,
+    _padding: u32,
+};
+
+struct VertexInput
+{
+    // Ui points from the top left.
+    @location(0) position: vec2<f32>,
+    @location(1) uv: vec2<f32>,
+    // Packed sRGBA8.
+    @location(2) color: u32,
+};
+
+struct VertexOutput
+{
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> params: UiParams;
+@group(1) @binding(0)
+var ui_texture: texture_2d<f32>;
+@group(1) @binding(1)
+var ui_sampler: sampler;
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput
+{
+    let ndc = in.position / params.screen_size * 2.0 - 1.0;
+
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(ndc.x, -ndc.y, 0.0, 1.0);
+    out.uv = in.uv;
+    out.color = unpack4x8unorm(in.color);
+    return out;
+}
+
+fn srgb_from_linear(color: vec3<f32>) -> vec3<f32>
+{
+    let c = clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+    let low = c * 12.92;
+    let high = 1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, c <= vec3<f32>(0.0031308));
+}
+
+fn linear_from_srgb(color: vec3<f32>) -> vec3<f32>
+{
+    let low = color / 12.92;
+    let high = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, color <= vec3<f32>(0.04045));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32>
+{
+    // The texture has an sRGB format, the sampling decodes it.
+    let texel = textureSample(ui_texture, ui_sampler, in.uv);
+    let color = in.color * vec4<f32>(srgb_from_linear(texel.rgb), texel.a);
+    if (params.decode_srgb == 0u)
+    {
+        return color;
+    }
+    return vec4<f32>(linear_from_srgb(color.rgb), color.a);
+}
@@ -0,0 +1,157 @@
+use glam::{Mat4, Vec3};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb
+{
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb
+{
+    // Contains nothing, the union with any box is that box.
+    pub const EMPTY: Self = Self { min: Vec3::splat(f32::INFINITY), max: Vec3::splat(f32::NEG_INFINITY) };
+
+    pub fn new(min: Vec3, max: Vec3) -> Self
+    {
+        Self { min, max }
+    }
+
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self
+    {
+        return points.into_iter().fold(Self::EMPTY, |aabb, point| Self::new(aabb.min.min(point), aabb.max.max(point)));
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        return self.min.cmpgt(self.max).any();
+    }
+
+    pub fn center(&self) -> Vec3
+    {
+        return (self.min + self.max) * 0.5;
+    }
+
+    pub fn half_extents(&self) -> Vec3
+    {
+        return (self.max - self.min) * 0.5;
+    }
+
+    pub fn union(&self, other: &Self) -> Self
+    {
+        return Self::new(self.min.min(other.min), self.max.max(other.max));
+    }
+
+    pub fn overlaps(&self, other: &Self) -> bool
+    {
+        return self.min.cmple(other.max).all() && other.min.cmple(self.max).all();
+    }
+
+    pub fn closest_point(&self, point: Vec3) -> Vec3
+    {
+        return point.clamp(self.min, self.max);
+    }
+
+    // Zero for a point inside.
+    pub fn distance_squared(&self, point: Vec3) -> f32
+    {
+        return self.closest_point(point).distance_squared(point);
+    }
+
+    pub fn surface_area(&self) -> f32
+    {
+        if self.is_empty()
+        {
+            return 0.0;
+        }
+        let size = self.max - self.min;
+        return 2.0 * (size.x * size.y + size.y * size.z + size.z * size.x);
+    }
+
+    // The box around the transformed box, from the absolute values of the rotation and scale.
+    pub fn transformed(&self, transform: &Mat4) -> Self
+    {
+        if self.is_empty()
+        {
+            return *self;
+        }
+        let center = transform.transform_point3(self.center());
+        let half_extents = self.half_extents();
+        let extents = transform.x_axis.truncate().abs() * half_extents.x
+            + transform.y_axis.truncate().abs() * half_extents.y
+            + transform.z_axis.truncate().abs() * half_extents.z;
+        return Self::new(center - extents, center + extents);
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BoundingSphere
+{
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere
+{
+    pub const EMPTY: Self = Self { center: Vec3::ZERO, radius: 0.0 };
+
+    pub fn new(center: Vec3, radius: f32) -> Self
+    {
+        Self { center, radius }
+    }
+
+    // Centered on the box of the points, not the smallest sphere but close for most meshes.
+    pub fn from_points(points: impl IntoIterator<Item = Vec3> + Clone) -> Self
+    {
+        let aabb = Aabb::from_points(points.clone());
+        if aabb.is_empty()
+        {
+            return Self::EMPTY;
+        }
+        let center = aabb.center();
+        let radius = points.into_iter().map(|point| point.distance_squared(center)).fold(0.0, f32::max).sqrt();
+        return Self::new(center, radius);
+    }
+
+    // Scaled by the longest axis, so it stays around the mesh with non-uniform scales.
+    pub fn transformed(&self, transform: &Mat4) -> Self
+    {
+        let scale = transform.x_axis.truncate().length_squared()
+            .max(transform.y_axis.truncate().length_squared())
+            .max(transform.z_axis.truncate().length_squared())
+            .sqrt();
+        return Self::new(transform.transform_point3(self.center), self.radius * scale);
+    }
+}
+
+// The bounds of a model in its own space, computed when the model is added to MeshData.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ModelBounds
+{
+    pub aabb: Aabb,
+    pub sphere: BoundingSphere,
+}
+
+impl ModelBounds
+{
+    pub const EMPTY: Self = Self { aabb: Aabb::EMPTY, sphere: BoundingSphere::EMPTY };
+
+    pub fn from_vertices(vertices: &[crate::MeshVertex]) -> Self
+    {
+        let points = vertices.iter().map(|vertex| Vec3::from_slice(&vertex.position[..3]));
+        Self
+        {
+            aabb: Aabb::from_points(points.clone()),
+            sphere: BoundingSphere::from_points(points),
+        }
+    }
+
+    pub fn transformed(&self, transform: &Mat4) -> Self
+    {
+        Self
+        {
+            aabb: self.aabb.transformed(transform),
+            sphere: self.sphere.transformed(transform),
+        }
+    }
+}
@@ -0,0 +1,659 @@
+use glam::Vec3;
+
+use crate::bounds::Aabb;
+use crate::ray::Ray;
+use crate::MeshVertex;
+
+// Items per leaf at most, a node with more is always split.
+const MAX_LEAF_ITEMS: usize = 4;
+// Buckets the centroids are sorted into along each axis when looking for the cheapest split.
+const SAH_BINS: usize = 12;
+// Cost of visiting a node relative to testing one item.
+const TRAVERSAL_COST: f32 = 1.0;
+
+#[derive(Debug, Copy, Clone)]
+struct BvhNode
+{
+    aabb: Aabb,
+    // A leaf's first item in Bvh::items, or the first child of an inner node. The second child
+    // comes right after it.
+    first: u32,
+    // Zero for inner nodes.
+    count: u32,
+}
+
+// A bounding volume hierarchy over items given by their boxes, split by the surface area
+// heuristic. The children of a node always come after it, so refitting walks the nodes
+// backwards. The queries call back with the items whose boxes pass.
+pub struct Bvh
+{
+    nodes: Vec<BvhNode>,
+    items: Vec<u32>,
+}
+
+impl Bvh
+{
+    pub fn empty() -> Self
+    {
+        Self
+        {
+            nodes: Vec::new(),
+            items: Vec::new(),
+        }
+    }
+
+    // Items with an empty box are left out.
+    pub fn build(bounds: &[Aabb]) -> Self
+    {
+        let items: Vec<u32> = (0..bounds.len() as u32).filter(|&item| !bounds[item as usize].is_empty()).collect();
+        if items.is_empty()
+        {
+            return Self::empty();
+        }
+        let mut bvh = Self
+        {
+            nodes: Vec::with_capacity(items.len() * 2 / MAX_LEAF_ITEMS + 1),
+            items,
+        };
+        bvh.nodes.push(BvhNode { aabb: Aabb::EMPTY, first: 0, count: bvh.items.len() as u32 });
+        let centroids: Vec<Vec3> = bounds.iter().map(|aabb| aabb.center()).collect();
+        bvh.split(0, bounds, &centroids);
+        return bvh;
+    }
+
+    fn split(&mut self, node: usize, bounds: &[Aabb], centroids: &[Vec3])
+    {
+        let first = self.nodes[node].first as usize;
+        let count = self.nodes[node].count as usize;
+        let items = &mut self.items[first..first + count];
+        let aabb = items.iter().fold(Aabb::EMPTY, |aabb, &item| aabb.union(&bounds[item as usize]));
+        self.nodes[node].aabb = aabb;
+        if count == 1
+        {
+            return;
+        }
+
+        let centroid_bounds = Aabb::from_points(items.iter().map(|&item| centroids[item as usize]));
+        let split = cheapest_split(items, bounds, centroids, &centroid_bounds, aabb.surface_area());
+        let leaf_cost = count as f32;
+        let middle = match split
+        {
+            Some((axis, bin, cost)) if cost < leaf_cost || count > MAX_LEAF_ITEMS =>
+            {
+                let scale = SAH_BINS as f32 / (centroid_bounds.max[axis] - centroid_bounds.min[axis]);
+                partition(items, |item| bin_index(centroids[item as usize][axis], centroid_bounds.min[axis], scale) <= bin)
+            },
+            // Every centroid in the same spot, halved so the leaves stay small.
+            None if count > MAX_LEAF_ITEMS => count / 2,
+            _ => return,
+        };
+
+        let left = self.nodes.len();
+        self.nodes.push(BvhNode { aabb: Aabb::EMPTY, first: first as u32, count: middle as u32 });
+        self.nodes.push(BvhNode { aabb: Aabb::EMPTY, first: (first + middle) as u32, count: (count - middle) as u32 });
+        self.nodes[node].first = left as u32;
+        self.nodes[node].count = 0;
+        self.split(left, bounds, centroids);
+        self.split(left + 1, bounds, centroids);
+    }
+
+    // Updates the boxes after the items moved, keeping the tree. The bounds of the same items
+    // as in build, the tree gets slower to query the further they move.
+    pub fn refit(&mut self, bounds: &[Aabb])
+    {
+        for node in (0..self.nodes.len()).rev()
+        {
+            let BvhNode { first, count, .. } = self.nodes[node];
+            let first = first as usize;
+            self.nodes[node].aabb = if count == 0
+            {
+                self.nodes[first].aabb.union(&self.nodes[first + 1].aabb)
+            }
+            else
+            {
+                self.items[first..first + count as usize]
+                    .iter()
+                    .fold(Aabb::EMPTY, |aabb, &item| aabb.union(&bounds[item as usize]))
+            };
+        }
+    }
+
+    pub fn aabb(&self) -> Aabb
+    {
+        return self.nodes.first().map_or(Aabb::EMPTY, |root| root.aabb);
+    }
+
+    // Items in the tree, without the ones left out for their empty boxes.
+    pub fn item_count(&self) -> usize
+    {
+        return self.items.len();
+    }
+
+    // Visits the items whose boxes the ray enters before max_t, nearer nodes first. hit returns
+    // the distance when the ray hits the item closer than the max_t it is given. Returns the
+    // closest hit item and its distance.
+    pub fn raycast(&self, ray: &Ray, max_t: f32, mut hit: impl FnMut(usize, f32) -> Option<f32>) -> Option<(usize, f32)>
+    {
+        let mut closest = None;
+        let mut max_t = max_t;
+        let mut stack: Vec<usize> = Vec::with_capacity(64);
+        if !self.nodes.is_empty()
+        {
+            stack.push(0);
+        }
+        while let Some(node) = stack.pop()
+        {
+            let node = &self.nodes[node];
+            if ray.intersect_aabb(&node.aabb, max_t).is_none()
+            {
+                continue;
+            }
+            if node.count == 0
+            {
+                // The nearer child is popped first.
+                let left = node.first as usize;
+                let left_t = ray.intersect_aabb(&self.nodes[left].aabb, max_t);
+                let right_t = ray.intersect_aabb(&self.nodes[left + 1].aabb, max_t);
+                if left_t.unwrap_or(f32::INFINITY) < right_t.unwrap_or(f32::INFINITY)
+                {
+                    stack.extend([left + 1, left]);
+                }
+                else
+                {
+                    stack.extend([left, left + 1]);
+                }
+                continue;
+            }
+            for &item in &self.items[node.first as usize..(node.first + node.count) as usize]
+            {
+                if let Some(t) = hit(item as usize, max_t).filter(|&t| t < max_t)
+                {
+                    max_t = t;
+                    closest = Some((item as usize, t));
+                }
+            }
+        }
+        return closest;
+    }
+
+    // Visits the items whose boxes overlap the box.
+    pub fn overlapping(&self, aabb: &Aabb, mut visit: impl FnMut(usize))
+    {
+        let mut stack: Vec<usize> = Vec::with_capacity(64);
+        if !self.nodes.is_empty()
+        {
+            stack.push(0);
+        }
+        while let Some(node) = stack.pop()
+        {
+            let node = &self.nodes[node];
+            if !node.aabb.overlaps(aabb)
+            {
+                continue;
+            }
+            if node.count == 0
+            {
+                stack.extend([node.first as usize, node.first as usize + 1]);
+                continue;
+            }
+            for &item in &self.items[node.first as usize..(node.first + node.count) as usize]
+            {
+                visit(item as usize);
+            }
+        }
+    }
+
+    // Visits the items whose boxes are closer to the point than the closest item found so far,
+    // nearer nodes first. distance returns the squared distance to the item when it is below
+    // the squared distance it is given. Returns the closest item and its squared distance.
+    pub fn nearest(
+        &self,
+        point: Vec3,
+        max_distance_squared: f32,
+        mut distance: impl FnMut(usize, f32) -> Option<f32>
+    ) -> Option<(usize, f32)>
+    {
+        let mut closest = None;
+        let mut max_distance_squared = max_distance_squared;
+        let mut stack: Vec<(usize, f32)> = Vec::with_capacity(64);
+        if !self.nodes.is_empty()
+        {
+            stack.push((0, self.nodes[0].aabb.distance_squared(point)));
+        }
+        while let Some((node, node_distance_squared)) = stack.pop()
+        {
+            if node_distance_squared >= max_distance_squared
+            {
+                continue;
+            }
+            let node = &self.nodes[node];
+            if node.count == 0
+            {
+                let left = node.first as usize;
+                let left_distance = self.nodes[left].aabb.distance_squared(point);
+                let right_distance = self.nodes[left + 1].aabb.distance_squared(point);
+                if left_distance < right_distance
+                {
+                    stack.extend([(left + 1, right_distance), (left, left_distance)]);
+                }
+                else
+                {
+                    stack.extend([(left, left_distance), (left + 1, right_distance)]);
+                }
+                continue;
+            }
+            for &item in &self.items[node.first as usize..(node.first + node.count) as usize]
+            {
+                if let Some(distance_squared) = distance(item as usize, max_distance_squared)
+                    .filter(|&distance_squared| distance_squared < max_distance_squared)
+                {
+                    max_distance_squared = distance_squared;
+                    closest = Some((item as usize, distance_squared));
+                }
+            }
+        }
+        return closest;
+    }
+}
+
+// The axis, the last bin on the left side and the cost of the cheapest binned split, relative
+// to testing every item of the node.
+fn cheapest_split(
+    items: &[u32],
+    bounds: &[Aabb],
+    centroids: &[Vec3],
+    centroid_bounds: &Aabb,
+    parent_area: f32
+) -> Option<(usize, usize, f32)>
+{
+    let mut best: Option<(usize, usize, f32)> = None;
+    for axis in 0..3
+    {
+        let extent = centroid_bounds.max[axis] - centroid_bounds.min[axis];
+        if extent <= 0.0
+        {
+            continue;
+        }
+        let scale = SAH_BINS as f32 / extent;
+        let mut bins = [(Aabb::EMPTY, 0usize); SAH_BINS];
+        for &item in items
+        {
+            let bin = &mut bins[bin_index(centroids[item as usize][axis], centroid_bounds.min[axis], scale)];
+            bin.0 = bin.0.union(&bounds[item as usize]);
+            bin.1 += 1;
+        }
+
+        // Areas and counts of everything left of each split, then right of it.
+        let mut left = [(0.0f32, 0usize); SAH_BINS - 1];
+        let mut aabb = Aabb::EMPTY;
+        let mut count = 0;
+        for split in 0..SAH_BINS - 1
+        {
+            aabb = aabb.union(&bins[split].0);
+            count += bins[split].1;
+            left[split] = (aabb.surface_area(), count);
+        }
+        let mut aabb = Aabb::EMPTY;
+        let mut count = 0;
+        for split in (0..SAH_BINS - 1).rev()
+        {
+            aabb = aabb.union(&bins[split + 1].0);
+            count += bins[split + 1].1;
+            let (left_area, left_count) = left[split];
+            if left_count == 0 || count == 0
+            {
+                continue;
+            }
+            let cost = TRAVERSAL_COST
+                + (left_area * left_count as f32 + aabb.surface_area() * count as f32) / parent_area.max(f32::MIN_POSITIVE);
+            if best.map_or(true, |(_, _, best_cost)| cost < best_cost)
+            {
+                best = Some((axis, split, cost));
+            }
+        }
+    }
+    return best;
+}
+
+fn bin_index(centroid: f32, min: f32, scale: f32) -> usize
+{
+    return (((centroid - min) * scale) as usize).min(SAH_BINS - 1);
+}
+
+// Moves the items going left before the others, returns how many went left.
+fn partition(items: &mut [u32], goes_left: impl Fn(u32) -> bool) -> usize
+{
+    let mut left = 0;
+    for index in 0..items.len()
+    {
+        if goes_left(items[index])
+        {
+            items.swap(left, index);
+            left += 1;
+        }
+    }
+    return left;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TriangleHit
+{
+    // Index of the triangle in the model, its indices start at triangle * 3.
+    pub triangle: usize,
+    pub distance: f32,
+    // Weights of the triangle's second and third vertex.
+    pub barycentric: [f32; 2],
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NearestTriangle
+{
+    pub triangle: usize,
+    pub point: Vec3,
+    pub distance: f32,
+}
+
+// A Bvh over the triangles of one model, in the model's space. Takes the model's vertices and
+// indices in every call, the indices relative to its first vertex.
+pub struct MeshBvh
+{
+    bvh: Bvh,
+}
+
+impl MeshBvh
+{
+    pub fn empty() -> Self
+    {
+        Self
+        {
+            bvh: Bvh::empty(),
+        }
+    }
+
+    pub fn build(vertices: &[MeshVertex], indices: &[u32]) -> Self
+    {
+        Self
+        {
+            bvh: Bvh::build(&triangle_bounds(vertices, indices)),
+        }
+    }
+
+    // After the vertices moved, the indices must stay the same.
+    pub fn refit(&mut self, vertices: &[MeshVertex], indices: &[u32])
+    {
+        self.bvh.refit(&triangle_bounds(vertices, indices));
+    }
+
+    pub fn aabb(&self) -> Aabb
+    {
+        return self.bvh.aabb();
+    }
+
+    // The closest triangle the ray hits before max_t.
+    pub fn raycast(&self, ray: &Ray, max_t: f32, vertices: &[MeshVertex], indices: &[u32]) -> Option<TriangleHit>
+    {
+        let mut barycentric = [0.0; 2];
+        let (triangle, distance) = self.bvh.raycast(ray, max_t, |triangle, max_t| {
+            let [a, b, c] = triangle_positions(vertices, indices, triangle);
+            let (t, u, v) = ray.intersect_triangle(a, b, c).filter(|&(t, _, _)| t < max_t)?;
+            barycentric = [u, v];
+            Some(t)
+        })?;
+        return Some(TriangleHit { triangle, distance, barycentric });
+    }
+
+    // The triangles whose boxes overlap the box.
+    pub fn overlapping(&self, aabb: &Aabb, triangles: &mut Vec<usize>)
+    {
+        self.bvh.overlapping(aabb, |triangle| triangles.push(triangle));
+    }
+
+    // The closest point on the mesh within max_distance.
+    pub fn nearest(&self, point: Vec3, max_distance: f32, vertices: &[MeshVertex], indices: &[u32]) -> Option<NearestTriangle>
+    {
+        let mut nearest_point = Vec3::ZERO;
+        let (triangle, distance_squared) = self.bvh.nearest(point, max_distance * max_distance, |triangle, max_distance_squared| {
+            let [a, b, c] = triangle_positions(vertices, indices, triangle);
+            let closest = closest_point_on_triangle(point, a, b, c);
+            let distance_squared = closest.distance_squared(point);
+            if distance_squared >= max_distance_squared
+            {
+                return None;
+            }
+            nearest_point = closest;
+            Some(distance_squared)
+        })?;
+        return Some(NearestTriangle { triangle, point: nearest_point, distance: distance_squared.sqrt() });
+    }
+}
+
+fn triangle_bounds(vertices: &[MeshVertex], indices: &[u32]) -> Vec<Aabb>
+{
+    return (0..indices.len() / 3)
+        .map(|triangle| Aabb::from_points(triangle_positions(vertices, indices, triangle)))
+        .collect();
+}
+
+pub fn triangle_positions(vertices: &[MeshVertex], indices: &[u32], triangle: usize) -> [Vec3; 3]
+{
+    return [0, 1, 2].map(|corner| Vec3::from_slice(&vertices[indices[triangle * 3 + corner] as usize].position[..3]));
+}
+
+// From Real-Time Collision Detection, by the region of the triangle the point projects to.
+pub fn closest_point_on_triangle(point: Vec3, a: Vec3, b: Vec3, c: Vec3) -> Vec3
+{
+    let ab = b - a;
+    let ac = c - a;
+    let ap = point - a;
+    let d1 = ab.dot(ap);
+    let d2 = ac.dot(ap);
+    if d1 <= 0.0 && d2 <= 0.0
+    {
+        return a;
+    }
+    let bp = point - b;
+    let d3 = ab.dot(bp);
+    let d4 = ac.dot(bp);
+    if d3 >= 0.0 && d4 <= d3
+    {
+        return b;
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0
+    {
+        return a + ab * (d1 / (d1 - d3));
+    }
+    let cp = point - c;
+    let d5 = ab.dot(cp);
+    let d6 = ac.dot(cp);
+    if d6 >= 0.0 && d5 <= d6
+    {
+        return c;
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0
+    {
+        return a + ac * (d2 / (d2 - d6));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0
+    {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+    let denominator = 1.0 / (va + vb + vc);
+    return a + ab * (vb * denominator) + ac * (vc * denominator);
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn vertex(position: Vec3) -> MeshVertex
+    {
+        return MeshVertex { position: position.extend(1.0).to_array(), normal: [0.0; 4], color: [1.0; 4] };
+    }
+
+    // A bumpy grid of quads, two triangles each.
+    fn terrain(size: usize) -> (Vec<MeshVertex>, Vec<u32>)
+    {
+        let mut vertices = Vec::new();
+        for z in 0..=size
+        {
+            for x in 0..=size
+            {
+                let height = ((x * 7 + z * 13) % 5) as f32 * 0.2;
+                vertices.push(vertex(Vec3::new(x as f32, height, z as f32)));
+            }
+        }
+        let mut indices = Vec::new();
+        let row = size as u32 + 1;
+        for z in 0..size as u32
+        {
+            for x in 0..size as u32
+            {
+                let corner = z * row + x;
+                indices.extend([corner, corner + row, corner + 1, corner + 1, corner + row, corner + row + 1]);
+            }
+        }
+        return (vertices, indices);
+    }
+
+    fn brute_force_raycast(ray: &Ray, vertices: &[MeshVertex], indices: &[u32]) -> Option<(usize, f32)>
+    {
+        return (0..indices.len() / 3)
+            .filter_map(|triangle| {
+                let [a, b, c] = triangle_positions(vertices, indices, triangle);
+                ray.intersect_triangle(a, b, c).map(|(t, _, _)| (triangle, t))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+    }
+
+    #[test]
+    fn raycast_matches_brute_force()
+    {
+        let (vertices, indices) = terrain(16);
+        let bvh = MeshBvh::build(&vertices, &indices);
+        assert_eq!(bvh.aabb(), Aabb::from_points(vertices.iter().map(|vertex| Vec3::from_slice(&vertex.position[..3]))));
+        for i in 0..200
+        {
+            let origin = Vec3::new((i % 20) as f32 * 0.9 - 1.0, 3.0, (i / 20) as f32 * 1.7 - 0.5);
+            let direction = Vec3::new(((i * 37) % 11) as f32 * 0.1 - 0.5, -1.0, ((i * 17) % 7) as f32 * 0.1 - 0.3);
+            let ray = Ray::new(origin, direction);
+            let expected = brute_force_raycast(&ray, &vertices, &indices);
+            let hit = bvh.raycast(&ray, f32::INFINITY, &vertices, &indices);
+            assert_eq!(hit.map(|hit| hit.distance), expected.map(|(_, t)| t), "ray {}", i);
+        }
+        // Pointing away and stopping short.
+        assert!(bvh.raycast(&Ray::new(Vec3::new(4.0, 3.0, 4.0), Vec3::Y), f32::INFINITY, &vertices, &indices).is_none());
+        assert!(bvh.raycast(&Ray::new(Vec3::new(4.0, 3.0, 4.0), Vec3::NEG_Y), 1.0, &vertices, &indices).is_none());
+    }
+
+    #[test]
+    fn overlap_matches_brute_force_after_refit()
+    {
+        let (mut vertices, indices) = terrain(12);
+        let mut bvh = MeshBvh::build(&vertices, &indices);
+        for vertex in &mut vertices
+        {
+            vertex.position[1] += vertex.position[0] * 0.5;
+        }
+        bvh.refit(&vertices, &indices);
+
+        let query = Aabb::new(Vec3::new(2.5, 0.0, 3.5), Vec3::new(6.0, 3.0, 5.0));
+        let mut triangles = Vec::new();
+        bvh.overlapping(&query, &mut triangles);
+        triangles.sort();
+        let expected: Vec<usize> = (0..indices.len() / 3)
+            .filter(|&triangle| Aabb::from_points(triangle_positions(&vertices, &indices, triangle)).overlaps(&query))
+            .collect();
+        assert!(!expected.is_empty());
+        assert_eq!(triangles, expected);
+    }
+
+    #[test]
+    fn nearest_matches_brute_force()
+    {
+        let (vertices, indices) = terrain(10);
+        let bvh = MeshBvh::build(&vertices, &indices);
+        for i in 0..100
+        {
+            let point = Vec3::new((i % 10) as f32 * 1.3 - 2.0, (i % 7) as f32 * 0.5 - 1.0, (i / 10) as f32 * 1.2 - 1.0);
+            let expected = (0..indices.len() / 3)
+                .map(|triangle| {
+                    let [a, b, c] = triangle_positions(&vertices, &indices, triangle);
+                    closest_point_on_triangle(point, a, b, c).distance(point)
+                })
+                .fold(f32::INFINITY, f32::min);
+            let nearest = bvh.nearest(point, f32::INFINITY, &vertices, &indices).unwrap();
+            assert!((nearest.distance - expected).abs() < 1e-5, "point {}", i);
+            assert!((nearest.point.distance(point) - nearest.distance).abs() < 1e-5);
+        }
+        assert!(bvh.nearest(Vec3::new(5.0, 10.0, 5.0), 1.0, &vertices, &indices).is_none());
+    }
+
+    #[test]
+    fn closest_points_on_a_triangle()
+    {
+        let (a, b, c) = (Vec3::ZERO, Vec3::X, Vec3::Y);
+        assert_eq!(closest_point_on_triangle(Vec3::new(-1.0, -1.0, 1.0), a, b, c), a);
+        assert_eq!(closest_point_on_triangle(Vec3::new(2.0, -0.5, 0.0), a, b, c), b);
+        assert_eq!(closest_point_on_triangle(Vec3::new(0.5, -1.0, 0.0), a, b, c), Vec3::new(0.5, 0.0, 0.0));
+        assert_eq!(closest_point_on_triangle(Vec3::new(1.0, 1.0, 0.0), a, b, c), Vec3::new(0.5, 0.5, 0.0));
+        assert_eq!(closest_point_on_triangle(Vec3::new(0.25, 0.25, 2.0), a, b, c), Vec3::new(0.25, 0.25, 0.0));
+    }
+
+    #[test]
+    fn boxes_with_the_same_centroid_still_split()
+    {
+        let bounds = vec![Aabb::new(Vec3::splat(-1.0), Vec3::splat(1.0)); 20];
+        let bvh = Bvh::build(&bounds);
+        let mut items = Vec::new();
+        bvh.overlapping(&Aabb::new(Vec3::ZERO, Vec3::ZERO), |item| items.push(item));
+        items.sort();
+        assert_eq!(items, (0..20).collect::<Vec<usize>>());
+        assert!(bvh.nodes.iter().all(|node| node.count as usize <= MAX_LEAF_ITEMS));
+    }
+
+    #[test]
+    fn scene_queries_follow_moved_entities()
+    {
+        let mut mesh_data = crate::MeshData::new();
+        let (vertices, indices) = terrain(4);
+        let model = mesh_data.add_model(&vertices, &indices);
+        let mut scene = crate::Scene::new(100.0, 100.0);
+        for i in 0..10
+        {
+            scene.entities.push(crate::Entity {
+                transform: crate::Transform {
+                    pos: glam::Vec3A::new(i as f32 * 10.0, 0.0, 0.0),
+                    rot: glam::Quat::IDENTITY,
+                    scale: glam::Vec3A::ONE,
+                },
+                model: Some(model),
+                transparent: false,
+            });
+        }
+        scene.update_bvh(&mesh_data);
+
+        let down = |x: f32| Ray::new(Vec3::new(x, 5.0, 2.0), Vec3::NEG_Y);
+        assert_eq!(scene.raycast(&down(32.0), &mesh_data).map(|hit| hit.entity), Some(3));
+        assert!(scene.raycast(&down(37.0), &mesh_data).is_none());
+
+        // Refitted, entity 3 now sits where the gap was.
+        scene.entities[3].transform.pos.x = 35.0;
+        scene.update_bvh(&mesh_data);
+        assert_eq!(scene.raycast(&down(37.0), &mesh_data).map(|hit| hit.entity), Some(3));
+        assert!(scene.raycast(&down(33.0), &mesh_data).is_none());
+
+        let mut entities = Vec::new();
+        scene.overlapping_entities(&Aabb::new(Vec3::new(38.0, 0.0, 0.0), Vec3::new(41.0, 1.0, 1.0)), &mut entities);
+        entities.sort();
+        assert_eq!(entities, vec![3, 4]);
+
+        let nearest = scene.nearest_point(Vec3::new(38.5, 1.5, 2.0), 2.0, &mesh_data).unwrap();
+        assert_eq!(nearest.entity, 3);
+        assert!((nearest.position.distance(Vec3::new(38.5, 1.5, 2.0)) - nearest.distance).abs() < 1e-5);
+        assert!(scene.nearest_point(Vec3::new(37.0, 20.0, 2.0), 2.0, &mesh_data).is_none());
+    }
+}
@@ -0,0 +1,280 @@
+use glam::Vec3;
+
+use crate::bounds::Aabb;
+use crate::bvh::triangle_positions;
+use crate::physics::{capsule_mesh_contacts, Contact};
+use crate::ray::Ray;
+use crate::{MeshData, Scene};
+
+const GROUND_ACCELERATION: f32 = 60.0;
+const AIR_ACCELERATION: f32 = 8.0;
+const MAX_FALL_SPEED: f32 = 50.0;
+// Pushing out of the deepest contact at a time, a corner takes a few rounds.
+const RESOLVE_ITERATIONS: usize = 8;
+// Shallower contacts are left alone, so standing still has nothing to fix.
+const CONTACT_SLOP: f32 = 1e-4;
+// Stepping onto a ledge lifts the capsule this much over it, clear of its edge.
+const STEP_CLEARANCE: f32 = 0.01;
+// How far into an edge and above it the ground under it is looked for.
+const EDGE_PROBE: f32 = 0.01;
+
+// What the player wants the character to do this frame.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct CharacterInput
+{
+    // Horizontal in world space, up to unit length for full speed.
+    pub direction: Vec3,
+    pub run: bool,
+    pub jump: bool,
+}
+
+// A capsule walking on the scene entities' meshes with y up. It is moved by its own velocity
+// rather than the physics, and pushed out of the geometry after every move. Walkable ground
+// holds it up, steeper slopes and walls block it. Uses the scene bvh, see Scene::update_bvh.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CharacterController
+{
+    // The bottom of the capsule.
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub radius: f32,
+    pub height: f32,
+    // From the bottom of the capsule.
+    pub eye_height: f32,
+    pub walk_speed: f32,
+    pub run_speed: f32,
+    pub jump_speed: f32,
+    // Ledges up to this high are walked onto without jumping.
+    pub step_height: f32,
+    // The steepest walkable ground in radians.
+    pub max_slope: f32,
+    grounded: bool,
+}
+
+impl CharacterController
+{
+    pub fn new(position: Vec3) -> Self
+    {
+        Self
+        {
+            position,
+            velocity: Vec3::ZERO,
+            radius: 0.3,
+            height: 1.8,
+            eye_height: 1.6,
+            walk_speed: 4.0,
+            run_speed: 8.0,
+            jump_speed: 5.0,
+            step_height: 0.35,
+            max_slope: 45.0f32.to_radians(),
+            grounded: false,
+        }
+    }
+
+    pub fn is_grounded(&self) -> bool
+    {
+        return self.grounded;
+    }
+
+    pub fn eye(&self) -> Vec3
+    {
+        return self.position + Vec3::Y * self.eye_height;
+    }
+
+    // Moves the character without colliding, e.g. to the camera when switching from flying.
+    pub fn teleport(&mut self, position: Vec3)
+    {
+        self.position = position;
+        self.velocity = Vec3::ZERO;
+        self.grounded = false;
+    }
+
+    pub fn update(&mut self, dt: f32, input: &CharacterInput, gravity: Vec3, scene: &Scene, mesh_data: &MeshData)
+    {
+        let speed = if input.run { self.run_speed } else { self.walk_speed };
+        let target = Vec3::new(input.direction.x, 0.0, input.direction.z).clamp_length_max(1.0) * speed;
+        let horizontal = Vec3::new(self.velocity.x, 0.0, self.velocity.z);
+        // In the air without input the character keeps its momentum.
+        let horizontal = if self.grounded
+        {
+            move_towards(horizontal, target, GROUND_ACCELERATION * dt)
+        }
+        else if target != Vec3::ZERO
+        {
+            move_towards(horizontal, target, AIR_ACCELERATION * dt)
+        }
+        else
+        {
+            horizontal
+        };
+        self.velocity = Vec3::new(horizontal.x, self.velocity.y, horizontal.z);
+        if input.jump && self.grounded
+        {
+            self.velocity.y = self.jump_speed;
+        }
+        self.velocity += gravity * dt;
+        self.velocity.y = self.velocity.y.max(-MAX_FALL_SPEED);
+
+        let was_grounded = self.grounded;
+        let start = self.position;
+        let start_velocity = self.velocity;
+        let walk = Vec3::new(self.velocity.x, 0.0, self.velocity.z) * dt;
+        let mut ground = self.move_by(walk, scene, mesh_data);
+        let progress = |position: Vec3| (position - start).dot(walk);
+        if was_grounded && progress(self.position) < walk.length_squared() * 0.99
+        {
+            // Blocked, tries again from the top of a low enough ledge ahead.
+            if let Some(step) = self.step_up_height(start, walk, scene, mesh_data)
+            {
+                let walked = (self.position, self.velocity);
+                self.position = start;
+                self.velocity = start_velocity;
+                self.move_by(Vec3::Y * (step + STEP_CLEARANCE), scene, mesh_data);
+                self.move_by(walk, scene, mesh_data);
+                if progress(self.position) <= progress(walked.0)
+                {
+                    (self.position, self.velocity) = walked;
+                }
+            }
+        }
+
+        ground = self.move_by(Vec3::Y * self.velocity.y * dt, scene, mesh_data).or(ground);
+        self.grounded = ground.is_some() && self.velocity.y <= 0.0;
+
+        // Snaps down slopes and stairs instead of running off them.
+        if was_grounded && !self.grounded && self.velocity.y <= 0.0
+        {
+            let (position, velocity) = (self.position, self.velocity);
+            if self.move_by(Vec3::NEG_Y * self.step_height, scene, mesh_data).is_some()
+            {
+                self.grounded = true;
+            }
+            else
+            {
+                self.position = position;
+                self.velocity = velocity;
+            }
+        }
+    }
+
+    // How far above the start the top of a ledge is, from a ray down in front of the capsule.
+    // None for ledges higher than the step height and for steep slopes.
+    fn step_up_height(&self, start: Vec3, walk: Vec3, scene: &Scene, mesh_data: &MeshData) -> Option<f32>
+    {
+        let ahead = walk.normalize_or_zero() * (self.radius + walk.length());
+        let ray = Ray::new(start + ahead + Vec3::Y * self.step_height, Vec3::NEG_Y);
+        let distance = self.walkable_hit(&ray, self.step_height, scene, mesh_data)?;
+        return Some(self.step_height - distance);
+    }
+
+    // The distance to the walkable ground the ray hits before max_distance.
+    fn walkable_hit(&self, ray: &Ray, max_distance: f32, scene: &Scene, mesh_data: &MeshData) -> Option<f32>
+    {
+        let hit = scene.raycast(ray, mesh_data).filter(|hit| hit.triangle.distance < max_distance)?;
+        let entity = &scene.entities[hit.entity];
+        let (vertices, indices) = mesh_data.model_mesh(entity.model?);
+        let transform = entity.transform.matrix();
+        let [a, b, c] = triangle_positions(vertices, indices, hit.triangle.triangle).map(|position| transform.transform_point3(position));
+        let normal = (b - a).cross(c - a).normalize_or_zero();
+        return if normal.y.abs() >= self.max_slope.cos() { Some(hit.triangle.distance) } else { None };
+    }
+
+    // Moves in parts shorter than the radius, so the capsule can't pass through a triangle, and
+    // pushes out of the geometry after each. Moving down stops on the ground. Returns the normal
+    // of the walkable ground touched.
+    fn move_by(&mut self, displacement: Vec3, scene: &Scene, mesh_data: &MeshData) -> Option<Vec3>
+    {
+        let parts = ((displacement.length() / (self.radius * 0.5)).ceil() as usize).max(1);
+        let mut ground = None;
+        for _ in 0..parts
+        {
+            self.position += displacement / parts as f32;
+            if let Some(normal) = self.resolve(scene, mesh_data)
+            {
+                ground = Some(normal);
+                if displacement.y < 0.0
+                {
+                    break;
+                }
+            }
+        }
+        return ground;
+    }
+
+    // Walkable ground pushes straight up, so standing on a slope doesn't slide down it. Walls,
+    // ceilings and steeper slopes push the capsule and its velocity away.
+    fn resolve(&mut self, scene: &Scene, mesh_data: &MeshData) -> Option<Vec3>
+    {
+        let min_ground_normal_y = self.max_slope.cos();
+        let mut ground = None;
+        for _ in 0..RESOLVE_ITERATIONS
+        {
+            let contacts = self.contacts(scene, mesh_data);
+            let Some(deepest) = contacts.iter()
+                .filter(|contact| contact.depth > CONTACT_SLOP)
+                .max_by(|a, b| a.depth.total_cmp(&b.depth)) else
+            {
+                break;
+            };
+            let push = -deepest.normal;
+            if push.y >= min_ground_normal_y
+            {
+                self.position.y += deepest.depth / push.y;
+                self.velocity.y = self.velocity.y.max(0.0);
+                ground = Some(push);
+                continue;
+            }
+            // The edge of walkable ground under the capsule, e.g. of a stair, is stood on too.
+            let touch = deepest.point - deepest.normal * deepest.depth * 0.5;
+            let inwards = Vec3::new(deepest.normal.x, 0.0, deepest.normal.z).normalize_or_zero();
+            let probe = Ray::new(touch + (inwards + Vec3::Y) * EDGE_PROBE, Vec3::NEG_Y);
+            if push.y > 0.0 && self.walkable_hit(&probe, 2.0 * EDGE_PROBE, scene, mesh_data).is_some()
+            {
+                // Up until the bottom sphere only touches the edge.
+                let bottom = self.position + Vec3::Y * self.radius;
+                let across = Vec3::new(bottom.x - touch.x, 0.0, bottom.z - touch.z).length_squared();
+                let lift = touch.y + (self.radius * self.radius - across).max(0.0).sqrt() - bottom.y;
+                self.position.y += lift.max(CONTACT_SLOP);
+                self.velocity.y = self.velocity.y.max(0.0);
+                ground = Some(Vec3::Y);
+                continue;
+            }
+            // Only sideways from steep slopes, so they can't be climbed.
+            let sideways = Vec3::new(push.x, 0.0, push.z).normalize_or_zero();
+            let direction = if push.y > 0.0 && sideways != Vec3::ZERO { sideways } else { push };
+            self.position += direction * deepest.depth / push.dot(direction).max(0.1);
+            let into = self.velocity.dot(direction);
+            if into < 0.0
+            {
+                self.velocity -= direction * into;
+            }
+        }
+        return ground;
+    }
+
+    fn contacts(&self, scene: &Scene, mesh_data: &MeshData) -> Vec<Contact>
+    {
+        let bottom = self.position + Vec3::Y * self.radius;
+        let top = self.position + Vec3::Y * (self.height - self.radius).max(self.radius);
+        let aabb = Aabb::new(bottom - self.radius, top + self.radius);
+        let mut entities = Vec::new();
+        scene.overlapping_entities(&aabb, &mut entities);
+        let mut contacts = Vec::new();
+        for entity in entities
+        {
+            let entity = &scene.entities[entity];
+            if let Some(model) = entity.model
+            {
+                capsule_mesh_contacts(bottom, top, self.radius, model, &entity.transform.matrix(), mesh_data, &mut contacts);
+            }
+        }
+        return contacts;
+    }
+}
+
+fn move_towards(from: Vec3, to: Vec3, max_distance: f32) -> Vec3
+{
+    let offset = to - from;
+    let distance = offset.length();
+    return if distance <= max_distance { to } else { from + offset * (max_distance / distance) };
+}
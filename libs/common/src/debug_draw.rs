@@ -0,0 +1,137 @@
+use glam::{Mat4, Quat, Vec3};
+
+// Line segments per circle of a sphere.
+const SPHERE_SEGMENTS: usize = 24;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DebugLine
+{
+    pub start: Vec3,
+    pub end: Vec3,
+    // sRGB with alpha.
+    pub color: [f32; 4],
+    // Hidden behind the scene geometry, otherwise drawn over it.
+    pub depth_test: bool,
+}
+
+// Immediate mode world space lines, filled by the systems every frame and cleared before the
+// next one. Every shape is drawn as lines.
+pub struct DebugDraw
+{
+    lines: Vec<DebugLine>,
+    // Applies to the shapes added after changing it, reset by clear.
+    pub depth_test: bool,
+}
+
+impl DebugDraw
+{
+    pub fn new() -> Self
+    {
+        Self
+        {
+            lines: Vec::new(),
+            depth_test: true,
+        }
+    }
+
+    pub fn clear(&mut self)
+    {
+        self.lines.clear();
+        self.depth_test = true;
+    }
+
+    pub fn lines(&self) -> &[DebugLine]
+    {
+        return &self.lines;
+    }
+
+    pub fn line(&mut self, start: Vec3, end: Vec3, color: [f32; 4])
+    {
+        self.lines.push(DebugLine { start, end, color, depth_test: self.depth_test });
+    }
+
+    pub fn aabb(&mut self, min: Vec3, max: Vec3, color: [f32; 4])
+    {
+        let center = (min + max) * 0.5;
+        self.oriented_box(center, (max - min) * 0.5, Quat::IDENTITY, color);
+    }
+
+    pub fn oriented_box(&mut self, center: Vec3, half_extents: Vec3, rotation: Quat, color: [f32; 4])
+    {
+        let corners: [Vec3; 8] = std::array::from_fn(|index| {
+            let sign = Vec3::new(
+                if index & 1 == 0 { -1.0 } else { 1.0 },
+                if index & 2 == 0 { -1.0 } else { 1.0 },
+                if index & 4 == 0 { -1.0 } else { 1.0 });
+            center + rotation * (sign * half_extents)
+        });
+        self.box_edges(&corners, color);
+    }
+
+    // Three great circles around the axes.
+    pub fn sphere(&mut self, center: Vec3, radius: f32, color: [f32; 4])
+    {
+        let axes = [(Vec3::X, Vec3::Y), (Vec3::Y, Vec3::Z), (Vec3::Z, Vec3::X)];
+        for (u, v) in axes
+        {
+            let point = |segment: usize| {
+                let angle = segment as f32 / SPHERE_SEGMENTS as f32 * std::f32::consts::TAU;
+                center + (u * angle.cos() + v * angle.sin()) * radius
+            };
+            for segment in 0..SPHERE_SEGMENTS
+            {
+                self.line(point(segment), point(segment + 1), color);
+            }
+        }
+    }
+
+    // The spheres at the ends joined by four lines along the sides.
+    pub fn capsule(&mut self, a: Vec3, b: Vec3, radius: f32, color: [f32; 4])
+    {
+        self.sphere(a, radius, color);
+        self.sphere(b, radius, color);
+        let (u, v) = (b - a).normalize_or_zero().any_orthonormal_pair();
+        for side in [u, -u, v, -v]
+        {
+            self.line(a + side * radius, b + side * radius, color);
+        }
+    }
+
+    // The volume a view projection matrix sees, e.g. Camera::build_view_projection_matrix.
+    pub fn frustum(&mut self, view_projection: Mat4, color: [f32; 4])
+    {
+        let inverse = view_projection.inverse();
+        // Clip space depth goes from 0 at the near plane to 1 at the far plane.
+        let corners: [Vec3; 8] = std::array::from_fn(|index| {
+            let ndc = Vec3::new(
+                if index & 1 == 0 { -1.0 } else { 1.0 },
+                if index & 2 == 0 { -1.0 } else { 1.0 },
+                if index & 4 == 0 { 0.0 } else { 1.0 });
+            inverse.project_point3(ndc)
+        });
+        self.box_edges(&corners, color);
+    }
+
+    // X in red, y in green and z in blue.
+    pub fn axes(&mut self, position: Vec3, rotation: Quat, length: f32)
+    {
+        self.line(position, position + rotation * Vec3::X * length, [1.0, 0.0, 0.0, 1.0]);
+        self.line(position, position + rotation * Vec3::Y * length, [0.0, 1.0, 0.0, 1.0]);
+        self.line(position, position + rotation * Vec3::Z * length, [0.0, 0.0, 1.0, 1.0]);
+    }
+
+    // Corners indexed by bit 0 for x, bit 1 for y and bit 2 for z.
+    fn box_edges(&mut self, corners: &[Vec3; 8], color: [f32; 4])
+    {
+        for index in 0..8
+        {
+            for bit in [1, 2, 4]
+            {
+                if index & bit == 0
+                {
+                    self.line(corners[index], corners[index | bit], color);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,51 @@
+pub const DEBUG_TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugText
+{
+    // Window pixels from the top left corner.
+    pub x: f32,
+    pub y: f32,
+    // sRGB with alpha.
+    pub color: [f32; 4],
+    // Lines are split at '\n'.
+    pub text: String,
+}
+
+// Immediate mode text drawn over the final image, filled by the systems every frame and
+// cleared before the next one.
+pub struct DebugOverlay
+{
+    texts: Vec<DebugText>,
+}
+
+impl DebugOverlay
+{
+    pub fn new() -> Self
+    {
+        Self
+        {
+            texts: Vec::new(),
+        }
+    }
+
+    pub fn clear(&mut self)
+    {
+        self.texts.clear();
+    }
+
+    pub fn text(&mut self, x: f32, y: f32, text: &str)
+    {
+        self.colored_text(x, y, DEBUG_TEXT_COLOR, text);
+    }
+
+    pub fn colored_text(&mut self, x: f32, y: f32, color: [f32; 4], text: &str)
+    {
+        self.texts.push(DebugText { x, y, color, text: text.to_string() });
+    }
+
+    pub fn texts(&self) -> &[DebugText]
+    {
+        return &self.texts;
+    }
+}
@@ -0,0 +1,78 @@
+use std::ops::Range;
+
+// Unused element ranges inside a buffer, sorted by start and never touching each other.
+pub(crate) struct FreeRanges
+{
+    ranges: Vec<Range<usize>>,
+}
+
+impl FreeRanges
+{
+    pub(crate) fn new() -> Self
+    {
+        Self
+        {
+            ranges: Vec::new(),
+        }
+    }
+
+    pub(crate) fn clear(&mut self)
+    {
+        self.ranges.clear();
+    }
+
+    // Elements in all the holes.
+    pub(crate) fn total(&self) -> usize
+    {
+        return self.ranges.iter().map(|range| range.len()).sum();
+    }
+
+    // The start of the first hole count elements fit in.
+    pub(crate) fn allocate(&mut self, count: usize) -> Option<usize>
+    {
+        let index = self.ranges.iter().position(|range| range.len() >= count)?;
+        let start = self.ranges[index].start;
+        self.ranges[index].start += count;
+        if self.ranges[index].is_empty()
+        {
+            self.ranges.remove(index);
+        }
+        return Some(start);
+    }
+
+    // Merges the range with the holes next to it.
+    pub(crate) fn free(&mut self, range: Range<usize>)
+    {
+        if range.is_empty()
+        {
+            return;
+        }
+        let index = self.ranges.partition_point(|free| free.start < range.start);
+        self.ranges.insert(index, range);
+        if index + 1 < self.ranges.len() && self.ranges[index].end == self.ranges[index + 1].start
+        {
+            self.ranges[index].end = self.ranges[index + 1].end;
+            self.ranges.remove(index + 1);
+        }
+        if index > 0 && self.ranges[index - 1].end == self.ranges[index].start
+        {
+            self.ranges[index - 1].end = self.ranges[index].end;
+            self.ranges.remove(index);
+        }
+    }
+
+    // Removes the hole ending at len, returns the new length of the buffer.
+    pub(crate) fn trim_end(&mut self, len: usize) -> usize
+    {
+        match self.ranges.last()
+        {
+            Some(last) if last.end == len =>
+            {
+                let start = last.start;
+                self.ranges.pop();
+                return start;
+            },
+            _ => return len,
+        }
+    }
+}
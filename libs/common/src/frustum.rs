@@ -0,0 +1,148 @@
+use glam::{Mat4, Vec3, Vec4};
+
+use crate::bounds::{Aabb, BoundingSphere};
+
+// The six planes of a view projection, normals pointing inside. Assumes the 0 to 1 depth range
+// of glam's perspective_rh, the one Camera::build_view_projection_matrix uses.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Frustum
+{
+    // Left, right, bottom, top, near, far. Normalized, so the distance to a plane is
+    // plane.xyz . point + plane.w.
+    pub planes: [Vec4; 6],
+}
+
+impl Frustum
+{
+    pub fn from_view_projection(view_projection: &Mat4) -> Self
+    {
+        let x = view_projection.row(0);
+        let y = view_projection.row(1);
+        let z = view_projection.row(2);
+        let w = view_projection.row(3);
+        let planes = [w + x, w - x, w + y, w - y, z, w - z].map(|plane| plane / plane.truncate().length());
+        Self { planes }
+    }
+
+    pub fn contains_point(&self, point: Vec3) -> bool
+    {
+        return self.planes.iter().all(|plane| plane.truncate().dot(point) + plane.w >= 0.0);
+    }
+
+    // Conservative, a sphere near a corner of the frustum can pass while being outside.
+    pub fn intersects_sphere(&self, sphere: &BoundingSphere) -> bool
+    {
+        return self.planes.iter().all(|plane| plane.truncate().dot(sphere.center) + plane.w >= -sphere.radius);
+    }
+
+    // Tests the corner furthest along each plane normal, conservative the same way as the sphere.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool
+    {
+        return self.planes.iter().all(|plane| {
+            let normal = plane.truncate();
+            let corner = Vec3::select(normal.cmpge(Vec3::ZERO), aabb.max, aabb.min);
+            normal.dot(corner) + plane.w >= 0.0
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::Camera;
+
+    // The default camera at 0, 1, 2 looking down -z, with a square aspect.
+    fn camera() -> Camera
+    {
+        return Camera::new(100.0, 100.0);
+    }
+
+    fn frustum(camera: &Camera) -> Frustum
+    {
+        return Frustum::from_view_projection(&camera.build_view_projection_matrix());
+    }
+
+    #[test]
+    fn planes_are_normalized()
+    {
+        for plane in frustum(&camera()).planes
+        {
+            assert!((plane.truncate().length() - 1.0).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn near_and_far_planes_match_the_camera()
+    {
+        let camera = camera();
+        let frustum = frustum(&camera);
+        let near = frustum.planes[4];
+        let far = frustum.planes[5];
+        assert!(near.truncate().abs_diff_eq(Vec3::NEG_Z, 1e-5));
+        assert!(far.truncate().abs_diff_eq(Vec3::Z, 1e-5));
+        // At znear and zfar in front of the eye.
+        assert!((near.truncate().dot(camera.eye) + near.w + camera.znear).abs() < 1e-4);
+        assert!((far.truncate().dot(camera.eye) + far.w - camera.zfar).abs() < 1e-3);
+    }
+
+    #[test]
+    fn points()
+    {
+        let camera = camera();
+        let frustum = frustum(&camera);
+        assert!(frustum.contains_point(Vec3::new(0.0, 1.0, -5.0)));
+        // Behind the eye, closer than znear and past zfar.
+        assert!(!frustum.contains_point(Vec3::new(0.0, 1.0, 5.0)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 1.0, 2.0 - camera.znear * 0.5)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 1.0, 2.0 - camera.zfar - 1.0)));
+        // The half angle is fovy / 2 on both axes with a square aspect.
+        let distance = 10.0;
+        let half_size = distance * (camera.fovy.to_radians() * 0.5).tan();
+        let center = Vec3::new(0.0, 1.0, 2.0 - distance);
+        for side in [Vec3::X, Vec3::NEG_X, Vec3::Y, Vec3::NEG_Y]
+        {
+            assert!(frustum.contains_point(center + side * half_size * 0.99));
+            assert!(!frustum.contains_point(center + side * half_size * 1.01));
+        }
+    }
+
+    #[test]
+    fn spheres()
+    {
+        let frustum = frustum(&camera());
+        assert!(frustum.intersects_sphere(&BoundingSphere::new(Vec3::new(0.0, 1.0, -5.0), 0.5)));
+        assert!(!frustum.intersects_sphere(&BoundingSphere::new(Vec3::new(0.0, 1.0, 5.0), 0.5)));
+        // Centered behind the eye but reaching in front of the near plane.
+        assert!(frustum.intersects_sphere(&BoundingSphere::new(Vec3::new(0.0, 1.0, 2.5), 1.0)));
+        // Far to the side, and the same sphere grown to reach the view.
+        assert!(!frustum.intersects_sphere(&BoundingSphere::new(Vec3::new(20.0, 1.0, -5.0), 1.0)));
+        assert!(frustum.intersects_sphere(&BoundingSphere::new(Vec3::new(20.0, 1.0, -5.0), 20.0)));
+    }
+
+    #[test]
+    fn aabbs()
+    {
+        let frustum = frustum(&camera());
+        assert!(frustum.intersects_aabb(&Aabb::new(Vec3::new(-0.5, 0.5, -5.5), Vec3::new(0.5, 1.5, -4.5))));
+        assert!(!frustum.intersects_aabb(&Aabb::new(Vec3::new(-0.5, 0.5, 4.5), Vec3::new(0.5, 1.5, 5.5))));
+        // Only a long thin edge pokes into the view.
+        assert!(frustum.intersects_aabb(&Aabb::new(Vec3::new(-20.0, 0.9, -5.1), Vec3::new(0.0, 1.1, -4.9))));
+        assert!(!frustum.intersects_aabb(&Aabb::new(Vec3::new(-20.0, 0.9, -5.1), Vec3::new(-10.0, 1.1, -4.9))));
+    }
+
+    #[test]
+    fn turned_camera()
+    {
+        // Looking down +x and slightly up.
+        let mut camera = camera();
+        camera.heading = std::f32::consts::FRAC_PI_2;
+        camera.pitch = 0.2;
+        let frustum = frustum(&camera);
+        let forward = camera.get_forward();
+        assert!(frustum.contains_point(camera.eye + forward * 10.0));
+        assert!(!frustum.contains_point(camera.eye - forward * 10.0));
+        assert!(!frustum.contains_point(camera.eye + Vec3::NEG_Z * 10.0));
+        assert!(frustum.intersects_sphere(&BoundingSphere::new(camera.eye + forward * 50.0, 1.0)));
+    }
+}
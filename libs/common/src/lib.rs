@@ -1,11 +1,47 @@
+use std::ops::Range;
+
+use free_ranges::FreeRanges;
+
+pub mod profiler;
+
+mod bounds;
+mod bvh;
+mod character;
+mod debug_draw;
+mod debug_overlay;
+mod free_ranges;
+mod frustum;
+mod physics;
+mod ray;
+mod ui;
+
+pub use bounds::{Aabb, BoundingSphere, ModelBounds};
+pub use bvh::{closest_point_on_triangle, triangle_positions, Bvh, MeshBvh, NearestTriangle, TriangleHit};
+pub use character::{CharacterController, CharacterInput};
+pub use debug_draw::{DebugDraw, DebugLine};
+pub use debug_overlay::{DebugOverlay, DebugText, DEBUG_TEXT_COLOR};
+pub use frustum::Frustum;
+pub use physics::{Collider, Contact, Physics, RigidBody, FIXED_STEP};
+pub use ray::Ray;
+pub use ui::Ui;
+// The ui library, so the systems and the renderer use the same version.
+pub use egui;
+
 pub trait System
 {
+    // Used for the profiler spans.
+    fn name(&self) -> &'static str
+    {
+        return std::any::type_name::<Self>();
+    }
     fn update(&mut self, _dt: f64, _game_state: &mut GameState) {}
     fn post_update(&mut self, _dt: f64, _game_state: &mut GameState) {}
+    // Builds the ui panels of the system, called every frame after post_update.
+    fn ui(&mut self, _ctx: &egui::Context, _game_state: &mut GameState) {}
 }
 
 #[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct MeshModelLocation
 {
     pub vertices_start_index: u32,
@@ -32,6 +68,29 @@ pub struct GpuOutInstanceMatrices
     pub v2: [f32; 4],
 }
 
+impl GpuOutInstanceMatrices
+{
+    // The rows of the affine transform, the last row is always 0, 0, 0, 1.
+    pub fn from_mat4(transform: glam::Mat4) -> Self
+    {
+        Self
+        {
+            v0: transform.row(0).to_array(),
+            v1: transform.row(1).to_array(),
+            v2: transform.row(2).to_array(),
+        }
+    }
+}
+
+// The world space bounding sphere of an instance, uploaded with its matrices.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct GpuOutInstanceBounds
+{
+    pub center: [f32; 3],
+    pub radius: f32,
+}
+
 
 
 
@@ -45,14 +104,41 @@ pub struct GpuOutInstanceMatrices
 
 
 
+// The vertices and indices of every model, laid out the same as in the renderer's mesh
+// buffers. Models added at runtime go to the holes freed models left or to the end, the
+// renderer uploads what changed.
 pub struct MeshData
 {
     pub models: Vec<MeshModelLocation>,
+    // Same index as models.
+    pub model_bounds: Vec<ModelBounds>,
+    model_bvhs: Vec<MeshBvh>,
     pub vertices: Vec<MeshVertex>,
     pub indices: Vec<u32>,
 
     pub gpu_out_instance_matrices: Vec<GpuOutInstanceMatrices>,
     pub gpu_out_instance_mesh_model_locations: Vec<MeshModelLocation>,
+    pub gpu_out_instance_bounds: Vec<GpuOutInstanceBounds>,
+    // Same index as the instances. The transparent ones are drawn blended after the opaque ones.
+    instance_transparent: Vec<bool>,
+    // The entity of each instance, for picking.
+    instance_entities: Vec<Option<usize>>,
+    // The model of each instance, to update the queued locations when models move or get freed.
+    instance_models: Vec<usize>,
+    // The instances from here on are drawn blended. Transparent instances added before an opaque
+    // one only get here when cull_instances sorts them.
+    transparent_instances_start: usize,
+
+    // Holes left by freed models, closed by defragment.
+    free_vertices: FreeRanges,
+    free_indices: FreeRanges,
+    // Freed model slots, reused by the next added models.
+    free_models: Vec<usize>,
+    // Changed since the last take_changes. Whatever was pushed past the synced lengths counts too.
+    changed_vertices: Range<usize>,
+    changed_indices: Range<usize>,
+    synced_vertices: usize,
+    synced_indices: usize,
 }
 
 impl MeshData
@@ -62,13 +148,349 @@ impl MeshData
         Self
         {
             models: Vec::with_capacity(1024),
+            model_bounds: Vec::with_capacity(1024),
+            model_bvhs: Vec::with_capacity(1024),
             vertices: Vec::with_capacity(1024 * 1024),
             indices: Vec::with_capacity(1024 * 1024),
 
             gpu_out_instance_matrices: Vec::with_capacity(1024 * 1024),
             gpu_out_instance_mesh_model_locations: Vec::with_capacity(1024 * 1024),
+            gpu_out_instance_bounds: Vec::with_capacity(1024 * 1024),
+            instance_transparent: Vec::with_capacity(1024 * 1024),
+            instance_entities: Vec::with_capacity(1024 * 1024),
+            instance_models: Vec::with_capacity(1024 * 1024),
+            transparent_instances_start: 0,
+
+            free_vertices: FreeRanges::new(),
+            free_indices: FreeRanges::new(),
+            free_models: Vec::new(),
+            changed_vertices: 0..0,
+            changed_indices: 0..0,
+            synced_vertices: 0,
+            synced_indices: 0,
         }
     }
+
+    // Returns the model index. The indices are relative to the model's first vertex. The model's
+    // bounds and bvh are computed from the vertex positions.
+    pub fn add_model(&mut self, vertices: &[MeshVertex], indices: &[u32]) -> usize
+    {
+        let vertices_start = Self::allocate(&mut self.vertices, &mut self.free_vertices, vertices);
+        let indices_start = Self::allocate(&mut self.indices, &mut self.free_indices, indices);
+        extend_range(&mut self.changed_vertices, vertices_start..vertices_start + vertices.len());
+        extend_range(&mut self.changed_indices, indices_start..indices_start + indices.len());
+
+        let location = MeshModelLocation {
+            vertices_start_index: vertices_start as u32,
+            vertices_count: vertices.len() as u32,
+            indices_start_index: indices_start as u32,
+            indices_count: indices.len() as u32,
+        };
+        let bounds = ModelBounds::from_vertices(vertices);
+        let bvh = MeshBvh::build(vertices, indices);
+        return match self.free_models.pop()
+        {
+            Some(model) =>
+            {
+                self.models[model] = location;
+                self.model_bounds[model] = bounds;
+                self.model_bvhs[model] = bvh;
+                model
+            },
+            None =>
+            {
+                self.models.push(location);
+                self.model_bounds.push(bounds);
+                self.model_bvhs.push(bvh);
+                self.models.len() - 1
+            },
+        };
+    }
+
+    fn allocate<T: Copy>(data: &mut Vec<T>, free: &mut FreeRanges, elements: &[T]) -> usize
+    {
+        let start = match free.allocate(elements.len())
+        {
+            Some(start) => start,
+            None =>
+            {
+                data.extend_from_slice(elements);
+                return data.len() - elements.len();
+            },
+        };
+        data[start..start + elements.len()].copy_from_slice(elements);
+        return start;
+    }
+
+    // The slot stays in models with zero counts until a new model reuses it.
+    pub fn free_model(&mut self, model: usize)
+    {
+        if model >= self.models.len() || self.free_models.contains(&model)
+        {
+            return;
+        }
+        let location = self.models[model];
+        let vertices_start = location.vertices_start_index as usize;
+        let indices_start = location.indices_start_index as usize;
+        self.free_vertices.free(vertices_start..vertices_start + location.vertices_count as usize);
+        self.free_indices.free(indices_start..indices_start + location.indices_count as usize);
+        // A hole at the end just shortens the data.
+        let vertices_len = self.free_vertices.trim_end(self.vertices.len());
+        self.vertices.truncate(vertices_len);
+        let indices_len = self.free_indices.trim_end(self.indices.len());
+        self.indices.truncate(indices_len);
+        self.synced_vertices = self.synced_vertices.min(vertices_len);
+        self.synced_indices = self.synced_indices.min(indices_len);
+
+        self.models[model] = MeshModelLocation {
+            vertices_start_index: 0,
+            vertices_count: 0,
+            indices_start_index: 0,
+            indices_count: 0,
+        };
+        self.model_bounds[model] = ModelBounds::EMPTY;
+        self.model_bvhs[model] = MeshBvh::empty();
+        self.free_models.push(model);
+        self.update_instance_locations();
+    }
+
+    // Vertices and indices in the holes of freed models.
+    pub fn free_counts(&self) -> (usize, usize)
+    {
+        return (self.free_vertices.total(), self.free_indices.total());
+    }
+
+    // Moves the models to close the holes, keeping their order. Changes the start indices of
+    // the moved models.
+    pub fn defragment(&mut self)
+    {
+        let mut models: Vec<usize> = (0..self.models.len())
+            .filter(|model| !self.free_models.contains(model))
+            .collect();
+
+        models.sort_by_key(|&model| self.models[model].vertices_start_index);
+        let mut end = 0;
+        for &model in &models
+        {
+            let location = &mut self.models[model];
+            let start = location.vertices_start_index as usize;
+            let count = location.vertices_count as usize;
+            if start != end
+            {
+                self.vertices.copy_within(start..start + count, end);
+                extend_range(&mut self.changed_vertices, end..end + count);
+                location.vertices_start_index = end as u32;
+            }
+            end += count;
+        }
+        self.vertices.truncate(end);
+
+        models.sort_by_key(|&model| self.models[model].indices_start_index);
+        let mut end = 0;
+        for &model in &models
+        {
+            let location = &mut self.models[model];
+            let start = location.indices_start_index as usize;
+            let count = location.indices_count as usize;
+            if start != end
+            {
+                self.indices.copy_within(start..start + count, end);
+                extend_range(&mut self.changed_indices, end..end + count);
+                location.indices_start_index = end as u32;
+            }
+            end += count;
+        }
+        self.indices.truncate(end);
+
+        self.free_vertices.clear();
+        self.free_indices.clear();
+        self.synced_vertices = self.synced_vertices.min(self.vertices.len());
+        self.synced_indices = self.synced_indices.min(self.indices.len());
+        self.update_instance_locations();
+    }
+
+    // Instances queued before the models moved or got freed would still point at the old ranges.
+    fn update_instance_locations(&mut self)
+    {
+        for (location, &model) in self.gpu_out_instance_mesh_model_locations.iter_mut().zip(&self.instance_models)
+        {
+            *location = self.models[model];
+        }
+    }
+
+    // Instances are added every frame after clearing the last frame's.
+    pub fn clear_instances(&mut self)
+    {
+        self.gpu_out_instance_matrices.clear();
+        self.gpu_out_instance_mesh_model_locations.clear();
+        self.gpu_out_instance_bounds.clear();
+        self.instance_transparent.clear();
+        self.instance_entities.clear();
+        self.instance_models.clear();
+        self.transparent_instances_start = 0;
+    }
+
+    pub fn add_instance(&mut self, model: usize, transform: glam::Mat4)
+    {
+        self.push_instance(model, transform, false, None);
+    }
+
+    pub fn add_transparent_instance(&mut self, model: usize, transform: glam::Mat4)
+    {
+        self.push_instance(model, transform, true, None);
+    }
+
+    // An instance the picking reports as the entity.
+    pub fn add_entity_instance(&mut self, entity: usize, model: usize, transform: glam::Mat4, transparent: bool)
+    {
+        self.push_instance(model, transform, transparent, Some(entity));
+    }
+
+    fn push_instance(&mut self, model: usize, transform: glam::Mat4, transparent: bool, entity: Option<usize>)
+    {
+        let sphere = self.model_bounds[model].sphere.transformed(&transform);
+        self.gpu_out_instance_matrices.push(GpuOutInstanceMatrices::from_mat4(transform));
+        self.gpu_out_instance_mesh_model_locations.push(self.models[model]);
+        self.gpu_out_instance_bounds.push(GpuOutInstanceBounds { center: sphere.center.to_array(), radius: sphere.radius });
+        self.instance_transparent.push(transparent);
+        self.instance_entities.push(entity);
+        self.instance_models.push(model);
+        if !transparent
+        {
+            self.transparent_instances_start = self.instance_transparent.len();
+        }
+    }
+
+    // Drops the instances outside the camera's frustum, then orders the opaque ones front to
+    // back and after them the transparent ones back to front.
+    pub fn cull_instances(&mut self, camera: &Camera)
+    {
+        let frustum = camera.frustum();
+        let mut opaque: Vec<(usize, f32)> = Vec::new();
+        let mut transparent: Vec<(usize, f32)> = Vec::new();
+        for (instance, bounds) in self.gpu_out_instance_bounds.iter().enumerate()
+        {
+            let sphere = BoundingSphere::new(glam::Vec3::from(bounds.center), bounds.radius);
+            if !frustum.intersects_sphere(&sphere)
+            {
+                continue;
+            }
+            let distance = sphere.center.distance_squared(camera.eye);
+            match self.instance_transparent.get(instance)
+            {
+                Some(true) => transparent.push((instance, distance)),
+                _ => opaque.push((instance, distance)),
+            }
+        }
+        opaque.sort_by(|a, b| a.1.total_cmp(&b.1));
+        transparent.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        let order: Vec<usize> = opaque.iter().chain(&transparent).map(|&(instance, _)| instance).collect();
+        reorder(&mut self.gpu_out_instance_matrices, &order);
+        reorder(&mut self.gpu_out_instance_mesh_model_locations, &order);
+        reorder(&mut self.gpu_out_instance_bounds, &order);
+        reorder(&mut self.instance_entities, &order);
+        reorder(&mut self.instance_models, &order);
+        self.instance_transparent.clear();
+        self.instance_transparent.resize(opaque.len(), false);
+        self.instance_transparent.resize(order.len(), true);
+        self.transparent_instances_start = opaque.len();
+    }
+
+    pub fn transparent_instances_start(&self) -> usize
+    {
+        return self.transparent_instances_start;
+    }
+
+    // Same index as the instances.
+    pub fn instance_entities(&self) -> &[Option<usize>]
+    {
+        return &self.instance_entities;
+    }
+
+    pub fn model_bvh(&self, model: usize) -> &MeshBvh
+    {
+        return &self.model_bvhs[model];
+    }
+
+    // The model's vertices and its indices, relative to the first vertex.
+    pub fn model_mesh(&self, model: usize) -> (&[MeshVertex], &[u32])
+    {
+        let location = self.models[model];
+        let vertices_start = location.vertices_start_index as usize;
+        let indices_start = location.indices_start_index as usize;
+        return (
+            &self.vertices[vertices_start..vertices_start + location.vertices_count as usize],
+            &self.indices[indices_start..indices_start + location.indices_count as usize]);
+    }
+
+    // Replaces the vertices of an animated model, the count and the indices stay. Refits the
+    // model's bvh instead of building it again.
+    pub fn update_model_vertices(&mut self, model: usize, vertices: &[MeshVertex])
+    {
+        let location = self.models[model];
+        assert_eq!(vertices.len(), location.vertices_count as usize);
+        let vertices_start = location.vertices_start_index as usize;
+        self.vertices[vertices_start..vertices_start + vertices.len()].copy_from_slice(vertices);
+        extend_range(&mut self.changed_vertices, vertices_start..vertices_start + vertices.len());
+
+        self.model_bounds[model] = ModelBounds::from_vertices(vertices);
+        let indices_start = location.indices_start_index as usize;
+        let indices = &self.indices[indices_start..indices_start + location.indices_count as usize];
+        self.model_bvhs[model].refit(vertices, indices);
+    }
+
+    // The closest triangle of the model the ray hits before max_t, the ray in the model's space.
+    pub fn raycast_model(&self, model: usize, ray: &Ray, max_t: f32) -> Option<TriangleHit>
+    {
+        let (vertices, indices) = self.model_mesh(model);
+        return self.model_bvhs[model].raycast(ray, max_t, vertices, indices);
+    }
+
+    // The closest point on the model within max_distance, the point in the model's space.
+    pub fn nearest_on_model(&self, model: usize, point: glam::Vec3, max_distance: f32) -> Option<NearestTriangle>
+    {
+        let (vertices, indices) = self.model_mesh(model);
+        return self.model_bvhs[model].nearest(point, max_distance, vertices, indices);
+    }
+
+    // The model's bounds moved into world space by the transform.
+    pub fn world_bounds(&self, model: usize, transform: &glam::Mat4) -> ModelBounds
+    {
+        return self.model_bounds[model].transformed(transform);
+    }
+
+    // The vertex and index ranges to upload since the last call, empty when nothing changed.
+    pub fn take_changes(&mut self) -> (Range<usize>, Range<usize>)
+    {
+        extend_range(&mut self.changed_vertices, self.synced_vertices..self.vertices.len());
+        extend_range(&mut self.changed_indices, self.synced_indices..self.indices.len());
+        let vertices = self.changed_vertices.start..self.changed_vertices.end.min(self.vertices.len());
+        let indices = self.changed_indices.start..self.changed_indices.end.min(self.indices.len());
+
+        self.changed_vertices = 0..0;
+        self.changed_indices = 0..0;
+        self.synced_vertices = self.vertices.len();
+        self.synced_indices = self.indices.len();
+        return (vertices, indices);
+    }
+}
+
+// Keeps the elements at the indices in order, the capacity stays.
+fn reorder<T: Copy>(data: &mut Vec<T>, order: &[usize])
+{
+    let reordered: Vec<T> = order.iter().map(|&index| data[index]).collect();
+    data.clear();
+    data.extend_from_slice(&reordered);
+}
+
+fn extend_range(range: &mut Range<usize>, other: Range<usize>)
+{
+    if other.start >= other.end
+    {
+        return;
+    }
+    *range = if range.start >= range.end { other } else { range.start.min(other.start)..range.end.max(other.end) };
 }
 
 
@@ -79,6 +501,11 @@ pub struct GameState
     pub scene: Scene,
 
     pub mesh_data: MeshData,
+    pub physics: Physics,
+
+    pub debug_overlay: DebugOverlay,
+    pub debug_draw: DebugDraw,
+    pub ui: Ui,
 }
 
 impl GameState
@@ -89,8 +516,18 @@ impl GameState
             input: input::Input::new(),
             scene: Scene::new(width, height),
             mesh_data: MeshData::new(),
+            physics: Physics::new(),
+            debug_overlay: DebugOverlay::new(),
+            debug_draw: DebugDraw::new(),
+            ui: Ui::new(),
         }
     }
+
+    // Draws text for this frame at window pixel x, y from the top left.
+    pub fn debug_text(&mut self, x: f32, y: f32, text: &str)
+    {
+        self.debug_overlay.text(x, y, text);
+    }
 }
 
 pub struct Transform
@@ -100,9 +537,47 @@ pub struct Transform
     pub scale: glam::Vec3A
 }
 
+impl Transform
+{
+    pub fn matrix(&self) -> glam::Mat4
+    {
+        return glam::Mat4::from_scale_rotation_translation(self.scale.into(), self.rot, self.pos.into());
+    }
+}
+
 pub struct Entity
 {
     pub transform: Transform,
+    // Index to MeshData::models, an entity without a model has no bounds.
+    pub model: Option<usize>,
+    pub transparent: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RayHit
+{
+    pub entity: usize,
+    pub triangle: TriangleHit,
+    // The world space point hit.
+    pub position: glam::Vec3,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NearestHit
+{
+    pub entity: usize,
+    pub triangle: usize,
+    // The world space point on the entity.
+    pub position: glam::Vec3,
+    pub distance: f32,
+}
+
+impl Entity
+{
+    pub fn world_bounds(&self, mesh_data: &MeshData) -> Option<ModelBounds>
+    {
+        return self.model.map(|model| mesh_data.world_bounds(model, &self.transform.matrix()));
+    }
 }
 
 pub struct Scene
@@ -110,6 +585,9 @@ pub struct Scene
     pub entities: Vec<Entity>,
     pub cameras: Vec<Camera>,
     pub current_cam_index: usize,
+    // Over the world boxes of the entities with a model, kept by update_bvh.
+    entity_bvh: Bvh,
+    entity_bounds: Vec<Aabb>,
 }
 
 impl Scene
@@ -122,6 +600,8 @@ impl Scene
             entities: Vec::new(),
             cameras,
             current_cam_index: 0,
+            entity_bvh: Bvh::empty(),
+            entity_bounds: Vec::new(),
         }
     }
 
@@ -139,6 +619,85 @@ impl Scene
     {
         return &mut self.cameras[self.current_cam_index];
     }
+
+    // Adds an instance for every entity with a model.
+    pub fn add_instances(&self, mesh_data: &mut MeshData)
+    {
+        for (index, entity) in self.entities.iter().enumerate()
+        {
+            if let Some(model) = entity.model
+            {
+                mesh_data.add_entity_instance(index, model, entity.transform.matrix(), entity.transparent);
+            }
+        }
+    }
+
+    // Refits the entity bvh to the moved entities, builds it again when entities or their models
+    // were added or removed. Call after the entities moved and before the queries.
+    pub fn update_bvh(&mut self, mesh_data: &MeshData)
+    {
+        let bounds: Vec<Aabb> = self.entities.iter()
+            .map(|entity| entity.world_bounds(mesh_data).map_or(Aabb::EMPTY, |bounds| bounds.aabb))
+            .collect();
+        let same_entities = bounds.len() == self.entity_bounds.len()
+            && bounds.iter().zip(&self.entity_bounds).all(|(new, old)| new.is_empty() == old.is_empty());
+        // Refitting keeps the tree of the old positions, so it degrades as the entities spread.
+        let grown = self.entity_bvh.aabb().surface_area() > 2.0 * Aabb::from_points(
+            bounds.iter().filter(|aabb| !aabb.is_empty()).flat_map(|aabb| [aabb.min, aabb.max])).surface_area();
+        if same_entities && !grown
+        {
+            self.entity_bvh.refit(&bounds);
+        }
+        else
+        {
+            self.entity_bvh = Bvh::build(&bounds);
+        }
+        self.entity_bounds = bounds;
+    }
+
+    // The closest entity triangle the ray hits.
+    pub fn raycast(&self, ray: &Ray, mesh_data: &MeshData) -> Option<RayHit>
+    {
+        let mut triangle_hit = None;
+        let (entity, _) = self.entity_bvh.raycast(ray, f32::INFINITY, |entity, max_t| {
+            let model = self.entities[entity].model?;
+            let model_ray = ray.transformed(&self.entities[entity].transform.matrix().inverse());
+            let hit = mesh_data.raycast_model(model, &model_ray, max_t)?;
+            triangle_hit = Some(hit);
+            Some(hit.distance)
+        })?;
+        let triangle = triangle_hit?;
+        return Some(RayHit { entity, triangle, position: ray.at(triangle.distance) });
+    }
+
+    // The entities whose world boxes overlap the box.
+    pub fn overlapping_entities(&self, aabb: &Aabb, entities: &mut Vec<usize>)
+    {
+        self.entity_bvh.overlapping(aabb, |entity| entities.push(entity));
+    }
+
+    // The closest point on any entity within max_distance. Searched in the model's space, exact
+    // for rotations, translations and uniform scales.
+    pub fn nearest_point(&self, point: glam::Vec3, max_distance: f32, mesh_data: &MeshData) -> Option<NearestHit>
+    {
+        let mut nearest = None;
+        let (entity, distance_squared) = self.entity_bvh.nearest(point, max_distance * max_distance, |entity, max_distance_squared| {
+            let model = self.entities[entity].model?;
+            let transform = self.entities[entity].transform.matrix();
+            let model_point = transform.inverse().transform_point3(point);
+            let hit = mesh_data.nearest_on_model(model, model_point, f32::INFINITY)?;
+            let position = transform.transform_point3(hit.point);
+            let distance_squared = position.distance_squared(point);
+            if distance_squared >= max_distance_squared
+            {
+                return None;
+            }
+            nearest = Some((hit.triangle, position));
+            Some(distance_squared)
+        })?;
+        let (triangle, position) = nearest?;
+        return Some(NearestHit { entity, triangle, position, distance: distance_squared.sqrt() });
+    }
 }
 
 
@@ -203,6 +762,34 @@ impl Camera
         return proj * view;
     }
 
+    pub fn frustum(&self) -> Frustum
+    {
+        return Frustum::from_view_projection(&self.build_view_projection_matrix());
+    }
+
+    // The ray from the eye through a pixel of a width x height window, y down. The direction
+    // is unit length, so the hit distances are world units from the eye.
+    pub fn screen_ray(&self, x: f32, y: f32, width: f32, height: f32) -> Ray
+    {
+        let ndc_x = x / width * 2.0 - 1.0;
+        let ndc_y = 1.0 - y / height * 2.0;
+        let inverse = self.build_view_projection_matrix().inverse();
+        let near = inverse.project_point3(glam::vec3(ndc_x, ndc_y, 0.0));
+        let far = inverse.project_point3(glam::vec3(ndc_x, ndc_y, 1.0));
+        return Ray::new(self.eye, (far - near).normalize());
+    }
+
+    // The view projection zoomed so the pixel at x, y of a width x height window covers the
+    // whole clip space, for rendering just that pixel.
+    pub fn pick_view_projection_matrix(&self, x: f32, y: f32, width: f32, height: f32) -> glam::Mat4
+    {
+        let ndc_x = x / width * 2.0 - 1.0;
+        let ndc_y = 1.0 - y / height * 2.0;
+        let zoom = glam::Mat4::from_scale(glam::vec3(width, height, 1.0))
+            * glam::Mat4::from_translation(glam::vec3(-ndc_x, -ndc_y, 0.0));
+        return zoom * self.build_view_projection_matrix();
+    }
+
     pub fn get_forward(&self) -> glam::Vec3
     {
         let sinx = self.heading.sin();
@@ -0,0 +1,928 @@
+use glam::{Mat3, Mat4, Quat, Vec3};
+
+use crate::bounds::Aabb;
+use crate::bvh::{closest_point_on_triangle, triangle_positions, Bvh};
+use crate::ray::Ray;
+use crate::{MeshData, Scene};
+
+// The length of a physics step in seconds, the frame times are consumed in steps of this.
+pub const FIXED_STEP: f64 = 1.0 / 60.0;
+// The rest of a longer frame is dropped, so a slow frame doesn't make the next ones slower.
+const MAX_STEPS_PER_UPDATE: usize = 5;
+const SOLVER_ITERATIONS: usize = 10;
+// Penetration left uncorrected, keeps the resting contacts from jittering.
+const PENETRATION_SLOP: f32 = 0.01;
+// The fraction of the penetration pushed out per step.
+const BAUMGARTE: f32 = 0.2;
+// A contact closer than this to one of the last step's between the same bodies starts from its
+// impulses.
+const WARM_START_DISTANCE: f32 = 0.05;
+// Slower impacts don't bounce, so the bodies come to rest.
+const RESTITUTION_THRESHOLD: f32 = 1.0;
+const LINEAR_DAMPING: f32 = 0.01;
+const ANGULAR_DAMPING: f32 = 0.05;
+const MAX_ANGULAR_SPEED: f32 = 50.0;
+
+// The shape of a body around its entity's position, in world units. The entity's scale only
+// applies to mesh colliders.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Collider
+{
+    Sphere { radius: f32 },
+    // Along the entity's axes.
+    Box { half_extents: Vec3 },
+    // Along the entity's y axis, half_height from the center to the centers of the caps.
+    Capsule { radius: f32, half_height: f32 },
+    // The triangles of a MeshData model with the entity's transform. Static bodies only.
+    Mesh { model: usize },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RigidBody
+{
+    // The scene entity the body moves. Static bodies follow the entity instead.
+    pub entity: usize,
+    pub collider: Collider,
+    pub velocity: Vec3,
+    pub angular_velocity: Vec3,
+    // From 0 to 1, a contact bounces with the larger of its bodies'.
+    pub restitution: f32,
+    pub friction: f32,
+    inverse_mass: f32,
+    // Around the entity's axes.
+    inverse_inertia: Vec3,
+    position: Vec3,
+    rotation: Quat,
+}
+
+impl RigidBody
+{
+    pub fn new_dynamic(entity: usize, collider: Collider, mass: f32) -> Self
+    {
+        assert!(!matches!(collider, Collider::Mesh { .. }), "Mesh colliders can only be static");
+        assert!(mass > 0.0);
+        let inertia = match collider
+        {
+            Collider::Sphere { radius } => Vec3::splat(0.4 * mass * radius * radius),
+            Collider::Box { half_extents } =>
+            {
+                let squared = half_extents * half_extents;
+                Vec3::new(squared.y + squared.z, squared.x + squared.z, squared.x + squared.y) * mass / 3.0
+            },
+            // As a cylinder over the whole length.
+            Collider::Capsule { radius, half_height } =>
+            {
+                let length = 2.0 * (half_height + radius);
+                let across = mass * (3.0 * radius * radius + length * length) / 12.0;
+                Vec3::new(across, 0.5 * mass * radius * radius, across)
+            },
+            Collider::Mesh { .. } => unreachable!(),
+        };
+        Self
+        {
+            inverse_mass: 1.0 / mass,
+            inverse_inertia: inertia.recip(),
+            ..Self::new_static(entity, collider)
+        }
+    }
+
+    // Doesn't move, but the entity can be moved by hand.
+    pub fn new_static(entity: usize, collider: Collider) -> Self
+    {
+        Self
+        {
+            entity,
+            collider,
+            velocity: Vec3::ZERO,
+            angular_velocity: Vec3::ZERO,
+            restitution: 0.2,
+            friction: 0.5,
+            inverse_mass: 0.0,
+            inverse_inertia: Vec3::ZERO,
+            position: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+        }
+    }
+
+    pub fn is_static(&self) -> bool
+    {
+        return self.inverse_mass == 0.0;
+    }
+
+    // Infinite for static bodies.
+    pub fn mass(&self) -> f32
+    {
+        return 1.0 / self.inverse_mass;
+    }
+
+    pub fn position(&self) -> Vec3
+    {
+        return self.position;
+    }
+
+    pub fn rotation(&self) -> Quat
+    {
+        return self.rotation;
+    }
+
+    // Moves the body, the entity follows on the next step.
+    pub fn teleport(&mut self, position: Vec3, rotation: Quat)
+    {
+        self.position = position;
+        self.rotation = rotation;
+    }
+
+    fn world_inverse_inertia(&self) -> Mat3
+    {
+        let rotation = Mat3::from_quat(self.rotation);
+        return rotation * Mat3::from_diagonal(self.inverse_inertia) * rotation.transpose();
+    }
+
+    fn shape(&self, scene: &Scene) -> Shape
+    {
+        return match self.collider
+        {
+            Collider::Sphere { radius } => Shape::Round { a: self.position, b: self.position, radius },
+            Collider::Box { half_extents } => Shape::Box(OrientedBox::new(self.position, self.rotation, half_extents)),
+            Collider::Capsule { radius, half_height } =>
+            {
+                let half = self.rotation * Vec3::Y * half_height;
+                Shape::Round { a: self.position - half, b: self.position + half, radius }
+            },
+            Collider::Mesh { model } => Shape::Mesh { model, transform: scene.entities[self.entity].transform.matrix() },
+        };
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Contact
+{
+    // Body indices.
+    pub a: usize,
+    pub b: usize,
+    // From a towards b.
+    pub normal: Vec3,
+    pub point: Vec3,
+    pub depth: f32,
+}
+
+// Rigid bodies moved by gravity and impulses, colliding with each other. Steps at FIXED_STEP
+// and writes the positions and rotations to the bodies' entities.
+pub struct Physics
+{
+    pub gravity: Vec3,
+    bodies: Vec<RigidBody>,
+    accumulator: f64,
+    // Of the last step.
+    contacts: Vec<Contact>,
+    // The normal and friction impulses of the last step's contacts.
+    contact_impulses: Vec<(f32, Vec3)>,
+}
+
+impl Physics
+{
+    pub fn new() -> Self
+    {
+        Self
+        {
+            gravity: Vec3::new(0.0, -9.81, 0.0),
+            bodies: Vec::new(),
+            accumulator: 0.0,
+            contacts: Vec::new(),
+            contact_impulses: Vec::new(),
+        }
+    }
+
+    // The body starts at its entity's position and rotation. Returns the body index.
+    pub fn add_body(&mut self, mut body: RigidBody, scene: &Scene) -> usize
+    {
+        let transform = &scene.entities[body.entity].transform;
+        body.position = transform.pos.into();
+        body.rotation = transform.rot;
+        self.bodies.push(body);
+        return self.bodies.len() - 1;
+    }
+
+    pub fn bodies(&self) -> &[RigidBody]
+    {
+        return &self.bodies;
+    }
+
+    pub fn body(&self, body: usize) -> &RigidBody
+    {
+        return &self.bodies[body];
+    }
+
+    pub fn body_mut(&mut self, body: usize) -> &mut RigidBody
+    {
+        return &mut self.bodies[body];
+    }
+
+    pub fn contacts(&self) -> &[Contact]
+    {
+        return &self.contacts;
+    }
+
+    // Changes the velocities as if the impulse hit the body at the world space point.
+    pub fn apply_impulse(&mut self, body: usize, impulse: Vec3, point: Vec3)
+    {
+        let body = &mut self.bodies[body];
+        body.velocity += impulse * body.inverse_mass;
+        body.angular_velocity += body.world_inverse_inertia() * (point - body.position).cross(impulse);
+    }
+
+    // Runs the steps that fit in the time since the last update. Returns the number of steps.
+    pub fn update(&mut self, dt: f64, scene: &mut Scene, mesh_data: &MeshData) -> usize
+    {
+        self.accumulator += dt;
+        let mut steps = 0;
+        while self.accumulator >= FIXED_STEP && steps < MAX_STEPS_PER_UPDATE
+        {
+            self.step(FIXED_STEP as f32, scene, mesh_data);
+            self.accumulator -= FIXED_STEP;
+            steps += 1;
+        }
+        if self.accumulator >= FIXED_STEP
+        {
+            self.accumulator = 0.0;
+        }
+        return steps;
+    }
+
+    pub fn step(&mut self, dt: f32, scene: &mut Scene, mesh_data: &MeshData)
+    {
+        for body in &mut self.bodies
+        {
+            if body.is_static()
+            {
+                let transform = &scene.entities[body.entity].transform;
+                body.position = transform.pos.into();
+                body.rotation = transform.rot;
+            }
+            else
+            {
+                body.velocity += self.gravity * dt;
+            }
+        }
+
+        let previous_contacts = self.find_contacts(scene, mesh_data);
+        self.solve_contacts(dt, &previous_contacts);
+
+        for body in &mut self.bodies
+        {
+            if body.is_static()
+            {
+                continue;
+            }
+            body.velocity /= 1.0 + dt * LINEAR_DAMPING;
+            body.angular_velocity = (body.angular_velocity / (1.0 + dt * ANGULAR_DAMPING)).clamp_length_max(MAX_ANGULAR_SPEED);
+            body.position += body.velocity * dt;
+            let spin = Quat::from_vec4((body.angular_velocity * 0.5 * dt).extend(0.0)) * body.rotation;
+            body.rotation = (body.rotation + spin).normalize();
+
+            let transform = &mut scene.entities[body.entity].transform;
+            transform.pos = body.position.into();
+            transform.rot = body.rotation;
+        }
+    }
+
+    // The pairs come from a bvh over the bodies' world boxes.
+    // Keeps the last step's contacts for warm starting.
+    fn find_contacts(&mut self, scene: &Scene, mesh_data: &MeshData) -> Vec<Contact>
+    {
+        let shapes: Vec<Shape> = self.bodies.iter().map(|body| body.shape(scene)).collect();
+        let bounds: Vec<Aabb> = shapes.iter().map(|shape| shape.aabb(mesh_data)).collect();
+        let bvh = Bvh::build(&bounds);
+        let mut pairs = Vec::new();
+        for (a, aabb) in bounds.iter().enumerate()
+        {
+            bvh.overlapping(aabb, |b| {
+                if b > a && !(self.bodies[a].is_static() && self.bodies[b].is_static())
+                {
+                    pairs.push((a, b));
+                }
+            });
+        }
+
+        let mut contacts = Vec::with_capacity(self.contacts.len());
+        for (a, b) in pairs
+        {
+            let start = contacts.len();
+            collide(&shapes[a], &shapes[b], mesh_data, &mut contacts);
+            for contact in &mut contacts[start..]
+            {
+                contact.a = a;
+                contact.b = b;
+            }
+        }
+        return std::mem::replace(&mut self.contacts, contacts);
+    }
+
+    // Sequential impulses, the friction and normal impulses of every contact in turn until the
+    // velocities settle. Penetration is pushed out with a velocity bias. The impulses start from
+    // the matching contacts of the last step, so resting bodies don't have to build them up again.
+    fn solve_contacts(&mut self, dt: f32, previous_contacts: &[Contact])
+    {
+        struct Constraint
+        {
+            a: usize,
+            b: usize,
+            ra: Vec3,
+            rb: Vec3,
+            normal: Vec3,
+            tangents: [Vec3; 2],
+            normal_mass: f32,
+            tangent_masses: [f32; 2],
+            bias: f32,
+            friction: f32,
+            normal_impulse: f32,
+            tangent_impulses: [f32; 2],
+        }
+
+        let inverse_inertias: Vec<Mat3> = self.bodies.iter().map(|body| body.world_inverse_inertia()).collect();
+        let inverse_masses: Vec<f32> = self.bodies.iter().map(|body| body.inverse_mass).collect();
+        let mut velocities: Vec<(Vec3, Vec3)> = self.bodies.iter().map(|body| (body.velocity, body.angular_velocity)).collect();
+        let relative_velocity = |velocities: &[(Vec3, Vec3)], constraint: &Constraint| {
+            let (va, wa) = velocities[constraint.a];
+            let (vb, wb) = velocities[constraint.b];
+            (vb + wb.cross(constraint.rb)) - (va + wa.cross(constraint.ra))
+        };
+        let effective_mass = |a: usize, b: usize, ra: Vec3, rb: Vec3, direction: Vec3| {
+            let angular_a = (inverse_inertias[a] * ra.cross(direction)).cross(ra);
+            let angular_b = (inverse_inertias[b] * rb.cross(direction)).cross(rb);
+            let inverse = inverse_masses[a] + inverse_masses[b] + (angular_a + angular_b).dot(direction);
+            if inverse > 0.0 { 1.0 / inverse } else { 0.0 }
+        };
+        let apply = |velocities: &mut [(Vec3, Vec3)], constraint: &Constraint, impulse: Vec3| {
+            velocities[constraint.a].0 -= impulse * inverse_masses[constraint.a];
+            velocities[constraint.a].1 -= inverse_inertias[constraint.a] * constraint.ra.cross(impulse);
+            velocities[constraint.b].0 += impulse * inverse_masses[constraint.b];
+            velocities[constraint.b].1 += inverse_inertias[constraint.b] * constraint.rb.cross(impulse);
+        };
+
+        let mut constraints: Vec<Constraint> = self.contacts.iter().map(|contact| {
+            let body_a = &self.bodies[contact.a];
+            let body_b = &self.bodies[contact.b];
+            let ra = contact.point - body_a.position;
+            let rb = contact.point - body_b.position;
+            let mut constraint = Constraint {
+                a: contact.a,
+                b: contact.b,
+                ra,
+                rb,
+                normal: contact.normal,
+                tangents: [Vec3::ZERO; 2],
+                normal_mass: effective_mass(contact.a, contact.b, ra, rb, contact.normal),
+                tangent_masses: [0.0; 2],
+                bias: 0.0,
+                friction: (body_a.friction * body_b.friction).sqrt(),
+                normal_impulse: 0.0,
+                tangent_impulses: [0.0; 2],
+            };
+            let velocity = relative_velocity(&velocities, &constraint);
+            let normal_velocity = velocity.dot(contact.normal);
+            // The first tangent along the sliding, so the friction opposes it directly.
+            let sliding = velocity - contact.normal * normal_velocity;
+            let (tangent, bitangent) = if sliding.length_squared() > 1e-6
+            {
+                let tangent = sliding.normalize();
+                (tangent, contact.normal.cross(tangent))
+            }
+            else
+            {
+                contact.normal.any_orthonormal_pair()
+            };
+            constraint.tangents = [tangent, bitangent];
+            constraint.tangent_masses = constraint.tangents.map(|tangent| effective_mass(contact.a, contact.b, ra, rb, tangent));
+            let push_out = BAUMGARTE / dt * (contact.depth - PENETRATION_SLOP).max(0.0);
+            let bounce = if normal_velocity < -RESTITUTION_THRESHOLD
+            {
+                -normal_velocity * body_a.restitution.max(body_b.restitution)
+            }
+            else
+            {
+                0.0
+            };
+            constraint.bias = push_out.max(bounce);
+
+            let previous = previous_contacts.iter().position(|previous| {
+                previous.a == contact.a
+                    && previous.b == contact.b
+                    && previous.point.distance_squared(contact.point) < WARM_START_DISTANCE * WARM_START_DISTANCE
+            });
+            if let Some(previous) = previous
+            {
+                let (normal_impulse, friction_impulse) = self.contact_impulses[previous];
+                constraint.normal_impulse = normal_impulse;
+                constraint.tangent_impulses = constraint.tangents.map(|tangent| friction_impulse.dot(tangent));
+            }
+            constraint
+        }).collect();
+
+        for constraint in &constraints
+        {
+            let impulse = constraint.normal * constraint.normal_impulse
+                + constraint.tangents[0] * constraint.tangent_impulses[0]
+                + constraint.tangents[1] * constraint.tangent_impulses[1];
+            apply(&mut velocities, constraint, impulse);
+        }
+
+        for _ in 0..SOLVER_ITERATIONS
+        {
+            for constraint in &mut constraints
+            {
+                let max_friction = constraint.friction * constraint.normal_impulse;
+                for index in 0..2
+                {
+                    let tangent = constraint.tangents[index];
+                    let velocity = relative_velocity(&velocities, constraint).dot(tangent);
+                    let total = (constraint.tangent_impulses[index] - velocity * constraint.tangent_masses[index])
+                        .clamp(-max_friction, max_friction);
+                    let impulse = total - constraint.tangent_impulses[index];
+                    constraint.tangent_impulses[index] = total;
+                    apply(&mut velocities, constraint, tangent * impulse);
+                }
+
+                let velocity = relative_velocity(&velocities, constraint).dot(constraint.normal);
+                let total = (constraint.normal_impulse + (constraint.bias - velocity) * constraint.normal_mass).max(0.0);
+                let impulse = total - constraint.normal_impulse;
+                constraint.normal_impulse = total;
+                apply(&mut velocities, constraint, constraint.normal * impulse);
+            }
+        }
+
+        self.contact_impulses = constraints.iter()
+            .map(|constraint| {
+                let friction_impulse = constraint.tangents[0] * constraint.tangent_impulses[0]
+                    + constraint.tangents[1] * constraint.tangent_impulses[1];
+                (constraint.normal_impulse, friction_impulse)
+            })
+            .collect();
+        for (body, (velocity, angular_velocity)) in self.bodies.iter_mut().zip(velocities)
+        {
+            if !body.is_static()
+            {
+                body.velocity = velocity;
+                body.angular_velocity = angular_velocity;
+            }
+        }
+    }
+}
+
+// A body's collider in world space.
+#[derive(Debug, Copy, Clone)]
+enum Shape
+{
+    // A sphere swept from a to b, a sphere when they are the same.
+    Round { a: Vec3, b: Vec3, radius: f32 },
+    Box(OrientedBox),
+    Mesh { model: usize, transform: Mat4 },
+}
+
+impl Shape
+{
+    fn aabb(&self, mesh_data: &MeshData) -> Aabb
+    {
+        return match self
+        {
+            Shape::Round { a, b, radius } => Aabb::new(a.min(*b) - *radius, a.max(*b) + *radius),
+            Shape::Box(oriented_box) => oriented_box.aabb(),
+            Shape::Mesh { model, transform } => mesh_data.world_bounds(*model, transform).aabb,
+        };
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct OrientedBox
+{
+    center: Vec3,
+    axes: [Vec3; 3],
+    half_extents: Vec3,
+}
+
+impl OrientedBox
+{
+    fn new(center: Vec3, rotation: Quat, half_extents: Vec3) -> Self
+    {
+        Self
+        {
+            center,
+            axes: [rotation * Vec3::X, rotation * Vec3::Y, rotation * Vec3::Z],
+            half_extents,
+        }
+    }
+
+    fn to_local(&self, point: Vec3) -> Vec3
+    {
+        let offset = point - self.center;
+        return Vec3::new(offset.dot(self.axes[0]), offset.dot(self.axes[1]), offset.dot(self.axes[2]));
+    }
+
+    fn to_world(&self, local: Vec3) -> Vec3
+    {
+        return self.center + self.axes[0] * local.x + self.axes[1] * local.y + self.axes[2] * local.z;
+    }
+
+    fn closest_point(&self, point: Vec3) -> Vec3
+    {
+        return self.to_world(self.to_local(point).clamp(-self.half_extents, self.half_extents));
+    }
+
+    // Half of the box's length along the unit axis.
+    fn projected_radius(&self, axis: Vec3) -> f32
+    {
+        return (0..3).map(|index| self.axes[index].dot(axis).abs() * self.half_extents[index]).sum();
+    }
+
+    fn corners(&self) -> [Vec3; 8]
+    {
+        return std::array::from_fn(|index| {
+            let sign = Vec3::new(
+                if index & 1 == 0 { -1.0 } else { 1.0 },
+                if index & 2 == 0 { -1.0 } else { 1.0 },
+                if index & 4 == 0 { -1.0 } else { 1.0 });
+            self.to_world(sign * self.half_extents)
+        });
+    }
+
+    fn aabb(&self) -> Aabb
+    {
+        let extents = Vec3::new(self.projected_radius(Vec3::X), self.projected_radius(Vec3::Y), self.projected_radius(Vec3::Z));
+        return Aabb::new(self.center - extents, self.center + extents);
+    }
+}
+
+// Adds the contacts between the shapes, the normals pointing from a to b.
+fn collide(a: &Shape, b: &Shape, mesh_data: &MeshData, contacts: &mut Vec<Contact>)
+{
+    match (a, b)
+    {
+        (Shape::Round { a: a0, b: a1, radius: radius_a }, Shape::Round { a: b0, b: b1, radius: radius_b }) =>
+        {
+            let (point_a, point_b) = closest_points_on_segments(*a0, *a1, *b0, *b1);
+            contacts.extend(sphere_contact(point_a, *radius_a, point_b, *radius_b));
+        },
+        (Shape::Round { a: a0, b: a1, radius }, Shape::Box(oriented_box)) =>
+        {
+            round_box_contacts(*a0, *a1, *radius, oriented_box, contacts);
+        },
+        (Shape::Box(box_a), Shape::Box(box_b)) => box_box_contacts(box_a, box_b, contacts),
+        (Shape::Round { a: a0, b: a1, radius }, Shape::Mesh { model, transform }) =>
+        {
+            mesh_triangles(*model, transform, &a.aabb(mesh_data), mesh_data, |triangle| {
+                round_triangle_contacts(*a0, *a1, *radius, triangle, contacts);
+            });
+        },
+        (Shape::Box(oriented_box), Shape::Mesh { model, transform }) =>
+        {
+            mesh_triangles(*model, transform, &oriented_box.aabb(), mesh_data, |triangle| {
+                box_triangle_contacts(oriented_box, triangle, contacts);
+            });
+        },
+        (Shape::Mesh { .. }, Shape::Mesh { .. }) => {},
+        // The pairs above the other way around.
+        _ =>
+        {
+            let start = contacts.len();
+            collide(b, a, mesh_data, contacts);
+            for contact in &mut contacts[start..]
+            {
+                contact.normal = -contact.normal;
+            }
+        },
+    }
+}
+
+// Adds the contacts of a capsule from a to b with a model's triangles, for the shapes moved
+// outside of the physics. The normals point from the capsule into the mesh.
+pub(crate) fn capsule_mesh_contacts(
+    a: Vec3,
+    b: Vec3,
+    radius: f32,
+    model: usize,
+    transform: &Mat4,
+    mesh_data: &MeshData,
+    contacts: &mut Vec<Contact>
+)
+{
+    collide(&Shape::Round { a, b, radius }, &Shape::Mesh { model, transform: *transform }, mesh_data, contacts);
+}
+
+// Calls visit with the world space corners of the model's triangles that may overlap the box.
+fn mesh_triangles(model: usize, transform: &Mat4, aabb: &Aabb, mesh_data: &MeshData, mut visit: impl FnMut([Vec3; 3]))
+{
+    let mut triangles = Vec::new();
+    mesh_data.model_bvh(model).overlapping(&aabb.transformed(&transform.inverse()), &mut triangles);
+    let (vertices, indices) = mesh_data.model_mesh(model);
+    for triangle in triangles
+    {
+        visit(triangle_positions(vertices, indices, triangle).map(|position| transform.transform_point3(position)));
+    }
+}
+
+// The contact between spheres at the points, none when apart.
+fn sphere_contact(point_a: Vec3, radius_a: f32, point_b: Vec3, radius_b: f32) -> Option<Contact>
+{
+    return surface_contact(point_a, radius_a, point_b, Vec3::Y, radius_b);
+}
+
+// From a sphere at point_a to a point on another shape's surface, grown by radius_b.
+// fallback_normal is used when the points are the same.
+fn surface_contact(point_a: Vec3, radius_a: f32, point_b: Vec3, fallback_normal: Vec3, radius_b: f32) -> Option<Contact>
+{
+    let offset = point_b - point_a;
+    let distance = offset.length();
+    if distance >= radius_a + radius_b
+    {
+        return None;
+    }
+    let normal = if distance > 1e-6 { offset / distance } else { fallback_normal };
+    let depth = radius_a + radius_b - distance;
+    return Some(Contact { a: 0, b: 0, normal, point: point_a + normal * (radius_a - depth * 0.5), depth });
+}
+
+fn sphere_box_contact(center: Vec3, radius: f32, oriented_box: &OrientedBox) -> Option<Contact>
+{
+    let local = oriented_box.to_local(center);
+    let half_extents = oriented_box.half_extents;
+    let clamped = local.clamp(-half_extents, half_extents);
+    if clamped != local
+    {
+        return surface_contact(center, radius, oriented_box.to_world(clamped), Vec3::Y, 0.0);
+    }
+    // The center is inside, pushed out through the closest face.
+    let face_distances = half_extents - local.abs();
+    let axis = if face_distances.x < face_distances.y && face_distances.x < face_distances.z { 0 }
+        else if face_distances.y < face_distances.z { 1 }
+        else { 2 };
+    let normal = -oriented_box.axes[axis] * local[axis].signum();
+    return Some(Contact { a: 0, b: 0, normal, point: center, depth: radius + face_distances[axis] });
+}
+
+// The point closest to the box along the segment, and the ends of a capsule so it can lie flat.
+fn round_box_contacts(a: Vec3, b: Vec3, radius: f32, oriented_box: &OrientedBox, contacts: &mut Vec<Contact>)
+{
+    // Projecting back and forth converges to the closest points of the two convex shapes.
+    let mut closest = (a + b) * 0.5;
+    for _ in 0..4
+    {
+        closest = closest_point_on_segment(a, b, oriented_box.closest_point(closest));
+    }
+    contacts.extend(sphere_box_contact(closest, radius, oriented_box));
+    if a != b
+    {
+        for end in [a, b]
+        {
+            if end.distance_squared(closest) > 1e-6
+            {
+                contacts.extend(sphere_box_contact(end, radius, oriented_box));
+            }
+        }
+    }
+}
+
+fn round_triangle_contacts(a: Vec3, b: Vec3, radius: f32, triangle: [Vec3; 3], contacts: &mut Vec<Contact>)
+{
+    let [t0, t1, t2] = triangle;
+    // Away from the shape, for a segment going through the triangle.
+    let mut normal = (t1 - t0).cross(t2 - t0).normalize_or_zero();
+    if normal.dot((a + b) * 0.5 - t0) > 0.0
+    {
+        normal = -normal;
+    }
+    let (closest, on_triangle) = closest_points_on_segment_triangle(a, b, triangle);
+    contacts.extend(surface_contact(closest, radius, on_triangle, normal, 0.0));
+    if a != b
+    {
+        for end in [a, b]
+        {
+            if end.distance_squared(closest) > 1e-6
+            {
+                contacts.extend(surface_contact(end, radius, closest_point_on_triangle(end, t0, t1, t2), normal, 0.0));
+            }
+        }
+    }
+}
+
+// The corners of the box behind the triangle's plane, over the triangle.
+fn box_triangle_contacts(oriented_box: &OrientedBox, triangle: [Vec3; 3], contacts: &mut Vec<Contact>)
+{
+    let [t0, t1, t2] = triangle;
+    let mut normal = (t1 - t0).cross(t2 - t0).normalize_or_zero();
+    if normal == Vec3::ZERO
+    {
+        return;
+    }
+    // Towards the box, the side it is coming from.
+    if normal.dot(oriented_box.center - t0) < 0.0
+    {
+        normal = -normal;
+    }
+    for corner in oriented_box.corners()
+    {
+        let distance = normal.dot(corner - t0);
+        if distance >= 0.0
+        {
+            continue;
+        }
+        let projected = corner - normal * distance;
+        if closest_point_on_triangle(projected, t0, t1, t2).distance_squared(projected) > 1e-6
+        {
+            continue;
+        }
+        contacts.push(Contact { a: 0, b: 0, normal: -normal, point: projected, depth: -distance });
+    }
+}
+
+// Separating axis test over the face normals and the edge cross products. The contacts of a
+// face axis are the corners of the other box through that face, clamped onto it.
+fn box_box_contacts(a: &OrientedBox, b: &OrientedBox, contacts: &mut Vec<Contact>)
+{
+    let offset = b.center - a.center;
+    let overlap = |axis: Vec3| a.projected_radius(axis) + b.projected_radius(axis) - offset.dot(axis).abs();
+    let towards_b = |axis: Vec3| if offset.dot(axis) < 0.0 { -axis } else { axis };
+
+    let mut face = (f32::INFINITY, Vec3::ZERO, 0);
+    for (index, &axis) in a.axes.iter().chain(&b.axes).enumerate()
+    {
+        let depth = overlap(axis);
+        if depth < 0.0
+        {
+            return;
+        }
+        if depth < face.0
+        {
+            face = (depth, towards_b(axis), index);
+        }
+    }
+    let mut edge = (f32::INFINITY, Vec3::ZERO, 0, 0);
+    for i in 0..3
+    {
+        for j in 0..3
+        {
+            // Parallel edges are separated by the face axes already.
+            let axis = a.axes[i].cross(b.axes[j]);
+            if axis.length_squared() < 1e-8
+            {
+                continue;
+            }
+            let axis = axis.normalize();
+            let depth = overlap(axis);
+            if depth < 0.0
+            {
+                return;
+            }
+            if depth < edge.0
+            {
+                edge = (depth, towards_b(axis), i, j);
+            }
+        }
+    }
+
+    // Faces give the steadier contacts, an edge has to be clearly shallower.
+    if edge.0 < face.0 * 0.95 - 0.001
+    {
+        let (depth, normal, i, j) = edge;
+        let (a0, a1) = box_edge(a, i, normal);
+        let (b0, b1) = box_edge(b, j, -normal);
+        let (point_a, point_b) = closest_points_on_segments(a0, a1, b0, b1);
+        contacts.push(Contact { a: 0, b: 0, normal, point: (point_a + point_b) * 0.5, depth });
+    }
+    else if face.2 < 3
+    {
+        face_contacts(a, b, face.2, face.1, contacts);
+    }
+    else
+    {
+        let start = contacts.len();
+        face_contacts(b, a, face.2 - 3, -face.1, contacts);
+        for contact in &mut contacts[start..]
+        {
+            contact.normal = -contact.normal;
+        }
+    }
+}
+
+// The corners of the incident box through the reference box's face along normal, which points
+// from the reference box to the incident one.
+fn face_contacts(reference: &OrientedBox, incident: &OrientedBox, axis: usize, normal: Vec3, contacts: &mut Vec<Contact>)
+{
+    let half_extents = reference.half_extents;
+    let face = normal.dot(reference.center) + half_extents[axis];
+    let face_side = half_extents[axis] * reference.axes[axis].dot(normal).signum();
+    for corner in incident.corners()
+    {
+        let depth = face - normal.dot(corner);
+        if depth <= 0.0
+        {
+            continue;
+        }
+        let mut local = reference.to_local(corner).clamp(-half_extents, half_extents);
+        local[axis] = face_side;
+        contacts.push(Contact { a: 0, b: 0, normal, point: reference.to_world(local), depth });
+    }
+}
+
+// The box's edge along the axis that is furthest in the direction.
+fn box_edge(oriented_box: &OrientedBox, axis: usize, direction: Vec3) -> (Vec3, Vec3)
+{
+    let mut center = oriented_box.center;
+    for other in (0..3).filter(|&other| other != axis)
+    {
+        let side = oriented_box.axes[other].dot(direction).signum();
+        center += oriented_box.axes[other] * oriented_box.half_extents[other] * side;
+    }
+    let half = oriented_box.axes[axis] * oriented_box.half_extents[axis];
+    return (center - half, center + half);
+}
+
+fn closest_point_on_segment(a: Vec3, b: Vec3, point: Vec3) -> Vec3
+{
+    let direction = b - a;
+    let length_squared = direction.length_squared();
+    if length_squared <= f32::EPSILON
+    {
+        return a;
+    }
+    return a + direction * ((point - a).dot(direction) / length_squared).clamp(0.0, 1.0);
+}
+
+// From Real-Time Collision Detection, the closest points of segments p1 q1 and p2 q2.
+fn closest_points_on_segments(p1: Vec3, q1: Vec3, p2: Vec3, q2: Vec3) -> (Vec3, Vec3)
+{
+    let d1 = q1 - p1;
+    let d2 = q2 - p2;
+    let r = p1 - p2;
+    let a = d1.length_squared();
+    let e = d2.length_squared();
+    let f = d2.dot(r);
+    if a <= f32::EPSILON && e <= f32::EPSILON
+    {
+        return (p1, p2);
+    }
+    let (s, t);
+    if a <= f32::EPSILON
+    {
+        s = 0.0;
+        t = (f / e).clamp(0.0, 1.0);
+    }
+    else
+    {
+        let c = d1.dot(r);
+        if e <= f32::EPSILON
+        {
+            t = 0.0;
+            s = (-c / a).clamp(0.0, 1.0);
+        }
+        else
+        {
+            let b = d1.dot(d2);
+            let denominator = a * e - b * b;
+            // Parallel segments pick any point on the first one.
+            let s_line = if denominator > 0.0 { ((b * f - c * e) / denominator).clamp(0.0, 1.0) } else { 0.0 };
+            let t_line = (b * s_line + f) / e;
+            if t_line < 0.0
+            {
+                t = 0.0;
+                s = (-c / a).clamp(0.0, 1.0);
+            }
+            else if t_line > 1.0
+            {
+                t = 1.0;
+                s = ((b - c) / a).clamp(0.0, 1.0);
+            }
+            else
+            {
+                t = t_line;
+                s = s_line;
+            }
+        }
+    }
+    return (p1 + d1 * s, p2 + d2 * t);
+}
+
+// The closest points on the segment and on the triangle, the same point where they cross.
+fn closest_points_on_segment_triangle(a: Vec3, b: Vec3, triangle: [Vec3; 3]) -> (Vec3, Vec3)
+{
+    let [t0, t1, t2] = triangle;
+    if a == b
+    {
+        return (a, closest_point_on_triangle(a, t0, t1, t2));
+    }
+    let ray = Ray::new(a, b - a);
+    if let Some((t, _, _)) = ray.intersect_triangle(t0, t1, t2).filter(|&(t, _, _)| t <= 1.0)
+    {
+        let point = ray.at(t);
+        return (point, point);
+    }
+    return [
+        (a, closest_point_on_triangle(a, t0, t1, t2)),
+        (b, closest_point_on_triangle(b, t0, t1, t2)),
+        closest_points_on_segments(a, b, t0, t1),
+        closest_points_on_segments(a, b, t1, t2),
+        closest_points_on_segments(a, b, t2, t0),
+    ]
+        .into_iter()
+        .min_by(|x, y| x.0.distance_squared(x.1).total_cmp(&y.0.distance_squared(y.1)))
+        .unwrap();
+}
@@ -0,0 +1,202 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+// Frames kept for the trace, about 10 seconds at 60 fps.
+const MAX_FRAMES: usize = 600;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static FRAMES: Mutex<VecDeque<Frame>> = Mutex::new(VecDeque::new());
+static THREAD_NAMES: Mutex<Vec<(u32, String)>> = Mutex::new(Vec::new());
+static NEXT_THREAD_ID: AtomicU32 = AtomicU32::new(0);
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+thread_local!
+{
+    static THREAD_ID: u32 = register_thread();
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span
+{
+    pub name: &'static str,
+    pub thread: u32,
+    // Both from the first use of the profiler.
+    pub start: Duration,
+    pub duration: Duration,
+}
+
+struct Frame
+{
+    index: u64,
+    // Thread calling begin_frame.
+    thread: u32,
+    start: Duration,
+    // Set when the next frame begins.
+    end: Option<Duration>,
+    spans: Vec<Span>,
+}
+
+// Records its span when dropped. Spans outside of a frame are ignored.
+#[must_use = "the span ends when the scope is dropped"]
+pub struct Scope
+{
+    name: &'static str,
+    start: Option<Duration>,
+}
+
+impl Drop for Scope
+{
+    fn drop(&mut self)
+    {
+        let Some(start) = self.start else
+        {
+            return;
+        };
+        let span = Span
+        {
+            name: self.name,
+            thread: THREAD_ID.with(|id| *id),
+            start,
+            duration: now().saturating_sub(start),
+        };
+        if let Some(frame) = FRAMES.lock().unwrap().back_mut()
+        {
+            frame.spans.push(span);
+        }
+    }
+}
+
+pub fn set_enabled(enabled: bool)
+{
+    ENABLED.store(enabled, Ordering::Relaxed);
+    if !enabled
+    {
+        FRAMES.lock().unwrap().clear();
+    }
+}
+
+pub fn is_enabled() -> bool
+{
+    return ENABLED.load(Ordering::Relaxed);
+}
+
+// Ends the previous frame and starts a new one, called once per frame from the main loop.
+pub fn begin_frame()
+{
+    if !is_enabled()
+    {
+        return;
+    }
+    let start = now();
+    let thread = THREAD_ID.with(|id| *id);
+    let mut frames = FRAMES.lock().unwrap();
+    let index = match frames.back_mut()
+    {
+        Some(frame) =>
+        {
+            frame.end = Some(start);
+            frame.index + 1
+        },
+        None => 0,
+    };
+    if frames.len() == MAX_FRAMES
+    {
+        frames.pop_front();
+    }
+    frames.push_back(Frame { index, thread, start, end: None, spans: Vec::new() });
+}
+
+// Times the rest of the enclosing block: let _scope = profiler::scope("Physics");
+pub fn scope(name: &'static str) -> Scope
+{
+    return Scope
+    {
+        name,
+        start: if is_enabled() { Some(now()) } else { None },
+    };
+}
+
+// Spans of the latest finished frame, in the order they ended.
+pub fn last_frame() -> Vec<Span>
+{
+    return FRAMES.lock().unwrap()
+        .iter()
+        .rev()
+        .find(|frame| frame.end.is_some())
+        .map(|frame| frame.spans.clone())
+        .unwrap_or_default();
+}
+
+// The recorded frames in the Chrome trace event format, opens in chrome://tracing, Perfetto
+// and with Tracy's import-chrome.
+pub fn chrome_trace() -> String
+{
+    let mut events = Vec::new();
+    for (thread, name) in THREAD_NAMES.lock().unwrap().iter()
+    {
+        events.push(format!(
+            "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":0,\"tid\":{},\"args\":{{\"name\":\"{}\"}}}}",
+            thread,
+            escape_json(name)));
+    }
+    for frame in FRAMES.lock().unwrap().iter()
+    {
+        if let Some(end) = frame.end
+        {
+            events.push(complete_event(&format!("Frame {}", frame.index), frame.thread, frame.start, end - frame.start));
+        }
+        for span in &frame.spans
+        {
+            events.push(complete_event(span.name, span.thread, span.start, span.duration));
+        }
+    }
+    return format!("{{\"traceEvents\":[\n{}\n]}}\n", events.join(",\n"));
+}
+
+pub fn write_chrome_trace(path: impl AsRef<Path>) -> std::io::Result<()>
+{
+    return std::fs::write(path, chrome_trace());
+}
+
+fn complete_event(name: &str, thread: u32, start: Duration, duration: Duration) -> String
+{
+    return format!(
+        "{{\"name\":\"{}\",\"ph\":\"X\",\"pid\":0,\"tid\":{},\"ts\":{:.3},\"dur\":{:.3}}}",
+        escape_json(name),
+        thread,
+        start.as_secs_f64() * 1_000_000.0,
+        duration.as_secs_f64() * 1_000_000.0);
+}
+
+fn escape_json(text: &str) -> String
+{
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars()
+    {
+        match c
+        {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => { let _ = write!(escaped, "\\u{:04x}", c as u32); },
+            c => escaped.push(c),
+        }
+    }
+    return escaped;
+}
+
+fn now() -> Duration
+{
+    return EPOCH.get_or_init(Instant::now).elapsed();
+}
+
+fn register_thread() -> u32
+{
+    let id = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+    let name = std::thread::current().name().unwrap_or("unnamed").to_string();
+    THREAD_NAMES.lock().unwrap().push((id, name));
+    return id;
+}
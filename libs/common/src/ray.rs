@@ -0,0 +1,76 @@
+use glam::{Mat4, Vec3};
+
+use crate::bounds::Aabb;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Ray
+{
+    pub origin: Vec3,
+    // Not necessarily unit length, the hit distances are in multiples of it.
+    pub direction: Vec3,
+}
+
+impl Ray
+{
+    pub fn new(origin: Vec3, direction: Vec3) -> Self
+    {
+        Self { origin, direction }
+    }
+
+    pub fn at(&self, t: f32) -> Vec3
+    {
+        return self.origin + self.direction * t;
+    }
+
+    // Keeps the distances, a hit at t on the transformed ray is at t on this one too.
+    pub fn transformed(&self, transform: &Mat4) -> Self
+    {
+        return Self::new(transform.transform_point3(self.origin), transform.transform_vector3(self.direction));
+    }
+
+    // The distance to the box along the ray, 0 when starting inside.
+    pub fn intersect_aabb(&self, aabb: &Aabb, max_t: f32) -> Option<f32>
+    {
+        // On the axes the ray is parallel to, the origin on a face would give 0 * inf.
+        let parallel = self.direction.cmpeq(Vec3::ZERO);
+        if (parallel & (self.origin.cmplt(aabb.min) | self.origin.cmpgt(aabb.max))).any()
+        {
+            return None;
+        }
+        let inverse_direction = self.direction.recip();
+        let t0 = (aabb.min - self.origin) * inverse_direction;
+        let t1 = (aabb.max - self.origin) * inverse_direction;
+        let t_near = Vec3::select(parallel, Vec3::splat(f32::NEG_INFINITY), t0.min(t1)).max_element().max(0.0);
+        let t_far = Vec3::select(parallel, Vec3::splat(f32::INFINITY), t0.max(t1)).min_element().min(max_t);
+        return if t_near <= t_far { Some(t_near) } else { None };
+    }
+
+    // Möller-Trumbore, both sides of the triangle count. Returns the distance and the
+    // barycentric coordinates of b and c.
+    pub fn intersect_triangle(&self, a: Vec3, b: Vec3, c: Vec3) -> Option<(f32, f32, f32)>
+    {
+        let edge1 = b - a;
+        let edge2 = c - a;
+        let p = self.direction.cross(edge2);
+        let determinant = edge1.dot(p);
+        if determinant.abs() < f32::EPSILON
+        {
+            return None;
+        }
+        let inverse_determinant = 1.0 / determinant;
+        let to_origin = self.origin - a;
+        let u = to_origin.dot(p) * inverse_determinant;
+        if !(0.0..=1.0).contains(&u)
+        {
+            return None;
+        }
+        let q = to_origin.cross(edge1);
+        let v = self.direction.dot(q) * inverse_determinant;
+        if v < 0.0 || u + v > 1.0
+        {
+            return None;
+        }
+        let t = edge2.dot(q) * inverse_determinant;
+        return if t >= 0.0 { Some((t, u, v)) } else { None };
+    }
+}
@@ -0,0 +1,232 @@
+use egui::{ClippedPrimitive, Event, FullOutput, Key, Modifiers, PointerButton, Pos2, RawInput, Rect, TexturesDelta, Vec2};
+use input::{Input, ModifiersState, MouseButton, VirtualKeyCode};
+
+const POINTER_BUTTONS: [(MouseButton, PointerButton); 5] = [
+    (MouseButton::Left, PointerButton::Primary),
+    (MouseButton::Right, PointerButton::Secondary),
+    (MouseButton::Middle, PointerButton::Middle),
+    (MouseButton::Other(0), PointerButton::Extra1),
+    (MouseButton::Other(1), PointerButton::Extra2),
+];
+
+// The immediate mode ui. Every frame the input is turned into an egui frame the systems build
+// their panels in, and the output is kept here for the renderer to draw over the back buffer.
+pub struct Ui
+{
+    context: egui::Context,
+    pixels_per_point: f32,
+    // Seconds since the first frame.
+    time: f64,
+    primitives: Vec<ClippedPrimitive>,
+    textures_delta: TexturesDelta,
+}
+
+impl Ui
+{
+    pub fn new() -> Self
+    {
+        Self
+        {
+            context: egui::Context::default(),
+            pixels_per_point: 1.0,
+            time: 0.0,
+            primitives: Vec::new(),
+            textures_delta: TexturesDelta::default(),
+        }
+    }
+
+    // Clone it to run a frame while the game state is borrowed.
+    pub fn context(&self) -> &egui::Context
+    {
+        return &self.context;
+    }
+
+    // The window scale factor, window pixels per ui point.
+    pub fn pixels_per_point(&self) -> f32
+    {
+        return self.pixels_per_point;
+    }
+
+    pub fn set_pixels_per_point(&mut self, pixels_per_point: f32)
+    {
+        self.pixels_per_point = pixels_per_point;
+    }
+
+    // A panel is using the mouse, e.g. the cursor is over it or something is dragged.
+    pub fn wants_pointer_input(&self) -> bool
+    {
+        return self.context.wants_pointer_input();
+    }
+
+    // A text field has the keyboard focus.
+    pub fn wants_keyboard_input(&self) -> bool
+    {
+        return self.context.wants_keyboard_input();
+    }
+
+    // Tessellated meshes of the last frame in ui points.
+    pub fn primitives(&self) -> &[ClippedPrimitive]
+    {
+        return &self.primitives;
+    }
+
+    // Textures the last frame created, changed or freed.
+    pub fn textures_delta(&self) -> &TexturesDelta
+    {
+        return &self.textures_delta;
+    }
+
+    // The input of this frame for a window of width x height pixels.
+    pub fn raw_input(&mut self, input: &Input, width: f32, height: f32, dt: f64) -> RawInput
+    {
+        self.time += dt;
+        let pixels_per_point = self.pixels_per_point;
+        let modifiers = modifiers(input.modifiers());
+        let mut events = Vec::new();
+
+        let to_points = |[x, y]: [f32; 2]| Pos2::new(x / pixels_per_point, y / pixels_per_point);
+        // Every press and release where it happened, so a click within one frame isn't lost.
+        for &(button, pressed, position) in input.mouse_events()
+        {
+            let pointer_button = POINTER_BUTTONS
+                .iter()
+                .find(|(mouse_button, _)| *mouse_button == button)
+                .map(|&(_, pointer_button)| pointer_button);
+            if let (Some(pointer_button), Some(position)) = (pointer_button, position)
+            {
+                let pos = to_points(position);
+                events.push(Event::PointerMoved(pos));
+                events.push(Event::PointerButton { pos, button: pointer_button, pressed, modifiers });
+            }
+        }
+        match input.mouse_position()
+        {
+            Some(position) => events.push(Event::PointerMoved(to_points(position))),
+            None => events.push(Event::PointerGone),
+        }
+        let [scroll_x, scroll_y] = input.scroll_delta();
+        if scroll_x != 0.0 || scroll_y != 0.0
+        {
+            events.push(Event::Scroll(Vec2::new(scroll_x, scroll_y) / pixels_per_point));
+        }
+
+        for &(key, pressed) in input.key_events()
+        {
+            if let Some(key) = key_from_virtual_key_code(key)
+            {
+                events.push(Event::Key { key, pressed, repeat: false, modifiers });
+            }
+        }
+        // Control characters come as key events, e.g. backspace, and the command shortcuts
+        // are not text.
+        let text: String = input.text().chars().filter(|character| !character.is_control()).collect();
+        if !text.is_empty() && !modifiers.ctrl && !modifiers.mac_cmd
+        {
+            events.push(Event::Text(text));
+        }
+
+        return RawInput {
+            screen_rect: Some(Rect::from_min_size(
+                Pos2::ZERO,
+                Vec2::new(width, height) / pixels_per_point)),
+            pixels_per_point: Some(pixels_per_point),
+            time: Some(self.time),
+            predicted_dt: dt as f32,
+            modifiers,
+            events,
+            focused: true,
+            ..Default::default()
+        };
+    }
+
+    // Keeps what the frame drew for the renderer.
+    pub fn end_frame(&mut self, output: FullOutput)
+    {
+        self.primitives = self.context.tessellate(output.shapes);
+        self.textures_delta = output.textures_delta;
+    }
+}
+
+fn modifiers(state: ModifiersState) -> Modifiers
+{
+    return Modifiers {
+        alt: state.alt(),
+        ctrl: state.ctrl(),
+        shift: state.shift(),
+        mac_cmd: cfg!(target_os = "macos") && state.logo(),
+        command: if cfg!(target_os = "macos") { state.logo() } else { state.ctrl() },
+    };
+}
+
+fn key_from_virtual_key_code(key: VirtualKeyCode) -> Option<Key>
+{
+    return Some(match key
+    {
+        VirtualKeyCode::Down => Key::ArrowDown,
+        VirtualKeyCode::Left => Key::ArrowLeft,
+        VirtualKeyCode::Right => Key::ArrowRight,
+        VirtualKeyCode::Up => Key::ArrowUp,
+        VirtualKeyCode::Escape => Key::Escape,
+        VirtualKeyCode::Tab => Key::Tab,
+        VirtualKeyCode::Back => Key::Backspace,
+        VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => Key::Enter,
+        VirtualKeyCode::Space => Key::Space,
+        VirtualKeyCode::Insert => Key::Insert,
+        VirtualKeyCode::Delete => Key::Delete,
+        VirtualKeyCode::Home => Key::Home,
+        VirtualKeyCode::End => Key::End,
+        VirtualKeyCode::PageUp => Key::PageUp,
+        VirtualKeyCode::PageDown => Key::PageDown,
+        VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract => Key::Minus,
+        VirtualKeyCode::Equals | VirtualKeyCode::Plus | VirtualKeyCode::NumpadAdd => Key::PlusEquals,
+        VirtualKeyCode::Key0 | VirtualKeyCode::Numpad0 => Key::Num0,
+        VirtualKeyCode::Key1 | VirtualKeyCode::Numpad1 => Key::Num1,
+        VirtualKeyCode::Key2 | VirtualKeyCode::Numpad2 => Key::Num2,
+        VirtualKeyCode::Key3 | VirtualKeyCode::Numpad3 => Key::Num3,
+        VirtualKeyCode::Key4 | VirtualKeyCode::Numpad4 => Key::Num4,
+        VirtualKeyCode::Key5 | VirtualKeyCode::Numpad5 => Key::Num5,
+        VirtualKeyCode::Key6 | VirtualKeyCode::Numpad6 => Key::Num6,
+        VirtualKeyCode::Key7 | VirtualKeyCode::Numpad7 => Key::Num7,
+        VirtualKeyCode::Key8 | VirtualKeyCode::Numpad8 => Key::Num8,
+        VirtualKeyCode::Key9 | VirtualKeyCode::Numpad9 => Key::Num9,
+        VirtualKeyCode::A => Key::A,
+        VirtualKeyCode::B => Key::B,
+        VirtualKeyCode::C => Key::C,
+        VirtualKeyCode::D => Key::D,
+        VirtualKeyCode::E => Key::E,
+        VirtualKeyCode::F => Key::F,
+        VirtualKeyCode::G => Key::G,
+        VirtualKeyCode::H => Key::H,
+        VirtualKeyCode::I => Key::I,
+        VirtualKeyCode::J => Key::J,
+        VirtualKeyCode::K => Key::K,
+        VirtualKeyCode::L => Key::L,
+        VirtualKeyCode::M => Key::M,
+        VirtualKeyCode::N => Key::N,
+        VirtualKeyCode::O => Key::O,
+        VirtualKeyCode::P => Key::P,
+        VirtualKeyCode::Q => Key::Q,
+        VirtualKeyCode::R => Key::R,
+        VirtualKeyCode::S => Key::S,
+        VirtualKeyCode::T => Key::T,
+        VirtualKeyCode::U => Key::U,
+        VirtualKeyCode::V => Key::V,
+        VirtualKeyCode::W => Key::W,
+        VirtualKeyCode::X => Key::X,
+        VirtualKeyCode::Y => Key::Y,
+        VirtualKeyCode::Z => Key::Z,
+        VirtualKeyCode::F1 => Key::F1,
+        VirtualKeyCode::F2 => Key::F2,
+        VirtualKeyCode::F3 => Key::F3,
+        VirtualKeyCode::F4 => Key::F4,
+        VirtualKeyCode::F5 => Key::F5,
+        VirtualKeyCode::F6 => Key::F6,
+        VirtualKeyCode::F7 => Key::F7,
+        VirtualKeyCode::F8 => Key::F8,
+        VirtualKeyCode::F9 => Key::F9,
+        VirtualKeyCode::F10 => Key::F10,
+        VirtualKeyCode::F11 => Key::F11,
+        VirtualKeyCode::F12 => Key::F12,
+        _ => return None,
+    });
+}
@@ -1,27 +1,69 @@
 use std::mem::transmute;
 use winit::{
-    event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent},
+    event::{ElementState, KeyboardInput, MouseScrollDelta, WindowEvent},
 };
 
+pub use winit::event::{ModifiersState, MouseButton, VirtualKeyCode};
+
 // Could add own keys, so we can remove the dependency to winit
 
+// Left, right, middle and the first other buttons.
+const MOUSE_BUTTONS: usize = 8;
+// Scroll lines are turned into pixels with this.
+const SCROLL_LINE_PIXELS: f32 = 50.0;
+
 pub struct Input
 {
     keys: [u8; 512],
-    changes: [u8; 512]
+    changes: [u8; 512],
+
+    // Key presses and releases in the order they happened this frame.
+    key_events: Vec<(VirtualKeyCode, bool)>,
+    // Characters typed this frame.
+    text: String,
+    modifiers: ModifiersState,
+
+    // Window pixels from the top left, None when the cursor is outside the window.
+    mouse_position: Option<[f32; 2]>,
+    mouse_buttons: [u8; MOUSE_BUTTONS],
+    mouse_changes: [u8; MOUSE_BUTTONS],
+    // Button presses and releases in the order they happened this frame, with the cursor
+    // position at the time.
+    mouse_events: Vec<(MouseButton, bool, Option<[f32; 2]>)>,
+    // Pixels scrolled this frame, positive y scrolls up.
+    scroll_delta: [f32; 2],
 }
 
 impl Input
 {
     pub fn new() -> Self
     {
-        let s: Self = unsafe { std::mem::zeroed() };
-        return s;
+        Self
+        {
+            keys: [0; 512],
+            changes: [0; 512],
+
+            key_events: Vec::new(),
+            text: String::new(),
+            modifiers: ModifiersState::empty(),
+
+            mouse_position: None,
+            mouse_buttons: [0; MOUSE_BUTTONS],
+            mouse_changes: [0; MOUSE_BUTTONS],
+            mouse_events: Vec::new(),
+            scroll_delta: [0.0; 2],
+        }
     }
 
+    // Clears what happened this frame, called after every system has seen it.
     pub fn reset(&mut self)
     {
-        unsafe { self.changes = std::mem::zeroed() };
+        self.changes = [0; 512];
+        self.key_events.clear();
+        self.text.clear();
+        self.mouse_changes = [0; MOUSE_BUTTONS];
+        self.mouse_events.clear();
+        self.scroll_delta = [0.0; 2];
     }
 
     pub fn is_down(&self, key: &VirtualKeyCode) -> bool
@@ -50,6 +92,63 @@ impl Input
         return result;
     }
 
+    pub fn key_events(&self) -> &[(VirtualKeyCode, bool)]
+    {
+        return &self.key_events;
+    }
+
+    pub fn text(&self) -> &str
+    {
+        return &self.text;
+    }
+
+    pub fn modifiers(&self) -> ModifiersState
+    {
+        return self.modifiers;
+    }
+
+    pub fn mouse_position(&self) -> Option<[f32; 2]>
+    {
+        return self.mouse_position;
+    }
+
+    pub fn scroll_delta(&self) -> [f32; 2]
+    {
+        return self.scroll_delta;
+    }
+
+    pub fn mouse_events(&self) -> &[(MouseButton, bool, Option<[f32; 2]>)]
+    {
+        return &self.mouse_events;
+    }
+
+    pub fn is_mouse_down(&self, button: &MouseButton) -> bool
+    {
+        return match mouse_button_index(button)
+        {
+            Some(index) => self.mouse_buttons[index] == 1,
+            None => false,
+        };
+    }
+
+    pub fn is_mouse_released(&self, button: &MouseButton) -> bool
+    {
+        return match mouse_button_index(button)
+        {
+            Some(index) => self.mouse_buttons[index] == 0 && self.mouse_changes[index] > 0,
+            None => false,
+        };
+    }
+
+    pub fn is_mouse_pressed(&self, button: &MouseButton) -> bool
+    {
+        return match mouse_button_index(button)
+        {
+            Some(index) => self.mouse_buttons[index] == 1 && self.mouse_changes[index] > 0,
+            None => false,
+        };
+    }
+
     pub fn update(&mut self, event: &WindowEvent)
     {
         match event
@@ -71,6 +170,32 @@ impl Input
                     ElementState::Released => self.set_released(keycode),
                 }
             }
+            WindowEvent::ReceivedCharacter(character) => self.text.push(*character),
+            WindowEvent::ModifiersChanged(modifiers) => self.modifiers = *modifiers,
+            WindowEvent::CursorMoved { position, .. } =>
+            {
+                self.mouse_position = Some([position.x as f32, position.y as f32]);
+            }
+            WindowEvent::CursorLeft { .. } => self.mouse_position = None,
+            WindowEvent::MouseInput { state, button, .. } =>
+            {
+                self.mouse_events.push((*button, *state == ElementState::Pressed, self.mouse_position));
+                if let Some(index) = mouse_button_index(button)
+                {
+                    self.mouse_changes[index] += 1;
+                    self.mouse_buttons[index] = (*state == ElementState::Pressed) as u8;
+                }
+            }
+            WindowEvent::MouseWheel { delta, .. } =>
+            {
+                let (x, y) = match *delta
+                {
+                    MouseScrollDelta::LineDelta(x, y) => (x * SCROLL_LINE_PIXELS, y * SCROLL_LINE_PIXELS),
+                    MouseScrollDelta::PixelDelta(position) => (position.x as f32, position.y as f32),
+                };
+                self.scroll_delta[0] += x;
+                self.scroll_delta[1] += y;
+            }
             _ => {},
         }
     }
@@ -82,6 +207,7 @@ impl Input
         let index = transformed as usize;
         self.changes[index] += 1;
         self.keys[index] = 1;
+        self.key_events.push((*key, true));
     }
     fn set_released(&mut self, key: &VirtualKeyCode)
     {
@@ -89,6 +215,18 @@ impl Input
         let index = transformed as usize;
         self.changes[index] += 1;
         self.keys[index] = 0;
+        self.key_events.push((*key, false));
     }
 }
 
+fn mouse_button_index(button: &MouseButton) -> Option<usize>
+{
+    let index = match *button
+    {
+        MouseButton::Left => 0,
+        MouseButton::Right => 1,
+        MouseButton::Middle => 2,
+        MouseButton::Other(other) => 3 + other as usize,
+    };
+    return if index < MOUSE_BUTTONS { Some(index) } else { None };
+}
@@ -1,4 +1,5 @@
 pub const WHITE_COLOR: [f32; 4] = [1.0f32, 1.0f32, 1.0f32, 1.0f32];
+pub const GLASS_COLOR: [f32; 4] = [0.4f32, 0.7f32, 1.0f32, 0.4f32];
 
 pub const VERTICES: &[common::MeshVertex] = &[
     common::MeshVertex { position: [-0.5, -0.5, 0.5, 1.0], normal: [0.0, 0.0, 1.0, 0.0], color: WHITE_COLOR },
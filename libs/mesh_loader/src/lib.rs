@@ -10,17 +10,13 @@ impl MeshLoader
     pub fn new(_game_state: &mut GameState) -> Self
     {
 
-        let mut mesh_model = common::MeshModelLocation {
-            vertices_start_index: _game_state.mesh_data.vertices.len() as u32,
-            vertices_count: cube::VERTICES.len() as u32,
-            indices_start_index: _game_state.mesh_data.indices.len() as u32,
-            indices_count: cube::INDICES.len() as u32,
-        };
-
-        _game_state.mesh_data.models.push(mesh_model);
-
-        _game_state.mesh_data.vertices.extend(cube::VERTICES);
-        _game_state.mesh_data.indices.extend(cube::INDICES);
+        _game_state.mesh_data.add_model(cube::VERTICES, cube::INDICES);
+        // A see-through cube for the transparent instances.
+        let glass_vertices: Vec<common::MeshVertex> = cube::VERTICES
+            .iter()
+            .map(|vertex| common::MeshVertex { color: cube::GLASS_COLOR, ..*vertex })
+            .collect();
+        _game_state.mesh_data.add_model(&glass_vertices, cube::INDICES);
 
         return Self {};
     }
@@ -1,66 +1,159 @@
-use std::borrow::Cow;
 use std::num::NonZeroU32;
 
-use wgpu::{ShaderModule, PipelineLayout, RenderPipeline, TextureFormat, Device, CommandEncoder, TextureView, Texture, BindGroupLayout, BindGroup, Sampler};
+use wgpu::{ShaderModule, PipelineLayout, RenderPipeline, TextureFormat, Device, CommandEncoder, TextureView, Texture, BindGroupLayout, BindGroup, Sampler, Buffer, Queue};
+use wgpu::util::DeviceExt;
+
+use crate::render_scale::UpscaleFilter;
+use crate::render_targets::{PingPong, RenderTargets, ScreenSize, ScreenSizeDependent, POST_PROCESS_FORMAT};
+use crate::shaders::{self, ShaderInterface, ShaderReloadable};
+
+const SHADER_NAME: &str = "blit.wgsl";
+const VERTEX_ENTRY: &str = "vs_main";
+const FRAGMENT_ENTRY: &str = "fs_main";
+const FRAGMENT_EASU_ENTRY: &str = "fs_easu";
+const FRAGMENT_RCAS_ENTRY: &str = "fs_rcas";
+
+const BIND_GROUP_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] = &[
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        // This should match the filterable field of the
+        // corresponding Texture entry above.
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 2,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+];
+
+pub(crate) const SHADER_INTERFACE: ShaderInterface = ShaderInterface {
+    shader_name: SHADER_NAME,
+    entry_points: &[
+        (wgpu::ShaderStages::VERTEX, VERTEX_ENTRY),
+        (wgpu::ShaderStages::FRAGMENT, FRAGMENT_ENTRY),
+        (wgpu::ShaderStages::FRAGMENT, FRAGMENT_EASU_ENTRY),
+        (wgpu::ShaderStages::FRAGMENT, FRAGMENT_RCAS_ENTRY),
+    ],
+    bind_group_layouts: &[BIND_GROUP_LAYOUT_ENTRIES],
+    vertex_attributes: &[],
+    defines: &[],
+};
+
+// Shared with blit.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct BlitParams
+{
+    pub(crate) sharpness: f32,
+    pub(crate) encode_srgb: u32,
+    pub(crate) _padding0: f32,
+    pub(crate) _padding1: f32,
+}
+
+impl BlitParams
+{
+    fn new(filter: UpscaleFilter, output_format: TextureFormat) -> Self
+    {
+        let sharpness = match filter
+        {
+            UpscaleFilter::Bilinear => 0.0,
+            UpscaleFilter::Fsr { sharpness } => (-sharpness.max(0.0)).exp2(),
+        };
+        Self
+        {
+            sharpness,
+            encode_srgb: !output_format.is_srgb() as u32,
+            _padding0: 0.0,
+            _padding1: 0.0,
+        }
+    }
+}
 
 pub struct TriangleSystem
 {
     _shader: ShaderModule,
     _pipeline_layout: PipelineLayout,
     render_pipeline: RenderPipeline,
+    easu_pipeline: RenderPipeline,
+    rcas_pipeline: RenderPipeline,
 
-    _input_texture_view: TextureView,
     _texture_sampler: Sampler,
     _bind_group_layout: BindGroupLayout,
-    bind_group: BindGroup,
+    // One for each render target texture the post processing can end in.
+    bind_groups: Vec<BindGroup>,
+    // Reads the upscaled image for the sharpening.
+    upscale_bind_group: BindGroup,
+
+    params_buffer: Buffer,
+    filter: UpscaleFilter,
+    // Without scaling the image is copied as is, whatever the filter.
+    pub(crate) size: ScreenSize,
+    pub(crate) display_size: ScreenSize,
+
+    texture_format: TextureFormat,
 }
 
 impl TriangleSystem
 {
-    pub fn new(device: &Device, texture_format: TextureFormat, input_texture: &Texture) -> Self
+    pub fn new(device: &Device, texture_format: TextureFormat, render_targets: &RenderTargets) -> Self
     {
-        // Load the shaders from disk
-        let _shader = device.create_shader_module(wgpu::ShaderModuleDescriptor
-        {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(
-                include_str!("../../../data/shaders/blit.wgsl"))),
-        });
+        let _shader = shaders::create_shader_module(device, SHADER_NAME, &shaders::embedded_source(SHADER_NAME));
 
 
 
 
 
-        let _bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("Back buffer blit bind group"),
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Texture {
-                        multisampled: false,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    // This should match the filterable field of the
-                    // corresponding Texture entry above.
-                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                    count: None,
-                },
-            ],
+        let _bind_group_layout = SHADER_INTERFACE.create_bind_group_layout(device, 0, "Back buffer blit bind group");
+
+        let _texture_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
         });
 
-        let (bind_group, _input_texture_view, _texture_sampler) =
-            Self::create_bind_group(
-                &device,
-                &_bind_group_layout,
-                &input_texture
-            );
+        let filter = UpscaleFilter::Bilinear;
+        let params_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Blit params"),
+                contents: bytemuck::cast_slice(&[BlitParams::new(filter, texture_format)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let bind_groups = Self::create_bind_groups(
+            device,
+            &_bind_group_layout,
+            &_texture_sampler,
+            &params_buffer,
+            render_targets);
+        let upscale_bind_group = Self::create_bind_group(
+            device,
+            &_bind_group_layout,
+            &_texture_sampler,
+            &params_buffer,
+            &render_targets.upscale_texture);
 
 
         let _pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor
@@ -71,20 +164,66 @@ impl TriangleSystem
         });
 
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor
+        let (render_pipeline, easu_pipeline, rcas_pipeline) =
+            Self::create_pipelines(device, &_pipeline_layout, &_shader, texture_format);
+
+
+        Self {
+            _shader,
+            _pipeline_layout,
+            render_pipeline,
+            easu_pipeline,
+            rcas_pipeline,
+
+            _texture_sampler,
+
+            _bind_group_layout,
+            bind_groups,
+            upscale_bind_group,
+
+            params_buffer,
+            filter,
+            size: render_targets.size,
+            display_size: render_targets.display_size,
+
+            texture_format,
+        }
+    }
+    fn create_pipelines(
+        device: &Device,
+        pipeline_layout: &PipelineLayout,
+        shader: &ShaderModule,
+        texture_format: TextureFormat
+    ) -> (RenderPipeline, RenderPipeline, RenderPipeline)
+    {
+        return (
+            Self::create_pipeline(device, pipeline_layout, shader, texture_format, FRAGMENT_ENTRY),
+            Self::create_pipeline(device, pipeline_layout, shader, POST_PROCESS_FORMAT, FRAGMENT_EASU_ENTRY),
+            Self::create_pipeline(device, pipeline_layout, shader, texture_format, FRAGMENT_RCAS_ENTRY),
+        );
+    }
+    fn create_pipeline(
+        device: &Device,
+        pipeline_layout: &PipelineLayout,
+        shader: &ShaderModule,
+        texture_format: TextureFormat,
+        fragment_entry: &str
+    ) -> RenderPipeline
+    {
+        return device.create_render_pipeline(&wgpu::RenderPipelineDescriptor
         {
             label: None,
-            layout: Some(&_pipeline_layout),
+            layout: Some(pipeline_layout),
             vertex: wgpu::VertexState
             {
-                module: &_shader,
-                entry_point: "vs_main",
+                module: shader,
+                entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::VERTEX, VERTEX_ENTRY),
                 buffers: &[],
             },
             fragment: Some(wgpu::FragmentState
             {
-                module: &_shader,
-                entry_point: "fs_main",
+                module: shader,
+                entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::FRAGMENT, fragment_entry),
                 targets: &[Some(texture_format.into())],
             }),
             primitive: wgpu::PrimitiveState::default(),
@@ -92,33 +231,72 @@ impl TriangleSystem
             multisample: wgpu::MultisampleState::default(),
             multiview: None,
         });
+    }
+    pub fn rebind_textures(&mut self, device: &Device, render_targets: &RenderTargets)
+    {
+        self.bind_groups = Self::create_bind_groups(
+            device,
+            &self._bind_group_layout,
+            &self._texture_sampler,
+            &self.params_buffer,
+            render_targets);
+        self.upscale_bind_group = Self::create_bind_group(
+            device,
+            &self._bind_group_layout,
+            &self._texture_sampler,
+            &self.params_buffer,
+            &render_targets.upscale_texture);
+        self.size = render_targets.size;
+        self.display_size = render_targets.display_size;
+    }
 
+    pub fn set_filter(&mut self, queue: &Queue, filter: UpscaleFilter)
+    {
+        self.filter = filter;
+        self.write_params(queue);
+    }
 
-        Self {
-            _shader,
-            _pipeline_layout,
-            render_pipeline,
-
-            _input_texture_view,
-            _texture_sampler,
-
-            _bind_group_layout,
-            bind_group,
+    // For a swapchain format change, the pipelines drawing to the back buffer are recreated.
+    pub fn set_texture_format(&mut self, device: &Device, queue: &Queue, texture_format: TextureFormat)
+    {
+        if texture_format == self.texture_format
+        {
+            return;
         }
+        self.texture_format = texture_format;
+        (self.render_pipeline, self.easu_pipeline, self.rcas_pipeline) =
+            Self::create_pipelines(device, &self._pipeline_layout, &self._shader, texture_format);
+        self.write_params(queue);
     }
-    pub fn rebind_texture(&mut self, device: &Device, texture: &Texture)
+
+    fn write_params(&self, queue: &Queue)
     {
-        let (bind_group, texture_view, sampler) =
-            Self::create_bind_group(
-                &device,
-                &self._bind_group_layout,
-                &texture
-            );
-        self.bind_group = bind_group;
-        self._input_texture_view = texture_view;
-        self._texture_sampler = sampler;
+        let params = BlitParams::new(self.filter, self.texture_format);
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
     }
-    pub fn render(&mut self, encoder: &mut CommandEncoder, view: &TextureView)
+
+    // Source is the render target texture the post processing ended in.
+    pub fn render(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        view: &TextureView,
+        upscale_view: &TextureView,
+        source: PingPong
+    )
+    {
+        let source_bind_group = &self.bind_groups[source.index()];
+        match self.filter
+        {
+            UpscaleFilter::Fsr { .. } if self.size != self.display_size =>
+            {
+                Self::draw(encoder, upscale_view, &self.easu_pipeline, source_bind_group);
+                Self::draw(encoder, view, &self.rcas_pipeline, &self.upscale_bind_group);
+            },
+            _ => Self::draw(encoder, view, &self.render_pipeline, source_bind_group),
+        }
+    }
+
+    fn draw(encoder: &mut CommandEncoder, view: &TextureView, pipeline: &RenderPipeline, bind_group: &BindGroup)
     {
         let mut render_pass= encoder.begin_render_pass(&wgpu::RenderPassDescriptor
         {
@@ -135,16 +313,35 @@ impl TriangleSystem
             })],
             depth_stencil_attachment: None,
         });
-        render_pass.set_pipeline(&self.render_pipeline);
-        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
         render_pass.draw(0..3, 0..1);
     }
 
+    fn create_bind_groups(
+        device: &Device,
+        bind_group_layout: &BindGroupLayout,
+        texture_sampler: &Sampler,
+        params_buffer: &Buffer,
+        render_targets: &RenderTargets
+    ) -> Vec<BindGroup>
+    {
+        return PingPong::BOTH
+            .iter()
+            .map(|&source| {
+                let (input_texture, _) = render_targets.ping_pong_textures(source);
+                Self::create_bind_group(device, bind_group_layout, texture_sampler, params_buffer, input_texture)
+            })
+            .collect();
+    }
+
     fn create_bind_group(
         device: &Device,
         bind_group_layout: &BindGroupLayout,
+        texture_sampler: &Sampler,
+        params_buffer: &Buffer,
         input_texture: &Texture
-    ) -> (BindGroup, TextureView, Sampler)
+    ) -> BindGroup
     {
         let _input_texture_view = input_texture.create_view(&wgpu::TextureViewDescriptor {
             label: Some(&format!("Input texture")),
@@ -154,16 +351,6 @@ impl TriangleSystem
             ..Default::default()
         });
 
-        let _texture_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
-
         let bind_group = device.create_bind_group(
             &wgpu::BindGroupDescriptor {
                 layout: &bind_group_layout,
@@ -174,13 +361,45 @@ impl TriangleSystem
                     },
                     wgpu::BindGroupEntry {
                         binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&_texture_sampler),
-                    }
+                        resource: wgpu::BindingResource::Sampler(texture_sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: params_buffer.as_entire_binding(),
+                    },
                 ],
                 label: Some("diffuse_bind_group"),
             }
         );
-        return (bind_group, _input_texture_view, _texture_sampler);
+        return bind_group;
     }
 
-}
\ No newline at end of file
+}
+
+impl ScreenSizeDependent for TriangleSystem
+{
+    fn rebind(&mut self, device: &Device, render_targets: &RenderTargets)
+    {
+        self.rebind_textures(device, render_targets);
+    }
+}
+
+impl ShaderReloadable for TriangleSystem
+{
+    fn shader_name(&self) -> &'static str
+    {
+        return SHADER_NAME;
+    }
+
+    fn reload_shader(&mut self, device: &Device, source: &str) -> Result<(), String>
+    {
+        let (shader, pipelines) = shaders::validated(device, || {
+            let shader = shaders::create_shader_module(device, SHADER_NAME, source);
+            let pipelines = Self::create_pipelines(device, &self._pipeline_layout, &shader, self.texture_format);
+            (shader, pipelines)
+        })?;
+        self._shader = shader;
+        (self.render_pipeline, self.easu_pipeline, self.rcas_pipeline) = pipelines;
+        return Ok(());
+    }
+}
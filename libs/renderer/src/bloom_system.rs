@@ -0,0 +1,448 @@
+use wgpu::*;
+use wgpu::util::DeviceExt;
+
+use crate::render_targets::{PingPong, RenderTargets, ScreenSize, ScreenSizeDependent, POST_PROCESS_FORMAT};
+use crate::shaders::{self, ShaderInterface, ShaderReloadable};
+
+const SHADER_NAME: &str = "bloom.wgsl";
+const COMPUTE_PREFILTER_ENTRY: &str = "prefilter_main";
+const COMPUTE_DOWNSAMPLE_ENTRY: &str = "downsample_main";
+const COMPUTE_UPSAMPLE_ENTRY: &str = "upsample_main";
+const COMPUTE_COMPOSITE_ENTRY: &str = "composite_main";
+
+// Mips of the bloom textures, the first one is half the screen size.
+const MAX_BLOOM_MIPS: u32 = 6;
+
+const BIND_GROUP_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] = &[
+    // Input
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    },
+    // Output
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::StorageTexture {
+            view_dimension: wgpu::TextureViewDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            access: wgpu::StorageTextureAccess::WriteOnly,
+        },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 2,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    },
+    // Params
+    wgpu::BindGroupLayoutEntry {
+        binding: 3,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+    // Bloom
+    wgpu::BindGroupLayoutEntry {
+        binding: 4,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    },
+];
+
+pub(crate) const SHADER_INTERFACE: ShaderInterface = ShaderInterface {
+    shader_name: SHADER_NAME,
+    entry_points: &[
+        (wgpu::ShaderStages::COMPUTE, COMPUTE_PREFILTER_ENTRY),
+        (wgpu::ShaderStages::COMPUTE, COMPUTE_DOWNSAMPLE_ENTRY),
+        (wgpu::ShaderStages::COMPUTE, COMPUTE_UPSAMPLE_ENTRY),
+        (wgpu::ShaderStages::COMPUTE, COMPUTE_COMPOSITE_ENTRY),
+    ],
+    bind_group_layouts: &[BIND_GROUP_LAYOUT_ENTRIES],
+    vertex_attributes: &[],
+    defines: &[],
+};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BloomSettings
+{
+    pub enabled: bool,
+    // Scene brightness where the bloom starts.
+    pub threshold: f32,
+    // Width of the soft transition around the threshold.
+    pub knee: f32,
+    pub intensity: f32,
+}
+
+impl Default for BloomSettings
+{
+    fn default() -> Self
+    {
+        Self
+        {
+            enabled: true,
+            threshold: 1.0,
+            knee: 0.5,
+            intensity: 0.1,
+        }
+    }
+}
+
+// Shared with bloom.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct BloomParams
+{
+    pub(crate) threshold: f32,
+    pub(crate) knee: f32,
+    pub(crate) intensity: f32,
+    pub(crate) _padding: f32,
+}
+
+impl BloomParams
+{
+    fn new(settings: &BloomSettings) -> Self
+    {
+        Self
+        {
+            threshold: settings.threshold,
+            knee: settings.knee,
+            intensity: settings.intensity,
+            _padding: 0.0,
+        }
+    }
+}
+
+// The screen sized bloom textures and everything bound to them.
+struct BloomTargets
+{
+    _down_texture: Texture,
+    _up_texture: Texture,
+    mip_sizes: Vec<ScreenSize>,
+
+    // One per PingPong source.
+    prefilter_bind_groups: Vec<BindGroup>,
+    composite_bind_groups: Vec<BindGroup>,
+    // Downsample into mip i + 1, and upsample into mip i.
+    downsample_bind_groups: Vec<BindGroup>,
+    upsample_bind_groups: Vec<BindGroup>,
+}
+
+pub struct TriangleSystem
+{
+    _shader: ShaderModule,
+    _bind_group_layout: BindGroupLayout,
+    _pipeline_layout: PipelineLayout,
+    compute_pipeline_prefilter: ComputePipeline,
+    compute_pipeline_downsample: ComputePipeline,
+    compute_pipeline_upsample: ComputePipeline,
+    compute_pipeline_composite: ComputePipeline,
+
+    _sampler: Sampler,
+    params_buffer: Buffer,
+    targets: BloomTargets,
+
+    settings: BloomSettings,
+    pub(crate) size: ScreenSize,
+}
+
+impl TriangleSystem
+{
+    pub fn new(device: &Device, render_targets: &RenderTargets) -> Self
+    {
+        let _shader = shaders::create_shader_module(device, SHADER_NAME, &shaders::embedded_source(SHADER_NAME));
+
+        let _bind_group_layout = SHADER_INTERFACE.create_bind_group_layout(device, 0, "Bloom bindings");
+
+        let _pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor
+        {
+            label: None,
+            bind_group_layouts: &[&_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let (
+            compute_pipeline_prefilter,
+            compute_pipeline_downsample,
+            compute_pipeline_upsample,
+            compute_pipeline_composite
+        ) = Self::create_pipelines(device, &_pipeline_layout, &_shader);
+
+        let _sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let settings = BloomSettings::default();
+        let params_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Bloom params"),
+                contents: bytemuck::cast_slice(&[BloomParams::new(&settings)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let targets = Self::create_targets(device, &_bind_group_layout, &_sampler, &params_buffer, render_targets);
+
+        Self {
+            _shader,
+            _bind_group_layout,
+            _pipeline_layout,
+            compute_pipeline_prefilter,
+            compute_pipeline_downsample,
+            compute_pipeline_upsample,
+            compute_pipeline_composite,
+
+            _sampler,
+            params_buffer,
+            targets,
+
+            settings,
+            size: render_targets.size,
+        }
+    }
+
+    fn create_pipelines(
+        device: &Device,
+        pipeline_layout: &PipelineLayout,
+        shader: &ShaderModule
+    ) -> (ComputePipeline, ComputePipeline, ComputePipeline, ComputePipeline)
+    {
+        let create = |entry_point: &str| device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(entry_point),
+            layout: Some(pipeline_layout),
+            module: shader,
+            entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::COMPUTE, entry_point),
+        });
+        return (
+            create(COMPUTE_PREFILTER_ENTRY),
+            create(COMPUTE_DOWNSAMPLE_ENTRY),
+            create(COMPUTE_UPSAMPLE_ENTRY),
+            create(COMPUTE_COMPOSITE_ENTRY),
+        );
+    }
+
+    // Sizes of the bloom mips, halving from half the screen size down to at most 1 pixel.
+    pub fn mip_sizes(size: ScreenSize) -> Vec<ScreenSize>
+    {
+        let mut width = (size.width + 1) / 2;
+        let mut height = (size.height + 1) / 2;
+        let mut sizes = Vec::new();
+        while sizes.len() < MAX_BLOOM_MIPS as usize
+        {
+            // Not going through ScreenSize::new, the small mips are allowed below the minimum screen size.
+            sizes.push(ScreenSize { width, height });
+            if width == 1 || height == 1
+            {
+                break;
+            }
+            width /= 2;
+            height /= 2;
+        }
+        return sizes;
+    }
+
+    fn create_bloom_texture(device: &Device, label: &str, mip_sizes: &[ScreenSize]) -> Texture
+    {
+        return device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: mip_sizes[0].width,
+                height: mip_sizes[0].height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: mip_sizes.len() as u32,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: POST_PROCESS_FORMAT,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::STORAGE_BINDING,
+            label: Some(label),
+            view_formats: &[],
+        });
+    }
+
+    fn mip_views(texture: &Texture, mip_count: usize) -> Vec<TextureView>
+    {
+        return (0..mip_count as u32)
+            .map(|mip| texture.create_view(&wgpu::TextureViewDescriptor {
+                base_mip_level: mip,
+                mip_level_count: Some(1),
+                ..Default::default()
+            }))
+            .collect();
+    }
+
+    fn create_targets(
+        device: &Device,
+        bind_group_layout: &BindGroupLayout,
+        sampler: &Sampler,
+        params_buffer: &Buffer,
+        render_targets: &RenderTargets
+    ) -> BloomTargets
+    {
+        let mip_sizes = Self::mip_sizes(render_targets.size);
+        let mips = mip_sizes.len();
+        let _down_texture = Self::create_bloom_texture(device, "Bloom downsample", &mip_sizes);
+        let _up_texture = Self::create_bloom_texture(device, "Bloom upsample", &mip_sizes);
+        let down_views = Self::mip_views(&_down_texture, mips);
+        let up_views = Self::mip_views(&_up_texture, mips);
+
+        let bind_group = |input: &TextureView, output: &TextureView, bloom: &TextureView| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Bloom Bind Group"),
+                layout: bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(input) },
+                    wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(output) },
+                    wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::Sampler(sampler) },
+                    wgpu::BindGroupEntry { binding: 3, resource: params_buffer.as_entire_binding() },
+                    wgpu::BindGroupEntry { binding: 4, resource: wgpu::BindingResource::TextureView(bloom) },
+                ],
+            })
+        };
+
+        // The bloom binding is unused before upsampling, the first up mip is bound as it is never
+        // written at the same time.
+        let downsample_bind_groups = (1..mips)
+            .map(|mip| bind_group(&down_views[mip - 1], &down_views[mip], &up_views[0]))
+            .collect();
+        // The smallest mip is only downsampled, the upsampling starts from it.
+        let upsample_bind_groups = (0..mips - 1)
+            .map(|mip| {
+                let smaller = if mip + 2 == mips { &down_views[mip + 1] } else { &up_views[mip + 1] };
+                bind_group(smaller, &up_views[mip], &down_views[mip])
+            })
+            .collect();
+        let bloom_result = if mips == 1 { &down_views[0] } else { &up_views[0] };
+
+        let mut prefilter_bind_groups = Vec::new();
+        let mut composite_bind_groups = Vec::new();
+        for source in PingPong::BOTH
+        {
+            let (input_texture, output_texture) = render_targets.ping_pong_textures(source);
+            let input_view = input_texture.create_view(&wgpu::TextureViewDescriptor::default());
+            let output_view = output_texture.create_view(&wgpu::TextureViewDescriptor::default());
+            prefilter_bind_groups.push(bind_group(&input_view, &down_views[0], &up_views[0]));
+            composite_bind_groups.push(bind_group(&input_view, &output_view, bloom_result));
+        }
+
+        return BloomTargets {
+            _down_texture,
+            _up_texture,
+            mip_sizes,
+
+            prefilter_bind_groups,
+            composite_bind_groups,
+            downsample_bind_groups,
+            upsample_bind_groups,
+        };
+    }
+
+    pub fn settings(&self) -> BloomSettings
+    {
+        return self.settings;
+    }
+
+    pub fn set_settings(&mut self, settings: BloomSettings)
+    {
+        self.settings = settings;
+    }
+
+    pub fn update(&mut self, queue: &Queue)
+    {
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[BloomParams::new(&self.settings)]));
+    }
+
+    // Reads the scene from source and writes it with bloom into the other render target texture.
+    pub fn render(&mut self, encoder: &mut CommandEncoder, source: PingPong)
+    {
+        let targets = &self.targets;
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("Bloom") });
+
+        compute_pass.set_pipeline(&self.compute_pipeline_prefilter);
+        compute_pass.set_bind_group(0, &targets.prefilter_bind_groups[source.index()], &[]);
+        let (x, y, z) = targets.mip_sizes[0].workgroups_2d(8, 8);
+        compute_pass.dispatch_workgroups(x, y, z);
+
+        compute_pass.set_pipeline(&self.compute_pipeline_downsample);
+        for (mip, bind_group) in targets.downsample_bind_groups.iter().enumerate()
+        {
+            compute_pass.set_bind_group(0, bind_group, &[]);
+            let (x, y, z) = targets.mip_sizes[mip + 1].workgroups_2d(8, 8);
+            compute_pass.dispatch_workgroups(x, y, z);
+        }
+
+        compute_pass.set_pipeline(&self.compute_pipeline_upsample);
+        for (mip, bind_group) in targets.upsample_bind_groups.iter().enumerate().rev()
+        {
+            compute_pass.set_bind_group(0, bind_group, &[]);
+            let (x, y, z) = targets.mip_sizes[mip].workgroups_2d(8, 8);
+            compute_pass.dispatch_workgroups(x, y, z);
+        }
+
+        compute_pass.set_pipeline(&self.compute_pipeline_composite);
+        compute_pass.set_bind_group(0, &targets.composite_bind_groups[source.index()], &[]);
+        let (x, y, z) = self.size.workgroups_2d(8, 8);
+        compute_pass.dispatch_workgroups(x, y, z);
+    }
+}
+
+impl ScreenSizeDependent for TriangleSystem
+{
+    fn rebind(&mut self, device: &Device, render_targets: &RenderTargets)
+    {
+        self.targets = Self::create_targets(
+            device,
+            &self._bind_group_layout,
+            &self._sampler,
+            &self.params_buffer,
+            render_targets
+        );
+        self.size = render_targets.size;
+    }
+}
+
+impl ShaderReloadable for TriangleSystem
+{
+    fn shader_name(&self) -> &'static str
+    {
+        return SHADER_NAME;
+    }
+
+    fn reload_shader(&mut self, device: &Device, source: &str) -> Result<(), String>
+    {
+        let (shader, pipelines) = shaders::validated(device, || {
+            let shader = shaders::create_shader_module(device, SHADER_NAME, source);
+            let pipelines = Self::create_pipelines(device, &self._pipeline_layout, &shader);
+            (shader, pipelines)
+        })?;
+        self._shader = shader;
+        (
+            self.compute_pipeline_prefilter,
+            self.compute_pipeline_downsample,
+            self.compute_pipeline_upsample,
+            self.compute_pipeline_composite
+        ) = pipelines;
+        return Ok(());
+    }
+}
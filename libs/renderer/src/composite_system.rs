@@ -0,0 +1,296 @@
+use std::num::NonZeroU32;
+
+use wgpu::*;
+use wgpu::util::DeviceExt;
+
+use crate::render_targets::{PingPong, RenderTargets, ScreenSize, ScreenSizeDependent};
+use crate::shaders::{self, ShaderInterface, ShaderReloadable};
+
+const SHADER_NAME: &str = "composite.wgsl";
+const COMPUTE_ENTRY: &str = "main";
+
+// How fast the upsampling weight of a low resolution texel falls off with its relative
+// depth difference to the pixel.
+const DEPTH_SHARPNESS: f32 = 50.0;
+
+const fn unfilterable_texture(binding: u32) -> wgpu::BindGroupLayoutEntry
+{
+    return wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    };
+}
+
+const BIND_GROUP_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] = &[
+    // Input, the hdr scene
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    },
+    // Output
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::StorageTexture {
+            view_dimension: wgpu::TextureViewDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            access: wgpu::StorageTextureAccess::WriteOnly,
+        },
+        count: None,
+    },
+    // Linear depth, ambient occlusion and fog
+    unfilterable_texture(2),
+    unfilterable_texture(3),
+    unfilterable_texture(4),
+    // Params
+    wgpu::BindGroupLayoutEntry {
+        binding: 5,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+];
+
+pub(crate) const SHADER_INTERFACE: ShaderInterface = ShaderInterface {
+    shader_name: SHADER_NAME,
+    entry_points: &[(wgpu::ShaderStages::COMPUTE, COMPUTE_ENTRY)],
+    bind_group_layouts: &[BIND_GROUP_LAYOUT_ENTRIES],
+    vertex_attributes: &[],
+    defines: &[],
+};
+
+// Shared with composite.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct CompositeParams
+{
+    pub(crate) apply_ao: u32,
+    pub(crate) apply_fog: u32,
+    pub(crate) depth_sharpness: f32,
+    pub(crate) _padding: f32,
+}
+
+impl CompositeParams
+{
+    fn new(apply_ao: bool, apply_fog: bool) -> Self
+    {
+        Self
+        {
+            apply_ao: apply_ao as u32,
+            apply_fog: apply_fog as u32,
+            depth_sharpness: DEPTH_SHARPNESS,
+            _padding: 0.0,
+        }
+    }
+}
+
+// Upsamples the ambient occlusion and fog and applies them to the hdr scene, writing the
+// result into the other render target texture.
+pub struct TriangleSystem
+{
+    _shader: ShaderModule,
+    _bind_group_layout: BindGroupLayout,
+    _pipeline_layout: PipelineLayout,
+    compute_pipeline: ComputePipeline,
+    // One per PingPong source.
+    bind_groups: Vec<BindGroup>,
+
+    params_buffer: Buffer,
+
+    pub(crate) size: ScreenSize,
+}
+
+impl TriangleSystem
+{
+    pub fn new(device: &Device, render_targets: &RenderTargets) -> Self
+    {
+        let _shader = shaders::create_shader_module(device, SHADER_NAME, &shaders::embedded_source(SHADER_NAME));
+
+        let _bind_group_layout = SHADER_INTERFACE.create_bind_group_layout(device, 0, "Composite bindings");
+
+        let _pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor
+        {
+            label: None,
+            bind_group_layouts: &[&_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let compute_pipeline = Self::create_pipeline(device, &_pipeline_layout, &_shader);
+
+        let params_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Composite params"),
+                contents: bytemuck::cast_slice(&[CompositeParams::new(false, false)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let bind_groups = Self::create_bind_groups(device, &_bind_group_layout, render_targets, &params_buffer);
+
+        Self {
+            _shader,
+            _bind_group_layout,
+            _pipeline_layout,
+            compute_pipeline,
+            bind_groups,
+
+            params_buffer,
+
+            size: render_targets.size,
+        }
+    }
+
+    fn create_pipeline(device: &Device, pipeline_layout: &PipelineLayout, shader: &ShaderModule) -> ComputePipeline
+    {
+        return device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Composite"),
+            layout: Some(pipeline_layout),
+            module: shader,
+            entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::COMPUTE, COMPUTE_ENTRY),
+        });
+    }
+
+    pub fn rebind_textures(&mut self, device: &Device, render_targets: &RenderTargets)
+    {
+        self.bind_groups =
+            Self::create_bind_groups(device, &self._bind_group_layout, render_targets, &self.params_buffer);
+        self.size = render_targets.size;
+    }
+
+    pub fn update(&mut self, queue: &Queue, apply_ao: bool, apply_fog: bool)
+    {
+        let params = CompositeParams::new(apply_ao, apply_fog);
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+    }
+
+    pub fn dispatch_size(size: ScreenSize) -> (u32, u32, u32)
+    {
+        return size.workgroups_2d(8, 8);
+    }
+
+    pub fn render(&mut self, encoder: &mut CommandEncoder, source: PingPong)
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("Composite") });
+        compute_pass.set_pipeline(&self.compute_pipeline);
+        compute_pass.set_bind_group(0, &self.bind_groups[source.index()], &[]);
+        let (x, y, z) = Self::dispatch_size(self.size);
+        compute_pass.dispatch_workgroups(x, y, z);
+    }
+
+    fn create_bind_groups(
+        device: &Device,
+        bind_group_layout: &BindGroupLayout,
+        render_targets: &RenderTargets,
+        params_buffer: &Buffer,
+    ) -> Vec<BindGroup>
+    {
+        return PingPong::BOTH
+            .iter()
+            .map(|&source| {
+                let (input_texture, output_texture) = render_targets.ping_pong_textures(source);
+                Self::create_bind_group(device, bind_group_layout, render_targets, input_texture, output_texture, params_buffer)
+            })
+            .collect();
+    }
+
+    fn create_bind_group(
+        device: &Device,
+        bind_group_layout: &BindGroupLayout,
+        render_targets: &RenderTargets,
+        input_texture: &Texture,
+        output_texture: &Texture,
+        params_buffer: &Buffer,
+    ) -> BindGroup
+    {
+        let _input_view = input_texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some(&format!("Composite input view")),
+            format: Some(input_texture.format()),
+            base_mip_level: 0,
+            mip_level_count: Some(NonZeroU32::try_from(1u32).unwrap().into()),
+            ..Default::default()
+        });
+
+        let _output_view = output_texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some(&format!("Composite output view")),
+            format: Some(output_texture.format()),
+            base_mip_level: 0,
+            mip_level_count: Some(NonZeroU32::try_from(1u32).unwrap().into()),
+            ..Default::default()
+        });
+
+        return device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Composite Bind Group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&_input_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&_output_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&render_targets.linear_depth_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&render_targets.ao_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&render_targets.fog_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+    }
+}
+
+impl ScreenSizeDependent for TriangleSystem
+{
+    fn rebind(&mut self, device: &Device, render_targets: &RenderTargets)
+    {
+        self.rebind_textures(device, render_targets);
+    }
+}
+
+impl ShaderReloadable for TriangleSystem
+{
+    fn shader_name(&self) -> &'static str
+    {
+        return SHADER_NAME;
+    }
+
+    fn reload_shader(&mut self, device: &Device, source: &str) -> Result<(), String>
+    {
+        let (shader, compute_pipeline) = shaders::validated(device, || {
+            let shader = shaders::create_shader_module(device, SHADER_NAME, source);
+            let compute_pipeline = Self::create_pipeline(device, &self._pipeline_layout, &shader);
+            (shader, compute_pipeline)
+        })?;
+        self._shader = shader;
+        self.compute_pipeline = compute_pipeline;
+        return Ok(());
+    }
+}
@@ -1,9 +1,51 @@
-use std::borrow::Cow;
 use std::num::NonZeroU32;
 
 use wgpu::*;
 use wgpu::BindingResource::Buffer;
 
+use crate::render_targets::{RenderTargets, ScreenSize, ScreenSizeDependent};
+use crate::shaders::{self, ShaderInterface, ShaderReloadable};
+
+const SHADER_NAME: &str = "compute_copy_vertices.wgsl";
+const COMPUTE_ENTRY: &str = "main";
+const COMPUTE_RESET_ENTRY: &str = "main_reset";
+
+const BIND_GROUP_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] = &[
+    // Input
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: false },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+    // Output
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: false },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+];
+
+pub(crate) const SHADER_INTERFACE: ShaderInterface = ShaderInterface {
+    shader_name: SHADER_NAME,
+    entry_points: &[
+        (wgpu::ShaderStages::COMPUTE, COMPUTE_ENTRY),
+        (wgpu::ShaderStages::COMPUTE, COMPUTE_RESET_ENTRY),
+    ],
+    bind_group_layouts: &[BIND_GROUP_LAYOUT_ENTRIES],
+    vertex_attributes: &[],
+    defines: &[],
+};
+
 pub struct TriangleSystem
 {
     _shader: ShaderModule,
@@ -13,23 +55,12 @@ pub struct TriangleSystem
     compute_pipeline_reset: ComputePipeline,
     bind_group: BindGroup,
 
-    texture_width: u32,
-    texture_height: u32,
+    pub(crate) size: ScreenSize,
 
     index_buffer: wgpu::Buffer,
     vertex_buffer: wgpu::Buffer,
 }
 
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct MeshGpu
-{
-    vertices_start_index: u32,
-    vertices_count: u32,
-    indices_start_index: u32,
-    indices_count: u32,
-}
-
 impl TriangleSystem
 {
     pub fn new(
@@ -38,13 +69,7 @@ impl TriangleSystem
         output_texture: &wgpu::Texture
     ) -> Self
     {
-        // Load the shaders from disk
-        let _shader = device.create_shader_module(wgpu::ShaderModuleDescriptor
-        {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(
-                Cow::Borrowed(include_str!("../../../data/shaders/compute_copy_vertices.wgsl"))),
-        });
+        let _shader = shaders::create_shader_module(device, SHADER_NAME, &shaders::embedded_source(SHADER_NAME));
 
         let index_buffer = device.create_buffer(
             &wgpu::BufferDescriptor {
@@ -64,33 +89,7 @@ impl TriangleSystem
             }
         );
 
-        let _bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("Compute bindings"),
-            entries: &[
-                // Input
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                // Output
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-            ],
-        });
+        let _bind_group_layout = SHADER_INTERFACE.create_bind_group_layout(device, 0, "Compute bindings");
 
         let _pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor
         {
@@ -99,28 +98,8 @@ impl TriangleSystem
             push_constant_ranges: &[],
         });
 
-        // Instantiates the pipeline.
-        let compute_pipeline = device.create_compute_pipeline(
-            &wgpu::ComputePipelineDescriptor {
-                label: None,
-                layout: Some(&_pipeline_layout),
-                module: &_shader,
-                entry_point: "main",
-            }
-        );
-        // Instantiates the pipeline.
-        let compute_pipeline_reset = device.create_compute_pipeline(
-            &wgpu::ComputePipelineDescriptor {
-                label: None,
-                layout: Some(&_pipeline_layout),
-                module: &_shader,
-                entry_point: "main_reset",
-            }
-        );
-
-
-
-
+        let (compute_pipeline, compute_pipeline_reset) =
+            Self::create_pipelines(device, &_pipeline_layout, &_shader);
 
         let bind_group =
             Self::create_bind_group(
@@ -140,15 +119,41 @@ impl TriangleSystem
             compute_pipeline_reset,
             bind_group,
 
-            texture_width: output_texture.width(),
-            texture_height: output_texture.height(),
+            size: ScreenSize::new(output_texture.width(), output_texture.height()),
 
             index_buffer,
             vertex_buffer,
         }
     }
+    fn create_pipelines(
+        device: &Device,
+        pipeline_layout: &PipelineLayout,
+        shader: &ShaderModule
+    ) -> (ComputePipeline, ComputePipeline)
+    {
+        // Instantiates the pipeline.
+        let compute_pipeline = device.create_compute_pipeline(
+            &wgpu::ComputePipelineDescriptor {
+                label: None,
+                layout: Some(pipeline_layout),
+                module: shader,
+                entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::COMPUTE, COMPUTE_ENTRY),
+            }
+        );
+        // Instantiates the pipeline.
+        let compute_pipeline_reset = device.create_compute_pipeline(
+            &wgpu::ComputePipelineDescriptor {
+                label: None,
+                layout: Some(pipeline_layout),
+                module: shader,
+                entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::COMPUTE, COMPUTE_RESET_ENTRY),
+            }
+        );
+        return (compute_pipeline, compute_pipeline_reset);
+    }
     pub fn rebind_textures(&mut self,
        device: &Device,
+       output_texture: &wgpu::Texture
     )
     {
         let bind_group =
@@ -159,6 +164,12 @@ impl TriangleSystem
                 &self.vertex_buffer,
             );
         self.bind_group = bind_group;
+        self.size = ScreenSize::new(output_texture.width(), output_texture.height());
+    }
+
+    pub fn dispatch_size(size: ScreenSize) -> (u32, u32, u32)
+    {
+        return size.workgroups_1d(64);
     }
     pub fn render(&mut self, encoder: &mut CommandEncoder)//, view: &TextureView)
     {
@@ -174,13 +185,8 @@ impl TriangleSystem
             compute_pass2.set_pipeline(&self.compute_pipeline);
             compute_pass2.set_bind_group(0, &self.bind_group, &[]);
             compute_pass2.insert_debug_marker("Compute testing");
-            compute_pass2.dispatch_workgroups(
-                (self.texture_width * self.texture_height + 63) / 64,
-//                (self.texture_width + 7) / 8,
-//                (self.texture_height + 7) / 8,
-                1,
-                1
-            );
+            let (x, y, z) = Self::dispatch_size(self.size);
+            compute_pass2.dispatch_workgroups(x, y, z);
         }
 
     }
@@ -213,4 +219,33 @@ impl TriangleSystem
         });
         return bind_group;
     }
-}
\ No newline at end of file
+}
+
+impl ScreenSizeDependent for TriangleSystem
+{
+    fn rebind(&mut self, device: &Device, render_targets: &RenderTargets)
+    {
+        self.rebind_textures(device, &render_targets.render_target_texture2);
+    }
+}
+
+impl ShaderReloadable for TriangleSystem
+{
+    fn shader_name(&self) -> &'static str
+    {
+        return SHADER_NAME;
+    }
+
+    fn reload_shader(&mut self, device: &Device, source: &str) -> Result<(), String>
+    {
+        let (shader, (compute_pipeline, compute_pipeline_reset)) = shaders::validated(device, || {
+            let shader = shaders::create_shader_module(device, SHADER_NAME, source);
+            let pipelines = Self::create_pipelines(device, &self._pipeline_layout, &shader);
+            (shader, pipelines)
+        })?;
+        self._shader = shader;
+        self.compute_pipeline = compute_pipeline;
+        self.compute_pipeline_reset = compute_pipeline_reset;
+        return Ok(());
+    }
+}
@@ -0,0 +1,350 @@
+use std::mem::size_of;
+
+use wgpu::*;
+use wgpu::util::DeviceExt;
+
+use crate::debug_text_system::srgb_to_linear;
+use crate::depth_system::{self, ViewParams};
+use crate::render_targets::{PingPong, RenderTargets, ScreenSize, ScreenSizeDependent, POST_PROCESS_FORMAT};
+use crate::shaders::{self, ShaderInterface, ShaderReloadable};
+use crate::triangle_system_camera_vertices::CameraUniform;
+
+const SHADER_NAME: &str = "debug_draw.wgsl";
+const VERTEX_ENTRY: &str = "vs_main";
+const FRAGMENT_ENTRY: &str = "fs_main";
+
+// Lines drawn per frame, the rest are dropped.
+const MAX_LINES: usize = 64 * 1024;
+
+const BIND_GROUP_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] = &[
+    // Camera
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::VERTEX,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+    // View
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::VERTEX,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+    // Linear depth
+    wgpu::BindGroupLayoutEntry {
+        binding: 2,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    },
+];
+
+pub(crate) const SHADER_INTERFACE: ShaderInterface = ShaderInterface {
+    shader_name: SHADER_NAME,
+    entry_points: &[
+        (wgpu::ShaderStages::VERTEX, VERTEX_ENTRY),
+        (wgpu::ShaderStages::FRAGMENT, FRAGMENT_ENTRY),
+    ],
+    bind_group_layouts: &[BIND_GROUP_LAYOUT_ENTRIES],
+    vertex_attributes: LineVertex::ATTRIBUTES,
+    defines: &[],
+};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LineVertex
+{
+    position: [f32; 3],
+    depth_test: u32,
+    color: [f32; 4],
+}
+
+impl LineVertex
+{
+    const ATTRIBUTES: &'static [wgpu::VertexAttribute] = &[
+        wgpu::VertexAttribute {
+            offset: 0,
+            shader_location: 0,
+            format: wgpu::VertexFormat::Float32x3,
+        },
+        wgpu::VertexAttribute {
+            offset: 12,
+            shader_location: 1,
+            format: wgpu::VertexFormat::Uint32,
+        },
+        wgpu::VertexAttribute {
+            offset: 16,
+            shader_location: 2,
+            format: wgpu::VertexFormat::Float32x4,
+        },
+    ];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static>
+    {
+        return SHADER_INTERFACE.vertex_buffer_layout(
+            size_of::<LineVertex>() as wgpu::BufferAddress, wgpu::VertexStepMode::Vertex);
+    }
+}
+
+// Draws the debug lines from the game state into the post processed image, before it is
+// scaled to the back buffer.
+pub struct TriangleSystem
+{
+    _shader: ShaderModule,
+    _bind_group_layout: BindGroupLayout,
+    _pipeline_layout: PipelineLayout,
+    render_pipeline: RenderPipeline,
+    bind_group: BindGroup,
+    // One per PingPong source.
+    target_views: Vec<TextureView>,
+    pub(crate) size: ScreenSize,
+
+    camera_buffer: Buffer,
+    view_buffer: Buffer,
+    vertex_buffer: Buffer,
+    vertex_count: u32,
+    depth_tested: bool,
+}
+
+impl TriangleSystem
+{
+    pub fn new(device: &Device, render_targets: &RenderTargets) -> Self
+    {
+        let _shader = shaders::create_shader_module(device, SHADER_NAME, &shaders::embedded_source(SHADER_NAME));
+
+        let _bind_group_layout = SHADER_INTERFACE.create_bind_group_layout(device, 0, "Debug draw bindings");
+
+        let _pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor
+        {
+            label: None,
+            bind_group_layouts: &[&_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let render_pipeline = Self::create_pipeline(device, &_pipeline_layout, &_shader);
+
+        let camera_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Debug draw camera"),
+                contents: bytemuck::cast_slice(&[CameraUniform::new()]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let view_buffer = depth_system::create_view_buffer(device, "Debug draw view");
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Debug draw lines"),
+            size: (MAX_LINES * 2 * size_of::<LineVertex>()) as BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = Self::create_bind_group(device, &_bind_group_layout, render_targets, &camera_buffer, &view_buffer);
+
+        Self {
+            _shader,
+            _bind_group_layout,
+            _pipeline_layout,
+            render_pipeline,
+            bind_group,
+            target_views: Self::create_target_views(render_targets),
+            size: render_targets.size,
+
+            camera_buffer,
+            view_buffer,
+            vertex_buffer,
+            vertex_count: 0,
+            depth_tested: false,
+        }
+    }
+
+    fn create_pipeline(device: &Device, pipeline_layout: &PipelineLayout, shader: &ShaderModule) -> RenderPipeline
+    {
+        return device.create_render_pipeline(&wgpu::RenderPipelineDescriptor
+        {
+            label: Some("Debug draw"),
+            layout: Some(pipeline_layout),
+            vertex: wgpu::VertexState
+            {
+                module: shader,
+                entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::VERTEX, VERTEX_ENTRY),
+                buffers: &[LineVertex::desc()],
+            },
+            fragment: Some(wgpu::FragmentState
+            {
+                module: shader,
+                entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::FRAGMENT, FRAGMENT_ENTRY),
+                // Both ping-pong textures have this format.
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: POST_PROCESS_FORMAT,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState
+            {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+    }
+
+    pub fn rebind_textures(&mut self, device: &Device, render_targets: &RenderTargets)
+    {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self._bind_group_layout,
+            render_targets,
+            &self.camera_buffer,
+            &self.view_buffer);
+        self.target_views = Self::create_target_views(render_targets);
+        self.size = render_targets.size;
+    }
+
+    // Depth tested lines read the linear depth, which is otherwise only made for ssao and fog.
+    pub fn needs_linear_depth(&self) -> bool
+    {
+        return self.depth_tested;
+    }
+
+    pub fn update(&mut self, camera: &common::Camera, queue: &Queue, debug_draw: &common::DebugDraw)
+    {
+        let lines = &debug_draw.lines()[..debug_draw.lines().len().min(MAX_LINES)];
+        self.vertex_count = lines.len() as u32 * 2;
+        self.depth_tested = lines.iter().any(|line| line.depth_test);
+        if lines.is_empty()
+        {
+            return;
+        }
+
+        let mut camera_uniform = CameraUniform::new();
+        camera_uniform.update_view_proj(camera);
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[camera_uniform]));
+        queue.write_buffer(&self.view_buffer, 0, bytemuck::cast_slice(&[ViewParams::new(camera)]));
+
+        let vertices: Vec<LineVertex> = lines
+            .iter()
+            .flat_map(|line| {
+                // The post processed image is linear, the colors are sRGB.
+                let color = srgb_to_linear(line.color);
+                let depth_test = line.depth_test as u32;
+                [
+                    LineVertex { position: line.start.to_array(), depth_test, color },
+                    LineVertex { position: line.end.to_array(), depth_test, color },
+                ]
+            })
+            .collect();
+        queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+    }
+
+    // Draws into the render target texture the post processing ended in.
+    pub fn render(&mut self, encoder: &mut CommandEncoder, source: PingPong)
+    {
+        if self.vertex_count == 0
+        {
+            return;
+        }
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor
+        {
+            label: Some("Debug draw"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment
+            {
+                view: &self.target_views[source.index()],
+                resolve_target: None,
+                ops: wgpu::Operations
+                {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_viewport(0.0, 0.0, self.size.width as f32, self.size.height as f32, 0.0, 1.0);
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.draw(0..self.vertex_count, 0..1);
+    }
+
+    fn create_target_views(render_targets: &RenderTargets) -> Vec<TextureView>
+    {
+        return PingPong::BOTH
+            .iter()
+            .map(|&source| {
+                let (input_texture, _) = render_targets.ping_pong_textures(source);
+                input_texture.create_view(&wgpu::TextureViewDescriptor::default())
+            })
+            .collect();
+    }
+
+    fn create_bind_group(
+        device: &Device,
+        bind_group_layout: &BindGroupLayout,
+        render_targets: &RenderTargets,
+        camera_buffer: &Buffer,
+        view_buffer: &Buffer,
+    ) -> BindGroup
+    {
+        return device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Debug draw Bind Group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: view_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&render_targets.linear_depth_texture_view),
+                },
+            ],
+        });
+    }
+}
+
+impl ScreenSizeDependent for TriangleSystem
+{
+    fn rebind(&mut self, device: &Device, render_targets: &RenderTargets)
+    {
+        self.rebind_textures(device, render_targets);
+    }
+}
+
+impl ShaderReloadable for TriangleSystem
+{
+    fn shader_name(&self) -> &'static str
+    {
+        return SHADER_NAME;
+    }
+
+    fn reload_shader(&mut self, device: &Device, source: &str) -> Result<(), String>
+    {
+        let (shader, render_pipeline) = shaders::validated(device, || {
+            let shader = shaders::create_shader_module(device, SHADER_NAME, source);
+            let render_pipeline = Self::create_pipeline(device, &self._pipeline_layout, &shader);
+            (shader, render_pipeline)
+        })?;
+        self._shader = shader;
+        self.render_pipeline = render_pipeline;
+        return Ok(());
+    }
+}
@@ -0,0 +1,497 @@
+use std::mem::size_of;
+
+use wgpu::*;
+use wgpu::util::DeviceExt;
+
+use crate::render_targets::ScreenSize;
+use crate::shaders::{self, ShaderInterface, ShaderReloadable};
+
+const SHADER_NAME: &str = "debug_text.wgsl";
+const VERTEX_ENTRY: &str = "vs_main";
+const FRAGMENT_ENTRY: &str = "fs_main";
+
+// Characters drawn per frame, the rest are dropped.
+const MAX_GLYPHS: usize = 16 * 1024;
+const GLYPH_SIZE: u32 = 8;
+// The atlas has the printable ascii characters 32..128 in 16 columns and 6 rows.
+const FIRST_GLYPH: u8 = b' ';
+const ATLAS_COLUMNS: u32 = 16;
+const ATLAS_ROWS: u32 = 6;
+// Advances in font pixels. The glyphs have an empty last column, the lines get extra room for
+// the shadow.
+const GLYPH_ADVANCE: f32 = GLYPH_SIZE as f32;
+const LINE_ADVANCE: f32 = GLYPH_SIZE as f32 + 2.0;
+
+const BIND_GROUP_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] = &[
+    // Font atlas
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    },
+    // Params
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::VERTEX,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+];
+
+pub(crate) const SHADER_INTERFACE: ShaderInterface = ShaderInterface {
+    shader_name: SHADER_NAME,
+    entry_points: &[
+        (wgpu::ShaderStages::VERTEX, VERTEX_ENTRY),
+        (wgpu::ShaderStages::FRAGMENT, FRAGMENT_ENTRY),
+    ],
+    bind_group_layouts: &[BIND_GROUP_LAYOUT_ENTRIES],
+    vertex_attributes: GlyphInstance::ATTRIBUTES,
+    defines: &[],
+};
+
+// Shared with debug_text.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct DebugTextParams
+{
+    pub(crate) inv_screen_size: [f32; 2],
+    pub(crate) scale: f32,
+    pub(crate) _padding: f32,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct GlyphInstance
+{
+    position: [f32; 2],
+    glyph: u32,
+    _padding: u32,
+    color: [f32; 4],
+}
+
+impl GlyphInstance
+{
+    const ATTRIBUTES: &'static [wgpu::VertexAttribute] = &[
+        wgpu::VertexAttribute {
+            offset: 0,
+            shader_location: 0,
+            format: wgpu::VertexFormat::Float32x2,
+        },
+        wgpu::VertexAttribute {
+            offset: 8,
+            shader_location: 1,
+            format: wgpu::VertexFormat::Uint32,
+        },
+        wgpu::VertexAttribute {
+            offset: 16,
+            shader_location: 2,
+            format: wgpu::VertexFormat::Float32x4,
+        },
+    ];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static>
+    {
+        return SHADER_INTERFACE.vertex_buffer_layout(
+            size_of::<GlyphInstance>() as wgpu::BufferAddress, wgpu::VertexStepMode::Instance);
+    }
+}
+
+// Draws the debug overlay text from the game state over the back buffer, after the blit.
+pub struct TriangleSystem
+{
+    _shader: ShaderModule,
+    _bind_group_layout: BindGroupLayout,
+    _pipeline_layout: PipelineLayout,
+    render_pipeline: RenderPipeline,
+    bind_group: BindGroup,
+
+    _font_texture: Texture,
+    instance_buffer: Buffer,
+    params_buffer: Buffer,
+    glyph_count: u32,
+    // Screen pixels per font pixel.
+    scale: u32,
+
+    texture_format: TextureFormat,
+}
+
+impl TriangleSystem
+{
+    pub fn new(device: &Device, queue: &Queue, texture_format: TextureFormat) -> Self
+    {
+        let _shader = shaders::create_shader_module(device, SHADER_NAME, &shaders::embedded_source(SHADER_NAME));
+
+        let _bind_group_layout = SHADER_INTERFACE.create_bind_group_layout(device, 0, "Debug text bindings");
+
+        let _pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor
+        {
+            label: None,
+            bind_group_layouts: &[&_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let render_pipeline = Self::create_pipeline(device, &_pipeline_layout, &_shader, texture_format);
+
+        let _font_texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("Debug font atlas"),
+                size: wgpu::Extent3d {
+                    width: ATLAS_COLUMNS * GLYPH_SIZE,
+                    height: ATLAS_ROWS * GLYPH_SIZE,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::R8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            &font_atlas(),
+        );
+        let font_view = _font_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Debug text glyphs"),
+            size: (MAX_GLYPHS * size_of::<GlyphInstance>()) as BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let params_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Debug text params"),
+                contents: bytemuck::cast_slice(&[DebugTextParams { inv_screen_size: [1.0, 1.0], scale: 1.0, _padding: 0.0 }]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Debug text Bind Group"),
+            layout: &_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&font_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            _shader,
+            _bind_group_layout,
+            _pipeline_layout,
+            render_pipeline,
+            bind_group,
+
+            _font_texture,
+            instance_buffer,
+            params_buffer,
+            glyph_count: 0,
+            scale: 2,
+
+            texture_format,
+        }
+    }
+
+    fn create_pipeline(
+        device: &Device,
+        pipeline_layout: &PipelineLayout,
+        shader: &ShaderModule,
+        texture_format: TextureFormat
+    ) -> RenderPipeline
+    {
+        return device.create_render_pipeline(&wgpu::RenderPipelineDescriptor
+        {
+            label: Some("Debug text"),
+            layout: Some(pipeline_layout),
+            vertex: wgpu::VertexState
+            {
+                module: shader,
+                entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::VERTEX, VERTEX_ENTRY),
+                buffers: &[GlyphInstance::desc()],
+            },
+            fragment: Some(wgpu::FragmentState
+            {
+                module: shader,
+                entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::FRAGMENT, FRAGMENT_ENTRY),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: texture_format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+    }
+
+    pub fn scale(&self) -> u32
+    {
+        return self.scale;
+    }
+
+    pub fn set_scale(&mut self, scale: u32)
+    {
+        self.scale = scale.max(1);
+    }
+
+    // For a swapchain format change, the pipeline drawing to the back buffer is recreated.
+    pub fn set_texture_format(&mut self, device: &Device, texture_format: TextureFormat)
+    {
+        if texture_format == self.texture_format
+        {
+            return;
+        }
+        self.texture_format = texture_format;
+        self.render_pipeline = Self::create_pipeline(device, &self._pipeline_layout, &self._shader, texture_format);
+    }
+
+    pub fn update(&mut self, queue: &Queue, overlay: &common::DebugOverlay, display_size: ScreenSize)
+    {
+        let scale = self.scale as f32;
+        // Colors are sRGB, an sRGB back buffer expects linear values.
+        let linear = self.texture_format.is_srgb();
+        let mut glyphs = Vec::new();
+        'texts: for text in overlay.texts()
+        {
+            let color = if linear { srgb_to_linear(text.color) } else { text.color };
+            for (line_index, line) in text.text.split('\n').enumerate()
+            {
+                let y = text.y + line_index as f32 * LINE_ADVANCE * scale;
+                for (column, character) in line.chars().enumerate()
+                {
+                    if character == ' '
+                    {
+                        continue;
+                    }
+                    if glyphs.len() == MAX_GLYPHS
+                    {
+                        break 'texts;
+                    }
+                    glyphs.push(GlyphInstance
+                    {
+                        position: [text.x + column as f32 * GLYPH_ADVANCE * scale, y],
+                        glyph: glyph_index(character),
+                        _padding: 0,
+                        color,
+                    });
+                }
+            }
+        }
+
+        self.glyph_count = glyphs.len() as u32;
+        if glyphs.is_empty()
+        {
+            return;
+        }
+        queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&glyphs));
+        let params = DebugTextParams
+        {
+            inv_screen_size: [1.0 / display_size.width.max(1) as f32, 1.0 / display_size.height.max(1) as f32],
+            scale,
+            _padding: 0.0,
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+    }
+
+    pub fn render(&mut self, encoder: &mut CommandEncoder, view: &TextureView)
+    {
+        if self.glyph_count == 0
+        {
+            return;
+        }
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor
+        {
+            label: Some("Debug text"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment
+            {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations
+                {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.instance_buffer.slice(..));
+        render_pass.draw(0..6, 0..self.glyph_count);
+    }
+}
+
+impl ShaderReloadable for TriangleSystem
+{
+    fn shader_name(&self) -> &'static str
+    {
+        return SHADER_NAME;
+    }
+
+    fn reload_shader(&mut self, device: &Device, source: &str) -> Result<(), String>
+    {
+        let (shader, render_pipeline) = shaders::validated(device, || {
+            let shader = shaders::create_shader_module(device, SHADER_NAME, source);
+            let render_pipeline = Self::create_pipeline(device, &self._pipeline_layout, &shader, self.texture_format);
+            (shader, render_pipeline)
+        })?;
+        self._shader = shader;
+        self.render_pipeline = render_pipeline;
+        return Ok(());
+    }
+}
+
+// Characters outside of the atlas are drawn as '?'.
+fn glyph_index(character: char) -> u32
+{
+    let code = character as u32;
+    let code = if (FIRST_GLYPH as u32..FIRST_GLYPH as u32 + FONT.len() as u32).contains(&code) { code } else { '?' as u32 };
+    return code - FIRST_GLYPH as u32;
+}
+
+pub(crate) fn srgb_to_linear(color: [f32; 4]) -> [f32; 4]
+{
+    let channel = |c: f32| if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) };
+    return [channel(color[0]), channel(color[1]), channel(color[2]), color[3]];
+}
+
+// One byte per texel, 255 where the font has a pixel.
+fn font_atlas() -> Vec<u8>
+{
+    let width = (ATLAS_COLUMNS * GLYPH_SIZE) as usize;
+    let mut atlas = vec![0u8; width * (ATLAS_ROWS * GLYPH_SIZE) as usize];
+    for (index, rows) in FONT.iter().enumerate()
+    {
+        let cell_x = (index as u32 % ATLAS_COLUMNS * GLYPH_SIZE) as usize;
+        let cell_y = (index as u32 / ATLAS_COLUMNS * GLYPH_SIZE) as usize;
+        for (y, row) in rows.iter().enumerate()
+        {
+            for x in 0..GLYPH_SIZE as usize
+            {
+                if row & (1 << x) != 0
+                {
+                    atlas[(cell_y + y) * width + cell_x + x] = 255;
+                }
+            }
+        }
+    }
+    return atlas;
+}
+
+// 8x8 font for the ascii characters 32..128 from the public domain font8x8 by Daniel Hepper,
+// based on the IBM PC BIOS font. One byte per row, the lowest bit is the leftmost pixel.
+const FONT: [[u8; 8]; 96] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // space
+    [0x18, 0x3C, 0x3C, 0x18, 0x18, 0x00, 0x18, 0x00], // '!'
+    [0x36, 0x36, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x36, 0x36, 0x7F, 0x36, 0x7F, 0x36, 0x36, 0x00], // '#'
+    [0x0C, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x0C, 0x00], // '$'
+    [0x00, 0x63, 0x33, 0x18, 0x0C, 0x66, 0x63, 0x00], // '%'
+    [0x1C, 0x36, 0x1C, 0x6E, 0x3B, 0x33, 0x6E, 0x00], // '&'
+    [0x06, 0x06, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00], // '''
+    [0x18, 0x0C, 0x06, 0x06, 0x06, 0x0C, 0x18, 0x00], // '('
+    [0x06, 0x0C, 0x18, 0x18, 0x18, 0x0C, 0x06, 0x00], // ')'
+    [0x00, 0x66, 0x3C, 0xFF, 0x3C, 0x66, 0x00, 0x00], // '*'
+    [0x00, 0x0C, 0x0C, 0x3F, 0x0C, 0x0C, 0x00, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x06], // ','
+    [0x00, 0x00, 0x00, 0x3F, 0x00, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x00], // '.'
+    [0x60, 0x30, 0x18, 0x0C, 0x06, 0x03, 0x01, 0x00], // '/'
+    [0x3E, 0x63, 0x73, 0x7B, 0x6F, 0x67, 0x3E, 0x00], // '0'
+    [0x0C, 0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x3F, 0x00], // '1'
+    [0x1E, 0x33, 0x30, 0x1C, 0x06, 0x33, 0x3F, 0x00], // '2'
+    [0x1E, 0x33, 0x30, 0x1C, 0x30, 0x33, 0x1E, 0x00], // '3'
+    [0x38, 0x3C, 0x36, 0x33, 0x7F, 0x30, 0x78, 0x00], // '4'
+    [0x3F, 0x03, 0x1F, 0x30, 0x30, 0x33, 0x1E, 0x00], // '5'
+    [0x1C, 0x06, 0x03, 0x1F, 0x33, 0x33, 0x1E, 0x00], // '6'
+    [0x3F, 0x33, 0x30, 0x18, 0x0C, 0x0C, 0x0C, 0x00], // '7'
+    [0x1E, 0x33, 0x33, 0x1E, 0x33, 0x33, 0x1E, 0x00], // '8'
+    [0x1E, 0x33, 0x33, 0x3E, 0x30, 0x18, 0x0E, 0x00], // '9'
+    [0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x00], // ':'
+    [0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x06], // ';'
+    [0x18, 0x0C, 0x06, 0x03, 0x06, 0x0C, 0x18, 0x00], // '<'
+    [0x00, 0x00, 0x3F, 0x00, 0x00, 0x3F, 0x00, 0x00], // '='
+    [0x06, 0x0C, 0x18, 0x30, 0x18, 0x0C, 0x06, 0x00], // '>'
+    [0x1E, 0x33, 0x30, 0x18, 0x0C, 0x00, 0x0C, 0x00], // '?'
+    [0x3E, 0x63, 0x7B, 0x7B, 0x7B, 0x03, 0x1E, 0x00], // '@'
+    [0x0C, 0x1E, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x00], // 'A'
+    [0x3F, 0x66, 0x66, 0x3E, 0x66, 0x66, 0x3F, 0x00], // 'B'
+    [0x3C, 0x66, 0x03, 0x03, 0x03, 0x66, 0x3C, 0x00], // 'C'
+    [0x1F, 0x36, 0x66, 0x66, 0x66, 0x36, 0x1F, 0x00], // 'D'
+    [0x7F, 0x46, 0x16, 0x1E, 0x16, 0x46, 0x7F, 0x00], // 'E'
+    [0x7F, 0x46, 0x16, 0x1E, 0x16, 0x06, 0x0F, 0x00], // 'F'
+    [0x3C, 0x66, 0x03, 0x03, 0x73, 0x66, 0x7C, 0x00], // 'G'
+    [0x33, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x33, 0x00], // 'H'
+    [0x1E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'I'
+    [0x78, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E, 0x00], // 'J'
+    [0x67, 0x66, 0x36, 0x1E, 0x36, 0x66, 0x67, 0x00], // 'K'
+    [0x0F, 0x06, 0x06, 0x06, 0x46, 0x66, 0x7F, 0x00], // 'L'
+    [0x63, 0x77, 0x7F, 0x7F, 0x6B, 0x63, 0x63, 0x00], // 'M'
+    [0x63, 0x67, 0x6F, 0x7B, 0x73, 0x63, 0x63, 0x00], // 'N'
+    [0x1C, 0x36, 0x63, 0x63, 0x63, 0x36, 0x1C, 0x00], // 'O'
+    [0x3F, 0x66, 0x66, 0x3E, 0x06, 0x06, 0x0F, 0x00], // 'P'
+    [0x1E, 0x33, 0x33, 0x33, 0x3B, 0x1E, 0x38, 0x00], // 'Q'
+    [0x3F, 0x66, 0x66, 0x3E, 0x36, 0x66, 0x67, 0x00], // 'R'
+    [0x1E, 0x33, 0x07, 0x0E, 0x38, 0x33, 0x1E, 0x00], // 'S'
+    [0x3F, 0x2D, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'T'
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x3F, 0x00], // 'U'
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00], // 'V'
+    [0x63, 0x63, 0x63, 0x6B, 0x7F, 0x77, 0x63, 0x00], // 'W'
+    [0x63, 0x63, 0x36, 0x1C, 0x1C, 0x36, 0x63, 0x00], // 'X'
+    [0x33, 0x33, 0x33, 0x1E, 0x0C, 0x0C, 0x1E, 0x00], // 'Y'
+    [0x7F, 0x63, 0x31, 0x18, 0x4C, 0x66, 0x7F, 0x00], // 'Z'
+    [0x1E, 0x06, 0x06, 0x06, 0x06, 0x06, 0x1E, 0x00], // '['
+    [0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x40, 0x00], // '\'
+    [0x1E, 0x18, 0x18, 0x18, 0x18, 0x18, 0x1E, 0x00], // ']'
+    [0x08, 0x1C, 0x36, 0x63, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF], // '_'
+    [0x0C, 0x0C, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00], // '`'
+    [0x00, 0x00, 0x1E, 0x30, 0x3E, 0x33, 0x6E, 0x00], // 'a'
+    [0x07, 0x06, 0x06, 0x3E, 0x66, 0x66, 0x3B, 0x00], // 'b'
+    [0x00, 0x00, 0x1E, 0x33, 0x03, 0x33, 0x1E, 0x00], // 'c'
+    [0x38, 0x30, 0x30, 0x3E, 0x33, 0x33, 0x6E, 0x00], // 'd'
+    [0x00, 0x00, 0x1E, 0x33, 0x3F, 0x03, 0x1E, 0x00], // 'e'
+    [0x1C, 0x36, 0x06, 0x0F, 0x06, 0x06, 0x0F, 0x00], // 'f'
+    [0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x1F], // 'g'
+    [0x07, 0x06, 0x36, 0x6E, 0x66, 0x66, 0x67, 0x00], // 'h'
+    [0x0C, 0x00, 0x0E, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'i'
+    [0x30, 0x00, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E], // 'j'
+    [0x07, 0x06, 0x66, 0x36, 0x1E, 0x36, 0x67, 0x00], // 'k'
+    [0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // 'l'
+    [0x00, 0x00, 0x33, 0x7F, 0x7F, 0x6B, 0x63, 0x00], // 'm'
+    [0x00, 0x00, 0x1F, 0x33, 0x33, 0x33, 0x33, 0x00], // 'n'
+    [0x00, 0x00, 0x1E, 0x33, 0x33, 0x33, 0x1E, 0x00], // 'o'
+    [0x00, 0x00, 0x3B, 0x66, 0x66, 0x3E, 0x06, 0x0F], // 'p'
+    [0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x78], // 'q'
+    [0x00, 0x00, 0x3B, 0x6E, 0x66, 0x06, 0x0F, 0x00], // 'r'
+    [0x00, 0x00, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x00], // 's'
+    [0x08, 0x0C, 0x3E, 0x0C, 0x0C, 0x2C, 0x18, 0x00], // 't'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x33, 0x6E, 0x00], // 'u'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00], // 'v'
+    [0x00, 0x00, 0x63, 0x6B, 0x7F, 0x7F, 0x36, 0x00], // 'w'
+    [0x00, 0x00, 0x63, 0x36, 0x1C, 0x36, 0x63, 0x00], // 'x'
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x3E, 0x30, 0x1F], // 'y'
+    [0x00, 0x00, 0x3F, 0x19, 0x0C, 0x26, 0x3F, 0x00], // 'z'
+    [0x38, 0x0C, 0x0C, 0x07, 0x0C, 0x0C, 0x38, 0x00], // '{'
+    [0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x18, 0x00], // '|'
+    [0x07, 0x0C, 0x0C, 0x38, 0x0C, 0x0C, 0x07, 0x00], // '}'
+    [0x6E, 0x3B, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '~'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // delete
+];
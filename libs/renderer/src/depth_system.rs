@@ -0,0 +1,294 @@
+use wgpu::*;
+
+use crate::render_targets::{RenderTargets, ScreenSize, ScreenSizeDependent};
+use crate::shaders::{self, ShaderInterface, ShaderLibrary, ShaderReloadable};
+
+const SHADER_NAME: &str = "linear_depth.wgsl";
+const COMPUTE_ENTRY: &str = "main";
+
+const MULTISAMPLED_DEFINES: &[(&str, &str)] = &[("MULTISAMPLED", "")];
+
+const fn bind_group_layout_entries(multisampled: bool) -> [wgpu::BindGroupLayoutEntry; 3]
+{
+    return [
+        // Scene depth
+        wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Depth,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled,
+            },
+            count: None,
+        },
+        // Linear depth
+        wgpu::BindGroupLayoutEntry {
+            binding: 1,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::StorageTexture {
+                view_dimension: wgpu::TextureViewDimension::D2,
+                format: wgpu::TextureFormat::R32Float,
+                access: wgpu::StorageTextureAccess::WriteOnly,
+            },
+            count: None,
+        },
+        // View
+        wgpu::BindGroupLayoutEntry {
+            binding: 2,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        },
+    ];
+}
+
+const BIND_GROUP_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] = &bind_group_layout_entries(false);
+const BIND_GROUP_LAYOUT_ENTRIES_MULTISAMPLED: &[wgpu::BindGroupLayoutEntry] = &bind_group_layout_entries(true);
+
+pub(crate) const SHADER_INTERFACE: ShaderInterface = ShaderInterface {
+    shader_name: SHADER_NAME,
+    entry_points: &[(wgpu::ShaderStages::COMPUTE, COMPUTE_ENTRY)],
+    bind_group_layouts: &[BIND_GROUP_LAYOUT_ENTRIES],
+    vertex_attributes: &[],
+    defines: &[],
+};
+
+// With MSAA the depth texture is multisampled and needs another binding type.
+pub(crate) const SHADER_INTERFACE_MULTISAMPLED: ShaderInterface = ShaderInterface {
+    shader_name: SHADER_NAME,
+    entry_points: &[(wgpu::ShaderStages::COMPUTE, COMPUTE_ENTRY)],
+    bind_group_layouts: &[BIND_GROUP_LAYOUT_ENTRIES_MULTISAMPLED],
+    vertex_attributes: &[],
+    defines: MULTISAMPLED_DEFINES,
+};
+
+// Shared with the shaders through shared/view.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct ViewParams
+{
+    pub(crate) right: [f32; 4],
+    pub(crate) up: [f32; 4],
+    pub(crate) forward: [f32; 4],
+    pub(crate) position: [f32; 4],
+    pub(crate) near: f32,
+    pub(crate) far: f32,
+    pub(crate) tan_half_fov_y: f32,
+    pub(crate) aspect: f32,
+}
+
+impl ViewParams
+{
+    pub(crate) fn new(camera: &common::Camera) -> Self
+    {
+        // Same basis as the look_at_rh in Camera::build_view_projection_matrix.
+        let forward = glam::Vec3::from_array(camera.get_forward().to_array()).normalize();
+        let right = forward.cross(glam::Vec3::Y).normalize();
+        let up = right.cross(forward);
+        Self
+        {
+            right: right.extend(0.0).to_array(),
+            up: up.extend(0.0).to_array(),
+            forward: forward.extend(0.0).to_array(),
+            position: [camera.eye.x, camera.eye.y, camera.eye.z, 1.0],
+            near: camera.znear,
+            far: camera.zfar,
+            tan_half_fov_y: (camera.fovy.to_radians() * 0.5).tan(),
+            aspect: camera.aspect,
+        }
+    }
+}
+
+// Uniform buffer with the camera for the passes reading the linear depth, written every update.
+pub(crate) fn create_view_buffer(device: &Device, label: &str) -> Buffer
+{
+    return device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size: std::mem::size_of::<ViewParams>() as BufferAddress,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+}
+
+// Converts the scene depth into linear view depth for the ssao, fog and composite passes.
+pub struct TriangleSystem
+{
+    _shader: ShaderModule,
+    _bind_group_layout: BindGroupLayout,
+    _pipeline_layout: PipelineLayout,
+    compute_pipeline: ComputePipeline,
+    bind_group: BindGroup,
+
+    view_buffer: Buffer,
+
+    sample_count: u32,
+    pub(crate) size: ScreenSize,
+}
+
+impl TriangleSystem
+{
+    pub fn new(device: &Device, render_targets: &RenderTargets) -> Self
+    {
+        let sample_count = render_targets.sample_count;
+        let defines = Self::interface(sample_count).defines;
+        let _shader = shaders::create_shader_module(
+            device,
+            SHADER_NAME,
+            &shaders::embedded_source_with_defines(SHADER_NAME, defines));
+
+        let (_bind_group_layout, _pipeline_layout) = Self::create_layouts(device, sample_count);
+        let compute_pipeline = Self::create_pipeline(device, &_pipeline_layout, &_shader);
+
+        let view_buffer = create_view_buffer(device, "Linear depth view");
+
+        let bind_group = Self::create_bind_group(device, &_bind_group_layout, render_targets, &view_buffer);
+
+        Self {
+            _shader,
+            _bind_group_layout,
+            _pipeline_layout,
+            compute_pipeline,
+            bind_group,
+
+            view_buffer,
+
+            sample_count,
+            size: render_targets.size,
+        }
+    }
+
+    fn interface(sample_count: u32) -> &'static ShaderInterface
+    {
+        return if sample_count > 1 { &SHADER_INTERFACE_MULTISAMPLED } else { &SHADER_INTERFACE };
+    }
+
+    fn create_layouts(device: &Device, sample_count: u32) -> (BindGroupLayout, PipelineLayout)
+    {
+        let bind_group_layout = Self::interface(sample_count).create_bind_group_layout(device, 0, "Linear depth bindings");
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor
+        {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        return (bind_group_layout, pipeline_layout);
+    }
+
+    fn create_pipeline(device: &Device, pipeline_layout: &PipelineLayout, shader: &ShaderModule) -> ComputePipeline
+    {
+        return device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Linear depth"),
+            layout: Some(pipeline_layout),
+            module: shader,
+            entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::COMPUTE, COMPUTE_ENTRY),
+        });
+    }
+
+    // The depth texture changes binding type with MSAA, so the layout, the shader variant and
+    // the pipeline are all recreated.
+    pub fn set_sample_count(
+        &mut self,
+        device: &Device,
+        render_targets: &RenderTargets,
+        shader_library: &ShaderLibrary
+    )
+    {
+        self.sample_count = render_targets.sample_count;
+        let source = shader_library.current_source(SHADER_NAME, Self::interface(self.sample_count).defines);
+        (self._bind_group_layout, self._pipeline_layout) = Self::create_layouts(device, self.sample_count);
+        self._shader = shaders::create_shader_module(device, SHADER_NAME, &source);
+        self.compute_pipeline = Self::create_pipeline(device, &self._pipeline_layout, &self._shader);
+        self.rebind_textures(device, render_targets);
+    }
+
+    pub fn rebind_textures(&mut self, device: &Device, render_targets: &RenderTargets)
+    {
+        self.bind_group = Self::create_bind_group(device, &self._bind_group_layout, render_targets, &self.view_buffer);
+        self.size = render_targets.size;
+    }
+
+    pub fn update(&mut self, camera: &common::Camera, queue: &Queue)
+    {
+        queue.write_buffer(&self.view_buffer, 0, bytemuck::cast_slice(&[ViewParams::new(camera)]));
+    }
+
+    pub fn dispatch_size(size: ScreenSize) -> (u32, u32, u32)
+    {
+        return size.workgroups_2d(8, 8);
+    }
+
+    pub fn render(&mut self, encoder: &mut CommandEncoder)
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("Linear depth") });
+        compute_pass.set_pipeline(&self.compute_pipeline);
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        let (x, y, z) = Self::dispatch_size(self.size);
+        compute_pass.dispatch_workgroups(x, y, z);
+    }
+
+    fn create_bind_group(
+        device: &Device,
+        bind_group_layout: &BindGroupLayout,
+        render_targets: &RenderTargets,
+        view_buffer: &Buffer,
+    ) -> BindGroup
+    {
+        return device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Linear depth Bind Group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&render_targets.render_target_depth_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&render_targets.linear_depth_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: view_buffer.as_entire_binding(),
+                },
+            ],
+        });
+    }
+}
+
+impl ScreenSizeDependent for TriangleSystem
+{
+    fn rebind(&mut self, device: &Device, render_targets: &RenderTargets)
+    {
+        self.rebind_textures(device, render_targets);
+    }
+}
+
+impl ShaderReloadable for TriangleSystem
+{
+    fn shader_name(&self) -> &'static str
+    {
+        return SHADER_NAME;
+    }
+
+    fn shader_defines(&self) -> &'static [(&'static str, &'static str)]
+    {
+        return Self::interface(self.sample_count).defines;
+    }
+
+    fn reload_shader(&mut self, device: &Device, source: &str) -> Result<(), String>
+    {
+        let (shader, compute_pipeline) = shaders::validated(device, || {
+            let shader = shaders::create_shader_module(device, SHADER_NAME, source);
+            let compute_pipeline = Self::create_pipeline(device, &self._pipeline_layout, &shader);
+            (shader, compute_pipeline)
+        })?;
+        self._shader = shader;
+        self.compute_pipeline = compute_pipeline;
+        return Ok(());
+    }
+}
@@ -0,0 +1,315 @@
+use wgpu::*;
+use wgpu::util::DeviceExt;
+
+use crate::depth_system::{self, ViewParams};
+use crate::render_targets::{RenderTargets, ScreenSize, ScreenSizeDependent};
+use crate::shaders::{self, ShaderInterface, ShaderReloadable};
+
+const SHADER_NAME: &str = "fog.wgsl";
+const COMPUTE_ENTRY: &str = "main";
+
+const BIND_GROUP_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] = &[
+    // Linear depth
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    },
+    // Fog inscattering and transmittance
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::StorageTexture {
+            view_dimension: wgpu::TextureViewDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            access: wgpu::StorageTextureAccess::WriteOnly,
+        },
+        count: None,
+    },
+    // View
+    wgpu::BindGroupLayoutEntry {
+        binding: 2,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+    // Params
+    wgpu::BindGroupLayoutEntry {
+        binding: 3,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+];
+
+pub(crate) const SHADER_INTERFACE: ShaderInterface = ShaderInterface {
+    shader_name: SHADER_NAME,
+    entry_points: &[(wgpu::ShaderStages::COMPUTE, COMPUTE_ENTRY)],
+    bind_group_layouts: &[BIND_GROUP_LAYOUT_ENTRIES],
+    vertex_attributes: &[],
+    defines: &[],
+};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FogSettings
+{
+    pub enabled: bool,
+    // Computed at half the screen size and upsampled.
+    pub half_resolution: bool,
+    // Linear rgb of the fog.
+    pub color: [f32; 3],
+    // Density of the fog filling the whole scene, per world unit.
+    pub distance_density: f32,
+    // Distance from the camera where the fog starts.
+    pub start_distance: f32,
+    // Density of the height fog at base_height, it thins out by height_falloff per unit above.
+    pub height_density: f32,
+    pub height_falloff: f32,
+    pub base_height: f32,
+}
+
+impl Default for FogSettings
+{
+    fn default() -> Self
+    {
+        Self
+        {
+            enabled: false,
+            half_resolution: true,
+            color: [0.5, 0.6, 0.7],
+            distance_density: 0.02,
+            start_distance: 2.0,
+            height_density: 0.1,
+            height_falloff: 0.5,
+            base_height: 0.0,
+        }
+    }
+}
+
+// Shared with fog.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct FogParams
+{
+    pub(crate) color: [f32; 4],
+    pub(crate) distance_density: f32,
+    pub(crate) start_distance: f32,
+    pub(crate) height_density: f32,
+    pub(crate) height_falloff: f32,
+    pub(crate) base_height: f32,
+    pub(crate) _padding0: f32,
+    pub(crate) _padding1: f32,
+    pub(crate) _padding2: f32,
+}
+
+impl FogParams
+{
+    fn new(settings: &FogSettings) -> Self
+    {
+        let [r, g, b] = settings.color;
+        Self
+        {
+            color: [r, g, b, 1.0],
+            distance_density: settings.distance_density,
+            start_distance: settings.start_distance,
+            height_density: settings.height_density,
+            height_falloff: settings.height_falloff,
+            base_height: settings.base_height,
+            _padding0: 0.0,
+            _padding1: 0.0,
+            _padding2: 0.0,
+        }
+    }
+}
+
+// Computes the distance and height fog from the linear depth into the fog texture.
+pub struct TriangleSystem
+{
+    _shader: ShaderModule,
+    _bind_group_layout: BindGroupLayout,
+    _pipeline_layout: PipelineLayout,
+    compute_pipeline: ComputePipeline,
+    bind_group: BindGroup,
+
+    view_buffer: Buffer,
+    params_buffer: Buffer,
+
+    settings: FogSettings,
+    pub(crate) size: ScreenSize,
+}
+
+impl TriangleSystem
+{
+    pub fn new(device: &Device, render_targets: &RenderTargets) -> Self
+    {
+        let _shader = shaders::create_shader_module(device, SHADER_NAME, &shaders::embedded_source(SHADER_NAME));
+
+        let _bind_group_layout = SHADER_INTERFACE.create_bind_group_layout(device, 0, "Fog bindings");
+
+        let _pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor
+        {
+            label: None,
+            bind_group_layouts: &[&_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let compute_pipeline = Self::create_pipeline(device, &_pipeline_layout, &_shader);
+
+        let settings = FogSettings::default();
+        let view_buffer = depth_system::create_view_buffer(device, "Fog view");
+        let params_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Fog params"),
+                contents: bytemuck::cast_slice(&[FogParams::new(&settings)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let bind_group = Self::create_bind_group(
+            device,
+            &_bind_group_layout,
+            render_targets,
+            &view_buffer,
+            &params_buffer,
+        );
+
+        Self {
+            _shader,
+            _bind_group_layout,
+            _pipeline_layout,
+            compute_pipeline,
+            bind_group,
+
+            view_buffer,
+            params_buffer,
+
+            settings,
+            size: render_targets.effect_size(render_targets.fog_half_resolution),
+        }
+    }
+
+    fn create_pipeline(device: &Device, pipeline_layout: &PipelineLayout, shader: &ShaderModule) -> ComputePipeline
+    {
+        return device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Fog"),
+            layout: Some(pipeline_layout),
+            module: shader,
+            entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::COMPUTE, COMPUTE_ENTRY),
+        });
+    }
+
+    pub fn rebind_textures(&mut self, device: &Device, render_targets: &RenderTargets)
+    {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self._bind_group_layout,
+            render_targets,
+            &self.view_buffer,
+            &self.params_buffer,
+        );
+        self.size = render_targets.effect_size(render_targets.fog_half_resolution);
+    }
+
+    pub fn settings(&self) -> FogSettings
+    {
+        return self.settings;
+    }
+
+    pub fn set_settings(&mut self, settings: FogSettings)
+    {
+        self.settings = settings;
+    }
+
+    pub fn update(&mut self, camera: &common::Camera, queue: &Queue)
+    {
+        queue.write_buffer(&self.view_buffer, 0, bytemuck::cast_slice(&[ViewParams::new(camera)]));
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[FogParams::new(&self.settings)]));
+    }
+
+    pub fn dispatch_size(size: ScreenSize) -> (u32, u32, u32)
+    {
+        return size.workgroups_2d(8, 8);
+    }
+
+    pub fn render(&mut self, encoder: &mut CommandEncoder)
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("Fog") });
+        compute_pass.set_pipeline(&self.compute_pipeline);
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        let (x, y, z) = Self::dispatch_size(self.size);
+        compute_pass.dispatch_workgroups(x, y, z);
+    }
+
+    fn create_bind_group(
+        device: &Device,
+        bind_group_layout: &BindGroupLayout,
+        render_targets: &RenderTargets,
+        view_buffer: &Buffer,
+        params_buffer: &Buffer,
+    ) -> BindGroup
+    {
+        return device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Fog Bind Group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&render_targets.linear_depth_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&render_targets.fog_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: view_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+    }
+}
+
+impl ScreenSizeDependent for TriangleSystem
+{
+    fn rebind(&mut self, device: &Device, render_targets: &RenderTargets)
+    {
+        self.rebind_textures(device, render_targets);
+    }
+}
+
+impl ShaderReloadable for TriangleSystem
+{
+    fn shader_name(&self) -> &'static str
+    {
+        return SHADER_NAME;
+    }
+
+    fn reload_shader(&mut self, device: &Device, source: &str) -> Result<(), String>
+    {
+        let (shader, compute_pipeline) = shaders::validated(device, || {
+            let shader = shaders::create_shader_module(device, SHADER_NAME, source);
+            let compute_pipeline = Self::create_pipeline(device, &self._pipeline_layout, &shader);
+            (shader, compute_pipeline)
+        })?;
+        self._shader = shader;
+        self.compute_pipeline = compute_pipeline;
+        return Ok(());
+    }
+}
@@ -0,0 +1,224 @@
+use std::mem::size_of;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use common::{GpuOutInstanceBounds, GpuOutInstanceMatrices, MeshModelLocation};
+use wgpu::*;
+
+// Instances drawn per frame, the rest are dropped.
+pub(crate) const MAX_INSTANCES: usize = 64 * 1024;
+// Staging buffers the cpu writes in turn, so the gpu can still copy from the earlier frames'.
+const STAGING_FRAMES: usize = 3;
+
+// States of a staging buffer set mapping, the map_async callbacks count up to MAP_DONE. A
+// set that failed to map is not used again.
+const MAP_PENDING: u8 = 0;
+const MAP_DONE: u8 = 3;
+const MAP_FAILED: u8 = 0x80;
+const MAP_UNUSED: u8 = 0x40;
+
+struct Staging
+{
+    frame_instance_model_data: Buffer,
+    frame_instance_model_transforms: Buffer,
+    frame_instance_bounds: Buffer,
+    map_state: Arc<AtomicU8>,
+}
+
+// The copy recorded into this frame's encoder.
+struct PendingCopy
+{
+    staging: usize,
+    model_data_size: BufferAddress,
+    model_transforms_size: BufferAddress,
+    bounds_size: BufferAddress,
+}
+
+// Uploads the per-frame instances of MeshData through a ring of mapped staging buffers, which
+// the frame's encoder copies to the storage buffers the draw passes read. A staging buffer is
+// mapped again after the frame's submit. When the gpu is still copying from the next one the
+// instances are written with write_buffer instead of waiting.
+pub(crate) struct InstanceUpload
+{
+    staging: Vec<Staging>,
+    next_staging: usize,
+    pending_copy: Option<PendingCopy>,
+
+    pub(crate) model_data: Buffer,
+    pub(crate) model_transforms: Buffer,
+    // World space bounding spheres of the instances, available to the passes.
+    pub(crate) bounds: Buffer,
+    instance_count: u32,
+}
+
+impl InstanceUpload
+{
+    pub(crate) fn new(device: &Device) -> Self
+    {
+        let staging = (0..STAGING_FRAMES)
+            .map(|_| Staging {
+                frame_instance_model_data: Self::create_buffer::<MeshModelLocation>(
+                    device,
+                    "Instance model data",
+                    wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_SRC,
+                    true),
+                frame_instance_model_transforms: Self::create_buffer::<GpuOutInstanceMatrices>(
+                    device,
+                    "Frame instance model transforms",
+                    wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_SRC,
+                    true),
+                frame_instance_bounds: Self::create_buffer::<GpuOutInstanceBounds>(
+                    device,
+                    "Frame instance bounds",
+                    wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_SRC,
+                    true),
+                map_state: Arc::new(AtomicU8::new(MAP_DONE)),
+            })
+            .collect();
+
+        Self
+        {
+            staging,
+            next_staging: 0,
+            pending_copy: None,
+
+            model_data: Self::create_buffer::<MeshModelLocation>(
+                device,
+                "Instance model data storage",
+                wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                false),
+            model_transforms: Self::create_buffer::<GpuOutInstanceMatrices>(
+                device,
+                "Instance model transforms storage",
+                wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                false),
+            bounds: Self::create_buffer::<GpuOutInstanceBounds>(
+                device,
+                "Instance bounds storage",
+                wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                false),
+            instance_count: 0,
+        }
+    }
+
+    fn create_buffer<T>(device: &Device, label: &str, usage: BufferUsages, mapped_at_creation: bool) -> Buffer
+    {
+        return device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: (MAX_INSTANCES * size_of::<T>()) as BufferAddress,
+            usage,
+            mapped_at_creation,
+        });
+    }
+
+    pub(crate) fn instance_count(&self) -> u32
+    {
+        return self.instance_count;
+    }
+
+    // Writes this frame's instances to the next staging buffer, or straight to the storage
+    // buffers when it is not mapped yet.
+    pub(crate) fn update(&mut self, device: &Device, queue: &Queue, mesh_data: &common::MeshData)
+    {
+        // A frame that was not rendered leaves its staging buffer unmapped.
+        if let Some(copy) = self.pending_copy.take()
+        {
+            self.map_staging(copy.staging);
+        }
+        device.poll(Maintain::Poll);
+
+        let count = mesh_data.gpu_out_instance_matrices.len()
+            .min(mesh_data.gpu_out_instance_mesh_model_locations.len())
+            .min(mesh_data.gpu_out_instance_bounds.len())
+            .min(MAX_INSTANCES);
+        self.instance_count = count as u32;
+        if count == 0
+        {
+            return;
+        }
+        let model_data: &[u8] = bytemuck::cast_slice(&mesh_data.gpu_out_instance_mesh_model_locations[..count]);
+        let model_transforms: &[u8] = bytemuck::cast_slice(&mesh_data.gpu_out_instance_matrices[..count]);
+        let bounds: &[u8] = bytemuck::cast_slice(&mesh_data.gpu_out_instance_bounds[..count]);
+
+        let index = self.next_staging;
+        let staging = &self.staging[index];
+        let map_state = staging.map_state.load(Ordering::Acquire);
+        if map_state != MAP_DONE
+        {
+            if map_state & MAP_FAILED != 0 && map_state & MAP_UNUSED == 0
+            {
+                log::warn!("Failed to map an instance staging buffer, writing the instances directly instead");
+                staging.map_state.store(MAP_FAILED | MAP_UNUSED, Ordering::Release);
+            }
+            // Also skips a staging buffer that failed for good.
+            self.next_staging = (index + 1) % STAGING_FRAMES;
+            queue.write_buffer(&self.model_data, 0, model_data);
+            queue.write_buffer(&self.model_transforms, 0, model_transforms);
+            queue.write_buffer(&self.bounds, 0, bounds);
+            return;
+        }
+
+        let model_data_size = model_data.len() as BufferAddress;
+        let model_transforms_size = model_transforms.len() as BufferAddress;
+        let bounds_size = bounds.len() as BufferAddress;
+        staging.frame_instance_model_data.slice(..model_data_size).get_mapped_range_mut().copy_from_slice(model_data);
+        staging.frame_instance_model_transforms.slice(..model_transforms_size).get_mapped_range_mut()
+            .copy_from_slice(model_transforms);
+        staging.frame_instance_bounds.slice(..bounds_size).get_mapped_range_mut().copy_from_slice(bounds);
+        staging.frame_instance_model_data.unmap();
+        staging.frame_instance_model_transforms.unmap();
+        staging.frame_instance_bounds.unmap();
+        staging.map_state.store(MAP_PENDING, Ordering::Release);
+
+        self.pending_copy = Some(PendingCopy { staging: index, model_data_size, model_transforms_size, bounds_size });
+        self.next_staging = (index + 1) % STAGING_FRAMES;
+    }
+
+    // Records the copy from the staging buffer, before the passes reading the instances.
+    pub(crate) fn copy(&mut self, encoder: &mut CommandEncoder)
+    {
+        let Some(copy) = &self.pending_copy else
+        {
+            return;
+        };
+        let staging = &self.staging[copy.staging];
+        encoder.copy_buffer_to_buffer(&staging.frame_instance_model_data, 0, &self.model_data, 0, copy.model_data_size);
+        encoder.copy_buffer_to_buffer(
+            &staging.frame_instance_model_transforms,
+            0,
+            &self.model_transforms,
+            0,
+            copy.model_transforms_size);
+        encoder.copy_buffer_to_buffer(&staging.frame_instance_bounds, 0, &self.bounds, 0, copy.bounds_size);
+    }
+
+    // Mapping the staging buffer is only allowed after the submit using it.
+    pub(crate) fn after_submit(&mut self)
+    {
+        if let Some(copy) = self.pending_copy.take()
+        {
+            self.map_staging(copy.staging);
+        }
+    }
+
+    fn map_staging(&self, index: usize)
+    {
+        let staging = &self.staging[index];
+        staging.map_state.store(MAP_PENDING, Ordering::Release);
+        for buffer in [
+            &staging.frame_instance_model_data,
+            &staging.frame_instance_model_transforms,
+            &staging.frame_instance_bounds,
+        ]
+        {
+            let map_state = staging.map_state.clone();
+            buffer.slice(..).map_async(MapMode::Write, move |result| {
+                match result
+                {
+                    Ok(()) => map_state.fetch_add(1, Ordering::AcqRel),
+                    Err(_) => map_state.fetch_or(MAP_FAILED, Ordering::AcqRel),
+                };
+            });
+        }
+    }
+}
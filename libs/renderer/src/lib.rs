@@ -1,14 +1,57 @@
 use std::mem::size_of;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use common::GameState;
 use wgpu::*;
-use wgpu::util::DeviceExt;
+
+use instance_upload::InstanceUpload;
+use mesh_buffers::MeshBuffers;
+use pass_profiler::PassProfiler;
+use render_scale::DynamicResolution;
+use render_targets::{PingPong, RenderTargets, ScreenSize, ScreenSizeDependent, SCENE_COLOR_FORMAT, SCENE_DEPTH_FORMAT};
+use shaders::{ShaderLibrary, ShaderReloadable};
+
+pub use bloom_system::BloomSettings;
+pub use fog_system::FogSettings;
+pub use pass_profiler::{PassTiming, TimingSource};
+pub use pick_system::GpuPick;
+pub use render_scale::{RenderScale, RenderScaleSettings, UpscaleFilter};
+pub use renderer_config::{PresentMode, RendererConfig};
+pub use renderer_error::RendererError;
+pub use post_process_system::{
+    ChromaticAberrationSettings, ColorGradingSettings, FxaaSettings, PostProcessSettings, VignetteSettings,
+};
+pub use ssao_system::SsaoSettings;
+pub use tonemap_system::{TonemapOperator, TonemapSettings};
 
 mod blit_to_backbuffer;
-mod compute_system;
+mod bloom_system;
+mod composite_system;
 mod compute_system_copy_vertices;
+mod debug_draw_system;
+mod debug_text_system;
+mod depth_system;
+mod fog_system;
+mod instance_upload;
+mod mesh_buffers;
+mod mesh_system;
+mod pass_profiler;
+mod pick_system;
+mod post_process_system;
+mod render_scale;
+mod render_targets;
+mod renderer_config;
+mod renderer_error;
+mod shader_preprocessor;
+#[cfg(test)]
+mod shader_validation;
+mod shaders;
+mod ssao_system;
+mod tonemap_system;
 mod triangle_system;
 mod triangle_system_vertices;
 mod triangle_system_camera_vertices;
+mod ui_system;
 
 pub struct PhysicalSize<P> {
     pub width: P,
@@ -26,42 +69,57 @@ pub struct Renderer
 {
     width: u32,
     height: u32,
-    _instance: Instance,
-    surface: Surface,
-    _adapter: Adapter,
+    // Shared with the renderer replacing this one after a device loss.
+    _instance: Arc<Instance>,
+    surface: Arc<Surface>,
+    adapter: Adapter,
 
     device: Device,
     queue: Queue,
+    // Set from the uncaptured error handler, the next update recreates the device.
+    device_lost: Arc<AtomicBool>,
+    // Kept for uploading again to a recreated device.
+    color_grading_lut: Option<(u32, Vec<u8>)>,
 
-    _swapchain_format: TextureFormat,
+    renderer_config: RendererConfig,
     config: SurfaceConfiguration,
 
-    render_target_texture: Texture,
-    render_target_texture2: Texture,
-
-    render_target_texture_view: TextureView,
-
-    render_target_depth_texture: Texture,
-    render_target_depth_texture_view: TextureView,
-    render_target_depth_texture_sampler: Sampler,
-
-    compute_system: compute_system::TriangleSystem,
+    render_targets: RenderTargets,
+    shader_library: ShaderLibrary,
+
+    render_scale_settings: RenderScaleSettings,
+    // Holds the current render scale, also when it is fixed.
+    dynamic_resolution: DynamicResolution,
+    pass_profiler: PassProfiler,
+
+    depth_system: depth_system::TriangleSystem,
+    ssao_system: ssao_system::TriangleSystem,
+    fog_system: fog_system::TriangleSystem,
+    composite_system: composite_system::TriangleSystem,
+    bloom_system: bloom_system::TriangleSystem,
+    tonemap_system: tonemap_system::TriangleSystem,
+    post_process_system: post_process_system::TriangleSystem,
     compute_system_copy_vertices: compute_system_copy_vertices::TriangleSystem,
+    debug_draw_system: debug_draw_system::TriangleSystem,
 
     triangle_system: triangle_system::TriangleSystem,
     triangle_system_vertices: triangle_system_vertices::TriangleSystem,
     triangle_system_camera_vertices: triangle_system_camera_vertices::TriangleSystem,
+    mesh_system: mesh_system::TriangleSystem,
+    pick_system: pick_system::TriangleSystem,
 
 
     blit_to_backbuffer: blit_to_backbuffer::TriangleSystem,
+    debug_text_system: debug_text_system::TriangleSystem,
+    ui_system: ui_system::TriangleSystem,
 
 
 
-    model_mesh_vertices: Buffer,
-    model_mesh_indices: Buffer,
+    // model_mesh_vertices and model_mesh_indices.
+    mesh_buffers: MeshBuffers,
 
-    frame_instance_model_data: Buffer,
-    frame_instance_model_transforms: Buffer,
+    // frame_instance_model_data and frame_instance_model_transforms.
+    instance_upload: InstanceUpload,
 
     gpu_frame_vertices: Buffer,
     gpu_frame_indices: Buffer,
@@ -70,47 +128,8 @@ pub struct Renderer
 
 impl Renderer
 { 
-    fn create_depth_texture(device: &Device, config: &wgpu::SurfaceConfiguration) -> 
-        (Texture, TextureView, Sampler)
-    {
-
-        let texture = Self::create_rendertarget_texture(
-            &device,
-            config.width,
-            config.height,
-            TextureFormat::Depth32Float,
-            TextureUsages::RENDER_ATTACHMENT
-                | TextureUsages::TEXTURE_BINDING
-        );
-
-
-        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-
-        let sampler = device.create_sampler( &wgpu::SamplerDescriptor{
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear ,
-            
-            mipmap_filter: wgpu::FilterMode::Nearest,
-
-            compare: Some(wgpu::CompareFunction::LessEqual),
-
-            lod_min_clamp: 0.0,
-            lod_max_clamp: 100.0,
-
-            ..Default::default()
-        });
-
-        return (texture, view, sampler)
-
-    }
-
-
     fn create_buffers(device: &Device, game_state: &common::GameState) -> (
-        Buffer, Buffer, Buffer, Buffer, Buffer, Buffer, Buffer
+        MeshBuffers, Buffer, Buffer, Buffer
     )
     {
         let gpu_frame_vertices = device.create_buffer(
@@ -140,45 +159,10 @@ impl Renderer
             }
         );
 
-        let model_mesh_vertices = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer all"),
-                contents: unsafe { game_state.mesh_data.vertices.align_to::<u8>().1 },
-                usage: wgpu::BufferUsages::STORAGE,
-            }
-        );
-
-        let model_mesh_indices = device.create_buffer_init(
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Index Buffer all"),
-                contents: unsafe { game_state.mesh_data.indices.align_to::<u8>().1 },
-                usage: wgpu::BufferUsages::STORAGE,
-            }
-        );
-
-        let frame_instance_model_data = device.create_buffer(
-            &wgpu::BufferDescriptor {
-                label: Some("Instance model data"),
-                size: (size_of::<common::MeshModelLocation>() * 1024 * 1024) as BufferAddress,
-                usage: wgpu::BufferUsages::MAP_WRITE, // | wgpu::BufferUsages::STORAGE,
-                mapped_at_creation: true,
-            }
-        );
-        let frame_instance_model_transforms = device.create_buffer(
-            &wgpu::BufferDescriptor {
-                label: Some("Frame instance model transforms"),
-                size: (size_of::<common::GpuOutInstanceMatrices>() * 1024 * 1024) as BufferAddress,
-                usage: wgpu::BufferUsages::MAP_WRITE, // | wgpu::BufferUsages::STORAGE,
-                mapped_at_creation: true,
-            }
-        );
+        let mesh_buffers = MeshBuffers::new(device, &game_state.mesh_data);
 
         (
-            model_mesh_vertices,
-            model_mesh_indices,
-        
-            frame_instance_model_data,
-            frame_instance_model_transforms,
+            mesh_buffers,
         
             gpu_frame_vertices,
             gpu_frame_indices,
@@ -188,85 +172,47 @@ impl Renderer
 
     }
 
-    fn create_rendertarget_texture(
-        device: &Device,
-        w: u32,
-        h: u32,
-        format: wgpu::TextureFormat,
-        usage: wgpu::TextureUsages,
-    ) -> wgpu::Texture
+    pub async fn new<W: raw_window_handle::HasRawWindowHandle + raw_window_handle::HasRawDisplayHandle>
+        (window: &W, width: u32, height: u32, game_state: &GameState, renderer_config: RendererConfig)
+        -> Result<Self, RendererError>
     {
-        //if w % 64 != 0
-        {
-            //return Err("Render target texture needs to be multiple of 64 pixels.")
-        }
 
-        let rt_desc = wgpu::TextureDescriptor {
-            size: wgpu::Extent3d {
-                width: w,
-                height: h,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format,
-            usage,
-            label: None,
-            view_formats: &[], //TextureFormat::Rgba8UnormSrgb, TextureFormat::Rgba8Unorm],
-        };
-        return device.create_texture(&rt_desc);
+        let instance = wgpu::Instance::default(); //new(wgpu::Backends::all());
+        let surface = unsafe { instance.create_surface(window) }
+            .map_err(|err| RendererError::SurfaceCreation(err.to_string()))?;
+        return Self::create(Arc::new(instance), Arc::new(surface), width, height, game_state, renderer_config).await;
     }
-    fn create_render_target_textures(
-        device: &Device,
-        width: u32,
-        height: u32
-    ) -> (Texture, Texture, TextureView)
-    {
-        let render_target_texture = Self::create_rendertarget_texture(
-            &device,
-            width,
-            height,
-            TextureFormat::Rgba8UnormSrgb,
-            TextureUsages::RENDER_ATTACHMENT
-                | TextureUsages::TEXTURE_BINDING
-        );
-        let render_target_texture2 = Self::create_rendertarget_texture(
-            &device,
-            width,
-            height,
-            TextureFormat::Rgba8Unorm,
-            TextureUsages::TEXTURE_BINDING
-                | TextureUsages::STORAGE_BINDING
-        );
 
-        let render_target_texture_view = render_target_texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+    // Checked up front, so a weak adapter gives a readable error instead of a failed device request.
+    fn check_adapter(adapter: &Adapter, limits: &Limits) -> Result<(), RendererError>
+    {
+        // The post processing and the vertex copy are compute passes.
+        if !adapter.get_downlevel_capabilities().flags.contains(DownlevelFlags::COMPUTE_SHADERS)
+        {
+            return Err(RendererError::UnsupportedFeatures(vec!["compute shaders".to_string()]));
+        }
 
-        return (render_target_texture, render_target_texture2,render_target_texture_view);
+        let mut failed = Vec::new();
+        limits.check_limits_with_fail_fn(&adapter.limits(), false, |name, required, allowed| {
+            failed.push((name.to_string(), required, allowed));
+        });
+        if !failed.is_empty()
+        {
+            return Err(RendererError::UnsupportedLimits(failed));
+        }
+        return Ok(());
     }
 
-
-
-
-
-
-
-
-
-
-
-
-
-
-    
-    pub async fn new<W: raw_window_handle::HasRawWindowHandle + raw_window_handle::HasRawDisplayHandle>
-        (window: &W, width: u32, height: u32, game_state: &GameState) -> Self
+    // Everything after the surface, also used to recreate the renderer after a device loss.
+    async fn create(
+        instance: Arc<Instance>,
+        surface: Arc<Surface>,
+        width: u32,
+        height: u32,
+        game_state: &GameState,
+        renderer_config: RendererConfig
+    ) -> Result<Self, RendererError>
     {
-
-        let instance = wgpu::Instance::default(); //new(wgpu::Backends::all());
-        let surface = unsafe { instance.create_surface(window) }
-            .unwrap();
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions
             {
@@ -276,7 +222,12 @@ impl Renderer
                 compatible_surface: Some(&surface),
             })
             .await
-            .expect("Failed to find an appropriate adapter");
+            .ok_or(RendererError::NoAdapter)?;
+
+        // Make sure we use the texture resolution limits from the adapter, so we can support images the size of the swapchain.
+        let limits = wgpu::Limits::downlevel_defaults() // downlevel_webgl2_defaults()
+            .using_resolution(adapter.limits());
+        Self::check_adapter(&adapter, &limits)?;
 
         // Create the logical device and command queue
         let (device, queue) = adapter
@@ -284,18 +235,34 @@ impl Renderer
                 &wgpu::DeviceDescriptor
                 {
                     label: None,
-                    features: wgpu::Features::empty(),
-                    // Make sure we use the texture resolution limits from the adapter, so we can support images the size of the swapchain.
-                    limits: wgpu::Limits::downlevel_defaults() // downlevel_webgl2_defaults()
-                        .using_resolution(adapter.limits()),
+                    // Lets MSAA use every sample count the adapter supports instead of only 4x.
+                    // Timestamp queries time the passes on the gpu when available.
+                    features: adapter.features()
+                        & (wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES | wgpu::Features::TIMESTAMP_QUERY),
+                    limits,
                 },
                 None,
             )
             .await
-            .expect("Failed to create device");
+            .map_err(|err| RendererError::DeviceCreation(err.to_string()))?;
+
+        let device_lost = Arc::new(AtomicBool::new(false));
+        let lost_flag = device_lost.clone();
+        device.on_uncaptured_error(Box::new(move |error| {
+            if is_device_lost_error(&error)
+            {
+                log::error!("{}", error);
+                lost_flag.store(true, Ordering::Relaxed);
+                return;
+            }
+            // Same as the default handler, other errors are bugs.
+            panic!("wgpu error: {}", error);
+        }));
 
         let swapchain_capabilities = surface.get_capabilities(&adapter);
-        let swapchain_format = swapchain_capabilities.formats[0];
+        let swapchain_format = renderer_config::pick_format(
+            renderer_config.prefer_srgb_format,
+            &swapchain_capabilities.formats);
 
         let config = wgpu::SurfaceConfiguration
         {
@@ -303,149 +270,667 @@ impl Renderer
             format: swapchain_format,
             width,
             height,
-            present_mode: wgpu::PresentMode::Fifo,
+            present_mode: renderer_config::pick_present_mode(
+                renderer_config.present_mode,
+                &swapchain_capabilities.present_modes),
             alpha_mode: swapchain_capabilities.alpha_modes[0],
             view_formats: vec![]
         };
-
-        //format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        log::info!("Surface format {:?}, present mode {:?}", config.format, config.present_mode);
 
         surface.configure(&device, &config);
 
-        let (render_target_texture, render_target_texture2, render_target_texture_view) =
-            Self::create_render_target_textures(&device, width, height);
-
-
-        let (render_target_depth_texture, render_target_depth_texture_view, render_target_depth_texture_sampler) =
-            Self::create_depth_texture(&device, &config);
+        let render_scale_settings = RenderScaleSettings::default();
+        let dynamic_resolution = DynamicResolution::new(Self::initial_render_scale(render_scale_settings.scale));
+        let display_size = ScreenSize::new(width, height);
+        let mut render_targets = RenderTargets::new(
+            &device,
+            display_size,
+            render_scale::scaled_size(display_size, dynamic_resolution.scale()),
+            1);
+        let default_settings = PostProcessSettings::default();
+        render_targets.set_effect_resolutions(
+            &device,
+            default_settings.ssao.half_resolution,
+            default_settings.fog.half_resolution);
 
         let (
-                model_mesh_vertices,
-                model_mesh_indices,
-            
-                frame_instance_model_data,
-                frame_instance_model_transforms,
+                mesh_buffers,
             
                 gpu_frame_vertices,
                 gpu_frame_indices,
                 gpu_frame_instance_data,
             ) = Self::create_buffers(&device, &game_state);
+        let instance_upload = InstanceUpload::new(&device);
 
 
+        // Shader and pipeline errors while creating the passes are returned instead of panicking.
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+
         let triangle_system =
             triangle_system::TriangleSystem::new(
                 &device,
-                render_target_texture.format());
+                SCENE_COLOR_FORMAT,
+                render_targets.sample_count);
         let triangle_system_vertices =
             triangle_system_vertices::TriangleSystem::new(
                 &device,
-                render_target_texture.format());
+                SCENE_COLOR_FORMAT,
+                render_targets.sample_count);
 
         let triangle_system_camera_vertices =
         triangle_system_camera_vertices::TriangleSystem::new(
             &device,
-            render_target_texture.format(),
-            render_target_depth_texture.format());
-
-
-        let compute_system = compute_system::TriangleSystem::new(
+            SCENE_COLOR_FORMAT,
+            SCENE_DEPTH_FORMAT,
+            render_targets.sample_count);
+        let mesh_system = mesh_system::TriangleSystem::new(
             &device,
-            &render_target_texture,
-            &render_target_texture2,
-        );
+            SCENE_COLOR_FORMAT,
+            SCENE_DEPTH_FORMAT,
+            render_targets.sample_count,
+            &mesh_buffers,
+            &instance_upload);
+        let pick_system = pick_system::TriangleSystem::new(&device, &mesh_buffers, &instance_upload);
+
+
+        let depth_system = depth_system::TriangleSystem::new(&device, &render_targets);
+        let ssao_system = ssao_system::TriangleSystem::new(&device, &render_targets);
+        let fog_system = fog_system::TriangleSystem::new(&device, &render_targets);
+        let composite_system = composite_system::TriangleSystem::new(&device, &render_targets);
+        let bloom_system = bloom_system::TriangleSystem::new(&device, &render_targets);
+        let tonemap_system = tonemap_system::TriangleSystem::new(&device, &render_targets);
+        let post_process_system = post_process_system::TriangleSystem::new(&device, &queue, &render_targets);
 
         let compute_system_copy_vertices = compute_system_copy_vertices::TriangleSystem::new(
             &device,
-            &render_target_texture,
-            &render_target_texture2,
+            &render_targets.render_target_texture,
+            &render_targets.render_target_texture2,
         );
+        let debug_draw_system = debug_draw_system::TriangleSystem::new(&device, &render_targets);
 
-        let blit_to_backbuffer = blit_to_backbuffer::TriangleSystem::new(
+        let mut blit_to_backbuffer = blit_to_backbuffer::TriangleSystem::new(
             &device,
             swapchain_format,
-            &render_target_texture2
+            &render_targets
         );
+        blit_to_backbuffer.set_filter(&queue, render_scale_settings.filter);
+        let debug_text_system = debug_text_system::TriangleSystem::new(&device, &queue, swapchain_format);
+        let ui_system = ui_system::TriangleSystem::new(&device, swapchain_format);
+
+        if let Some(error) = device.pop_error_scope().await
+        {
+            return Err(RendererError::ShaderValidation(error.to_string()));
+        }
 
+        let pass_profiler = PassProfiler::new(&device, &queue);
 
 
 
 
 
-        Self {
+
+        return Ok(Self {
             width,
             height,
 
             _instance: instance,
             surface,
-            _adapter: adapter,
+            adapter,
 
             device,
             queue,
+            device_lost,
+            color_grading_lut: None,
 
-            _swapchain_format: swapchain_format,
+            renderer_config,
             config,
 
-            render_target_texture,
-            render_target_texture2,
-
-            render_target_texture_view,
+            render_targets,
+            shader_library: ShaderLibrary::new(),
 
-            render_target_depth_texture,
-            render_target_depth_texture_view,
-            render_target_depth_texture_sampler,
+            render_scale_settings,
+            dynamic_resolution,
+            pass_profiler,
 
-            compute_system,
+            depth_system,
+            ssao_system,
+            fog_system,
+            composite_system,
+            bloom_system,
+            tonemap_system,
+            post_process_system,
             compute_system_copy_vertices,
+            debug_draw_system,
             
             triangle_system,
             triangle_system_vertices,
             triangle_system_camera_vertices,
+            mesh_system,
+            pick_system,
 
             blit_to_backbuffer,
+            debug_text_system,
+            ui_system,
 
 
-
-            model_mesh_vertices,
-            model_mesh_indices,
-        
-            frame_instance_model_data,
-            frame_instance_model_transforms,
+            mesh_buffers,
+            instance_upload,
         
             gpu_frame_vertices,
             gpu_frame_indices,
             gpu_frame_instance_data,
+        });
+    }
+
+    // Development mode, shaders get loaded from data/shaders and reloaded when the files change.
+    pub fn set_shader_hot_reload(&mut self, enabled: bool)
+    {
+        self.shader_library.set_hot_reload(enabled);
+    }
+
+    // Times every pass, on the gpu when the adapter supports timestamp queries. Off by default.
+    pub fn set_pass_profiling(&mut self, enabled: bool)
+    {
+        self.pass_profiler.set_enabled(enabled);
+    }
+
+    pub fn pass_timing_source(&self) -> TimingSource
+    {
+        return self.pass_profiler.source();
+    }
+
+    // Milliseconds per pass of the latest measured frame and averaged over the last frames, in
+    // render order. Empty while profiling is off.
+    pub fn pass_timings(&self) -> Vec<PassTiming>
+    {
+        return self.pass_profiler.timings();
+    }
+
+    // Picks the instance at a window pixel on the gpu, the result arrives from take_gpu_pick a
+    // frame or more later.
+    pub fn request_gpu_pick(&mut self, x: u32, y: u32)
+    {
+        self.pick_system.request(x, y);
+    }
+
+    pub fn take_gpu_pick(&mut self) -> Option<GpuPick>
+    {
+        return self.pick_system.take_result();
+    }
+
+    // Screen pixels per pixel of the 8x8 debug overlay font.
+    pub fn set_debug_text_scale(&mut self, scale: u32)
+    {
+        self.debug_text_system.set_scale(scale);
+    }
+
+    // Sets the MSAA sample count of the scene targets, 1 disables it. Falls back to the largest
+    // supported count below the requested one. Returns the sample count in use.
+    pub fn set_msaa_samples(&mut self, samples: u32) -> u32
+    {
+        let device_features = self.device.features();
+        let format_supports = |format: TextureFormat, count: u32|
+        {
+            let features = if device_features.contains(Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+            {
+                self.adapter.get_texture_format_features(format)
+            }
+            else
+            {
+                format.guaranteed_format_features(device_features)
+            };
+            features.flags.sample_count_supported(count)
+        };
+        let sample_count = render_targets::pick_sample_count(samples, |count| {
+            format_supports(SCENE_COLOR_FORMAT, count) && format_supports(SCENE_DEPTH_FORMAT, count)
+        });
+        if sample_count != samples
+        {
+            log::warn!("MSAA {}x is not supported, using {}x", samples, sample_count);
+        }
+        if sample_count == self.render_targets.sample_count
+        {
+            return sample_count;
+        }
+
+        self.render_targets.set_sample_count(&self.device, sample_count);
+        self.triangle_system.set_sample_count(&self.device, sample_count);
+        self.triangle_system_vertices.set_sample_count(&self.device, sample_count);
+        self.triangle_system_camera_vertices.set_sample_count(&self.device, sample_count);
+        self.mesh_system.set_sample_count(&self.device, sample_count);
+        self.depth_system.set_sample_count(&self.device, &self.render_targets, &self.shader_library);
+        return sample_count;
+    }
+
+    pub fn renderer_config(&self) -> RendererConfig
+    {
+        return self.renderer_config;
+    }
+
+    // Reconfigures the surface, falling back to what the surface supports.
+    pub fn set_renderer_config(&mut self, renderer_config: RendererConfig)
+    {
+        let capabilities = self.surface.get_capabilities(&self.adapter);
+        let format = renderer_config::pick_format(renderer_config.prefer_srgb_format, &capabilities.formats);
+        let present_mode = renderer_config::pick_present_mode(renderer_config.present_mode, &capabilities.present_modes);
+        self.renderer_config = renderer_config;
+        if format == self.config.format && present_mode == self.config.present_mode
+        {
+            return;
+        }
+        log::info!("Surface format {:?}, present mode {:?}", format, present_mode);
+
+        self.config.format = format;
+        self.config.present_mode = present_mode;
+        self.surface.configure(&self.device, &self.config);
+        self.blit_to_backbuffer.set_texture_format(&self.device, &self.queue, format);
+        self.debug_text_system.set_texture_format(&self.device, format);
+        self.ui_system.set_texture_format(&self.device, format);
+    }
+
+    fn initial_render_scale(scale: RenderScale) -> f32
+    {
+        return match scale
+        {
+            RenderScale::Fixed(scale) => scale,
+            RenderScale::Dynamic { max_scale, .. } => max_scale,
+        };
+    }
+
+    pub fn render_scale_settings(&self) -> RenderScaleSettings
+    {
+        return self.render_scale_settings;
+    }
+
+    pub fn set_render_scale_settings(&mut self, settings: RenderScaleSettings)
+    {
+        let scale = match settings.scale
+        {
+            RenderScale::Fixed(scale) => scale,
+            // Starts from the current scale, the next updates move it into the limits.
+            RenderScale::Dynamic { .. } => self.dynamic_resolution.scale(),
+        };
+        self.render_scale_settings = settings;
+        self.dynamic_resolution = DynamicResolution::new(scale);
+        self.blit_to_backbuffer.set_filter(&self.queue, settings.filter);
+        self.apply_render_scale(self.render_targets.display_size);
+    }
+
+    // Render size relative to the window size used for the current frame.
+    pub fn render_scale(&self) -> f32
+    {
+        return self.dynamic_resolution.scale();
+    }
+
+    // Recreates the render targets if the window size or the render scale changed.
+    fn apply_render_scale(&mut self, display_size: ScreenSize)
+    {
+        let size = render_scale::scaled_size(display_size, self.dynamic_resolution.scale());
+        if self.render_targets.resize(&self.device, display_size, size)
+        {
+            self.rebind_screen_size_dependents();
         }
     }
 
-    pub fn update(&mut self, _dt: f64, game_state: &common::GameState)
+    pub fn tonemap_settings(&self) -> TonemapSettings
+    {
+        return self.tonemap_system.settings();
+    }
+
+    pub fn set_tonemap_settings(&mut self, settings: TonemapSettings)
     {
-        self.triangle_system_camera_vertices.update(game_state.scene.get_current_camera(), &self.queue);
+        self.tonemap_system.set_settings(settings);
+    }
+
+    pub fn post_process_settings(&self) -> PostProcessSettings
+    {
+        return self.post_process_system.settings();
+    }
+
+    pub fn set_post_process_settings(&mut self, settings: PostProcessSettings)
+    {
+        self.ssao_system.set_settings(settings.ssao);
+        self.fog_system.set_settings(settings.fog);
+        self.bloom_system.set_settings(settings.bloom);
+        self.post_process_system.set_settings(settings);
+
+        let resolutions_changed = self.render_targets.set_effect_resolutions(
+            &self.device,
+            settings.ssao.half_resolution,
+            settings.fog.half_resolution);
+        if resolutions_changed
+        {
+            self.rebind_screen_size_dependents();
+        }
+    }
+
+    // Data is size^3 rgba8 texels with red changing fastest and blue slowest.
+    pub fn set_color_grading_lut(&mut self, size: u32, data: &[u8]) -> Result<(), RendererError>
+    {
+        self.post_process_system.set_lut(&self.device, &self.queue, &self.render_targets, size, data)
+            .map_err(RendererError::InvalidLut)?;
+        self.color_grading_lut = Some((size, data.to_vec()));
+        return Ok(());
+    }
+
+    // Loads a lut image laid out as a horizontal strip of square slices, e.g. 256x16 for a 16^3 lut.
+    pub fn load_color_grading_lut(&mut self, path: &str) -> Result<(), RendererError>
+    {
+        let image = image::open(path)
+            .map_err(|err| RendererError::InvalidLut(format!("Failed to load {}: {}", path, err)))?
+            .to_rgba8();
+        let (size, data) = post_process_system::lut_from_strip(image.width(), image.height(), image.as_raw())
+            .map_err(RendererError::InvalidLut)?;
+        return self.set_color_grading_lut(size, &data);
+    }
+
+    // Uploads the meshes added, moved or freed since the last call, growing the mesh buffers
+    // when they are full.
+    pub fn upload_meshes(&mut self, mesh_data: &mut common::MeshData)
+    {
+        self.mesh_buffers.upload(&self.device, &self.queue, mesh_data);
+    }
+
+    pub fn is_device_lost(&self) -> bool
+    {
+        return self.device_lost.load(Ordering::Relaxed);
+    }
+
+    // Creates a new device and every gpu resource again, the meshes from game_state and the
+    // settings from this renderer. Keeps the window surface. On error this renderer is kept.
+    pub fn recreate_device(&mut self, game_state: &GameState) -> Result<(), RendererError>
+    {
+        log::warn!("Recreating the device and all gpu resources");
+        let mut renderer = pollster::block_on(Self::create(
+            self._instance.clone(),
+            self.surface.clone(),
+            self.width,
+            self.height,
+            game_state,
+            self.renderer_config))?;
+
+        renderer.set_shader_hot_reload(self.shader_library.hot_reload());
+        renderer.set_pass_profiling(self.pass_profiler.enabled());
+        renderer.set_debug_text_scale(self.debug_text_system.scale());
+        renderer.ui_system.copy_textures(&renderer.device, &renderer.queue, &self.ui_system);
+        renderer.set_msaa_samples(self.render_targets.sample_count);
+        renderer.set_render_scale_settings(self.render_scale_settings);
+        renderer.set_tonemap_settings(self.tonemap_settings());
+        renderer.set_post_process_settings(self.post_process_settings());
+        if let Some((size, data)) = &self.color_grading_lut
+        {
+            if let Err(err) = renderer.set_color_grading_lut(*size, data)
+            {
+                log::error!("{}", err);
+            }
+        }
+        *self = renderer;
+        return Ok(());
+    }
+
+    pub fn update(&mut self, dt: f64, game_state: &common::GameState)
+    {
+        // Before a device recreation, which copies the ui textures.
+        self.ui_system.update_textures(&self.device, &self.queue, game_state.ui.textures_delta());
+        // Retried every update until a device can be created again.
+        if self.is_device_lost()
+        {
+            if let Err(err) = self.recreate_device(game_state)
+            {
+                log::error!("{}", err);
+                return;
+            }
+        }
+        self.reload_changed_shaders(dt);
+        if let RenderScale::Dynamic { target_frame_time, min_scale, max_scale } = self.render_scale_settings.scale
+        {
+            let scale = self.dynamic_resolution.scale();
+            if self.dynamic_resolution.update(dt, target_frame_time, min_scale, max_scale) != scale
+            {
+                self.apply_render_scale(self.render_targets.display_size);
+            }
+        }
+        let camera = game_state.scene.get_current_camera();
+        let ssao_enabled = self.ssao_system.settings().enabled;
+        let fog_enabled = self.fog_system.settings().enabled;
+        self.depth_system.update(camera, &self.queue);
+        self.ssao_system.update(camera, &self.queue);
+        self.fog_system.update(camera, &self.queue);
+        self.composite_system.update(&self.queue, ssao_enabled, fog_enabled);
+        self.bloom_system.update(&self.queue);
+        self.tonemap_system.update(&self.queue, dt);
+        self.post_process_system.update(&self.queue);
+        self.triangle_system_camera_vertices.update(camera, &self.queue);
+        self.instance_upload.update(&self.device, &self.queue, &game_state.mesh_data);
+        self.mesh_system.update(
+            &self.device,
+            &self.queue,
+            camera,
+            &game_state.mesh_data,
+            &self.mesh_buffers,
+            &self.instance_upload);
+        self.pick_system.update(
+            &self.device,
+            &self.queue,
+            camera,
+            &game_state.mesh_data,
+            &self.mesh_buffers,
+            &self.instance_upload,
+            self.render_targets.display_size);
+        self.debug_draw_system.update(camera, &self.queue, &game_state.debug_draw);
+        self.debug_text_system.update(&self.queue, &game_state.debug_overlay, self.render_targets.display_size);
+        self.ui_system.update(&self.device, &self.queue, &game_state.ui, self.render_targets.display_size);
+    }
+
+    fn reload_changed_shaders(&mut self, dt: f64)
+    {
+        let changed = self.shader_library.poll_changed(dt);
+        if changed.is_empty()
+        {
+            return;
+        }
+
+        let Self {
+            device,
+            shader_library,
+            depth_system,
+            ssao_system,
+            fog_system,
+            composite_system,
+            bloom_system,
+            tonemap_system,
+            post_process_system,
+            compute_system_copy_vertices,
+            debug_draw_system,
+            triangle_system,
+            triangle_system_vertices,
+            triangle_system_camera_vertices,
+            mesh_system,
+            pick_system,
+            blit_to_backbuffer,
+            debug_text_system,
+            ui_system,
+            ..
+        } = self;
+        let mut reloadables: [&mut dyn ShaderReloadable; 17] = [
+            depth_system,
+            ssao_system,
+            fog_system,
+            composite_system,
+            bloom_system,
+            tonemap_system,
+            post_process_system,
+            compute_system_copy_vertices,
+            debug_draw_system,
+            triangle_system,
+            triangle_system_vertices,
+            triangle_system_camera_vertices,
+            mesh_system,
+            pick_system,
+            blit_to_backbuffer,
+            debug_text_system,
+            ui_system,
+        ];
+
+        for name in changed
+        {
+            for pass in reloadables.iter_mut().filter(|pass| pass.shader_name() == name)
+            {
+                let source = match shader_library.load(name, pass.shader_defines())
+                {
+                    Ok(source) => source,
+                    Err(err) =>
+                    {
+                        log::error!("{}", err);
+                        continue;
+                    }
+                };
+                // The pass keeps its last good pipeline on failure.
+                match pass.reload_shader(device, &source)
+                {
+                    Ok(()) => log::info!("Reloaded shader {}", name),
+                    Err(err) => log::error!("Failed to reload shader {}, keeping the old pipeline: {}", name, err),
+                }
+            }
+        }
     }
 
     pub fn render(&mut self)
     {
-        let frame = self.surface
-            .get_current_texture()
-            .expect("Failed to acquire next swap chain texture");
+        if self.is_device_lost()
+        {
+            return;
+        }
+        let acquire_scope = common::profiler::scope("Acquire frame");
+        let frame = match self.surface.get_current_texture()
+        {
+            Ok(frame) => frame,
+            // The frame is skipped, the next one gets a texture from the reconfigured surface.
+            Err(SurfaceError::Lost | SurfaceError::Outdated) =>
+            {
+                self.surface.configure(&self.device, &self.config);
+                return;
+            },
+            Err(SurfaceError::Timeout) =>
+            {
+                log::warn!("Timed out acquiring the next swap chain texture, skipping the frame");
+                return;
+            },
+            Err(SurfaceError::OutOfMemory) =>
+            {
+                // Not a lost device, the next frame tries again.
+                log::error!("Out of memory acquiring the next swap chain texture, skipping the frame");
+                return;
+            },
+        };
+        drop(acquire_scope);
+        let encode_scope = common::profiler::scope("Encode");
         let back_buffer_view = frame
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
+        self.pass_profiler.begin_frame(&self.device);
         let mut encoder =
             self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-
-        self.triangle_system.render(&mut encoder, &self.render_target_texture_view);
-        self.triangle_system_vertices.render(&mut encoder, &self.render_target_texture_view);
+        let profiler = &mut self.pass_profiler;
+
+        self.instance_upload.copy(&mut encoder);
+        // With MSAA every scene pass resolves into render_target_texture before tonemapping.
+        let (scene_view, scene_resolve_target) = self.render_targets.scene_color_target();
+        profiler.begin_pass(&mut encoder, "Triangles");
+        self.triangle_system.render(&mut encoder, scene_view, scene_resolve_target);
+        profiler.end_pass(&mut encoder);
+        profiler.begin_pass(&mut encoder, "Vertices");
+        self.triangle_system_vertices.render(&mut encoder, scene_view, scene_resolve_target);
+        profiler.end_pass(&mut encoder);
+        profiler.begin_pass(&mut encoder, "Camera vertices");
         self.triangle_system_camera_vertices.render(
             &mut encoder,
-            &self.render_target_texture_view,
-            &self.render_target_depth_texture_view);
-        self.compute_system.render(&mut encoder, &self.render_target_texture_view);
+            scene_view,
+            scene_resolve_target,
+            &self.render_targets.render_target_depth_texture_view);
+        profiler.end_pass(&mut encoder);
+        profiler.begin_pass(&mut encoder, "Mesh");
+        self.mesh_system.render(
+            &mut encoder,
+            scene_view,
+            scene_resolve_target,
+            &self.render_targets.render_target_depth_texture_view);
+        profiler.end_pass(&mut encoder);
+        profiler.begin_pass(&mut encoder, "Pick");
+        self.pick_system.render(&mut encoder, self.mesh_system.draws());
+        profiler.end_pass(&mut encoder);
+
+        // The post processing ping-pongs between the two render target textures, starting
+        // from the scene in render_target_texture.
+        let mut source = PingPong::First;
+        let ssao_enabled = self.ssao_system.settings().enabled;
+        let fog_enabled = self.fog_system.settings().enabled;
+        if ssao_enabled || fog_enabled || self.debug_draw_system.needs_linear_depth()
+        {
+            profiler.begin_pass(&mut encoder, "Linear depth");
+            self.depth_system.render(&mut encoder);
+            profiler.end_pass(&mut encoder);
+        }
+        if ssao_enabled || fog_enabled
+        {
+            if ssao_enabled
+            {
+                profiler.begin_pass(&mut encoder, "Ssao");
+                self.ssao_system.render(&mut encoder);
+                profiler.end_pass(&mut encoder);
+            }
+            if fog_enabled
+            {
+                profiler.begin_pass(&mut encoder, "Fog");
+                self.fog_system.render(&mut encoder);
+                profiler.end_pass(&mut encoder);
+            }
+            profiler.begin_pass(&mut encoder, "Composite");
+            self.composite_system.render(&mut encoder, source);
+            profiler.end_pass(&mut encoder);
+            source = source.next();
+        }
+        if self.bloom_system.settings().enabled
+        {
+            profiler.begin_pass(&mut encoder, "Bloom");
+            self.bloom_system.render(&mut encoder, source);
+            profiler.end_pass(&mut encoder);
+            source = source.next();
+        }
+        profiler.begin_pass(&mut encoder, "Tonemap");
+        self.tonemap_system.render(&mut encoder, source);
+        profiler.end_pass(&mut encoder);
+        source = source.next();
+        profiler.begin_pass(&mut encoder, "Post process");
+        source = self.post_process_system.render(&mut encoder, source);
+        profiler.end_pass(&mut encoder);
+        profiler.begin_pass(&mut encoder, "Debug draw");
+        self.debug_draw_system.render(&mut encoder, source);
+        profiler.end_pass(&mut encoder);
+
+        profiler.begin_pass(&mut encoder, "Copy vertices");
         self.compute_system_copy_vertices.render(&mut encoder);
-        self.blit_to_backbuffer.render(&mut encoder, &back_buffer_view);
+        profiler.end_pass(&mut encoder);
+        profiler.begin_pass(&mut encoder, "Blit");
+        self.blit_to_backbuffer.render(
+            &mut encoder,
+            &back_buffer_view,
+            &self.render_targets.upscale_texture_view,
+            source);
+        profiler.end_pass(&mut encoder);
+        // The overlay goes over the final image, after the upscale.
+        profiler.begin_pass(&mut encoder, "Debug text");
+        self.debug_text_system.render(&mut encoder, &back_buffer_view);
+        profiler.end_pass(&mut encoder);
+        profiler.begin_pass(&mut encoder, "Ui");
+        self.ui_system.render(&mut encoder, &back_buffer_view);
+        profiler.end_pass(&mut encoder);
+        profiler.end_frame(&mut encoder);
         /*
         encoder.copy_texture_to_texture(
             wgpu::ImageCopyTexture {
-                texture: &self.render_target_texture,
+                texture: &self.render_targets.render_target_texture,
                 mip_level: 0,
                 origin: wgpu::Origin3d::ZERO,
                 aspect: TextureAspect::All
@@ -456,59 +941,119 @@ impl Renderer
                 origin: wgpu::Origin3d::ZERO,
                 aspect: TextureAspect::All
             },
-            self.render_target_texture.size()
+            self.render_targets.render_target_texture.size()
         );
         */
+        drop(encode_scope);
+        let submit_scope = common::profiler::scope("Submit");
         self.queue.submit(Some(encoder.finish()));
+        self.pass_profiler.after_submit();
+        self.instance_upload.after_submit();
+        self.pick_system.after_submit();
+        drop(submit_scope);
 
 
 
 
+        let _present_scope = common::profiler::scope("Present");
+        let suboptimal = frame.suboptimal;
         frame.present();
+        if suboptimal
+        {
+            self.surface.configure(&self.device, &self.config);
+        }
     }
 
     pub fn resize(&mut self, width: u32, height: u32)
     {
-        if width == self.width && height == self.height
+        let size = ScreenSize::new(width, height);
+        if size == self.render_targets.display_size
         {
             return;
         }
-        let width = std::cmp::max(4u32, width);
-        let height = std::cmp::max(4u32, height);
         // Reconfigure the surface with the new size
-        self.width = width;
-        self.height = height;
-        self.config.width = width;
-        self.config.height = height;
+        self.width = size.width;
+        self.height = size.height;
+        self.config.width = size.width;
+        self.config.height = size.height;
         self.surface.configure(&self.device, &self.config);
-        //self.render_target_texture.destroy();
-        //self.render_target_texture2.destroy();
-
-        let (render_target_texture, render_target_texture2, render_target_texture_view) =
-            Self::create_render_target_textures(&self.device, width, height);
-
-        let (render_target_depth_texture, render_target_depth_texture_view, render_target_depth_texture_sampler) =
-            Self::create_depth_texture(&self.device, &self.config);
 
+        self.apply_render_scale(size);
+    }
 
-        self.compute_system.rebind_textures(
-            &self.device,
-            &render_target_texture,
-            &render_target_texture2
-        );
-        self.blit_to_backbuffer.rebind_texture(
-            &self.device,
-            &render_target_texture2,
-        );
-        self.render_target_texture = render_target_texture;
-        self.render_target_texture2 = render_target_texture2;
-        self.render_target_texture_view = render_target_texture_view;
+    fn rebind_screen_size_dependents(&mut self)
+    {
+        let Self {
+            device,
+            render_targets,
+            depth_system,
+            ssao_system,
+            fog_system,
+            composite_system,
+            bloom_system,
+            tonemap_system,
+            post_process_system,
+            compute_system_copy_vertices,
+            debug_draw_system,
+            blit_to_backbuffer,
+            ..
+        } = self;
+        let screen_size_dependents = screen_size_dependents(
+            depth_system,
+            ssao_system,
+            fog_system,
+            composite_system,
+            bloom_system,
+            tonemap_system,
+            post_process_system,
+            compute_system_copy_vertices,
+            debug_draw_system,
+            blit_to_backbuffer);
+        for pass in screen_size_dependents
+        {
+            pass.rebind(device, render_targets);
+        }
+    }
+}
 
-        self.render_target_depth_texture = render_target_depth_texture;
-        self.render_target_depth_texture_view = render_target_depth_texture_view;
-        self.render_target_depth_texture_sampler = render_target_depth_texture_sampler;
+// wgpu 0.16 has no error variant for a lost device, it comes as a validation error with the
+// wgpu-core DeviceError::Lost only in the source chain, so its message is all there is to check.
+fn is_device_lost_error(error: &wgpu::Error) -> bool
+{
+    return match error
+    {
+        wgpu::Error::Validation { description, .. } => description.contains("device is lost"),
+        wgpu::Error::OutOfMemory { .. } => false,
+    };
+}
 
-    }
+// Every pass using screen sized resources has to be listed here to get rebound when the render
+// targets are recreated. The resize test rebinds through this too.
+pub(crate) fn screen_size_dependents<'a>(
+    depth_system: &'a mut depth_system::TriangleSystem,
+    ssao_system: &'a mut ssao_system::TriangleSystem,
+    fog_system: &'a mut fog_system::TriangleSystem,
+    composite_system: &'a mut composite_system::TriangleSystem,
+    bloom_system: &'a mut bloom_system::TriangleSystem,
+    tonemap_system: &'a mut tonemap_system::TriangleSystem,
+    post_process_system: &'a mut post_process_system::TriangleSystem,
+    compute_system_copy_vertices: &'a mut compute_system_copy_vertices::TriangleSystem,
+    debug_draw_system: &'a mut debug_draw_system::TriangleSystem,
+    blit_to_backbuffer: &'a mut blit_to_backbuffer::TriangleSystem
+) -> [&'a mut dyn ScreenSizeDependent; 10]
+{
+    return [
+        depth_system,
+        ssao_system,
+        fog_system,
+        composite_system,
+        bloom_system,
+        tonemap_system,
+        post_process_system,
+        compute_system_copy_vertices,
+        debug_draw_system,
+        blit_to_backbuffer,
+    ];
 }
 
 impl Drop for Renderer
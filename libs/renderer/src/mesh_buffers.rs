@@ -0,0 +1,92 @@
+use std::mem::size_of;
+
+use wgpu::*;
+
+// Elements the buffers have room for at least, they grow in powers of two.
+const MIN_VERTICES: usize = 64 * 1024;
+const MIN_INDICES: usize = 256 * 1024;
+
+// The vertices and indices of every model in common::MeshData, mirrored with the same layout.
+// Changed ranges are written with write_buffer, a buffer too small for the data is replaced by
+// a bigger one holding all of it.
+pub(crate) struct MeshBuffers
+{
+    pub(crate) vertices: Buffer,
+    pub(crate) indices: Buffer,
+    // Bumped when a buffer is replaced, the passes binding them check it.
+    generation: u32,
+}
+
+impl MeshBuffers
+{
+    pub(crate) fn new(device: &Device, mesh_data: &common::MeshData) -> Self
+    {
+        Self
+        {
+            vertices: Self::create_buffer(device, "Vertex Buffer all", MIN_VERTICES, &mesh_data.vertices),
+            indices: Self::create_buffer(device, "Index Buffer all", MIN_INDICES, &mesh_data.indices),
+            generation: 0,
+        }
+    }
+
+    pub(crate) fn generation(&self) -> u32
+    {
+        return self.generation;
+    }
+
+    fn create_buffer<T: bytemuck::Pod>(device: &Device, label: &str, min_elements: usize, data: &[T]) -> Buffer
+    {
+        let capacity = data.len().max(min_elements).next_power_of_two();
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: (capacity * size_of::<T>()) as BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: true,
+        });
+        let bytes: &[u8] = bytemuck::cast_slice(data);
+        if !bytes.is_empty()
+        {
+            buffer.slice(..bytes.len() as BufferAddress).get_mapped_range_mut().copy_from_slice(bytes);
+        }
+        buffer.unmap();
+        return buffer;
+    }
+
+    // Uploads what was added, moved or freed in the mesh data since the last upload.
+    pub(crate) fn upload(&mut self, device: &Device, queue: &Queue, mesh_data: &mut common::MeshData)
+    {
+        let (vertices, indices) = mesh_data.take_changes();
+        let vertices_replaced = Self::upload_range(
+            device, queue, &mut self.vertices, "Vertex Buffer all", MIN_VERTICES, &mesh_data.vertices, vertices);
+        let indices_replaced = Self::upload_range(
+            device, queue, &mut self.indices, "Index Buffer all", MIN_INDICES, &mesh_data.indices, indices);
+        if vertices_replaced || indices_replaced
+        {
+            self.generation += 1;
+        }
+    }
+
+    fn upload_range<T: bytemuck::Pod>(
+        device: &Device,
+        queue: &Queue,
+        buffer: &mut Buffer,
+        label: &str,
+        min_elements: usize,
+        data: &[T],
+        range: std::ops::Range<usize>,
+    ) -> bool
+    {
+        if range.is_empty()
+        {
+            return false;
+        }
+        if (data.len() * size_of::<T>()) as BufferAddress > buffer.size()
+        {
+            log::info!("Growing {} to {} elements", label, data.len().next_power_of_two());
+            *buffer = Self::create_buffer(device, label, min_elements, data);
+            return true;
+        }
+        queue.write_buffer(buffer, (range.start * size_of::<T>()) as BufferAddress, bytemuck::cast_slice(&data[range]));
+        return false;
+    }
+}
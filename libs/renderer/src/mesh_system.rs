@@ -0,0 +1,387 @@
+use wgpu::*;
+use wgpu::util::DeviceExt;
+
+use crate::instance_upload::{InstanceUpload, MAX_INSTANCES};
+use crate::mesh_buffers::MeshBuffers;
+use crate::shaders::{self, ShaderInterface, ShaderReloadable};
+use crate::triangle_system_camera_vertices::CameraUniform;
+
+const SHADER_NAME: &str = "mesh.wgsl";
+const VERTEX_ENTRY: &str = "vs_main";
+const FRAGMENT_ENTRY: &str = "fs_main";
+
+const fn storage_entry(binding: u32) -> wgpu::BindGroupLayoutEntry
+{
+    return wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::VERTEX,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: true },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+}
+
+const BIND_GROUP_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] = &[
+    // Camera
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::VERTEX,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+    // Mesh vertices
+    storage_entry(1),
+    // Mesh indices
+    storage_entry(2),
+    // Instance model locations
+    storage_entry(3),
+    // Instance transforms
+    storage_entry(4),
+];
+
+pub(crate) const SHADER_INTERFACE: ShaderInterface = ShaderInterface {
+    shader_name: SHADER_NAME,
+    entry_points: &[
+        (wgpu::ShaderStages::VERTEX, VERTEX_ENTRY),
+        (wgpu::ShaderStages::FRAGMENT, FRAGMENT_ENTRY),
+    ],
+    bind_group_layouts: &[BIND_GROUP_LAYOUT_ENTRIES],
+    vertex_attributes: &[],
+    defines: &[],
+};
+
+// Instances in a row drawing the same model, drawn with one call.
+pub(crate) struct InstanceDraw
+{
+    pub(crate) index_count: u32,
+    pub(crate) instances: std::ops::Range<u32>,
+    pub(crate) transparent: bool,
+}
+
+// Draws the MeshData instances uploaded by InstanceUpload into the scene, after the camera
+// vertices pass cleared the depth. The transparent instances at the end are blended without
+// writing depth.
+pub struct TriangleSystem
+{
+    _shader: ShaderModule,
+    _bind_group_layout: BindGroupLayout,
+    _pipeline_layout: PipelineLayout,
+    render_pipeline: RenderPipeline,
+    transparent_pipeline: RenderPipeline,
+    bind_group: BindGroup,
+    // The MeshBuffers generation the bind group has.
+    mesh_buffers_generation: u32,
+
+    texture_format: TextureFormat,
+    depth_texture_format: TextureFormat,
+    sample_count: u32,
+
+    camera_buffer: Buffer,
+    draws: Vec<InstanceDraw>,
+}
+
+impl TriangleSystem
+{
+    pub fn new(
+        device: &Device,
+        texture_format: TextureFormat,
+        depth_texture_format: TextureFormat,
+        sample_count: u32,
+        mesh_buffers: &MeshBuffers,
+        instance_upload: &InstanceUpload,
+    ) -> Self
+    {
+        let _shader = shaders::create_shader_module(device, SHADER_NAME, &shaders::embedded_source(SHADER_NAME));
+
+        let _bind_group_layout = SHADER_INTERFACE.create_bind_group_layout(device, 0, "Mesh bindings");
+
+        let _pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor
+        {
+            label: None,
+            bind_group_layouts: &[&_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let render_pipeline = Self::create_pipeline(
+            device, &_pipeline_layout, &_shader, texture_format, depth_texture_format, sample_count, false);
+        let transparent_pipeline = Self::create_pipeline(
+            device, &_pipeline_layout, &_shader, texture_format, depth_texture_format, sample_count, true);
+
+        let camera_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Mesh camera"),
+                contents: bytemuck::cast_slice(&[CameraUniform::new()]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let bind_group = Self::create_bind_group(device, &_bind_group_layout, &camera_buffer, mesh_buffers, instance_upload);
+
+        Self {
+            _shader,
+            _bind_group_layout,
+            _pipeline_layout,
+            render_pipeline,
+            transparent_pipeline,
+            bind_group,
+            mesh_buffers_generation: mesh_buffers.generation(),
+
+            texture_format,
+            depth_texture_format,
+            sample_count,
+
+            camera_buffer,
+            draws: Vec::new(),
+        }
+    }
+
+    fn create_pipeline(
+        device: &Device,
+        pipeline_layout: &PipelineLayout,
+        shader: &ShaderModule,
+        texture_format: TextureFormat,
+        depth_texture_format: TextureFormat,
+        sample_count: u32,
+        transparent: bool
+    ) -> RenderPipeline
+    {
+        let blend = if transparent { Some(wgpu::BlendState::ALPHA_BLENDING) } else { None };
+        return device.create_render_pipeline(&wgpu::RenderPipelineDescriptor
+        {
+            label: Some(if transparent { "Mesh transparent" } else { "Mesh" }),
+            layout: Some(pipeline_layout),
+            vertex: wgpu::VertexState
+            {
+                module: shader,
+                entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::VERTEX, VERTEX_ENTRY),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState
+            {
+                module: shader,
+                entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::FRAGMENT, FRAGMENT_ENTRY),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: texture_format,
+                    blend,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth_texture_format,
+                depth_write_enabled: !transparent,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+        });
+    }
+
+    fn create_bind_group(
+        device: &Device,
+        bind_group_layout: &BindGroupLayout,
+        camera_buffer: &Buffer,
+        mesh_buffers: &MeshBuffers,
+        instance_upload: &InstanceUpload,
+    ) -> BindGroup
+    {
+        return device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Mesh Bind Group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: mesh_buffers.vertices.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: mesh_buffers.indices.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: instance_upload.model_data.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: instance_upload.model_transforms.as_entire_binding(),
+                },
+            ],
+        });
+    }
+
+    // Rebuilds the pipeline to render into targets with a different sample count.
+    pub fn set_sample_count(&mut self, device: &Device, sample_count: u32)
+    {
+        self.sample_count = sample_count;
+        self.render_pipeline = Self::create_pipeline(
+            device,
+            &self._pipeline_layout,
+            &self._shader,
+            self.texture_format,
+            self.depth_texture_format,
+            sample_count,
+            false);
+        self.transparent_pipeline = Self::create_pipeline(
+            device,
+            &self._pipeline_layout,
+            &self._shader,
+            self.texture_format,
+            self.depth_texture_format,
+            sample_count,
+            true);
+    }
+
+    pub fn update(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        camera: &common::Camera,
+        mesh_data: &common::MeshData,
+        mesh_buffers: &MeshBuffers,
+        instance_upload: &InstanceUpload,
+    )
+    {
+        if mesh_buffers.generation() != self.mesh_buffers_generation
+        {
+            self.bind_group = Self::create_bind_group(
+                device,
+                &self._bind_group_layout,
+                &self.camera_buffer,
+                mesh_buffers,
+                instance_upload);
+            self.mesh_buffers_generation = mesh_buffers.generation();
+        }
+
+        let mut camera_uniform = CameraUniform::new();
+        camera_uniform.update_view_proj(camera);
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[camera_uniform]));
+
+        self.draws.clear();
+        let count = (instance_upload.instance_count() as usize).min(MAX_INSTANCES);
+        let transparent_start = mesh_data.transparent_instances_start();
+        let locations = &mesh_data.gpu_out_instance_mesh_model_locations[..count];
+        for (instance, location) in locations.iter().enumerate()
+        {
+            let previous = instance.checked_sub(1).map(|previous| locations[previous]);
+            let transparent = instance >= transparent_start;
+            match self.draws.last_mut()
+            {
+                Some(draw) if previous == Some(*location) && draw.transparent == transparent => draw.instances.end += 1,
+                _ =>
+                {
+                    let instance = instance as u32;
+                    self.draws.push(InstanceDraw {
+                        index_count: location.indices_count,
+                        instances: instance..instance + 1,
+                        transparent,
+                    });
+                },
+            }
+        }
+    }
+
+    // This frame's draws, also used by the pick pass.
+    pub(crate) fn draws(&self) -> &[InstanceDraw]
+    {
+        return &self.draws;
+    }
+
+    pub fn render(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        view: &TextureView,
+        resolve_target: Option<&TextureView>,
+        depth_view: &TextureView
+    )
+    {
+        if self.draws.is_empty()
+        {
+            return;
+        }
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor
+        {
+            label: Some("Mesh"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment
+            {
+                view,
+                resolve_target,
+                ops: wgpu::Operations
+                {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        let mut transparent = None;
+        for draw in &self.draws
+        {
+            if transparent != Some(draw.transparent)
+            {
+                transparent = Some(draw.transparent);
+                render_pass.set_pipeline(if draw.transparent { &self.transparent_pipeline } else { &self.render_pipeline });
+            }
+            render_pass.draw(0..draw.index_count, draw.instances.clone());
+        }
+    }
+}
+
+impl ShaderReloadable for TriangleSystem
+{
+    fn shader_name(&self) -> &'static str
+    {
+        return SHADER_NAME;
+    }
+
+    fn reload_shader(&mut self, device: &Device, source: &str) -> Result<(), String>
+    {
+        let (shader, render_pipeline, transparent_pipeline) = shaders::validated(device, || {
+            let shader = shaders::create_shader_module(device, SHADER_NAME, source);
+            let render_pipeline = Self::create_pipeline(
+                device,
+                &self._pipeline_layout,
+                &shader,
+                self.texture_format,
+                self.depth_texture_format,
+                self.sample_count,
+                false);
+            let transparent_pipeline = Self::create_pipeline(
+                device,
+                &self._pipeline_layout,
+                &shader,
+                self.texture_format,
+                self.depth_texture_format,
+                self.sample_count,
+                true);
+            (shader, render_pipeline, transparent_pipeline)
+        })?;
+        self._shader = shader;
+        self.render_pipeline = render_pipeline;
+        self.transparent_pipeline = transparent_pipeline;
+        return Ok(());
+    }
+}
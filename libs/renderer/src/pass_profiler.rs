@@ -0,0 +1,342 @@
+use std::collections::VecDeque;
+use std::mem::size_of;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::Instant;
+
+use wgpu::*;
+
+// Most passes measured in one frame, the rest of the frame's passes are not timed.
+const MAX_PASSES: u32 = 32;
+// Frames whose timestamps can wait for the readback at the same time. A frame gets no gpu
+// timings when all of them are in use.
+const READBACK_FRAMES: usize = 3;
+// Number of frames in the rolling average.
+const AVERAGE_FRAMES: usize = 60;
+
+const TIMESTAMPS_SIZE: BufferAddress = (MAX_PASSES as usize * 2 * size_of::<u64>()) as BufferAddress;
+
+// States of a readback buffer mapping, written from the map_async callback.
+const MAP_PENDING: u8 = 0;
+const MAP_DONE: u8 = 1;
+const MAP_FAILED: u8 = 2;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TimingSource
+{
+    // Timestamps written by the gpu before and after every pass.
+    Gpu,
+    // Time spent recording the pass on the cpu, used when the adapter has no timestamp queries.
+    Cpu,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassTiming
+{
+    pub name: &'static str,
+    // Milliseconds of the latest measured frame.
+    pub last: f64,
+    // Milliseconds averaged over the last AVERAGE_FRAMES measured frames.
+    pub average: f64,
+}
+
+struct PassHistory
+{
+    name: &'static str,
+    samples: VecDeque<f64>,
+}
+
+struct Readback
+{
+    buffer: Buffer,
+    passes: Vec<&'static str>,
+    // From the frame's submit until its timestamps are read.
+    in_use: bool,
+    map_state: Arc<AtomicU8>,
+}
+
+struct GpuQueries
+{
+    query_set: QuerySet,
+    resolve_buffer: Buffer,
+    readbacks: Vec<Readback>,
+    // Readback of the frame being recorded.
+    current: Option<usize>,
+    // Nanoseconds per timestamp tick.
+    timestamp_period: f64,
+}
+
+// Times the render passes of a frame, with gpu timestamps when the device has TIMESTAMP_QUERY.
+// Gpu timings arrive a few frames late, after their readback buffer is mapped.
+pub struct PassProfiler
+{
+    enabled: bool,
+    gpu: Option<GpuQueries>,
+    frame_passes: Vec<&'static str>,
+    cpu_pass_start: Option<Instant>,
+    cpu_frame_timings: Vec<(&'static str, f64)>,
+    history: Vec<PassHistory>,
+}
+
+impl PassProfiler
+{
+    pub fn new(device: &Device, queue: &Queue) -> Self
+    {
+        let gpu = if device.features().contains(Features::TIMESTAMP_QUERY)
+        {
+            Some(GpuQueries::new(device, queue))
+        }
+        else
+        {
+            None
+        };
+
+        Self
+        {
+            enabled: false,
+            gpu,
+            frame_passes: Vec::new(),
+            cpu_pass_start: None,
+            cpu_frame_timings: Vec::new(),
+            history: Vec::new(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool
+    {
+        return self.enabled;
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool)
+    {
+        self.enabled = enabled;
+        if !enabled
+        {
+            self.history.clear();
+        }
+    }
+
+    pub fn source(&self) -> TimingSource
+    {
+        return if self.gpu.is_some() { TimingSource::Gpu } else { TimingSource::Cpu };
+    }
+
+    // Passes in the order of the latest measured frame.
+    pub fn timings(&self) -> Vec<PassTiming>
+    {
+        return self.history
+            .iter()
+            .map(|pass| PassTiming
+            {
+                name: pass.name,
+                last: *pass.samples.back().unwrap_or(&0.0),
+                average: pass.samples.iter().sum::<f64>() / pass.samples.len().max(1) as f64,
+            })
+            .collect();
+    }
+
+    // Reads the timestamps of earlier frames that are ready, called before recording a frame.
+    pub fn begin_frame(&mut self, device: &Device)
+    {
+        self.frame_passes.clear();
+        self.cpu_pass_start = None;
+        self.cpu_frame_timings.clear();
+        if !self.enabled
+        {
+            return;
+        }
+
+        let mut finished = Vec::new();
+        if let Some(gpu) = &mut self.gpu
+        {
+            device.poll(Maintain::Poll);
+            for readback in gpu.readbacks.iter_mut().filter(|readback| readback.in_use)
+            {
+                match readback.map_state.load(Ordering::Acquire)
+                {
+                    MAP_DONE =>
+                    {
+                        finished.push(readback.read(gpu.timestamp_period));
+                        readback.buffer.unmap();
+                    },
+                    MAP_FAILED => log::warn!("Failed to read the pass timestamps"),
+                    _ => continue,
+                }
+                readback.in_use = false;
+            }
+            gpu.current = gpu.readbacks.iter().position(|readback| !readback.in_use);
+        }
+        for timings in finished
+        {
+            self.record_frame(&timings);
+        }
+    }
+
+    pub fn begin_pass(&mut self, encoder: &mut CommandEncoder, name: &'static str)
+    {
+        let index = self.frame_passes.len() as u32;
+        if !self.enabled || index >= MAX_PASSES
+        {
+            return;
+        }
+        self.frame_passes.push(name);
+        match &self.gpu
+        {
+            Some(GpuQueries { query_set, current: Some(_), .. }) => encoder.write_timestamp(query_set, index * 2),
+            Some(_) => {},
+            None => self.cpu_pass_start = Some(Instant::now()),
+        }
+    }
+
+    pub fn end_pass(&mut self, encoder: &mut CommandEncoder)
+    {
+        if !self.enabled || self.frame_passes.is_empty()
+        {
+            return;
+        }
+        let index = self.frame_passes.len() as u32 - 1;
+        match &self.gpu
+        {
+            Some(GpuQueries { query_set, current: Some(_), .. }) => encoder.write_timestamp(query_set, index * 2 + 1),
+            Some(_) => {},
+            None =>
+            {
+                if let Some(start) = self.cpu_pass_start.take()
+                {
+                    let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+                    self.cpu_frame_timings.push((self.frame_passes[index as usize], elapsed));
+                }
+            },
+        }
+    }
+
+    // Copies the frame's timestamps to its readback buffer, called before finishing the encoder.
+    pub fn end_frame(&mut self, encoder: &mut CommandEncoder)
+    {
+        if !self.enabled || self.frame_passes.is_empty()
+        {
+            return;
+        }
+        let Some(gpu) = &mut self.gpu else
+        {
+            let timings = std::mem::take(&mut self.cpu_frame_timings);
+            self.record_frame(&timings);
+            return;
+        };
+        let Some(current) = gpu.current else
+        {
+            return;
+        };
+        let query_count = self.frame_passes.len() as u32 * 2;
+        let readback = &mut gpu.readbacks[current];
+        encoder.resolve_query_set(&gpu.query_set, 0..query_count, &gpu.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &gpu.resolve_buffer,
+            0,
+            &readback.buffer,
+            0,
+            (query_count as usize * size_of::<u64>()) as BufferAddress);
+        readback.passes = self.frame_passes.clone();
+    }
+
+    // Starts mapping the frame's readback buffer, which is only allowed after the submit.
+    pub fn after_submit(&mut self)
+    {
+        if !self.enabled || self.frame_passes.is_empty()
+        {
+            return;
+        }
+        let Some(GpuQueries { readbacks, current: Some(current), .. }) = &mut self.gpu else
+        {
+            return;
+        };
+        let readback = &mut readbacks[*current];
+        readback.in_use = true;
+        readback.map_state.store(MAP_PENDING, Ordering::Release);
+        let map_state = readback.map_state.clone();
+        readback.buffer.slice(..).map_async(MapMode::Read, move |result| {
+            map_state.store(if result.is_ok() { MAP_DONE } else { MAP_FAILED }, Ordering::Release);
+        });
+    }
+
+    fn record_frame(&mut self, timings: &[(&'static str, f64)])
+    {
+        // Passes that didn't run in this frame are dropped, the rest follow its order.
+        let mut history = Vec::with_capacity(timings.len());
+        for &(name, milliseconds) in timings
+        {
+            let mut pass = match self.history.iter().position(|pass| pass.name == name)
+            {
+                Some(index) => self.history.swap_remove(index),
+                None => PassHistory { name, samples: VecDeque::with_capacity(AVERAGE_FRAMES) },
+            };
+            if pass.samples.len() == AVERAGE_FRAMES
+            {
+                pass.samples.pop_front();
+            }
+            pass.samples.push_back(milliseconds);
+            history.push(pass);
+        }
+        self.history = history;
+    }
+}
+
+impl GpuQueries
+{
+    fn new(device: &Device, queue: &Queue) -> Self
+    {
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Pass timestamps"),
+            ty: wgpu::QueryType::Timestamp,
+            count: MAX_PASSES * 2,
+        });
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Pass timestamps resolve"),
+            size: TIMESTAMPS_SIZE,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readbacks = (0..READBACK_FRAMES)
+            .map(|_| Readback
+            {
+                buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Pass timestamps readback"),
+                    size: TIMESTAMPS_SIZE,
+                    usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }),
+                passes: Vec::new(),
+                in_use: false,
+                map_state: Arc::new(AtomicU8::new(MAP_PENDING)),
+            })
+            .collect();
+
+        Self
+        {
+            query_set,
+            resolve_buffer,
+            readbacks,
+            current: None,
+            timestamp_period: queue.get_timestamp_period() as f64,
+        }
+    }
+}
+
+impl Readback
+{
+    // Milliseconds of every pass, from the mapped buffer.
+    fn read(&self, timestamp_period: f64) -> Vec<(&'static str, f64)>
+    {
+        let data = self.buffer.slice(..).get_mapped_range();
+        let timestamps: &[u64] = bytemuck::cast_slice(&data);
+        return self.passes
+            .iter()
+            .enumerate()
+            .map(|(index, &name)| {
+                // Saturating, some drivers give out of order timestamps around power state changes.
+                let ticks = timestamps[index * 2 + 1].saturating_sub(timestamps[index * 2]);
+                (name, ticks as f64 * timestamp_period / 1_000_000.0)
+            })
+            .collect();
+    }
+}
@@ -0,0 +1,421 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use wgpu::*;
+use wgpu::util::DeviceExt;
+
+use crate::instance_upload::InstanceUpload;
+use crate::mesh_buffers::MeshBuffers;
+use crate::mesh_system::InstanceDraw;
+use crate::render_targets::ScreenSize;
+use crate::shaders::{self, ShaderInterface, ShaderReloadable};
+use crate::triangle_system_camera_vertices::CameraUniform;
+
+const SHADER_NAME: &str = "pick.wgsl";
+const VERTEX_ENTRY: &str = "vs_main";
+const FRAGMENT_ENTRY: &str = "fs_main";
+
+const ID_FORMAT: TextureFormat = TextureFormat::R32Uint;
+const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+
+// States of the readback buffer mapping, written from the map_async callback.
+const MAP_PENDING: u8 = 0;
+const MAP_DONE: u8 = 1;
+const MAP_FAILED: u8 = 2;
+
+const fn storage_entry(binding: u32) -> wgpu::BindGroupLayoutEntry
+{
+    return wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::VERTEX,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: true },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+}
+
+const BIND_GROUP_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] = &[
+    // Camera zoomed to the picked pixel
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::VERTEX,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+    // Mesh vertices
+    storage_entry(1),
+    // Mesh indices
+    storage_entry(2),
+    // Instance model locations
+    storage_entry(3),
+    // Instance transforms
+    storage_entry(4),
+];
+
+pub(crate) const SHADER_INTERFACE: ShaderInterface = ShaderInterface {
+    shader_name: SHADER_NAME,
+    entry_points: &[
+        (wgpu::ShaderStages::VERTEX, VERTEX_ENTRY),
+        (wgpu::ShaderStages::FRAGMENT, FRAGMENT_ENTRY),
+    ],
+    bind_group_layouts: &[BIND_GROUP_LAYOUT_ENTRIES],
+    vertex_attributes: &[],
+    defines: &[],
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct GpuPick
+{
+    // The window pixel that was picked.
+    pub x: u32,
+    pub y: u32,
+    // The instance drawn at the pixel in the picked frame, and its entity.
+    pub instance: Option<usize>,
+    pub entity: Option<usize>,
+}
+
+// A pick rendered and waiting for its readback.
+struct InFlight
+{
+    position: [u32; 2],
+    instance_entities: Vec<Option<usize>>,
+}
+
+// Picks the instance under a window pixel on the gpu. The instances are drawn with their ids
+// into a 1x1 target the pixel is zoomed to, which is read back a frame or more later. Another
+// way to the same result as raycasting the scene on the cpu.
+pub struct TriangleSystem
+{
+    _shader: ShaderModule,
+    _bind_group_layout: BindGroupLayout,
+    _pipeline_layout: PipelineLayout,
+    render_pipeline: RenderPipeline,
+    bind_group: BindGroup,
+    // The MeshBuffers generation the bind group has.
+    mesh_buffers_generation: u32,
+
+    id_texture: Texture,
+    id_texture_view: TextureView,
+    _depth_texture: Texture,
+    depth_texture_view: TextureView,
+    readback_buffer: Buffer,
+    map_state: Arc<AtomicU8>,
+
+    camera_buffer: Buffer,
+    requested: Option<[u32; 2]>,
+    // Set in update when this frame renders the requested pick.
+    render_pick: bool,
+    in_flight: Option<InFlight>,
+    result: Option<GpuPick>,
+}
+
+impl TriangleSystem
+{
+    pub fn new(device: &Device, mesh_buffers: &MeshBuffers, instance_upload: &InstanceUpload) -> Self
+    {
+        let _shader = shaders::create_shader_module(device, SHADER_NAME, &shaders::embedded_source(SHADER_NAME));
+
+        let _bind_group_layout = SHADER_INTERFACE.create_bind_group_layout(device, 0, "Pick bindings");
+
+        let _pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor
+        {
+            label: None,
+            bind_group_layouts: &[&_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let render_pipeline = Self::create_pipeline(device, &_pipeline_layout, &_shader);
+
+        let camera_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Pick camera"),
+                contents: bytemuck::cast_slice(&[CameraUniform::new()]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let bind_group = Self::create_bind_group(device, &_bind_group_layout, &camera_buffer, mesh_buffers, instance_upload);
+
+        let (id_texture, id_texture_view) = Self::create_texture(
+            device, "Pick ids", ID_FORMAT, wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC);
+        let (_depth_texture, depth_texture_view) = Self::create_texture(
+            device, "Pick depth", DEPTH_FORMAT, wgpu::TextureUsages::RENDER_ATTACHMENT);
+
+        // One texel, padded to the row alignment of texture copies.
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Pick readback"),
+            size: wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            _shader,
+            _bind_group_layout,
+            _pipeline_layout,
+            render_pipeline,
+            bind_group,
+            mesh_buffers_generation: mesh_buffers.generation(),
+
+            id_texture,
+            id_texture_view,
+            _depth_texture,
+            depth_texture_view,
+            readback_buffer,
+            map_state: Arc::new(AtomicU8::new(MAP_PENDING)),
+
+            camera_buffer,
+            requested: None,
+            render_pick: false,
+            in_flight: None,
+            result: None,
+        }
+    }
+
+    fn create_texture(device: &Device, label: &str, format: TextureFormat, usage: TextureUsages) -> (Texture, TextureView)
+    {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        return (texture, view);
+    }
+
+    fn create_pipeline(device: &Device, pipeline_layout: &PipelineLayout, shader: &ShaderModule) -> RenderPipeline
+    {
+        return device.create_render_pipeline(&wgpu::RenderPipelineDescriptor
+        {
+            label: Some("Pick"),
+            layout: Some(pipeline_layout),
+            vertex: wgpu::VertexState
+            {
+                module: shader,
+                entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::VERTEX, VERTEX_ENTRY),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState
+            {
+                module: shader,
+                entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::FRAGMENT, FRAGMENT_ENTRY),
+                targets: &[Some(ID_FORMAT.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+    }
+
+    fn create_bind_group(
+        device: &Device,
+        bind_group_layout: &BindGroupLayout,
+        camera_buffer: &Buffer,
+        mesh_buffers: &MeshBuffers,
+        instance_upload: &InstanceUpload,
+    ) -> BindGroup
+    {
+        return device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Pick Bind Group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: camera_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: mesh_buffers.vertices.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: mesh_buffers.indices.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: instance_upload.model_data.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: instance_upload.model_transforms.as_entire_binding(),
+                },
+            ],
+        });
+    }
+
+    // A newer request replaces one that was not rendered yet.
+    pub fn request(&mut self, x: u32, y: u32)
+    {
+        self.requested = Some([x, y]);
+    }
+
+    pub fn take_result(&mut self) -> Option<GpuPick>
+    {
+        return self.result.take();
+    }
+
+    // Reads a finished pick, then starts the requested one when no pick is in flight.
+    pub fn update(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        camera: &common::Camera,
+        mesh_data: &common::MeshData,
+        mesh_buffers: &MeshBuffers,
+        instance_upload: &InstanceUpload,
+        display_size: ScreenSize,
+    )
+    {
+        if mesh_buffers.generation() != self.mesh_buffers_generation
+        {
+            self.bind_group = Self::create_bind_group(
+                device,
+                &self._bind_group_layout,
+                &self.camera_buffer,
+                mesh_buffers,
+                instance_upload);
+            self.mesh_buffers_generation = mesh_buffers.generation();
+        }
+
+        self.render_pick = false;
+        if let Some(in_flight) = &self.in_flight
+        {
+            device.poll(Maintain::Poll);
+            match self.map_state.load(Ordering::Acquire)
+            {
+                MAP_DONE =>
+                {
+                    let id = bytemuck::pod_read_unaligned::<u32>(&self.readback_buffer.slice(..4).get_mapped_range());
+                    self.readback_buffer.unmap();
+                    let instance = (id as usize).checked_sub(1);
+                    self.result = Some(GpuPick {
+                        x: in_flight.position[0],
+                        y: in_flight.position[1],
+                        instance,
+                        entity: instance.and_then(|instance| in_flight.instance_entities.get(instance).copied().flatten()),
+                    });
+                },
+                MAP_FAILED => log::warn!("Failed to read the picked instance"),
+                _ => return,
+            }
+            self.in_flight = None;
+        }
+
+        let Some([x, y]) = self.requested.take() else
+        {
+            return;
+        };
+        let view_proj = camera.pick_view_projection_matrix(
+            x as f32 + 0.5,
+            y as f32 + 0.5,
+            display_size.width as f32,
+            display_size.height as f32);
+        let camera_uniform = CameraUniform { view_proj: view_proj.to_cols_array() };
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[camera_uniform]));
+        self.in_flight = Some(InFlight { position: [x, y], instance_entities: mesh_data.instance_entities().to_vec() });
+        self.render_pick = true;
+    }
+
+    // Draws the mesh pass's instances when a pick was started this frame.
+    pub fn render(&mut self, encoder: &mut CommandEncoder, draws: &[InstanceDraw])
+    {
+        if !self.render_pick
+        {
+            return;
+        }
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor
+            {
+                label: Some("Pick"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment
+                {
+                    view: &self.id_texture_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations
+                    {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_texture_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: false,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_bind_group(0, &self.bind_group, &[]);
+            for draw in draws
+            {
+                render_pass.draw(0..draw.index_count, draw.instances.clone());
+            }
+        }
+        encoder.copy_texture_to_buffer(
+            self.id_texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &self.readback_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 });
+    }
+
+    // Mapping the readback buffer is only allowed after the submit writing it.
+    pub fn after_submit(&mut self)
+    {
+        if !self.render_pick
+        {
+            return;
+        }
+        self.map_state.store(MAP_PENDING, Ordering::Release);
+        let map_state = self.map_state.clone();
+        self.readback_buffer.slice(..).map_async(MapMode::Read, move |result| {
+            map_state.store(if result.is_ok() { MAP_DONE } else { MAP_FAILED }, Ordering::Release);
+        });
+    }
+}
+
+impl ShaderReloadable for TriangleSystem
+{
+    fn shader_name(&self) -> &'static str
+    {
+        return SHADER_NAME;
+    }
+
+    fn reload_shader(&mut self, device: &Device, source: &str) -> Result<(), String>
+    {
+        let (shader, render_pipeline) = shaders::validated(device, || {
+            let shader = shaders::create_shader_module(device, SHADER_NAME, source);
+            let render_pipeline = Self::create_pipeline(device, &self._pipeline_layout, &shader);
+            (shader, render_pipeline)
+        })?;
+        self._shader = shader;
+        self.render_pipeline = render_pipeline;
+        return Ok(());
+    }
+}
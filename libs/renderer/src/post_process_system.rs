@@ -0,0 +1,530 @@
+use wgpu::*;
+use wgpu::util::DeviceExt;
+
+use crate::bloom_system::BloomSettings;
+use crate::fog_system::FogSettings;
+use crate::ssao_system::SsaoSettings;
+use crate::render_targets::{PingPong, RenderTargets, ScreenSize, ScreenSizeDependent};
+use crate::shaders::{self, ShaderInterface, ShaderReloadable};
+
+const SHADER_NAME: &str = "post_process.wgsl";
+const COMPUTE_FXAA_ENTRY: &str = "fxaa_main";
+const COMPUTE_COLOR_GRADING_ENTRY: &str = "color_grading_main";
+const COMPUTE_VIGNETTE_ENTRY: &str = "vignette_main";
+const COMPUTE_CHROMATIC_ABERRATION_ENTRY: &str = "chromatic_aberration_main";
+
+// Size of the identity lut used until one is loaded.
+const IDENTITY_LUT_SIZE: u32 = 16;
+
+const BIND_GROUP_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] = &[
+    // Input
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    },
+    // Output
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::StorageTexture {
+            view_dimension: wgpu::TextureViewDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            access: wgpu::StorageTextureAccess::WriteOnly,
+        },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 2,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    },
+    // Params
+    wgpu::BindGroupLayoutEntry {
+        binding: 3,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+    // Color grading lut
+    wgpu::BindGroupLayoutEntry {
+        binding: 4,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D3,
+            multisampled: false,
+        },
+        count: None,
+    },
+];
+
+pub(crate) const SHADER_INTERFACE: ShaderInterface = ShaderInterface {
+    shader_name: SHADER_NAME,
+    entry_points: &[
+        (wgpu::ShaderStages::COMPUTE, COMPUTE_FXAA_ENTRY),
+        (wgpu::ShaderStages::COMPUTE, COMPUTE_COLOR_GRADING_ENTRY),
+        (wgpu::ShaderStages::COMPUTE, COMPUTE_VIGNETTE_ENTRY),
+        (wgpu::ShaderStages::COMPUTE, COMPUTE_CHROMATIC_ABERRATION_ENTRY),
+    ],
+    bind_group_layouts: &[BIND_GROUP_LAYOUT_ENTRIES],
+    vertex_attributes: &[],
+    defines: &[],
+};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FxaaSettings
+{
+    pub enabled: bool,
+    // Local contrast needed to count as an edge, relative to the brightest pixel around.
+    pub edge_threshold: f32,
+    // Edges darker than this are skipped.
+    pub edge_threshold_min: f32,
+    // How far along the edge is searched, in pixels.
+    pub span_max: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ColorGradingSettings
+{
+    pub enabled: bool,
+    // Blend between the ungraded (0) and graded (1) color.
+    pub strength: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VignetteSettings
+{
+    pub enabled: bool,
+    pub intensity: f32,
+    // Distance from the center where the darkening starts, 1 is the middle of the screen edge.
+    pub radius: f32,
+    pub smoothness: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ChromaticAberrationSettings
+{
+    pub enabled: bool,
+    // Color separation at the screen edges, in pixels.
+    pub intensity: f32,
+}
+
+// Bloom runs on the hdr scene before tonemapping, the rest after it in the order of the fields.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PostProcessSettings
+{
+    pub ssao: SsaoSettings,
+    pub fog: FogSettings,
+    pub bloom: BloomSettings,
+    pub color_grading: ColorGradingSettings,
+    pub fxaa: FxaaSettings,
+    pub chromatic_aberration: ChromaticAberrationSettings,
+    pub vignette: VignetteSettings,
+}
+
+impl Default for PostProcessSettings
+{
+    fn default() -> Self
+    {
+        Self
+        {
+            ssao: SsaoSettings::default(),
+            fog: FogSettings::default(),
+            bloom: BloomSettings::default(),
+            color_grading: ColorGradingSettings
+            {
+                enabled: false,
+                strength: 1.0,
+            },
+            fxaa: FxaaSettings
+            {
+                enabled: true,
+                edge_threshold: 0.125,
+                edge_threshold_min: 0.0312,
+                span_max: 8.0,
+            },
+            chromatic_aberration: ChromaticAberrationSettings
+            {
+                enabled: false,
+                intensity: 2.0,
+            },
+            vignette: VignetteSettings
+            {
+                enabled: false,
+                intensity: 0.3,
+                radius: 0.75,
+                smoothness: 0.6,
+            },
+        }
+    }
+}
+
+// Shared with post_process.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct PostProcessParams
+{
+    pub(crate) inv_size: [f32; 2],
+    pub(crate) fxaa_edge_threshold: f32,
+    pub(crate) fxaa_edge_threshold_min: f32,
+    pub(crate) fxaa_span_max: f32,
+    pub(crate) lut_strength: f32,
+    pub(crate) lut_size: f32,
+    pub(crate) vignette_intensity: f32,
+    pub(crate) vignette_radius: f32,
+    pub(crate) vignette_smoothness: f32,
+    pub(crate) chromatic_aberration: f32,
+    pub(crate) _padding: f32,
+}
+
+impl PostProcessParams
+{
+    fn new(settings: &PostProcessSettings, size: ScreenSize, lut_size: u32) -> Self
+    {
+        Self
+        {
+            inv_size: [1.0 / size.width as f32, 1.0 / size.height as f32],
+            fxaa_edge_threshold: settings.fxaa.edge_threshold,
+            fxaa_edge_threshold_min: settings.fxaa.edge_threshold_min,
+            fxaa_span_max: settings.fxaa.span_max,
+            lut_strength: settings.color_grading.strength,
+            lut_size: lut_size as f32,
+            vignette_intensity: settings.vignette.intensity,
+            vignette_radius: settings.vignette.radius,
+            vignette_smoothness: settings.vignette.smoothness,
+            chromatic_aberration: settings.chromatic_aberration.intensity,
+            _padding: 0.0,
+        }
+    }
+}
+
+// Rgba8 texels of a lut that maps every color to itself.
+fn identity_lut(size: u32) -> Vec<u8>
+{
+    let mut data = Vec::with_capacity((size * size * size * 4) as usize);
+    let scale = 255.0 / (size - 1) as f32;
+    for b in 0..size
+    {
+        for g in 0..size
+        {
+            for r in 0..size
+            {
+                data.extend_from_slice(&[
+                    (r as f32 * scale).round() as u8,
+                    (g as f32 * scale).round() as u8,
+                    (b as f32 * scale).round() as u8,
+                    255
+                ]);
+            }
+        }
+    }
+    return data;
+}
+
+// Converts a lut stored as a horizontal strip of size x size slices, blue growing from left
+// to right, into the texel order of a 3d texture. Returns the lut size and the texels.
+pub fn lut_from_strip(width: u32, height: u32, rgba: &[u8]) -> Result<(u32, Vec<u8>), String>
+{
+    let size = height;
+    if size < 2 || width != size * size || rgba.len() != (width * height * 4) as usize
+    {
+        return Err(format!("A {}x{} image is not a lut strip, expected {}x{}", width, height, size * size, size));
+    }
+    let mut data = Vec::with_capacity(rgba.len());
+    for b in 0..size
+    {
+        for g in 0..size
+        {
+            let row_start = ((g * width + b * size) * 4) as usize;
+            data.extend_from_slice(&rgba[row_start..row_start + (size * 4) as usize]);
+        }
+    }
+    return Ok((size, data));
+}
+
+// The post effects after tonemapping, each one reads one render target texture and writes
+// the other.
+pub struct TriangleSystem
+{
+    _shader: ShaderModule,
+    _bind_group_layout: BindGroupLayout,
+    _pipeline_layout: PipelineLayout,
+    compute_pipeline_fxaa: ComputePipeline,
+    compute_pipeline_color_grading: ComputePipeline,
+    compute_pipeline_vignette: ComputePipeline,
+    compute_pipeline_chromatic_aberration: ComputePipeline,
+
+    _sampler: Sampler,
+    params_buffer: Buffer,
+    _lut_texture: Texture,
+    lut_view: TextureView,
+    lut_size: u32,
+    // One per PingPong source.
+    bind_groups: Vec<BindGroup>,
+
+    settings: PostProcessSettings,
+    pub(crate) size: ScreenSize,
+}
+
+impl TriangleSystem
+{
+    pub fn new(device: &Device, queue: &Queue, render_targets: &RenderTargets) -> Self
+    {
+        let _shader = shaders::create_shader_module(device, SHADER_NAME, &shaders::embedded_source(SHADER_NAME));
+
+        let _bind_group_layout = SHADER_INTERFACE.create_bind_group_layout(device, 0, "Post process bindings");
+
+        let _pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor
+        {
+            label: None,
+            bind_group_layouts: &[&_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let (
+            compute_pipeline_fxaa,
+            compute_pipeline_color_grading,
+            compute_pipeline_vignette,
+            compute_pipeline_chromatic_aberration
+        ) = Self::create_pipelines(device, &_pipeline_layout, &_shader);
+
+        let _sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        let settings = PostProcessSettings::default();
+        let size = render_targets.size;
+        let params_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Post process params"),
+                contents: bytemuck::cast_slice(&[PostProcessParams::new(&settings, size, IDENTITY_LUT_SIZE)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let _lut_texture = Self::create_lut_texture(device, queue, IDENTITY_LUT_SIZE, &identity_lut(IDENTITY_LUT_SIZE));
+        let lut_view = _lut_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bind_groups = Self::create_bind_groups(
+            device,
+            &_bind_group_layout,
+            &_sampler,
+            &params_buffer,
+            &lut_view,
+            render_targets
+        );
+
+        Self {
+            _shader,
+            _bind_group_layout,
+            _pipeline_layout,
+            compute_pipeline_fxaa,
+            compute_pipeline_color_grading,
+            compute_pipeline_vignette,
+            compute_pipeline_chromatic_aberration,
+
+            _sampler,
+            params_buffer,
+            _lut_texture,
+            lut_view,
+            lut_size: IDENTITY_LUT_SIZE,
+            bind_groups,
+
+            settings,
+            size,
+        }
+    }
+
+    fn create_pipelines(
+        device: &Device,
+        pipeline_layout: &PipelineLayout,
+        shader: &ShaderModule
+    ) -> (ComputePipeline, ComputePipeline, ComputePipeline, ComputePipeline)
+    {
+        let create = |entry_point: &str| device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(entry_point),
+            layout: Some(pipeline_layout),
+            module: shader,
+            entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::COMPUTE, entry_point),
+        });
+        return (
+            create(COMPUTE_FXAA_ENTRY),
+            create(COMPUTE_COLOR_GRADING_ENTRY),
+            create(COMPUTE_VIGNETTE_ENTRY),
+            create(COMPUTE_CHROMATIC_ABERRATION_ENTRY),
+        );
+    }
+
+    fn create_lut_texture(device: &Device, queue: &Queue, size: u32, data: &[u8]) -> Texture
+    {
+        return device.create_texture_with_data(queue, &wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: size,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            // The shader does the encoding, so the lut can be sampled as it is stored.
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: TextureUsages::TEXTURE_BINDING,
+            label: Some("Color grading lut"),
+            view_formats: &[],
+        }, data);
+    }
+
+    fn create_bind_groups(
+        device: &Device,
+        bind_group_layout: &BindGroupLayout,
+        sampler: &Sampler,
+        params_buffer: &Buffer,
+        lut_view: &TextureView,
+        render_targets: &RenderTargets
+    ) -> Vec<BindGroup>
+    {
+        return PingPong::BOTH
+            .iter()
+            .map(|&source| {
+                let (input_texture, output_texture) = render_targets.ping_pong_textures(source);
+                let input_view = input_texture.create_view(&wgpu::TextureViewDescriptor::default());
+                let output_view = output_texture.create_view(&wgpu::TextureViewDescriptor::default());
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("Post process Bind Group"),
+                    layout: bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&input_view) },
+                        wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::TextureView(&output_view) },
+                        wgpu::BindGroupEntry { binding: 2, resource: wgpu::BindingResource::Sampler(sampler) },
+                        wgpu::BindGroupEntry { binding: 3, resource: params_buffer.as_entire_binding() },
+                        wgpu::BindGroupEntry { binding: 4, resource: wgpu::BindingResource::TextureView(lut_view) },
+                    ],
+                })
+            })
+            .collect();
+    }
+
+    // Replaces the color grading lut, data is size^3 rgba8 texels with red changing fastest.
+    pub fn set_lut(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        render_targets: &RenderTargets,
+        size: u32,
+        data: &[u8]
+    ) -> Result<(), String>
+    {
+        if size < 2 || data.len() != (size * size * size * 4) as usize
+        {
+            return Err(format!("Lut of size {} needs {} bytes, got {}", size, size * size * size * 4, data.len()));
+        }
+        let max_size = device.limits().max_texture_dimension_3d;
+        if size > max_size
+        {
+            return Err(format!("Lut size {} is over the device limit {}", size, max_size));
+        }
+        self._lut_texture = Self::create_lut_texture(device, queue, size, data);
+        self.lut_view = self._lut_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.lut_size = size;
+        self.rebind(device, render_targets);
+        return Ok(());
+    }
+
+    pub fn settings(&self) -> PostProcessSettings
+    {
+        return self.settings;
+    }
+
+    pub fn set_settings(&mut self, settings: PostProcessSettings)
+    {
+        self.settings = settings;
+    }
+
+    pub fn update(&mut self, queue: &Queue)
+    {
+        let params = PostProcessParams::new(&self.settings, self.size, self.lut_size);
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+    }
+
+    // Runs the enabled effects starting from source, returns where the result ended up.
+    pub fn render(&mut self, encoder: &mut CommandEncoder, source: PingPong) -> PingPong
+    {
+        let settings = &self.settings;
+        let effects = [
+            (settings.color_grading.enabled, &self.compute_pipeline_color_grading),
+            (settings.fxaa.enabled, &self.compute_pipeline_fxaa),
+            (settings.chromatic_aberration.enabled, &self.compute_pipeline_chromatic_aberration),
+            (settings.vignette.enabled, &self.compute_pipeline_vignette),
+        ];
+
+        let mut source = source;
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("Post process") });
+        let (x, y, z) = self.size.workgroups_2d(8, 8);
+        for (_, pipeline) in effects.iter().filter(|(enabled, _)| *enabled)
+        {
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, &self.bind_groups[source.index()], &[]);
+            compute_pass.dispatch_workgroups(x, y, z);
+            source = source.next();
+        }
+        return source;
+    }
+}
+
+impl ScreenSizeDependent for TriangleSystem
+{
+    fn rebind(&mut self, device: &Device, render_targets: &RenderTargets)
+    {
+        self.bind_groups = Self::create_bind_groups(
+            device,
+            &self._bind_group_layout,
+            &self._sampler,
+            &self.params_buffer,
+            &self.lut_view,
+            render_targets
+        );
+        self.size = render_targets.size;
+    }
+}
+
+impl ShaderReloadable for TriangleSystem
+{
+    fn shader_name(&self) -> &'static str
+    {
+        return SHADER_NAME;
+    }
+
+    fn reload_shader(&mut self, device: &Device, source: &str) -> Result<(), String>
+    {
+        let (shader, pipelines) = shaders::validated(device, || {
+            let shader = shaders::create_shader_module(device, SHADER_NAME, source);
+            let pipelines = Self::create_pipelines(device, &self._pipeline_layout, &shader);
+            (shader, pipelines)
+        })?;
+        self._shader = shader;
+        (
+            self.compute_pipeline_fxaa,
+            self.compute_pipeline_color_grading,
+            self.compute_pipeline_vignette,
+            self.compute_pipeline_chromatic_aberration
+        ) = pipelines;
+        return Ok(());
+    }
+}
@@ -0,0 +1,132 @@
+use crate::render_targets::ScreenSize;
+
+// Smallest change of the dynamic scale, so the render targets aren't recreated for every
+// small frame time change.
+const DYNAMIC_SCALE_STEP: f32 = 0.05;
+// Seconds to wait after a scale change before measuring again.
+const DYNAMIC_CHANGE_INTERVAL: f64 = 0.5;
+// Weight of a new frame time in the running average.
+const FRAME_TIME_SMOOTHING: f64 = 0.1;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RenderScale
+{
+    // Render size relative to the window size, 1.0 renders at the window size.
+    Fixed(f32),
+    // Adjusts the scale between the limits so the frame time stays near the target, in seconds.
+    Dynamic
+    {
+        target_frame_time: f64,
+        min_scale: f32,
+        max_scale: f32,
+    },
+}
+
+// How the rendered image is scaled to the window size.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum UpscaleFilter
+{
+    Bilinear,
+    // Edge adaptive upsampling followed by sharpening, as in AMD FSR 1. Sharpness is in stops,
+    // 0 is the sharpest and every step up halves the sharpening.
+    Fsr
+    {
+        sharpness: f32,
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RenderScaleSettings
+{
+    pub scale: RenderScale,
+    pub filter: UpscaleFilter,
+}
+
+impl Default for RenderScaleSettings
+{
+    fn default() -> Self
+    {
+        Self
+        {
+            scale: RenderScale::Fixed(1.0),
+            filter: UpscaleFilter::Fsr { sharpness: 0.2 },
+        }
+    }
+}
+
+// Size of the render targets for a window size and render scale.
+pub fn scaled_size(display_size: ScreenSize, scale: f32) -> ScreenSize
+{
+    return ScreenSize::new(
+        (display_size.width as f32 * scale).round() as u32,
+        (display_size.height as f32 * scale).round() as u32,
+    );
+}
+
+// Picks the render scale from the frame times.
+pub struct DynamicResolution
+{
+    scale: f32,
+    average_frame_time: Option<f64>,
+    time_since_change: f64,
+}
+
+impl DynamicResolution
+{
+    pub fn new(scale: f32) -> Self
+    {
+        Self
+        {
+            scale,
+            average_frame_time: None,
+            time_since_change: 0.0,
+        }
+    }
+
+    pub fn scale(&self) -> f32
+    {
+        return self.scale;
+    }
+
+    // Takes the latest frame time and returns the scale to render the next frame with.
+    // The frame time includes waiting for vsync, so with Fifo presenting the target has to be
+    // at or above the refresh interval for the scale to grow.
+    pub fn update(&mut self, frame_time: f64, target_frame_time: f64, min_scale: f32, max_scale: f32) -> f32
+    {
+        let average = match self.average_frame_time
+        {
+            Some(average) => average + (frame_time - average) * FRAME_TIME_SMOOTHING,
+            None => frame_time,
+        };
+        self.average_frame_time = Some(average);
+        self.time_since_change += frame_time;
+
+        let clamped = self.scale.clamp(min_scale, max_scale);
+        if clamped != self.scale
+        {
+            self.set_scale(clamped);
+            return self.scale;
+        }
+        if self.time_since_change < DYNAMIC_CHANGE_INTERVAL || average <= 0.0
+        {
+            return self.scale;
+        }
+
+        // The cost mostly follows the pixel count, which grows with the square of the scale.
+        let desired = self.scale * (target_frame_time / average).sqrt() as f32;
+        if (desired - self.scale).abs() >= DYNAMIC_SCALE_STEP
+        {
+            let stepped = (desired / DYNAMIC_SCALE_STEP).round() * DYNAMIC_SCALE_STEP;
+            self.set_scale(stepped.clamp(min_scale, max_scale));
+        }
+        return self.scale;
+    }
+
+    fn set_scale(&mut self, scale: f32)
+    {
+        self.scale = scale;
+        self.time_since_change = 0.0;
+        // The old frame times were measured at another scale.
+        self.average_frame_time = None;
+    }
+}
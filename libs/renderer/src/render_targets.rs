@@ -0,0 +1,572 @@
+use wgpu::{Device, Texture, TextureFormat, TextureUsages, TextureView};
+
+// Smallest size any screen sized resource is created with, zero sized textures are not allowed.
+pub const MIN_SCREEN_SIZE: u32 = 4;
+
+// The scene is rendered in hdr, the post processing passes ping-pong between the two render
+// target textures, so both use the same format.
+pub const SCENE_COLOR_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+pub const POST_PROCESS_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+pub const SCENE_DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+// Linear view depth and ambient occlusion, written by compute passes so not a depth format.
+pub const LINEAR_DEPTH_FORMAT: TextureFormat = TextureFormat::R32Float;
+pub const AO_FORMAT: TextureFormat = TextureFormat::R32Float;
+pub const FOG_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ScreenSize
+{
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ScreenSize
+{
+    pub fn new(width: u32, height: u32) -> Self
+    {
+        Self
+        {
+            width: std::cmp::max(MIN_SCREEN_SIZE, width),
+            height: std::cmp::max(MIN_SCREEN_SIZE, height),
+        }
+    }
+
+    // Amount of workgroups needed to cover every pixel with 2d workgroups.
+    pub fn workgroups_2d(&self, group_width: u32, group_height: u32) -> (u32, u32, u32)
+    {
+        return (
+            (self.width + group_width - 1) / group_width,
+            (self.height + group_height - 1) / group_height,
+            1
+        );
+    }
+
+    // Half of the size, rounded up so odd sizes stay covered.
+    pub fn half(&self) -> Self
+    {
+        return Self::new((self.width + 1) / 2, (self.height + 1) / 2);
+    }
+
+    // Amount of workgroups needed to cover every pixel with 1d workgroups.
+    pub fn workgroups_1d(&self, group_size: u32) -> (u32, u32, u32)
+    {
+        return ((self.width * self.height + group_size - 1) / group_size, 1, 1);
+    }
+}
+
+// The post processing passes read one of the two render target textures and write the other.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PingPong
+{
+    // Reads render_target_texture, writes render_target_texture2.
+    First,
+    // Reads render_target_texture2, writes render_target_texture.
+    Second,
+}
+
+impl PingPong
+{
+    pub const BOTH: [PingPong; 2] = [PingPong::First, PingPong::Second];
+
+    // Source of the pass after this one.
+    pub fn next(self) -> Self
+    {
+        return match self
+        {
+            PingPong::First => PingPong::Second,
+            PingPong::Second => PingPong::First,
+        };
+    }
+
+    pub fn index(self) -> usize
+    {
+        return self as usize;
+    }
+}
+
+// Every pass that holds bindings or sizes depending on the screen sized render targets
+// implements this, the renderer notifies all of them after the targets are recreated.
+pub trait ScreenSizeDependent
+{
+    fn rebind(&mut self, device: &Device, render_targets: &RenderTargets);
+}
+
+// Picks the largest sample count, not above the requested one, that passes the support check.
+pub fn pick_sample_count(requested: u32, supported: impl Fn(u32) -> bool) -> u32
+{
+    return [16, 8, 4, 2]
+        .into_iter()
+        .find(|&count| count <= requested && supported(count))
+        .unwrap_or(1);
+}
+
+// Owns all the screen sized resources, so they are recreated in one place on resize.
+pub struct RenderTargets
+{
+    // Size of the window, the scene is rendered at size and scaled to this in the blit.
+    pub display_size: ScreenSize,
+    pub size: ScreenSize,
+    pub sample_count: u32,
+
+    pub render_target_texture: Texture,
+    pub render_target_texture2: Texture,
+    pub render_target_texture_view: TextureView,
+
+    // Multisampled scene color, resolved into render_target_texture. None without MSAA.
+    pub msaa_color_texture: Option<Texture>,
+    pub msaa_color_texture_view: Option<TextureView>,
+
+    pub render_target_depth_texture: Texture,
+    pub render_target_depth_texture_view: TextureView,
+
+    // Always single sampled, from the first sample of the depth texture.
+    pub linear_depth_texture: Texture,
+    pub linear_depth_texture_view: TextureView,
+
+    // Ambient occlusion and fog, at half the screen size when the flags are set.
+    pub ao_half_resolution: bool,
+    pub ao_texture: Texture,
+    pub ao_texture_view: TextureView,
+    pub fog_half_resolution: bool,
+    pub fog_texture: Texture,
+    pub fog_texture_view: TextureView,
+
+    // Window sized, the edge adaptive upscale writes here before the sharpening.
+    pub upscale_texture: Texture,
+    pub upscale_texture_view: TextureView,
+}
+
+impl RenderTargets
+{
+    pub fn new(device: &Device, display_size: ScreenSize, size: ScreenSize, sample_count: u32) -> Self
+    {
+        let (render_target_texture, render_target_texture2, render_target_texture_view) =
+            Self::create_render_target_textures(device, size);
+        let (msaa_color_texture, msaa_color_texture_view) =
+            Self::create_msaa_color_texture(device, size, sample_count);
+        let (render_target_depth_texture, render_target_depth_texture_view) =
+            Self::create_depth_texture(device, size, sample_count);
+        let (linear_depth_texture, linear_depth_texture_view) =
+            Self::create_effect_texture(device, size, LINEAR_DEPTH_FORMAT);
+        let (ao_texture, ao_texture_view) = Self::create_effect_texture(device, size, AO_FORMAT);
+        let (fog_texture, fog_texture_view) = Self::create_effect_texture(device, size, FOG_FORMAT);
+        let (upscale_texture, upscale_texture_view) = Self::create_upscale_texture(device, display_size);
+
+        return Self {
+            display_size,
+            size,
+            sample_count,
+
+            render_target_texture,
+            render_target_texture2,
+            render_target_texture_view,
+
+            msaa_color_texture,
+            msaa_color_texture_view,
+
+            render_target_depth_texture,
+            render_target_depth_texture_view,
+
+            linear_depth_texture,
+            linear_depth_texture_view,
+
+            ao_half_resolution: false,
+            ao_texture,
+            ao_texture_view,
+            fog_half_resolution: false,
+            fog_texture,
+            fog_texture_view,
+
+            upscale_texture,
+            upscale_texture_view,
+        };
+    }
+
+    // Returns false if neither size changed and nothing was recreated.
+    pub fn resize(&mut self, device: &Device, display_size: ScreenSize, size: ScreenSize) -> bool
+    {
+        if display_size == self.display_size && size == self.size
+        {
+            return false;
+        }
+        if display_size != self.display_size
+        {
+            let (upscale_texture, upscale_texture_view) = Self::create_upscale_texture(device, display_size);
+            self.display_size = display_size;
+            self.upscale_texture = upscale_texture;
+            self.upscale_texture_view = upscale_texture_view;
+        }
+        if size == self.size
+        {
+            return true;
+        }
+
+        let (render_target_texture, render_target_texture2, render_target_texture_view) =
+            Self::create_render_target_textures(device, size);
+
+        self.size = size;
+
+        self.render_target_texture = render_target_texture;
+        self.render_target_texture2 = render_target_texture2;
+        self.render_target_texture_view = render_target_texture_view;
+
+        let (linear_depth_texture, linear_depth_texture_view) =
+            Self::create_effect_texture(device, size, LINEAR_DEPTH_FORMAT);
+        self.linear_depth_texture = linear_depth_texture;
+        self.linear_depth_texture_view = linear_depth_texture_view;
+
+        self.recreate_scene_targets(device);
+        self.recreate_effect_targets(device);
+        return true;
+    }
+
+    // Returns false if neither resolution changed and nothing was recreated.
+    pub fn set_effect_resolutions(&mut self, device: &Device, ao_half_resolution: bool, fog_half_resolution: bool) -> bool
+    {
+        if ao_half_resolution == self.ao_half_resolution && fog_half_resolution == self.fog_half_resolution
+        {
+            return false;
+        }
+        self.ao_half_resolution = ao_half_resolution;
+        self.fog_half_resolution = fog_half_resolution;
+        self.recreate_effect_targets(device);
+        return true;
+    }
+
+    pub fn effect_size(&self, half_resolution: bool) -> ScreenSize
+    {
+        return if half_resolution { self.size.half() } else { self.size };
+    }
+
+    // Only the multisampled targets change, the resolved render targets stay bound as they are.
+    pub fn set_sample_count(&mut self, device: &Device, sample_count: u32)
+    {
+        if sample_count == self.sample_count
+        {
+            return;
+        }
+        self.sample_count = sample_count;
+        self.recreate_scene_targets(device);
+    }
+
+    // (input, output) of a post processing pass reading from source.
+    pub fn ping_pong_textures(&self, source: PingPong) -> (&Texture, &Texture)
+    {
+        return match source
+        {
+            PingPong::First => (&self.render_target_texture, &self.render_target_texture2),
+            PingPong::Second => (&self.render_target_texture2, &self.render_target_texture),
+        };
+    }
+
+    // The view the scene passes draw into, and the view to resolve it into when multisampled.
+    pub fn scene_color_target(&self) -> (&TextureView, Option<&TextureView>)
+    {
+        return match &self.msaa_color_texture_view
+        {
+            Some(msaa_view) => (msaa_view, Some(&self.render_target_texture_view)),
+            None => (&self.render_target_texture_view, None),
+        };
+    }
+
+    fn recreate_scene_targets(&mut self, device: &Device)
+    {
+        let (msaa_color_texture, msaa_color_texture_view) =
+            Self::create_msaa_color_texture(device, self.size, self.sample_count);
+        let (render_target_depth_texture, render_target_depth_texture_view) =
+            Self::create_depth_texture(device, self.size, self.sample_count);
+
+        self.msaa_color_texture = msaa_color_texture;
+        self.msaa_color_texture_view = msaa_color_texture_view;
+
+        self.render_target_depth_texture = render_target_depth_texture;
+        self.render_target_depth_texture_view = render_target_depth_texture_view;
+    }
+
+    fn recreate_effect_targets(&mut self, device: &Device)
+    {
+        let (ao_texture, ao_texture_view) =
+            Self::create_effect_texture(device, self.effect_size(self.ao_half_resolution), AO_FORMAT);
+        let (fog_texture, fog_texture_view) =
+            Self::create_effect_texture(device, self.effect_size(self.fog_half_resolution), FOG_FORMAT);
+
+        self.ao_texture = ao_texture;
+        self.ao_texture_view = ao_texture_view;
+        self.fog_texture = fog_texture;
+        self.fog_texture_view = fog_texture_view;
+    }
+
+    pub fn create_rendertarget_texture(
+        device: &Device,
+        w: u32,
+        h: u32,
+        sample_count: u32,
+        format: TextureFormat,
+        usage: TextureUsages,
+    ) -> Texture
+    {
+        let rt_desc = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width: w,
+                height: h,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage,
+            label: None,
+            view_formats: &[], //TextureFormat::Rgba8UnormSrgb, TextureFormat::Rgba8Unorm],
+        };
+        return device.create_texture(&rt_desc);
+    }
+
+    fn create_render_target_textures(device: &Device, size: ScreenSize) -> (Texture, Texture, TextureView)
+    {
+        let render_target_texture = Self::create_rendertarget_texture(
+            device,
+            size.width,
+            size.height,
+            1,
+            SCENE_COLOR_FORMAT,
+            TextureUsages::RENDER_ATTACHMENT
+                | TextureUsages::TEXTURE_BINDING
+                | TextureUsages::STORAGE_BINDING
+        );
+        let render_target_texture2 = Self::create_rendertarget_texture(
+            device,
+            size.width,
+            size.height,
+            1,
+            POST_PROCESS_FORMAT,
+            // Render attachment for the debug lines drawn after the post processing.
+            TextureUsages::RENDER_ATTACHMENT
+                | TextureUsages::TEXTURE_BINDING
+                | TextureUsages::STORAGE_BINDING
+        );
+
+        let render_target_texture_view = render_target_texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        return (render_target_texture, render_target_texture2, render_target_texture_view);
+    }
+
+    fn create_msaa_color_texture(
+        device: &Device,
+        size: ScreenSize,
+        sample_count: u32
+    ) -> (Option<Texture>, Option<TextureView>)
+    {
+        if sample_count <= 1
+        {
+            return (None, None);
+        }
+        let texture = Self::create_rendertarget_texture(
+            device,
+            size.width,
+            size.height,
+            sample_count,
+            SCENE_COLOR_FORMAT,
+            TextureUsages::RENDER_ATTACHMENT
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        return (Some(texture), Some(view));
+    }
+
+    fn create_depth_texture(device: &Device, size: ScreenSize, sample_count: u32) -> (Texture, TextureView)
+    {
+        let texture = Self::create_rendertarget_texture(
+            device,
+            size.width,
+            size.height,
+            sample_count,
+            SCENE_DEPTH_FORMAT,
+            TextureUsages::RENDER_ATTACHMENT
+                | TextureUsages::TEXTURE_BINDING
+        );
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        return (texture, view);
+    }
+
+    // Written by a compute pass and read by the later ones.
+    fn create_effect_texture(device: &Device, size: ScreenSize, format: TextureFormat) -> (Texture, TextureView)
+    {
+        let texture = Self::create_rendertarget_texture(
+            device,
+            size.width,
+            size.height,
+            1,
+            format,
+            TextureUsages::STORAGE_BINDING
+                | TextureUsages::TEXTURE_BINDING
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        return (texture, view);
+    }
+
+    fn create_upscale_texture(device: &Device, display_size: ScreenSize) -> (Texture, TextureView)
+    {
+        let texture = Self::create_rendertarget_texture(
+            device,
+            display_size.width,
+            display_size.height,
+            1,
+            POST_PROCESS_FORMAT,
+            TextureUsages::RENDER_ATTACHMENT
+                | TextureUsages::TEXTURE_BINDING
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        return (texture, view);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::{
+        blit_to_backbuffer, bloom_system, composite_system, compute_system_copy_vertices, debug_draw_system,
+        depth_system, fog_system, post_process_system, ssao_system, tonemap_system,
+    };
+
+    const SIZES: &[(u32, u32)] = &[
+        (1024, 768),
+        (1, 1),
+        (0, 0),
+        (1920, 1080),
+        (7, 9),
+        (640, 480),
+        (3840, 2160),
+        (1024, 768),
+    ];
+
+    #[test]
+    fn screen_size_is_clamped()
+    {
+        assert_eq!(ScreenSize::new(0, 0), ScreenSize::new(MIN_SCREEN_SIZE, MIN_SCREEN_SIZE));
+        assert_eq!(ScreenSize::new(1, 100), ScreenSize { width: MIN_SCREEN_SIZE, height: 100 });
+        assert_eq!(ScreenSize::new(100, 2), ScreenSize { width: 100, height: MIN_SCREEN_SIZE });
+    }
+
+    #[test]
+    fn tonemap_dispatch_covers_every_pixel_after_resizes()
+    {
+        for &(w, h) in SIZES
+        {
+            let size = ScreenSize::new(w, h);
+            let (x, y, z) = tonemap_system::TriangleSystem::dispatch_size(size);
+            assert!(x * 8 >= size.width && (x - 1) * 8 < size.width);
+            assert!(y * 8 >= size.height && (y - 1) * 8 < size.height);
+            assert_eq!(z, 1);
+
+            let (x, y, z) = tonemap_system::TriangleSystem::histogram_dispatch_size(size);
+            assert!(x * 16 >= size.width && (x - 1) * 16 < size.width);
+            assert!(y * 16 >= size.height && (y - 1) * 16 < size.height);
+            assert_eq!(z, 1);
+        }
+    }
+
+    #[test]
+    fn copy_vertices_dispatch_follows_resizes()
+    {
+        for &(w, h) in SIZES
+        {
+            let size = ScreenSize::new(w, h);
+            let pixels = size.width * size.height;
+            let (x, y, z) = compute_system_copy_vertices::TriangleSystem::dispatch_size(size);
+            assert!(x * 64 >= pixels && (x - 1) * 64 < pixels);
+            assert_eq!((y, z), (1, 1));
+        }
+    }
+
+    // A device on any adapter, software ones included. Fails the test on machines without one
+    // instead of passing without checking anything.
+    fn test_device() -> (Device, wgpu::Queue)
+    {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions::default()))
+            .expect("No adapter found, the resize test needs one, a software adapter is enough");
+        let descriptor = wgpu::DeviceDescriptor
+        {
+            label: None,
+            features: wgpu::Features::empty(),
+            limits: wgpu::Limits::downlevel_defaults().using_resolution(adapter.limits()),
+        };
+        return pollster::block_on(adapter.request_device(&descriptor, None)).expect("Failed to create a device");
+    }
+
+    #[test]
+    fn passes_follow_resizes_after_rebinds()
+    {
+        let (device, queue) = test_device();
+        let errors = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let handler_errors = errors.clone();
+        device.on_uncaptured_error(Box::new(move |error| handler_errors.lock().unwrap().push(error.to_string())));
+
+        let size = ScreenSize::new(SIZES[0].0, SIZES[0].1);
+        let mut render_targets = RenderTargets::new(&device, size, size, 1);
+
+        let mut depth = depth_system::TriangleSystem::new(&device, &render_targets);
+        let mut ssao = ssao_system::TriangleSystem::new(&device, &render_targets);
+        let mut fog = fog_system::TriangleSystem::new(&device, &render_targets);
+        let mut composite = composite_system::TriangleSystem::new(&device, &render_targets);
+        let mut bloom = bloom_system::TriangleSystem::new(&device, &render_targets);
+        let mut tonemap = tonemap_system::TriangleSystem::new(&device, &render_targets);
+        let mut post_process = post_process_system::TriangleSystem::new(&device, &queue, &render_targets);
+        let mut copy_vertices = compute_system_copy_vertices::TriangleSystem::new(
+            &device,
+            &render_targets.render_target_texture,
+            &render_targets.render_target_texture2);
+        let mut debug_draw = debug_draw_system::TriangleSystem::new(&device, &render_targets);
+        let mut blit = blit_to_backbuffer::TriangleSystem::new(
+            &device,
+            TextureFormat::Bgra8UnormSrgb,
+            &render_targets);
+        // Software adapters may not compile every shader, only the rebinds are checked.
+        errors.lock().unwrap().clear();
+
+        for (index, &(width, height)) in SIZES.iter().enumerate()
+        {
+            // Changes the render scale and the effect resolutions along with the window size.
+            let display_size = ScreenSize::new(width, height);
+            let size = if index % 2 == 0 { display_size } else { display_size.half() };
+            let ao_half_resolution = index % 3 == 1;
+            let resized = render_targets.resize(&device, display_size, size);
+            let effects_changed = render_targets.set_effect_resolutions(&device, ao_half_resolution, !ao_half_resolution);
+            if resized || effects_changed
+            {
+                let screen_size_dependents = crate::screen_size_dependents(
+                    &mut depth,
+                    &mut ssao,
+                    &mut fog,
+                    &mut composite,
+                    &mut bloom,
+                    &mut tonemap,
+                    &mut post_process,
+                    &mut copy_vertices,
+                    &mut debug_draw,
+                    &mut blit);
+                for pass in screen_size_dependents
+                {
+                    pass.rebind(&device, &render_targets);
+                }
+            }
+
+            assert_eq!(*errors.lock().unwrap(), Vec::<String>::new());
+            assert_eq!(render_targets.size, size);
+            assert_eq!(depth.size, size);
+            assert_eq!(ssao.size, render_targets.effect_size(ao_half_resolution));
+            assert_eq!(fog.size, render_targets.effect_size(!ao_half_resolution));
+            assert_eq!(composite.size, size);
+            assert_eq!(bloom.size, size);
+            assert_eq!(tonemap.size, size);
+            assert_eq!(post_process.size, size);
+            assert_eq!(copy_vertices.size, size);
+            assert_eq!(debug_draw.size, size);
+            assert_eq!(blit.size, size);
+            assert_eq!(blit.display_size, display_size);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use wgpu::TextureFormat;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PresentMode
+{
+    // Vsync, always supported.
+    Fifo,
+    // Vsync without blocking, the newest frame replaces the queued one. Falls back to Fifo.
+    Mailbox,
+    // No vsync, may tear. Falls back to Mailbox, then Fifo.
+    Immediate,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RendererConfig
+{
+    pub present_mode: PresentMode,
+    // With an sRGB swapchain the hardware encodes the output, with a linear one the blit does.
+    pub prefer_srgb_format: bool,
+}
+
+impl Default for RendererConfig
+{
+    fn default() -> Self
+    {
+        Self
+        {
+            present_mode: PresentMode::Fifo,
+            prefer_srgb_format: true,
+        }
+    }
+}
+
+// First supported mode in the fallback order of the preferred one.
+pub fn pick_present_mode(preferred: PresentMode, supported: &[wgpu::PresentMode]) -> wgpu::PresentMode
+{
+    let fallbacks: &[wgpu::PresentMode] = match preferred
+    {
+        PresentMode::Fifo => &[],
+        PresentMode::Mailbox => &[wgpu::PresentMode::Mailbox],
+        PresentMode::Immediate => &[wgpu::PresentMode::Immediate, wgpu::PresentMode::Mailbox],
+    };
+    return fallbacks
+        .iter()
+        .copied()
+        .find(|mode| supported.contains(mode))
+        .unwrap_or(wgpu::PresentMode::Fifo);
+}
+
+// First format with the preferred encoding, otherwise the surface's preferred format.
+pub fn pick_format(prefer_srgb: bool, supported: &[TextureFormat]) -> TextureFormat
+{
+    return supported
+        .iter()
+        .copied()
+        .find(|format| format.is_srgb() == prefer_srgb)
+        .unwrap_or(supported[0]);
+}
@@ -0,0 +1,45 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RendererError
+{
+    SurfaceCreation(String),
+    // No adapter can present to the window surface.
+    NoAdapter,
+    // Names of the downlevel capabilities the renderer needs but the adapter lacks.
+    UnsupportedFeatures(Vec<String>),
+    // (name, required, allowed) of every limit the adapter doesn't reach.
+    UnsupportedLimits(Vec<(String, u64, u64)>),
+    DeviceCreation(String),
+    // A shader or pipeline failed validation while creating the passes.
+    ShaderValidation(String),
+    InvalidLut(String),
+}
+
+impl fmt::Display for RendererError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        return match self
+        {
+            RendererError::SurfaceCreation(err) => write!(f, "Failed to create the window surface: {}", err),
+            RendererError::NoAdapter => write!(f, "No graphics adapter can render to the window"),
+            RendererError::UnsupportedFeatures(features) =>
+                write!(f, "The graphics adapter does not support {}", features.join(", ")),
+            RendererError::UnsupportedLimits(limits) =>
+            {
+                write!(f, "The graphics adapter limits are too low:")?;
+                for (name, required, allowed) in limits
+                {
+                    write!(f, " {} {} (needs {})", name, allowed, required)?;
+                }
+                Ok(())
+            },
+            RendererError::DeviceCreation(err) => write!(f, "Failed to create the device: {}", err),
+            RendererError::ShaderValidation(err) => write!(f, "Shader validation failed: {}", err),
+            RendererError::InvalidLut(err) => write!(f, "Invalid color grading lut: {}", err),
+        };
+    }
+}
+
+impl std::error::Error for RendererError {}
@@ -0,0 +1,207 @@
+use std::collections::{HashMap, HashSet};
+
+// Small preprocessor run on the WGSL sources before they are given to wgpu.
+// Directives have to be on their own line:
+//   #include "shared/camera.wgsl"   Pastes the file once, later includes of the same file are skipped.
+//   #define NAME value              Replaces NAME identifiers on the following lines with value.
+//   #ifdef NAME / #ifndef NAME      Keeps the lines up to #else / #endif if NAME is (not) defined.
+//   #else / #endif
+pub struct Preprocessor<'a>
+{
+    load: &'a dyn Fn(&str) -> Result<String, String>,
+    defines: HashMap<String, String>,
+    included: HashSet<String>,
+}
+
+struct Conditional
+{
+    // Whether the lines of the current branch are kept.
+    active: bool,
+    // Whether the block containing this one is kept.
+    parent_active: bool,
+    seen_else: bool,
+}
+
+impl<'a> Preprocessor<'a>
+{
+    pub fn new(load: &'a dyn Fn(&str) -> Result<String, String>) -> Self
+    {
+        Self
+        {
+            load,
+            defines: HashMap::new(),
+            included: HashSet::new(),
+        }
+    }
+
+    pub fn define(&mut self, name: &str, value: &str)
+    {
+        self.defines.insert(name.to_string(), value.to_string());
+    }
+
+    pub fn process(&mut self, name: &str) -> Result<String, String>
+    {
+        let mut output = String::new();
+        self.process_file(name, &mut output)?;
+        return Ok(output);
+    }
+
+    fn process_file(&mut self, name: &str, output: &mut String) -> Result<(), String>
+    {
+        if !self.included.insert(name.to_string())
+        {
+            return Ok(());
+        }
+        let source = (self.load)(name)?;
+        let mut conditionals: Vec<Conditional> = Vec::new();
+
+        for (line_index, line) in source.lines().enumerate()
+        {
+            let error = |msg: &str| format!("{}:{}: {}", name, line_index + 1, msg);
+            let active = conditionals.last().map_or(true, |c| c.active);
+            let trimmed = line.trim();
+            if !trimmed.starts_with('#')
+            {
+                if active
+                {
+                    output.push_str(&self.substitute(line));
+                    output.push('\n');
+                }
+                continue;
+            }
+
+            let mut parts = trimmed[1..].splitn(2, char::is_whitespace);
+            let directive = parts.next().unwrap_or("");
+            let argument = parts.next().unwrap_or("").trim();
+            match directive
+            {
+                "ifdef" | "ifndef" =>
+                {
+                    let defined = self.defines.contains_key(argument);
+                    conditionals.push(Conditional {
+                        active: active && (defined == (directive == "ifdef")),
+                        parent_active: active,
+                        seen_else: false,
+                    });
+                },
+                "else" =>
+                {
+                    let conditional = conditionals.last_mut()
+                        .ok_or_else(|| error("#else without #ifdef"))?;
+                    if conditional.seen_else
+                    {
+                        return Err(error("Multiple #else in the same block"));
+                    }
+                    conditional.seen_else = true;
+                    conditional.active = conditional.parent_active && !conditional.active;
+                },
+                "endif" =>
+                {
+                    conditionals.pop().ok_or_else(|| error("#endif without #ifdef"))?;
+                },
+                _ if !active => {},
+                "define" =>
+                {
+                    let mut define_parts = argument.splitn(2, char::is_whitespace);
+                    let define_name = define_parts.next().unwrap_or("");
+                    if define_name.is_empty()
+                    {
+                        return Err(error("#define without a name"));
+                    }
+                    let value = self.substitute(define_parts.next().unwrap_or("").trim());
+                    self.defines.insert(define_name.to_string(), value);
+                },
+                "include" =>
+                {
+                    let include_name = argument.trim_matches('"');
+                    if include_name.is_empty() || include_name.len() + 2 != argument.len()
+                    {
+                        return Err(error("Expected #include \"file\""));
+                    }
+                    self.process_file(include_name, output)
+                        .map_err(|err| format!("{}\n  included from {}", err, error("")))?;
+                },
+                _ => return Err(error(&format!("Unknown directive #{}", directive))),
+            }
+        }
+
+        if !conditionals.is_empty()
+        {
+            return Err(format!("{}: Missing #endif", name));
+        }
+        return Ok(());
+    }
+
+    // Replaces every defined identifier in the line with its value.
+    fn substitute(&self, line: &str) -> String
+    {
+        if self.defines.is_empty()
+        {
+            return line.to_string();
+        }
+        let mut result = String::with_capacity(line.len());
+        let mut identifier = String::new();
+        for c in line.chars().chain(std::iter::once('\n'))
+        {
+            if c.is_alphanumeric() || c == '_'
+            {
+                identifier.push(c);
+                continue;
+            }
+            if !identifier.is_empty()
+            {
+                let starts_with_digit = identifier.starts_with(|c: char| c.is_ascii_digit());
+                match self.defines.get(&identifier)
+                {
+                    Some(value) if !starts_with_digit => result.push_str(value),
+                    _ => result.push_str(&identifier),
+                }
+                identifier.clear();
+            }
+            if c != '\n'
+            {
+                result.push(c);
+            }
+        }
+        return result;
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn run(files: &[(&str, &str)]) -> Result<String, String>
+    {
+        let files: HashMap<String, String> = files
+            .iter()
+            .map(|(name, source)| (name.to_string(), source.to_string()))
+            .collect();
+        let load = |name: &str| files.get(name).cloned().ok_or_else(|| format!("No file {}", name));
+        return Preprocessor::new(&load).process("main.wgsl");
+    }
+
+    #[test]
+    fn includes_once_and_substitutes_defines()
+    {
+        let output = run(&[
+            ("main.wgsl", "#include \"a.wgsl\"\n#include \"a.wgsl\"\n#define SIZE 8\nx(SIZE, SIZE_2)\n"),
+            ("a.wgsl", "struct A { v: u32, };\n"),
+        ]).unwrap();
+        assert_eq!(output, "struct A { v: u32, };\nx(8, SIZE_2)\n");
+    }
+
+    #[test]
+    fn conditional_blocks()
+    {
+        let output = run(&[
+            ("main.wgsl", "#define A\n#ifdef A\na\n#ifndef A\nnot_a\n#else\nnested\n#endif\n#else\nb\n#endif\n#ifdef B\nc\n#endif\n"),
+        ]).unwrap();
+        assert_eq!(output, "a\nnested\n");
+
+        assert!(run(&[("main.wgsl", "#ifdef A\n")]).is_err());
+        assert!(run(&[("main.wgsl", "#endif\n")]).is_err());
+        assert!(run(&[("main.wgsl", "#include \"missing.wgsl\"\n")]).is_err());
+    }
+}
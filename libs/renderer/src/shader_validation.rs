@@ -0,0 +1,280 @@
+// Checks every shader in data/shaders with naga, and that the entry points, bind group layouts
+// and vertex attributes the passes declare match what the shaders use. Runs without a GPU.
+
+use std::path::Path;
+
+use naga::valid::{Capabilities, ModuleInfo, ValidationFlags, Validator};
+use naga::{AddressSpace, Binding, ImageClass, ImageDimension, Module, ScalarKind, StorageAccess, TypeInner};
+use wgpu::{BindingType, BufferBindingType, ShaderStages, TextureSampleType, TextureViewDimension};
+
+use crate::shaders::{self, ShaderInterface};
+use crate::{
+    blit_to_backbuffer, bloom_system, composite_system, compute_system_copy_vertices, debug_draw_system, debug_text_system,
+    depth_system, fog_system, mesh_system,
+    pick_system, post_process_system, ssao_system, tonemap_system, triangle_system, triangle_system_camera_vertices,
+    triangle_system_vertices, ui_system,
+};
+
+// The files of the source tree, not the ones found at runtime.
+const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../data/shaders");
+
+const INTERFACES: &[&ShaderInterface] = &[
+    &blit_to_backbuffer::SHADER_INTERFACE,
+    &bloom_system::SHADER_INTERFACE,
+    &composite_system::SHADER_INTERFACE,
+    &compute_system_copy_vertices::SHADER_INTERFACE,
+    &debug_draw_system::SHADER_INTERFACE,
+    &debug_text_system::SHADER_INTERFACE,
+    &depth_system::SHADER_INTERFACE,
+    &depth_system::SHADER_INTERFACE_MULTISAMPLED,
+    &fog_system::SHADER_INTERFACE,
+    &mesh_system::SHADER_INTERFACE,
+    &pick_system::SHADER_INTERFACE,
+    &post_process_system::SHADER_INTERFACE,
+    &ssao_system::SHADER_INTERFACE,
+    &tonemap_system::SHADER_INTERFACE,
+    &triangle_system::SHADER_INTERFACE,
+    &triangle_system_camera_vertices::SHADER_INTERFACE,
+    &triangle_system_vertices::SHADER_INTERFACE,
+    &ui_system::SHADER_INTERFACE,
+];
+
+fn wgsl_files(dir: &str) -> Vec<String>
+{
+    let mut names: Vec<String> = std::fs::read_dir(format!("{}/{}", SHADER_DIR, dir))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "wgsl"))
+        .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+        .map(|name| if dir.is_empty() { name } else { format!("{}/{}", dir, name) })
+        .collect();
+    names.sort();
+    return names;
+}
+
+fn parse_and_validate(name: &str, defines: &[(&str, &str)]) -> (Module, ModuleInfo)
+{
+    let source = shaders::load_source(Path::new(SHADER_DIR), name, defines).unwrap();
+    let module = naga::front::wgsl::parse_str(&source)
+        .unwrap_or_else(|err| panic!("{}: {}", name, err.emit_to_string(&source)));
+    let info = Validator::new(ValidationFlags::all(), Capabilities::empty())
+        .validate(&module)
+        .unwrap_or_else(|err| panic!("{}: {:?}", name, err));
+    return (module, info);
+}
+
+fn stage_flags(stage: naga::ShaderStage) -> ShaderStages
+{
+    return match stage
+    {
+        naga::ShaderStage::Vertex => ShaderStages::VERTEX,
+        naga::ShaderStage::Fragment => ShaderStages::FRAGMENT,
+        naga::ShaderStage::Compute => ShaderStages::COMPUTE,
+    };
+}
+
+fn view_dimension(dim: ImageDimension, arrayed: bool) -> TextureViewDimension
+{
+    return match (dim, arrayed)
+    {
+        (ImageDimension::D1, _) => TextureViewDimension::D1,
+        (ImageDimension::D2, false) => TextureViewDimension::D2,
+        (ImageDimension::D2, true) => TextureViewDimension::D2Array,
+        (ImageDimension::D3, _) => TextureViewDimension::D3,
+        (ImageDimension::Cube, false) => TextureViewDimension::Cube,
+        (ImageDimension::Cube, true) => TextureViewDimension::CubeArray,
+    };
+}
+
+fn storage_format(format: naga::StorageFormat) -> wgpu::TextureFormat
+{
+    return match format
+    {
+        naga::StorageFormat::Rgba8Unorm => wgpu::TextureFormat::Rgba8Unorm,
+        naga::StorageFormat::Rgba16Float => wgpu::TextureFormat::Rgba16Float,
+        naga::StorageFormat::Rgba32Float => wgpu::TextureFormat::Rgba32Float,
+        naga::StorageFormat::R32Float => wgpu::TextureFormat::R32Float,
+        naga::StorageFormat::R32Uint => wgpu::TextureFormat::R32Uint,
+        format => panic!("Storage format {:?} not handled by the validation tests", format),
+    };
+}
+
+fn storage_texture_access(access: StorageAccess) -> wgpu::StorageTextureAccess
+{
+    return if access == StorageAccess::STORE
+    {
+        wgpu::StorageTextureAccess::WriteOnly
+    }
+    else if access == StorageAccess::LOAD
+    {
+        wgpu::StorageTextureAccess::ReadOnly
+    }
+    else
+    {
+        wgpu::StorageTextureAccess::ReadWrite
+    };
+}
+
+// Returns an error message if the layout entry can't be bound to the shader variable.
+fn check_binding_type(module: &Module, var: &naga::GlobalVariable, ty: &BindingType) -> Result<(), String>
+{
+    let inner = &module.types[var.ty].inner;
+    let ok = match (var.space, inner, ty)
+    {
+        (AddressSpace::Uniform, _, BindingType::Buffer { ty: BufferBindingType::Uniform, .. }) => true,
+        (AddressSpace::Storage { access }, _, BindingType::Buffer { ty: BufferBindingType::Storage { read_only }, .. }) =>
+            *read_only == !access.contains(StorageAccess::STORE),
+        (AddressSpace::Handle, TypeInner::Sampler { comparison }, BindingType::Sampler(sampler_type)) =>
+            *comparison == (*sampler_type == wgpu::SamplerBindingType::Comparison),
+        (AddressSpace::Handle, TypeInner::Image { dim, arrayed, class },
+            BindingType::Texture { sample_type, view_dimension: rust_dim, multisampled }) =>
+        {
+            let class_ok = match (class, sample_type)
+            {
+                (ImageClass::Sampled { kind: ScalarKind::Float, multi }, TextureSampleType::Float { .. })
+                | (ImageClass::Sampled { kind: ScalarKind::Sint, multi }, TextureSampleType::Sint)
+                | (ImageClass::Sampled { kind: ScalarKind::Uint, multi }, TextureSampleType::Uint)
+                | (ImageClass::Depth { multi }, TextureSampleType::Depth) => multi == multisampled,
+                _ => false,
+            };
+            class_ok && view_dimension(*dim, *arrayed) == *rust_dim
+        },
+        (AddressSpace::Handle, TypeInner::Image { dim, arrayed, class: ImageClass::Storage { format, access } },
+            BindingType::StorageTexture { access: rust_access, format: rust_format, view_dimension: rust_dim }) =>
+            storage_format(*format) == *rust_format
+                && storage_texture_access(*access) == *rust_access
+                && view_dimension(*dim, *arrayed) == *rust_dim,
+        _ => false,
+    };
+    return if ok { Ok(()) } else { Err(format!("shader has {:?} {:?}, layout has {:?}", var.space, inner, ty)) };
+}
+
+fn check_vertex_format(inner: &TypeInner, format: wgpu::VertexFormat) -> bool
+{
+    let (size, kind) = match format
+    {
+        wgpu::VertexFormat::Float32 => (None, ScalarKind::Float),
+        wgpu::VertexFormat::Float32x2 => (Some(naga::VectorSize::Bi), ScalarKind::Float),
+        wgpu::VertexFormat::Float32x3 => (Some(naga::VectorSize::Tri), ScalarKind::Float),
+        wgpu::VertexFormat::Float32x4 => (Some(naga::VectorSize::Quad), ScalarKind::Float),
+        wgpu::VertexFormat::Uint32 => (None, ScalarKind::Uint),
+        wgpu::VertexFormat::Uint32x4 => (Some(naga::VectorSize::Quad), ScalarKind::Uint),
+        format => panic!("Vertex format {:?} not handled by the validation tests", format),
+    };
+    return match (inner, size)
+    {
+        (&TypeInner::Scalar { kind: shader_kind, width: 4 }, None) => shader_kind == kind,
+        (&TypeInner::Vector { size: shader_size, kind: shader_kind, width: 4 }, Some(size)) =>
+            shader_size == size && shader_kind == kind,
+        _ => false,
+    };
+}
+
+// (location, type) of every vertex shader input.
+fn vertex_inputs(module: &Module, function: &naga::Function) -> Vec<(u32, TypeInner)>
+{
+    let mut inputs = Vec::new();
+    for argument in &function.arguments
+    {
+        match (&argument.binding, &module.types[argument.ty].inner)
+        {
+            (Some(Binding::Location { location, .. }), inner) => inputs.push((*location, inner.clone())),
+            (None, TypeInner::Struct { members, .. }) =>
+            {
+                for member in members
+                {
+                    if let Some(Binding::Location { location, .. }) = member.binding
+                    {
+                        inputs.push((location, module.types[member.ty].inner.clone()));
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+    return inputs;
+}
+
+fn check_interface(interface: &ShaderInterface)
+{
+    let name = interface.shader_name;
+    let (module, info) = parse_and_validate(name, interface.defines);
+
+    for &(stage, entry_name) in interface.entry_points
+    {
+        let found = module.entry_points
+            .iter()
+            .any(|entry| entry.name == entry_name && stage_flags(entry.stage) == stage);
+        assert!(found, "{}: no {:?} entry point {}", name, stage, entry_name);
+    }
+
+    for (index, entry) in module.entry_points.iter().enumerate()
+    {
+        let stage = stage_flags(entry.stage);
+        if !interface.entry_points.contains(&(stage, entry.name.as_str()))
+        {
+            continue;
+        }
+        let function_info = info.get_entry_point(index);
+        for (handle, var) in module.global_variables.iter()
+        {
+            let binding = match &var.binding
+            {
+                Some(binding) if !function_info[handle].is_empty() => binding,
+                _ => continue,
+            };
+            let layout_entry = interface.bind_group_layouts
+                .get(binding.group as usize)
+                .and_then(|entries| entries.iter().find(|e| e.binding == binding.binding))
+                .unwrap_or_else(|| panic!("{}: {} uses @group({}) @binding({}) missing from the layout",
+                    name, entry.name, binding.group, binding.binding));
+            assert!(layout_entry.visibility.contains(stage),
+                "{}: @group({}) @binding({}) is not visible to {:?}", name, binding.group, binding.binding, stage);
+            if let Err(err) = check_binding_type(&module, var, &layout_entry.ty)
+            {
+                panic!("{}: @group({}) @binding({}) mismatch, {}", name, binding.group, binding.binding, err);
+            }
+        }
+
+        if entry.stage == naga::ShaderStage::Vertex
+        {
+            for (location, inner) in vertex_inputs(&module, &entry.function)
+            {
+                let attribute = interface.vertex_attributes
+                    .iter()
+                    .find(|attribute| attribute.shader_location == location)
+                    .unwrap_or_else(|| panic!("{}: vertex input @location({}) has no attribute", name, location));
+                assert!(check_vertex_format(&inner, attribute.format),
+                    "{}: vertex input @location({}) is {:?}, attribute is {:?}", name, location, inner, attribute.format);
+            }
+        }
+    }
+}
+
+#[test]
+fn every_shader_parses_and_validates()
+{
+    for name in wgsl_files("").iter().chain(&wgsl_files("shared"))
+    {
+        parse_and_validate(name, &[]);
+    }
+}
+
+#[test]
+fn every_shader_has_an_interface()
+{
+    let declared: Vec<&str> = INTERFACES.iter().map(|interface| interface.shader_name).collect();
+    for name in wgsl_files("")
+    {
+        assert!(declared.contains(&name.as_str()), "{} is not used by any pass", name);
+    }
+}
+
+#[test]
+fn passes_match_their_shaders()
+{
+    for interface in INTERFACES
+    {
+        check_interface(interface);
+    }
+}
@@ -0,0 +1,438 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use wgpu::{BindGroupLayout, Device, ShaderModule};
+
+use crate::shader_preprocessor::Preprocessor;
+
+// Shader directory loaded at runtime, looked up from the working directory and from the
+// executable's directory and its parents, so a build run from anywhere finds the project's.
+const SHADER_DIR: &str = "data/shaders";
+
+// How often the shader files are checked for changes while hot reloading.
+const POLL_INTERVAL_SECONDS: f64 = 0.5;
+
+// Shaders built into the binary, used at startup and whenever hot reloading is disabled.
+const EMBEDDED_SHADERS: &[(&str, &str)] = &[
+    ("blit.wgsl", include_str!("../../../data/shaders/blit.wgsl")),
+    ("bloom.wgsl", include_str!("../../../data/shaders/bloom.wgsl")),
+    ("composite.wgsl", include_str!("../../../data/shaders/composite.wgsl")),
+    ("compute_copy_vertices.wgsl", include_str!("../../../data/shaders/compute_copy_vertices.wgsl")),
+    ("debug_draw.wgsl", include_str!("../../../data/shaders/debug_draw.wgsl")),
+    ("debug_text.wgsl", include_str!("../../../data/shaders/debug_text.wgsl")),
+    ("fog.wgsl", include_str!("../../../data/shaders/fog.wgsl")),
+    ("linear_depth.wgsl", include_str!("../../../data/shaders/linear_depth.wgsl")),
+    ("mesh.wgsl", include_str!("../../../data/shaders/mesh.wgsl")),
+    ("pick.wgsl", include_str!("../../../data/shaders/pick.wgsl")),
+    ("triangle_shader.wgsl", include_str!("../../../data/shaders/triangle_shader.wgsl")),
+    ("triangle_shader_camera_vertices.wgsl", include_str!("../../../data/shaders/triangle_shader_camera_vertices.wgsl")),
+    ("post_process.wgsl", include_str!("../../../data/shaders/post_process.wgsl")),
+    ("ssao.wgsl", include_str!("../../../data/shaders/ssao.wgsl")),
+    ("tonemap.wgsl", include_str!("../../../data/shaders/tonemap.wgsl")),
+    ("triangle_shader_vertices.wgsl", include_str!("../../../data/shaders/triangle_shader_vertices.wgsl")),
+    ("ui.wgsl", include_str!("../../../data/shaders/ui.wgsl")),
+];
+
+// Files only used through #include.
+const EMBEDDED_INCLUDES: &[(&str, &str)] = &[
+    ("shared/camera.wgsl", include_str!("../../../data/shaders/shared/camera.wgsl")),
+    ("shared/mesh.wgsl", include_str!("../../../data/shaders/shared/mesh.wgsl")),
+    ("shared/view.wgsl", include_str!("../../../data/shaders/shared/view.wgsl")),
+];
+
+fn load_embedded_file(name: &str) -> Result<String, String>
+{
+    return EMBEDDED_SHADERS
+        .iter()
+        .chain(EMBEDDED_INCLUDES)
+        .find(|(file_name, _)| *file_name == name)
+        .map(|(_, source)| source.to_string())
+        .ok_or_else(|| format!("No embedded shader file named {}", name));
+}
+
+fn load_file(dir: &Path, name: &str) -> Result<String, String>
+{
+    let path = dir.join(name);
+    return std::fs::read_to_string(&path)
+        .map_err(|err| format!("Failed to read shader {}: {}", path.display(), err));
+}
+
+// None when neither place has the directory, then only the embedded shaders are used.
+fn find_shader_dir() -> Option<PathBuf>
+{
+    let working_dir = std::env::current_dir().ok();
+    let exe = std::env::current_exe().ok();
+    let exe_dirs = exe.iter().flat_map(|exe| exe.ancestors().skip(1));
+    return working_dir
+        .iter()
+        .map(|dir| dir.as_path())
+        .chain(exe_dirs)
+        .map(|dir| dir.join(SHADER_DIR))
+        .find(|dir| dir.is_dir());
+}
+
+// Preprocessed source of a shader read from the directory.
+pub fn load_source(dir: &Path, name: &str, defines: &[(&str, &str)]) -> Result<String, String>
+{
+    return preprocess(&|file_name| load_file(dir, file_name), name, defines);
+}
+
+// Preprocessed source of a shader built into the binary.
+pub fn embedded_source(name: &str) -> String
+{
+    return embedded_source_with_defines(name, &[]);
+}
+
+pub fn embedded_source_with_defines(name: &str, defines: &[(&str, &str)]) -> String
+{
+    return preprocess(&load_embedded_file, name, defines)
+        .unwrap_or_else(|err| panic!("Failed to preprocess embedded shader {}: {}", name, err));
+}
+
+fn preprocess(
+    load: &dyn Fn(&str) -> Result<String, String>,
+    name: &str,
+    defines: &[(&str, &str)]
+) -> Result<String, String>
+{
+    let mut preprocessor = Preprocessor::new(load);
+    for (define, value) in defines
+    {
+        preprocessor.define(define, value);
+    }
+    return preprocessor.process(name);
+}
+
+pub fn create_shader_module(device: &Device, name: &str, source: &str) -> ShaderModule
+{
+    return device.create_shader_module(wgpu::ShaderModuleDescriptor
+    {
+        label: Some(name),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+}
+
+// Runs the creation inside a validation error scope, so broken shaders give an error
+// instead of hitting the uncaptured error handler.
+pub fn validated<T>(device: &Device, create: impl FnOnce() -> T) -> Result<T, String>
+{
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let result = create();
+    return match pollster::block_on(device.pop_error_scope())
+    {
+        Some(error) => Err(error.to_string()),
+        None => Ok(result),
+    };
+}
+
+// What a pass expects from its shader. The pass creates its bind group layouts, vertex buffer
+// layouts and pipeline entry points from this, and the shader validation tests check the
+// shader files against it.
+pub struct ShaderInterface
+{
+    pub shader_name: &'static str,
+    pub entry_points: &'static [(wgpu::ShaderStages, &'static str)],
+    pub bind_group_layouts: &'static [&'static [wgpu::BindGroupLayoutEntry]],
+    pub vertex_attributes: &'static [wgpu::VertexAttribute],
+    // Defines the shader is preprocessed with, a pass has one interface per shader variant.
+    pub defines: &'static [(&'static str, &'static str)],
+}
+
+impl ShaderInterface
+{
+    pub fn create_bind_group_layout(&self, device: &Device, group: usize, label: &str) -> BindGroupLayout
+    {
+        return device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor
+        {
+            label: Some(label),
+            entries: self.bind_group_layouts[group],
+        });
+    }
+
+    // Panics for an entry point missing from the interface, so every pipeline only uses the
+    // entry points the shader validation checks.
+    pub fn entry_point(&self, stage: wgpu::ShaderStages, name: &str) -> &'static str
+    {
+        return self.entry_points
+            .iter()
+            .find(|&&(entry_stage, entry_name)| entry_stage == stage && entry_name == name)
+            .map(|&(_, entry_name)| entry_name)
+            .unwrap_or_else(|| panic!("{} has no {:?} entry point {} in its interface", self.shader_name, stage, name));
+    }
+
+    pub fn vertex_buffer_layout(
+        &self,
+        array_stride: wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode
+    ) -> wgpu::VertexBufferLayout<'static>
+    {
+        return wgpu::VertexBufferLayout
+        {
+            array_stride,
+            step_mode,
+            attributes: self.vertex_attributes,
+        };
+    }
+}
+
+// Passes that can rebuild their pipelines from new shader source.
+pub trait ShaderReloadable
+{
+    fn shader_name(&self) -> &'static str;
+
+    // Defines of the shader variant the pass currently uses.
+    fn shader_defines(&self) -> &'static [(&'static str, &'static str)]
+    {
+        return &[];
+    }
+
+    // On error the pass has to keep its current pipelines.
+    fn reload_shader(&mut self, device: &Device, source: &str) -> Result<(), String>;
+}
+
+pub struct ShaderLibrary
+{
+    hot_reload: bool,
+    shader_dir: Option<PathBuf>,
+    time_since_poll: f64,
+    modified_times: HashMap<&'static str, SystemTime>,
+}
+
+impl ShaderLibrary
+{
+    pub fn new() -> Self
+    {
+        Self
+        {
+            hot_reload: false,
+            shader_dir: find_shader_dir(),
+            time_since_poll: 0.0,
+            modified_times: HashMap::new(),
+        }
+    }
+
+    pub fn hot_reload(&self) -> bool
+    {
+        return self.hot_reload;
+    }
+
+    pub fn set_hot_reload(&mut self, enabled: bool)
+    {
+        self.hot_reload = enabled;
+        if enabled
+        {
+            // Looked up again in case the directory was moved while running.
+            self.shader_dir = find_shader_dir();
+            if self.shader_dir.is_none()
+            {
+                log::warn!("No {} directory found, hot reloading keeps the embedded shaders", SHADER_DIR);
+            }
+        }
+        // Forget the old times, so the first poll loads everything from disk in case the
+        // files were edited after the binary was built.
+        self.modified_times.clear();
+        self.time_since_poll = POLL_INTERVAL_SECONDS;
+    }
+
+    // Preprocessed source of a shader read from data/shaders.
+    pub fn load(&self, name: &str, defines: &[(&str, &str)]) -> Result<String, String>
+    {
+        return match &self.shader_dir
+        {
+            Some(dir) => load_source(dir, name, defines),
+            None => Err(format!("No {} directory found", SHADER_DIR)),
+        };
+    }
+
+    // Source for a pass switching shader variants, from disk while hot reloading so the
+    // edits are kept, otherwise the embedded one.
+    pub fn current_source(&self, name: &str, defines: &[(&str, &str)]) -> String
+    {
+        if self.hot_reload
+        {
+            match self.load(name, defines)
+            {
+                Ok(source) => return source,
+                Err(err) => log::error!("{}, using the embedded shader", err),
+            }
+        }
+        return embedded_source_with_defines(name, defines);
+    }
+
+    // Returns the names of the shaders whose files changed since the last poll.
+    pub fn poll_changed(&mut self, dt: f64) -> Vec<&'static str>
+    {
+        let mut changed = Vec::new();
+        let shader_dir = match (&self.shader_dir, self.hot_reload)
+        {
+            (Some(shader_dir), true) => shader_dir,
+            _ => return changed,
+        };
+        self.time_since_poll += dt;
+        if self.time_since_poll < POLL_INTERVAL_SECONDS
+        {
+            return changed;
+        }
+        self.time_since_poll = 0.0;
+
+        let mut include_changed = false;
+        for (name, _) in EMBEDDED_SHADERS.iter().chain(EMBEDDED_INCLUDES)
+        {
+            let path = shader_dir.join(name);
+            let modified = match std::fs::metadata(path).and_then(|m| m.modified())
+            {
+                Ok(modified) => modified,
+                Err(_) => continue,
+            };
+            if self.modified_times.insert(name, modified) != Some(modified)
+            {
+                changed.push(*name);
+                include_changed |= EMBEDDED_INCLUDES.iter().any(|(include, _)| include == name);
+            }
+        }
+        // Not tracking who includes what, any changed include reloads every shader.
+        if include_changed
+        {
+            return EMBEDDED_SHADERS.iter().map(|(name, _)| *name).collect();
+        }
+        return changed;
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // (name, offset, size) of every field of a #[repr(C)] struct.
+    macro_rules! rust_fields {
+        ($ty:ty, $($field:ident),+) => {
+            &[$((
+                stringify!($field),
+                std::mem::offset_of!($ty, $field),
+                std::mem::size_of_val(&<$ty as bytemuck::Zeroable>::zeroed().$field),
+            )),+]
+        };
+    }
+
+    fn parse(name: &str) -> naga::Module
+    {
+        let source = embedded_source(name);
+        return naga::front::wgsl::parse_str(&source)
+            .unwrap_or_else(|err| panic!("{}: {}", name, err.emit_to_string(&source)));
+    }
+
+    fn check_struct(
+        module: &naga::Module,
+        wgsl_name: &str,
+        rust_size: usize,
+        rust_fields: &[(&str, usize, usize)]
+    )
+    {
+        let (members, span) = module.types
+            .iter()
+            .find_map(|(_, ty)| match ty.inner
+            {
+                naga::TypeInner::Struct { ref members, span } if ty.name.as_deref() == Some(wgsl_name) =>
+                    Some((members, span)),
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("No struct {} in the shader", wgsl_name));
+
+        assert_eq!(span as usize, rust_size, "Size of {} differs", wgsl_name);
+        assert_eq!(members.len(), rust_fields.len(), "Field count of {} differs", wgsl_name);
+        for (member, &(name, offset, size)) in members.iter().zip(rust_fields)
+        {
+            let member_size = module.types[member.ty].inner.size(&module.constants);
+            assert_eq!(member.name.as_deref(), Some(name), "Field order of {} differs", wgsl_name);
+            assert_eq!(member.offset as usize, offset, "Offset of {}.{} differs", wgsl_name, name);
+            assert_eq!(member_size as usize, size, "Size of {}.{} differs", wgsl_name, name);
+        }
+    }
+
+    #[test]
+    fn embedded_shaders_preprocess()
+    {
+        for (name, _) in EMBEDDED_SHADERS
+        {
+            parse(name);
+        }
+    }
+
+    #[test]
+    fn shared_structs_match_rust_layouts()
+    {
+        use crate::triangle_system_camera_vertices::CameraUniform;
+        use common::{GpuOutInstanceBounds, GpuOutInstanceMatrices, MeshModelLocation, MeshVertex};
+
+        let camera = parse("triangle_shader_camera_vertices.wgsl");
+        check_struct(&camera, "CameraUniform", std::mem::size_of::<CameraUniform>(),
+            rust_fields!(CameraUniform, view_proj));
+
+        let source = Preprocessor::new(&load_embedded_file).process("shared/mesh.wgsl").unwrap();
+        let mesh = naga::front::wgsl::parse_str(&source).unwrap();
+        check_struct(&mesh, "MeshVertex", std::mem::size_of::<MeshVertex>(),
+            rust_fields!(MeshVertex, position, normal, color));
+        check_struct(&mesh, "MeshModelLocation", std::mem::size_of::<MeshModelLocation>(),
+            rust_fields!(MeshModelLocation, vertices_start_index, vertices_count, indices_start_index, indices_count));
+        check_struct(&mesh, "GpuOutInstanceMatrices", std::mem::size_of::<GpuOutInstanceMatrices>(),
+            rust_fields!(GpuOutInstanceMatrices, v0, v1, v2));
+        check_struct(&mesh, "GpuOutInstanceBounds", std::mem::size_of::<GpuOutInstanceBounds>(),
+            rust_fields!(GpuOutInstanceBounds, center, radius));
+
+        use crate::tonemap_system::TonemapParams;
+        let tonemap = parse("tonemap.wgsl");
+        check_struct(&tonemap, "TonemapParams", std::mem::size_of::<TonemapParams>(),
+            rust_fields!(TonemapParams, exposure, tonemapper, auto_exposure, min_log_luminance,
+                log_luminance_range, dt, adaptation_rate, pixel_count));
+
+        use crate::bloom_system::BloomParams;
+        let bloom = parse("bloom.wgsl");
+        check_struct(&bloom, "BloomParams", std::mem::size_of::<BloomParams>(),
+            rust_fields!(BloomParams, threshold, knee, intensity, _padding));
+
+        use crate::post_process_system::PostProcessParams;
+        let post_process = parse("post_process.wgsl");
+        check_struct(&post_process, "PostProcessParams", std::mem::size_of::<PostProcessParams>(),
+            rust_fields!(PostProcessParams, inv_size, fxaa_edge_threshold, fxaa_edge_threshold_min, fxaa_span_max,
+                lut_strength, lut_size, vignette_intensity, vignette_radius, vignette_smoothness,
+                chromatic_aberration, _padding));
+
+        use crate::depth_system::ViewParams;
+        let linear_depth = parse("linear_depth.wgsl");
+        check_struct(&linear_depth, "ViewParams", std::mem::size_of::<ViewParams>(),
+            rust_fields!(ViewParams, right, up, forward, position, near, far, tan_half_fov_y, aspect));
+
+        use crate::ssao_system::SsaoParams;
+        let ssao = parse("ssao.wgsl");
+        check_struct(&ssao, "SsaoParams", std::mem::size_of::<SsaoParams>(),
+            rust_fields!(SsaoParams, radius, bias, intensity, sample_count));
+
+        use crate::fog_system::FogParams;
+        let fog = parse("fog.wgsl");
+        check_struct(&fog, "FogParams", std::mem::size_of::<FogParams>(),
+            rust_fields!(FogParams, color, distance_density, start_distance, height_density, height_falloff,
+                base_height, _padding0, _padding1, _padding2));
+
+        use crate::composite_system::CompositeParams;
+        let composite = parse("composite.wgsl");
+        check_struct(&composite, "CompositeParams", std::mem::size_of::<CompositeParams>(),
+            rust_fields!(CompositeParams, apply_ao, apply_fog, depth_sharpness, _padding));
+
+        use crate::blit_to_backbuffer::BlitParams;
+        let blit = parse("blit.wgsl");
+        check_struct(&blit, "BlitParams", std::mem::size_of::<BlitParams>(),
+            rust_fields!(BlitParams, sharpness, encode_srgb, _padding0, _padding1));
+
+        use crate::debug_text_system::DebugTextParams;
+        let debug_text = parse("debug_text.wgsl");
+        check_struct(&debug_text, "DebugTextParams", std::mem::size_of::<DebugTextParams>(),
+            rust_fields!(DebugTextParams, inv_screen_size, scale, _padding));
+
+        use crate::ui_system::UiParams;
+        let ui = parse("ui.wgsl");
+        check_struct(&ui, "UiParams", std::mem::size_of::<UiParams>(),
+            rust_fields!(UiParams, screen_size, decode_srgb, _padding));
+    }
+}
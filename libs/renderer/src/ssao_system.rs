@@ -0,0 +1,299 @@
+use wgpu::*;
+use wgpu::util::DeviceExt;
+
+use crate::depth_system::{self, ViewParams};
+use crate::render_targets::{RenderTargets, ScreenSize, ScreenSizeDependent};
+use crate::shaders::{self, ShaderInterface, ShaderReloadable};
+
+const SHADER_NAME: &str = "ssao.wgsl";
+const COMPUTE_ENTRY: &str = "main";
+
+const BIND_GROUP_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] = &[
+    // Linear depth
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    },
+    // Ambient occlusion
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::StorageTexture {
+            view_dimension: wgpu::TextureViewDimension::D2,
+            format: wgpu::TextureFormat::R32Float,
+            access: wgpu::StorageTextureAccess::WriteOnly,
+        },
+        count: None,
+    },
+    // View
+    wgpu::BindGroupLayoutEntry {
+        binding: 2,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+    // Params
+    wgpu::BindGroupLayoutEntry {
+        binding: 3,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+];
+
+pub(crate) const SHADER_INTERFACE: ShaderInterface = ShaderInterface {
+    shader_name: SHADER_NAME,
+    entry_points: &[(wgpu::ShaderStages::COMPUTE, COMPUTE_ENTRY)],
+    bind_group_layouts: &[BIND_GROUP_LAYOUT_ENTRIES],
+    vertex_attributes: &[],
+    defines: &[],
+};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SsaoSettings
+{
+    pub enabled: bool,
+    // Computed at half the screen size and upsampled.
+    pub half_resolution: bool,
+    // Sampling radius in world units.
+    pub radius: f32,
+    // Depth difference ignored as occlusion, avoids self shadowing on flat surfaces.
+    pub bias: f32,
+    // Power applied to the occlusion, higher is darker.
+    pub intensity: f32,
+    pub sample_count: u32,
+}
+
+impl Default for SsaoSettings
+{
+    fn default() -> Self
+    {
+        Self
+        {
+            enabled: true,
+            half_resolution: true,
+            radius: 0.5,
+            bias: 0.025,
+            intensity: 1.5,
+            sample_count: 16,
+        }
+    }
+}
+
+// Shared with ssao.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct SsaoParams
+{
+    pub(crate) radius: f32,
+    pub(crate) bias: f32,
+    pub(crate) intensity: f32,
+    pub(crate) sample_count: u32,
+}
+
+impl SsaoParams
+{
+    fn new(settings: &SsaoSettings) -> Self
+    {
+        Self
+        {
+            radius: settings.radius,
+            bias: settings.bias,
+            intensity: settings.intensity,
+            sample_count: settings.sample_count,
+        }
+    }
+}
+
+// Computes the ambient occlusion from the linear depth into the ao texture.
+pub struct TriangleSystem
+{
+    _shader: ShaderModule,
+    _bind_group_layout: BindGroupLayout,
+    _pipeline_layout: PipelineLayout,
+    compute_pipeline: ComputePipeline,
+    bind_group: BindGroup,
+
+    view_buffer: Buffer,
+    params_buffer: Buffer,
+
+    settings: SsaoSettings,
+    pub(crate) size: ScreenSize,
+}
+
+impl TriangleSystem
+{
+    pub fn new(device: &Device, render_targets: &RenderTargets) -> Self
+    {
+        let _shader = shaders::create_shader_module(device, SHADER_NAME, &shaders::embedded_source(SHADER_NAME));
+
+        let _bind_group_layout = SHADER_INTERFACE.create_bind_group_layout(device, 0, "Ssao bindings");
+
+        let _pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor
+        {
+            label: None,
+            bind_group_layouts: &[&_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let compute_pipeline = Self::create_pipeline(device, &_pipeline_layout, &_shader);
+
+        let settings = SsaoSettings::default();
+        let view_buffer = depth_system::create_view_buffer(device, "Ssao view");
+        let params_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Ssao params"),
+                contents: bytemuck::cast_slice(&[SsaoParams::new(&settings)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        let bind_group = Self::create_bind_group(
+            device,
+            &_bind_group_layout,
+            render_targets,
+            &view_buffer,
+            &params_buffer,
+        );
+
+        Self {
+            _shader,
+            _bind_group_layout,
+            _pipeline_layout,
+            compute_pipeline,
+            bind_group,
+
+            view_buffer,
+            params_buffer,
+
+            settings,
+            size: render_targets.effect_size(render_targets.ao_half_resolution),
+        }
+    }
+
+    fn create_pipeline(device: &Device, pipeline_layout: &PipelineLayout, shader: &ShaderModule) -> ComputePipeline
+    {
+        return device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Ssao"),
+            layout: Some(pipeline_layout),
+            module: shader,
+            entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::COMPUTE, COMPUTE_ENTRY),
+        });
+    }
+
+    pub fn rebind_textures(&mut self, device: &Device, render_targets: &RenderTargets)
+    {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self._bind_group_layout,
+            render_targets,
+            &self.view_buffer,
+            &self.params_buffer,
+        );
+        self.size = render_targets.effect_size(render_targets.ao_half_resolution);
+    }
+
+    pub fn settings(&self) -> SsaoSettings
+    {
+        return self.settings;
+    }
+
+    pub fn set_settings(&mut self, settings: SsaoSettings)
+    {
+        self.settings = settings;
+    }
+
+    pub fn update(&mut self, camera: &common::Camera, queue: &Queue)
+    {
+        queue.write_buffer(&self.view_buffer, 0, bytemuck::cast_slice(&[ViewParams::new(camera)]));
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[SsaoParams::new(&self.settings)]));
+    }
+
+    pub fn dispatch_size(size: ScreenSize) -> (u32, u32, u32)
+    {
+        return size.workgroups_2d(8, 8);
+    }
+
+    pub fn render(&mut self, encoder: &mut CommandEncoder)
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("Ssao") });
+        compute_pass.set_pipeline(&self.compute_pipeline);
+        compute_pass.set_bind_group(0, &self.bind_group, &[]);
+        let (x, y, z) = Self::dispatch_size(self.size);
+        compute_pass.dispatch_workgroups(x, y, z);
+    }
+
+    fn create_bind_group(
+        device: &Device,
+        bind_group_layout: &BindGroupLayout,
+        render_targets: &RenderTargets,
+        view_buffer: &Buffer,
+        params_buffer: &Buffer,
+    ) -> BindGroup
+    {
+        return device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Ssao Bind Group"),
+            layout: bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&render_targets.linear_depth_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&render_targets.ao_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: view_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+    }
+}
+
+impl ScreenSizeDependent for TriangleSystem
+{
+    fn rebind(&mut self, device: &Device, render_targets: &RenderTargets)
+    {
+        self.rebind_textures(device, render_targets);
+    }
+}
+
+impl ShaderReloadable for TriangleSystem
+{
+    fn shader_name(&self) -> &'static str
+    {
+        return SHADER_NAME;
+    }
+
+    fn reload_shader(&mut self, device: &Device, source: &str) -> Result<(), String>
+    {
+        let (shader, compute_pipeline) = shaders::validated(device, || {
+            let shader = shaders::create_shader_module(device, SHADER_NAME, source);
+            let compute_pipeline = Self::create_pipeline(device, &self._pipeline_layout, &shader);
+            (shader, compute_pipeline)
+        })?;
+        self._shader = shader;
+        self.compute_pipeline = compute_pipeline;
+        return Ok(());
+    }
+}
@@ -0,0 +1,442 @@
+use std::num::NonZeroU32;
+
+use wgpu::*;
+use wgpu::util::DeviceExt;
+
+use crate::render_targets::{PingPong, RenderTargets, ScreenSize, ScreenSizeDependent};
+use crate::shaders::{self, ShaderInterface, ShaderReloadable};
+
+const SHADER_NAME: &str = "tonemap.wgsl";
+const COMPUTE_ENTRY: &str = "main";
+const COMPUTE_HISTOGRAM_ENTRY: &str = "histogram_main";
+const COMPUTE_AVERAGE_ENTRY: &str = "average_main";
+
+const HISTOGRAM_BINS: usize = 256;
+
+// Luminance the auto exposure starts from, gives an exposure of 1.
+const INITIAL_ADAPTED_LUMINANCE: f32 = 0.18;
+
+const BIND_GROUP_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] = &[
+    // Input, the hdr scene
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    },
+    // Output
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::StorageTexture {
+            view_dimension: wgpu::TextureViewDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            access: wgpu::StorageTextureAccess::WriteOnly,
+        },
+        count: None,
+    },
+    // Params
+    wgpu::BindGroupLayoutEntry {
+        binding: 2,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+    // Luminance histogram
+    wgpu::BindGroupLayoutEntry {
+        binding: 3,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: false },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+    // Adapted luminance
+    wgpu::BindGroupLayoutEntry {
+        binding: 4,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: false },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+];
+
+pub(crate) const SHADER_INTERFACE: ShaderInterface = ShaderInterface {
+    shader_name: SHADER_NAME,
+    entry_points: &[
+        (wgpu::ShaderStages::COMPUTE, COMPUTE_ENTRY),
+        (wgpu::ShaderStages::COMPUTE, COMPUTE_HISTOGRAM_ENTRY),
+        (wgpu::ShaderStages::COMPUTE, COMPUTE_AVERAGE_ENTRY),
+    ],
+    bind_group_layouts: &[BIND_GROUP_LAYOUT_ENTRIES],
+    vertex_attributes: &[],
+    defines: &[],
+};
+
+// The order is used as the operator index in tonemap.wgsl.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TonemapOperator
+{
+    Aces,
+    Reinhard,
+    AgX,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TonemapSettings
+{
+    pub operator: TonemapOperator,
+    // Multiplier for the scene color, with auto exposure it works as exposure compensation.
+    pub exposure: f32,
+    pub auto_exposure: bool,
+    // Range of scene luminance the histogram covers, as log2 of the luminance.
+    pub min_log_luminance: f32,
+    pub max_log_luminance: f32,
+    // How fast the auto exposure adapts to the scene luminance, higher is faster.
+    pub adaptation_rate: f32,
+}
+
+impl Default for TonemapSettings
+{
+    fn default() -> Self
+    {
+        Self
+        {
+            operator: TonemapOperator::Aces,
+            exposure: 1.0,
+            auto_exposure: false,
+            min_log_luminance: -8.0,
+            max_log_luminance: 4.0,
+            adaptation_rate: 1.5,
+        }
+    }
+}
+
+// Shared with tonemap.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct TonemapParams
+{
+    pub(crate) exposure: f32,
+    pub(crate) tonemapper: u32,
+    pub(crate) auto_exposure: u32,
+    pub(crate) min_log_luminance: f32,
+    pub(crate) log_luminance_range: f32,
+    pub(crate) dt: f32,
+    pub(crate) adaptation_rate: f32,
+    pub(crate) pixel_count: u32,
+}
+
+impl TonemapParams
+{
+    fn new(settings: &TonemapSettings, size: ScreenSize, dt: f64) -> Self
+    {
+        Self
+        {
+            exposure: settings.exposure,
+            tonemapper: settings.operator as u32,
+            auto_exposure: settings.auto_exposure as u32,
+            min_log_luminance: settings.min_log_luminance,
+            log_luminance_range: (settings.max_log_luminance - settings.min_log_luminance).max(0.001),
+            dt: dt as f32,
+            adaptation_rate: settings.adaptation_rate,
+            pixel_count: size.width * size.height,
+        }
+    }
+}
+
+// Tonemaps the hdr scene into the other render target texture, optionally with auto exposure
+// from a luminance histogram.
+pub struct TriangleSystem
+{
+    _shader: ShaderModule,
+    _bind_group_layout: BindGroupLayout,
+    _pipeline_layout: PipelineLayout,
+    compute_pipeline: ComputePipeline,
+    compute_pipeline_histogram: ComputePipeline,
+    compute_pipeline_average: ComputePipeline,
+    // One per PingPong source.
+    bind_groups: Vec<BindGroup>,
+
+    params_buffer: Buffer,
+    histogram_buffer: Buffer,
+    adapted_luminance_buffer: Buffer,
+
+    settings: TonemapSettings,
+    pub(crate) size: ScreenSize,
+}
+
+impl TriangleSystem
+{
+    pub fn new(device: &Device, render_targets: &RenderTargets) -> Self
+    {
+        let _shader = shaders::create_shader_module(device, SHADER_NAME, &shaders::embedded_source(SHADER_NAME));
+
+        let _bind_group_layout = SHADER_INTERFACE.create_bind_group_layout(device, 0, "Tonemap bindings");
+
+        let _pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor
+        {
+            label: None,
+            bind_group_layouts: &[&_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let (compute_pipeline, compute_pipeline_histogram, compute_pipeline_average) =
+            Self::create_pipelines(device, &_pipeline_layout, &_shader);
+
+        let settings = TonemapSettings::default();
+        let size = render_targets.size;
+
+        let params_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Tonemap params"),
+                contents: bytemuck::cast_slice(&[TonemapParams::new(&settings, size, 0.0)]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+
+        // Zeroed at creation, the average pass clears it again after reading it.
+        let histogram_buffer = device.create_buffer(
+            &wgpu::BufferDescriptor {
+                label: Some("Luminance histogram"),
+                size: (std::mem::size_of::<u32>() * HISTOGRAM_BINS) as BufferAddress,
+                usage: wgpu::BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            }
+        );
+
+        let adapted_luminance_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Adapted luminance"),
+                contents: bytemuck::cast_slice(&[INITIAL_ADAPTED_LUMINANCE]),
+                usage: wgpu::BufferUsages::STORAGE,
+            }
+        );
+
+        let bind_groups = Self::create_bind_groups(
+            &device,
+            &_bind_group_layout,
+            render_targets,
+            &params_buffer,
+            &histogram_buffer,
+            &adapted_luminance_buffer,
+        );
+
+        Self {
+            _shader,
+            _bind_group_layout,
+            _pipeline_layout,
+            compute_pipeline,
+            compute_pipeline_histogram,
+            compute_pipeline_average,
+            bind_groups,
+
+            params_buffer,
+            histogram_buffer,
+            adapted_luminance_buffer,
+
+            settings,
+            size,
+        }
+    }
+
+    fn create_pipelines(
+        device: &Device,
+        pipeline_layout: &PipelineLayout,
+        shader: &ShaderModule
+    ) -> (ComputePipeline, ComputePipeline, ComputePipeline)
+    {
+        let create = |entry_point: &str| device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(entry_point),
+            layout: Some(pipeline_layout),
+            module: shader,
+            entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::COMPUTE, entry_point),
+        });
+        return (
+            create(COMPUTE_ENTRY),
+            create(COMPUTE_HISTOGRAM_ENTRY),
+            create(COMPUTE_AVERAGE_ENTRY),
+        );
+    }
+
+    pub fn rebind_textures(&mut self, device: &Device, render_targets: &RenderTargets)
+    {
+        self.bind_groups = Self::create_bind_groups(
+            &device,
+            &self._bind_group_layout,
+            render_targets,
+            &self.params_buffer,
+            &self.histogram_buffer,
+            &self.adapted_luminance_buffer,
+        );
+        self.size = render_targets.size;
+    }
+
+    pub fn settings(&self) -> TonemapSettings
+    {
+        return self.settings;
+    }
+
+    pub fn set_settings(&mut self, settings: TonemapSettings)
+    {
+        self.settings = settings;
+    }
+
+    pub fn update(&mut self, queue: &Queue, dt: f64)
+    {
+        let params = TonemapParams::new(&self.settings, self.size, dt);
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+    }
+
+    pub fn dispatch_size(size: ScreenSize) -> (u32, u32, u32)
+    {
+        return size.workgroups_2d(8, 8);
+    }
+
+    pub fn histogram_dispatch_size(size: ScreenSize) -> (u32, u32, u32)
+    {
+        return size.workgroups_2d(16, 16);
+    }
+
+    pub fn render(&mut self, encoder: &mut CommandEncoder, source: PingPong)
+    {
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("Tonemap") });
+        compute_pass.set_bind_group(0, &self.bind_groups[source.index()], &[]);
+        if self.settings.auto_exposure
+        {
+            compute_pass.set_pipeline(&self.compute_pipeline_histogram);
+            let (x, y, z) = Self::histogram_dispatch_size(self.size);
+            compute_pass.dispatch_workgroups(x, y, z);
+
+            compute_pass.set_pipeline(&self.compute_pipeline_average);
+            compute_pass.dispatch_workgroups(1, 1, 1);
+        }
+        compute_pass.set_pipeline(&self.compute_pipeline);
+        let (x, y, z) = Self::dispatch_size(self.size);
+        compute_pass.dispatch_workgroups(x, y, z);
+    }
+
+    fn create_bind_groups(
+        device: &Device,
+        bind_group_layout: &BindGroupLayout,
+        render_targets: &RenderTargets,
+        params_buffer: &Buffer,
+        histogram_buffer: &Buffer,
+        adapted_luminance_buffer: &Buffer,
+    ) -> Vec<BindGroup>
+    {
+        return PingPong::BOTH
+            .iter()
+            .map(|&source| {
+                let (input_texture, output_texture) = render_targets.ping_pong_textures(source);
+                Self::create_bind_group(
+                    device,
+                    bind_group_layout,
+                    input_texture,
+                    output_texture,
+                    params_buffer,
+                    histogram_buffer,
+                    adapted_luminance_buffer,
+                )
+            })
+            .collect();
+    }
+
+    fn create_bind_group(
+        device: &Device,
+        bind_group_layout: &BindGroupLayout,
+        input_texture: &wgpu::Texture,
+        output_texture: &wgpu::Texture,
+        params_buffer: &Buffer,
+        histogram_buffer: &Buffer,
+        adapted_luminance_buffer: &Buffer,
+    ) -> BindGroup
+    {
+        let _input_view = input_texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some(&format!("Tonemap input view")),
+            format: Some(input_texture.format()),
+            base_mip_level: 0,
+            mip_level_count: Some(NonZeroU32::try_from(1u32).unwrap().into()),
+            ..Default::default()
+        });
+
+        let _output_view = output_texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some(&format!("Tonemap output view")),
+            format: Some(output_texture.format()),
+            base_mip_level: 0,
+            mip_level_count: Some(NonZeroU32::try_from(1u32).unwrap().into()),
+            ..Default::default()
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Tonemap Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                // Input
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&_input_view),
+                },
+                // Output
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&_output_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: histogram_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: adapted_luminance_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        return bind_group;
+    }
+}
+
+impl ScreenSizeDependent for TriangleSystem
+{
+    fn rebind(&mut self, device: &Device, render_targets: &RenderTargets)
+    {
+        self.rebind_textures(device, render_targets);
+    }
+}
+
+impl ShaderReloadable for TriangleSystem
+{
+    fn shader_name(&self) -> &'static str
+    {
+        return SHADER_NAME;
+    }
+
+    fn reload_shader(&mut self, device: &Device, source: &str) -> Result<(), String>
+    {
+        let (shader, pipelines) = shaders::validated(device, || {
+            let shader = shaders::create_shader_module(device, SHADER_NAME, source);
+            let pipelines = Self::create_pipelines(device, &self._pipeline_layout, &shader);
+            (shader, pipelines)
+        })?;
+        self._shader = shader;
+        (self.compute_pipeline, self.compute_pipeline_histogram, self.compute_pipeline_average) = pipelines;
+        return Ok(());
+    }
+}
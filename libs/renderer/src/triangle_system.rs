@@ -1,24 +1,37 @@
-use std::borrow::Cow;
-
 use wgpu::{ShaderModule, PipelineLayout, RenderPipeline, TextureFormat, Device, CommandEncoder, TextureView};
 
+use crate::shaders::{self, ShaderInterface, ShaderReloadable};
+
+const SHADER_NAME: &str = "triangle_shader.wgsl";
+const VERTEX_ENTRY: &str = "vs_main";
+const FRAGMENT_ENTRY: &str = "fs_main";
+
+pub(crate) const SHADER_INTERFACE: ShaderInterface = ShaderInterface {
+    shader_name: SHADER_NAME,
+    entry_points: &[
+        (wgpu::ShaderStages::VERTEX, VERTEX_ENTRY),
+        (wgpu::ShaderStages::FRAGMENT, FRAGMENT_ENTRY),
+    ],
+    bind_group_layouts: &[],
+    vertex_attributes: &[],
+    defines: &[],
+};
+
 pub struct TriangleSystem
 {
     pub shader: ShaderModule,
     pub pipeline_layout: PipelineLayout,
-    pub render_pipeline: RenderPipeline
+    pub render_pipeline: RenderPipeline,
+
+    texture_format: TextureFormat,
+    sample_count: u32,
 }
 
 impl TriangleSystem
 {
-    pub fn new(device: &Device, textureformat: TextureFormat) -> Self
+    pub fn new(device: &Device, textureformat: TextureFormat, sample_count: u32) -> Self
     {
-        // Load the shaders from disk
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor
-        {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("../../../data/shaders/triangle_shader.wgsl"))),
-        });
+        let shader = shaders::create_shader_module(device, SHADER_NAME, &shaders::embedded_source(SHADER_NAME));
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor
         {
@@ -27,37 +40,70 @@ impl TriangleSystem
             push_constant_ranges: &[],
         });
 
+        let render_pipeline = Self::create_pipeline(device, &pipeline_layout, &shader, textureformat, sample_count);
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor
+        Self {
+            shader,
+            pipeline_layout,
+            render_pipeline,
+
+            texture_format: textureformat,
+            sample_count,
+        }
+    }
+
+    fn create_pipeline(
+        device: &Device,
+        pipeline_layout: &PipelineLayout,
+        shader: &ShaderModule,
+        textureformat: TextureFormat,
+        sample_count: u32
+    ) -> RenderPipeline
+    {
+        return device.create_render_pipeline(&wgpu::RenderPipelineDescriptor
         {
             label: None,
-            layout: Some(&pipeline_layout),
+            layout: Some(pipeline_layout),
             vertex: wgpu::VertexState
             {
-                module: &shader,
-                entry_point: "vs_main",
+                module: shader,
+                entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::VERTEX, VERTEX_ENTRY),
                 buffers: &[],
             },
             fragment: Some(wgpu::FragmentState
             {
-                module: &shader,
-                entry_point: "fs_main",
+                module: shader,
+                entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::FRAGMENT, FRAGMENT_ENTRY),
                 targets: &[Some(textureformat.into())],
             }),
             primitive: wgpu::PrimitiveState::default(),
             depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
             multiview: None,
         });
+    }
 
-        Self {
-            shader,
-            pipeline_layout,
-            render_pipeline
-        }
+    // Rebuilds the pipeline to render into targets with a different sample count.
+    pub fn set_sample_count(&mut self, device: &Device, sample_count: u32)
+    {
+        self.sample_count = sample_count;
+        self.render_pipeline = Self::create_pipeline(
+            device,
+            &self.pipeline_layout,
+            &self.shader,
+            self.texture_format,
+            sample_count);
     }
 
-    pub fn render(&mut self, encoder: &mut CommandEncoder, view: &TextureView)
+    pub fn render(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        view: &TextureView,
+        resolve_target: Option<&TextureView>
+    )
     {
         let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor
         {
@@ -65,7 +111,7 @@ impl TriangleSystem
             color_attachments: &[Some(wgpu::RenderPassColorAttachment
             {
                 view,
-                resolve_target: None,
+                resolve_target,
                 ops: wgpu::Operations
                 {
                     load: wgpu::LoadOp::Clear(wgpu::Color::GREEN),
@@ -77,4 +123,25 @@ impl TriangleSystem
         rpass.set_pipeline(&self.render_pipeline);
         rpass.draw(0..3, 0..1);
     }
-}
\ No newline at end of file
+}
+
+impl ShaderReloadable for TriangleSystem
+{
+    fn shader_name(&self) -> &'static str
+    {
+        return SHADER_NAME;
+    }
+
+    fn reload_shader(&mut self, device: &Device, source: &str) -> Result<(), String>
+    {
+        let (shader, render_pipeline) = shaders::validated(device, || {
+            let shader = shaders::create_shader_module(device, SHADER_NAME, source);
+            let render_pipeline =
+                Self::create_pipeline(device, &self.pipeline_layout, &shader, self.texture_format, self.sample_count);
+            (shader, render_pipeline)
+        })?;
+        self.shader = shader;
+        self.render_pipeline = render_pipeline;
+        return Ok(());
+    }
+}
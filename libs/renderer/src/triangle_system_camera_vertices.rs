@@ -1,18 +1,20 @@
-use std::borrow::Cow;
 use wgpu::util::DeviceExt;
 
+use crate::shaders::{self, ShaderInterface, ShaderReloadable};
+
 use wgpu::{ShaderModule, PipelineLayout, RenderPipeline, TextureFormat, Device, CommandEncoder, TextureView, Texture};
 
+// Shared with the shaders through shared/camera.wgsl.
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct CameraUniform
+pub(crate) struct CameraUniform
 {
-    view_proj: [f32; 16],
+    pub(crate) view_proj: [f32; 16],
 }
 
 impl CameraUniform
 {
-    fn new() -> Self
+    pub(crate) fn new() -> Self
     {
         Self
         {
@@ -20,7 +22,7 @@ impl CameraUniform
         }
     }
 
-    fn update_view_proj(&mut self, camera: &common::Camera)
+    pub(crate) fn update_view_proj(&mut self, camera: &common::Camera)
     {
         self.view_proj = camera.build_view_projection_matrix().to_cols_array();
     }
@@ -30,12 +32,44 @@ impl CameraUniform
 
 
 
+const SHADER_NAME: &str = "triangle_shader_camera_vertices.wgsl";
+const VERTEX_ENTRY: &str = "vs_main";
+const FRAGMENT_ENTRY: &str = "fs_main";
+
+const CAMERA_BIND_GROUP_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] = &[
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::VERTEX,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    }
+];
+
+pub(crate) const SHADER_INTERFACE: ShaderInterface = ShaderInterface {
+    shader_name: SHADER_NAME,
+    entry_points: &[
+        (wgpu::ShaderStages::VERTEX, VERTEX_ENTRY),
+        (wgpu::ShaderStages::FRAGMENT, FRAGMENT_ENTRY),
+    ],
+    bind_group_layouts: &[CAMERA_BIND_GROUP_LAYOUT_ENTRIES],
+    vertex_attributes: Vertex::ATTRIBUTES,
+    defines: &[],
+};
+
 pub struct TriangleSystem
 {
     pub shader: ShaderModule,
     pub pipeline_layout: PipelineLayout,
     pub render_pipeline: RenderPipeline,
 
+    texture_format: TextureFormat,
+    sample_count: u32,
+    depth_texture_format: TextureFormat,
+
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
 
@@ -55,29 +89,28 @@ struct Vertex
 // lib.rs
 impl Vertex
 {
+    const ATTRIBUTES: &'static [wgpu::VertexAttribute] = &[
+        wgpu::VertexAttribute {
+            offset: 0,
+            shader_location: 0,
+            format: wgpu::VertexFormat::Float32x4,
+        },
+        wgpu::VertexAttribute {
+            offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+            shader_location: 2,
+            format: wgpu::VertexFormat::Float32x4,
+        },
+        wgpu::VertexAttribute {
+            offset: (std::mem::size_of::<[f32; 4]>() * 2) as wgpu::BufferAddress,
+            shader_location: 1,
+            format: wgpu::VertexFormat::Float32x4,
+        },
+    ];
+
     fn desc() -> wgpu::VertexBufferLayout<'static>
     {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    shader_location: 0,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
-                    shader_location: 2,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset: (std::mem::size_of::<[f32; 4]>() * 2) as wgpu::BufferAddress,
-                    shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-            ]
-        }
+        return SHADER_INTERFACE.vertex_buffer_layout(
+            std::mem::size_of::<Vertex>() as wgpu::BufferAddress, wgpu::VertexStepMode::Vertex);
     }
 }
 const WHITE_COLOR: [f32; 4] = [1.0f32, 1.0f32, 1.0f32, 1.0f32];
@@ -150,7 +183,12 @@ const INDICES: &[u16] = &[
 
 impl TriangleSystem
 {
-    pub fn new(device: &Device, textureformat: TextureFormat, depth_texture_format: TextureFormat) -> Self
+    pub fn new(
+        device: &Device,
+        textureformat: TextureFormat,
+        depth_texture_format: TextureFormat,
+        sample_count: u32
+    ) -> Self
     {
 
         let camera_uniform = CameraUniform::new();
@@ -162,21 +200,7 @@ impl TriangleSystem
             }
         );
         let camera_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::VERTEX,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: None,
-                        },
-                        count: None,
-                    }
-                ],
-            label: Some("camera_bind_group_layout"),
-        });
+            SHADER_INTERFACE.create_bind_group_layout(device, 0, "camera_bind_group_layout");
         let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &camera_bind_group_layout,
             entries: &[
@@ -188,13 +212,7 @@ impl TriangleSystem
             label: Some("camera_bind_group"),
         });
 
-        // Load the shaders from disk
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor
-        {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
-                "../../../data/shaders/triangle_shader_camera_vertices.wgsl"))),
-        });
+        let shader = shaders::create_shader_module(device, SHADER_NAME, &shaders::embedded_source(SHADER_NAME));
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor
         {
@@ -206,35 +224,8 @@ impl TriangleSystem
         });
 
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor
-        {
-            label: None,
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState
-            {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[
-                    Vertex::desc()
-                ],
-            },
-            fragment: Some(wgpu::FragmentState
-            {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(textureformat.into())],
-            }),
-            primitive: wgpu::PrimitiveState::default(),
-            depth_stencil: Some( wgpu::DepthStencilState{
-                format: depth_texture_format,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None,
-        });
+        let render_pipeline = Self::create_pipeline(
+            device, &pipeline_layout, &shader, textureformat, depth_texture_format, sample_count);
         let vertex_buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
                 label: Some("Vertex Buffer"),
@@ -260,6 +251,10 @@ impl TriangleSystem
             pipeline_layout,
             render_pipeline,
 
+            texture_format: textureformat,
+            sample_count,
+            depth_texture_format,
+
             vertex_buffer,
             index_buffer,
 
@@ -268,6 +263,49 @@ impl TriangleSystem
             camera_bind_group,
         }
     }
+    fn create_pipeline(
+        device: &Device,
+        pipeline_layout: &PipelineLayout,
+        shader: &ShaderModule,
+        textureformat: TextureFormat,
+        depth_texture_format: TextureFormat,
+        sample_count: u32
+    ) -> RenderPipeline
+    {
+        return device.create_render_pipeline(&wgpu::RenderPipelineDescriptor
+        {
+            label: None,
+            layout: Some(pipeline_layout),
+            vertex: wgpu::VertexState
+            {
+                module: shader,
+                entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::VERTEX, VERTEX_ENTRY),
+                buffers: &[
+                    Vertex::desc()
+                ],
+            },
+            fragment: Some(wgpu::FragmentState
+            {
+                module: shader,
+                entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::FRAGMENT, FRAGMENT_ENTRY),
+                targets: &[Some(textureformat.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some( wgpu::DepthStencilState{
+                format: depth_texture_format,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+        });
+    }
+
     pub fn update(&mut self, camera: &common::Camera, queue: &wgpu::Queue)
     {
         self.camera_uniform.update_view_proj(camera);
@@ -277,7 +315,26 @@ impl TriangleSystem
             bytemuck::cast_slice(&[self.camera_uniform]));
 
     }
-    pub fn render(&mut self, encoder: &mut CommandEncoder, view: &TextureView, depth_view: &TextureView)
+    // Rebuilds the pipeline to render into targets with a different sample count.
+    pub fn set_sample_count(&mut self, device: &Device, sample_count: u32)
+    {
+        self.sample_count = sample_count;
+        self.render_pipeline = Self::create_pipeline(
+            device,
+            &self.pipeline_layout,
+            &self.shader,
+            self.texture_format,
+            self.depth_texture_format,
+            sample_count);
+    }
+
+    pub fn render(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        view: &TextureView,
+        resolve_target: Option<&TextureView>,
+        depth_view: &TextureView
+    )
     {
         let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor
         {
@@ -285,7 +342,7 @@ impl TriangleSystem
             color_attachments: &[Some(wgpu::RenderPassColorAttachment
             {
                 view,
-                resolve_target: None,
+                resolve_target,
                 ops: wgpu::Operations
                 {
                     load: wgpu::LoadOp::Load,
@@ -312,4 +369,30 @@ impl TriangleSystem
         // Draw vertices without index buffer
         //rpass.draw(0..VERTICES.len() as u32, 0..1);
     }
-}
\ No newline at end of file
+}
+
+impl ShaderReloadable for TriangleSystem
+{
+    fn shader_name(&self) -> &'static str
+    {
+        return SHADER_NAME;
+    }
+
+    fn reload_shader(&mut self, device: &Device, source: &str) -> Result<(), String>
+    {
+        let (shader, render_pipeline) = shaders::validated(device, || {
+            let shader = shaders::create_shader_module(device, SHADER_NAME, source);
+            let render_pipeline = Self::create_pipeline(
+                device,
+                &self.pipeline_layout,
+                &shader,
+                self.texture_format,
+                self.depth_texture_format,
+                self.sample_count);
+            (shader, render_pipeline)
+        })?;
+        self.shader = shader;
+        self.render_pipeline = render_pipeline;
+        return Ok(());
+    }
+}
@@ -1,14 +1,33 @@
-use std::borrow::Cow;
 use wgpu::util::DeviceExt;
 
+use crate::shaders::{self, ShaderInterface, ShaderReloadable};
+
 use wgpu::{ShaderModule, PipelineLayout, RenderPipeline, TextureFormat, Device, CommandEncoder, TextureView};
 
+const SHADER_NAME: &str = "triangle_shader_vertices.wgsl";
+const VERTEX_ENTRY: &str = "vs_main";
+const FRAGMENT_ENTRY: &str = "fs_main";
+
+pub(crate) const SHADER_INTERFACE: ShaderInterface = ShaderInterface {
+    shader_name: SHADER_NAME,
+    entry_points: &[
+        (wgpu::ShaderStages::VERTEX, VERTEX_ENTRY),
+        (wgpu::ShaderStages::FRAGMENT, FRAGMENT_ENTRY),
+    ],
+    bind_group_layouts: &[],
+    vertex_attributes: Vertex::ATTRIBUTES,
+    defines: &[],
+};
+
 pub struct TriangleSystem
 {
     pub shader: ShaderModule,
     pub pipeline_layout: PipelineLayout,
     pub render_pipeline: RenderPipeline,
 
+    texture_format: TextureFormat,
+    sample_count: u32,
+
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
 }
@@ -22,24 +41,23 @@ pub struct TriangleSystem
 // lib.rs
 impl Vertex
 {
+    const ATTRIBUTES: &'static [wgpu::VertexAttribute] = &[
+        wgpu::VertexAttribute {
+            offset: 0,
+            shader_location: 0,
+            format: wgpu::VertexFormat::Float32x4,
+        },
+        wgpu::VertexAttribute {
+            offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
+            shader_location: 1,
+            format: wgpu::VertexFormat::Float32x4,
+        }
+    ];
+
     fn desc() -> wgpu::VertexBufferLayout<'static>
     {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    shader_location: 0,
-                    format: wgpu::VertexFormat::Float32x4,
-                },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 4]>() as wgpu::BufferAddress,
-                    shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x4,
-                }
-            ]
-        }
+        return SHADER_INTERFACE.vertex_buffer_layout(
+            std::mem::size_of::<Vertex>() as wgpu::BufferAddress, wgpu::VertexStepMode::Vertex);
     }
 }
 
@@ -61,15 +79,9 @@ const INDICES: &[u16] = &[
 
 impl TriangleSystem
 {
-    pub fn new(device: &Device, textureformat: TextureFormat) -> Self
+    pub fn new(device: &Device, textureformat: TextureFormat, sample_count: u32) -> Self
     {
-        // Load the shaders from disk
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor
-        {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
-                "../../../data/shaders/triangle_shader_vertices.wgsl"))),
-        });
+        let shader = shaders::create_shader_module(device, SHADER_NAME, &shaders::embedded_source(SHADER_NAME));
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor
         {
@@ -79,29 +91,7 @@ impl TriangleSystem
         });
 
 
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor
-        {
-            label: None,
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState
-            {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[
-                    Vertex::desc()
-                ],
-            },
-            fragment: Some(wgpu::FragmentState
-            {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(textureformat.into())],
-            }),
-            primitive: wgpu::PrimitiveState::default(),
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None,
-        });
+        let render_pipeline = Self::create_pipeline(device, &pipeline_layout, &shader, textureformat, sample_count);
         let vertex_buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
                 label: Some("Vertex Buffer"),
@@ -122,12 +112,68 @@ impl TriangleSystem
             pipeline_layout,
             render_pipeline,
 
+            texture_format: textureformat,
+            sample_count,
+
             vertex_buffer,
             index_buffer,
         }
     }
 
-    pub fn render(&mut self, encoder: &mut CommandEncoder, view: &TextureView)
+    fn create_pipeline(
+        device: &Device,
+        pipeline_layout: &PipelineLayout,
+        shader: &ShaderModule,
+        textureformat: TextureFormat,
+        sample_count: u32
+    ) -> RenderPipeline
+    {
+        return device.create_render_pipeline(&wgpu::RenderPipelineDescriptor
+        {
+            label: None,
+            layout: Some(pipeline_layout),
+            vertex: wgpu::VertexState
+            {
+                module: shader,
+                entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::VERTEX, VERTEX_ENTRY),
+                buffers: &[
+                    Vertex::desc()
+                ],
+            },
+            fragment: Some(wgpu::FragmentState
+            {
+                module: shader,
+                entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::FRAGMENT, FRAGMENT_ENTRY),
+                targets: &[Some(textureformat.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                ..Default::default()
+            },
+            multiview: None,
+        });
+    }
+
+    // Rebuilds the pipeline to render into targets with a different sample count.
+    pub fn set_sample_count(&mut self, device: &Device, sample_count: u32)
+    {
+        self.sample_count = sample_count;
+        self.render_pipeline = Self::create_pipeline(
+            device,
+            &self.pipeline_layout,
+            &self.shader,
+            self.texture_format,
+            sample_count);
+    }
+
+    pub fn render(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        view: &TextureView,
+        resolve_target: Option<&TextureView>
+    )
     {
         let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor
         {
@@ -135,7 +181,7 @@ impl TriangleSystem
             color_attachments: &[Some(wgpu::RenderPassColorAttachment
             {
                 view: view,
-                resolve_target: None,
+                resolve_target,
                 ops: wgpu::Operations
                 {
                     load: wgpu::LoadOp::Load,
@@ -153,4 +199,29 @@ impl TriangleSystem
         // Draw vertices without index buffer
         //rpass.draw(0..VERTICES.len() as u32, 0..1);
     }
-}
\ No newline at end of file
+}
+
+impl ShaderReloadable for TriangleSystem
+{
+    fn shader_name(&self) -> &'static str
+    {
+        return SHADER_NAME;
+    }
+
+    fn reload_shader(&mut self, device: &Device, source: &str) -> Result<(), String>
+    {
+        let (shader, render_pipeline) = shaders::validated(device, || {
+            let shader = shaders::create_shader_module(device, SHADER_NAME, source);
+            let render_pipeline = Self::create_pipeline(
+                device,
+                &self.pipeline_layout,
+                &shader,
+                self.texture_format,
+                self.sample_count);
+            (shader, render_pipeline)
+        })?;
+        self.shader = shader;
+        self.render_pipeline = render_pipeline;
+        return Ok(());
+    }
+}
@@ -0,0 +1,523 @@
+use std::collections::HashMap;
+use std::mem::size_of;
+
+use common::egui::{self, epaint::Primitive, ColorImage, ImageData, TextureFilter, TextureId, TextureOptions, TexturesDelta};
+use wgpu::*;
+use wgpu::util::DeviceExt;
+
+use crate::render_targets::ScreenSize;
+use crate::shaders::{self, ShaderInterface, ShaderReloadable};
+
+const SHADER_NAME: &str = "ui.wgsl";
+const VERTEX_ENTRY: &str = "vs_main";
+const FRAGMENT_ENTRY: &str = "fs_main";
+
+// Vertices and indices the buffers start with, they grow to fit the ui.
+const INITIAL_VERTICES: usize = 16 * 1024;
+const INITIAL_INDICES: usize = 32 * 1024;
+
+const PARAMS_BIND_GROUP_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] = &[
+    // Params
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    },
+];
+
+const TEXTURE_BIND_GROUP_LAYOUT_ENTRIES: &[wgpu::BindGroupLayoutEntry] = &[
+    // Ui texture
+    wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    },
+    wgpu::BindGroupLayoutEntry {
+        binding: 1,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    },
+];
+
+pub(crate) const SHADER_INTERFACE: ShaderInterface = ShaderInterface {
+    shader_name: SHADER_NAME,
+    entry_points: &[
+        (wgpu::ShaderStages::VERTEX, VERTEX_ENTRY),
+        (wgpu::ShaderStages::FRAGMENT, FRAGMENT_ENTRY),
+    ],
+    bind_group_layouts: &[PARAMS_BIND_GROUP_LAYOUT_ENTRIES, TEXTURE_BIND_GROUP_LAYOUT_ENTRIES],
+    vertex_attributes: VERTEX_ATTRIBUTES,
+    defines: &[],
+};
+
+// Shared with ui.wgsl.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct UiParams
+{
+    pub(crate) screen_size: [f32; 2],
+    pub(crate) decode_srgb: u32,
+    pub(crate) _padding: u32,
+}
+
+// egui::epaint::Vertex, uploaded as is.
+const VERTEX_ATTRIBUTES: &[wgpu::VertexAttribute] = &[
+    wgpu::VertexAttribute {
+        offset: 0,
+        shader_location: 0,
+        format: wgpu::VertexFormat::Float32x2,
+    },
+    wgpu::VertexAttribute {
+        offset: 8,
+        shader_location: 1,
+        format: wgpu::VertexFormat::Float32x2,
+    },
+    wgpu::VertexAttribute {
+        offset: 16,
+        shader_location: 2,
+        format: wgpu::VertexFormat::Uint32,
+    },
+];
+
+fn vertex_desc() -> wgpu::VertexBufferLayout<'static>
+{
+    return SHADER_INTERFACE.vertex_buffer_layout(
+        size_of::<egui::epaint::Vertex>() as wgpu::BufferAddress, wgpu::VertexStepMode::Vertex);
+}
+
+struct UiTexture
+{
+    _texture: Texture,
+    bind_group: BindGroup,
+    // Kept for the patches and for copying the textures to a recreated device.
+    image: ColorImage,
+    options: TextureOptions,
+}
+
+// One draw call, the meshes are in one vertex and index buffer.
+struct UiDraw
+{
+    texture_id: TextureId,
+    // x, y, width, height in back buffer pixels.
+    scissor: [u32; 4],
+    first_index: u32,
+    index_count: u32,
+    base_vertex: i32,
+}
+
+// Draws the ui the systems built this frame over the back buffer, after the debug text.
+pub struct TriangleSystem
+{
+    _shader: ShaderModule,
+    _params_bind_group_layout: BindGroupLayout,
+    texture_bind_group_layout: BindGroupLayout,
+    _pipeline_layout: PipelineLayout,
+    render_pipeline: RenderPipeline,
+    params_bind_group: BindGroup,
+
+    params_buffer: Buffer,
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    textures: HashMap<TextureId, UiTexture>,
+    // Freed after the frame using them is drawn, so at the next update.
+    textures_to_free: Vec<TextureId>,
+    draws: Vec<UiDraw>,
+
+    texture_format: TextureFormat,
+}
+
+impl TriangleSystem
+{
+    pub fn new(device: &Device, texture_format: TextureFormat) -> Self
+    {
+        let _shader = shaders::create_shader_module(device, SHADER_NAME, &shaders::embedded_source(SHADER_NAME));
+
+        let _params_bind_group_layout = SHADER_INTERFACE.create_bind_group_layout(device, 0, "Ui params bindings");
+        let texture_bind_group_layout = SHADER_INTERFACE.create_bind_group_layout(device, 1, "Ui texture bindings");
+
+        let _pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor
+        {
+            label: None,
+            bind_group_layouts: &[&_params_bind_group_layout, &texture_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let render_pipeline = Self::create_pipeline(device, &_pipeline_layout, &_shader, texture_format);
+
+        let params_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Ui params"),
+                contents: bytemuck::cast_slice(&[UiParams { screen_size: [1.0, 1.0], decode_srgb: 0, _padding: 0 }]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let params_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Ui params Bind Group"),
+            layout: &_params_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let vertex_buffer = Self::create_buffer(
+            device,
+            "Ui vertices",
+            INITIAL_VERTICES * size_of::<egui::epaint::Vertex>(),
+            wgpu::BufferUsages::VERTEX);
+        let index_buffer = Self::create_buffer(
+            device,
+            "Ui indices",
+            INITIAL_INDICES * size_of::<u32>(),
+            wgpu::BufferUsages::INDEX);
+
+        Self {
+            _shader,
+            _params_bind_group_layout,
+            texture_bind_group_layout,
+            _pipeline_layout,
+            render_pipeline,
+            params_bind_group,
+
+            params_buffer,
+            vertex_buffer,
+            index_buffer,
+            textures: HashMap::new(),
+            textures_to_free: Vec::new(),
+            draws: Vec::new(),
+
+            texture_format,
+        }
+    }
+
+    fn create_pipeline(
+        device: &Device,
+        pipeline_layout: &PipelineLayout,
+        shader: &ShaderModule,
+        texture_format: TextureFormat
+    ) -> RenderPipeline
+    {
+        return device.create_render_pipeline(&wgpu::RenderPipelineDescriptor
+        {
+            label: Some("Ui"),
+            layout: Some(pipeline_layout),
+            vertex: wgpu::VertexState
+            {
+                module: shader,
+                entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::VERTEX, VERTEX_ENTRY),
+                buffers: &[vertex_desc()],
+            },
+            fragment: Some(wgpu::FragmentState
+            {
+                module: shader,
+                entry_point: SHADER_INTERFACE.entry_point(wgpu::ShaderStages::FRAGMENT, FRAGMENT_ENTRY),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: texture_format,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+    }
+
+    fn create_buffer(device: &Device, label: &str, size: usize, usage: BufferUsages) -> Buffer
+    {
+        return device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: size as BufferAddress,
+            usage: usage | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+    }
+
+    // For a swapchain format change, the pipeline drawing to the back buffer is recreated.
+    pub fn set_texture_format(&mut self, device: &Device, texture_format: TextureFormat)
+    {
+        if texture_format == self.texture_format
+        {
+            return;
+        }
+        self.texture_format = texture_format;
+        self.render_pipeline = Self::create_pipeline(device, &self._pipeline_layout, &self._shader, texture_format);
+    }
+
+    // The ui library only sends a texture again when it changes, a recreated device gets them
+    // from the old renderer.
+    pub fn copy_textures(&mut self, device: &Device, queue: &Queue, other: &TriangleSystem)
+    {
+        for (&id, texture) in &other.textures
+        {
+            self.create_texture(device, queue, id, texture.image.clone(), texture.options);
+        }
+    }
+
+    // Applies the texture changes of the ui frame, done even while the device is lost so the
+    // copies stay current.
+    pub fn update_textures(&mut self, device: &Device, queue: &Queue, textures_delta: &TexturesDelta)
+    {
+        for id in self.textures_to_free.drain(..)
+        {
+            self.textures.remove(&id);
+        }
+        for (id, delta) in &textures_delta.set
+        {
+            let image = match &delta.image
+            {
+                ImageData::Color(image) => image.clone(),
+                ImageData::Font(image) => ColorImage { size: image.size, pixels: image.srgba_pixels(None).collect() },
+            };
+            if image.width() == 0 || image.height() == 0
+            {
+                continue;
+            }
+            match (delta.pos, self.textures.get_mut(id))
+            {
+                (Some(pos), Some(texture)) => Self::patch_texture(queue, texture, pos, &image),
+                (Some(_), None) => log::warn!("Ui texture {:?} patched before it was created", id),
+                (None, _) => self.create_texture(device, queue, *id, image, delta.options),
+            }
+        }
+        self.textures_to_free.extend_from_slice(&textures_delta.free);
+    }
+
+    fn create_texture(&mut self, device: &Device, queue: &Queue, id: TextureId, image: ColorImage, options: TextureOptions)
+    {
+        let texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("Ui texture"),
+                size: wgpu::Extent3d {
+                    width: image.width() as u32,
+                    height: image.height() as u32,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            bytemuck::cast_slice(&image.pixels),
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Ui sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: filter_mode(options.magnification),
+            min_filter: filter_mode(options.minification),
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Ui texture Bind Group"),
+            layout: &self.texture_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+        self.textures.insert(id, UiTexture { _texture: texture, bind_group, image, options });
+    }
+
+    fn patch_texture(queue: &Queue, texture: &mut UiTexture, pos: [usize; 2], patch: &ColorImage)
+    {
+        let [x, y] = pos;
+        if x + patch.width() > texture.image.width() || y + patch.height() > texture.image.height()
+        {
+            log::warn!("Ui texture patch at {:?} doesn't fit the texture", pos);
+            return;
+        }
+        for row in 0..patch.height()
+        {
+            let start = (y + row) * texture.image.width() + x;
+            texture.image.pixels[start..start + patch.width()]
+                .copy_from_slice(&patch.pixels[row * patch.width()..(row + 1) * patch.width()]);
+        }
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture._texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d { x: x as u32, y: y as u32, z: 0 },
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(&patch.pixels),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * patch.width() as u32),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: patch.width() as u32,
+                height: patch.height() as u32,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    pub fn update(&mut self, device: &Device, queue: &Queue, ui: &common::Ui, display_size: ScreenSize)
+    {
+        self.draws.clear();
+        let pixels_per_point = ui.pixels_per_point();
+        let mut vertices: Vec<egui::epaint::Vertex> = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+        for clipped in ui.primitives()
+        {
+            let mesh = match &clipped.primitive
+            {
+                Primitive::Mesh(mesh) => mesh,
+                Primitive::Callback(_) => continue,
+            };
+            if mesh.indices.is_empty() || !self.textures.contains_key(&mesh.texture_id)
+            {
+                continue;
+            }
+            // Clip rects are in points and can extend past the screen.
+            let clip = clipped.clip_rect;
+            let min_x = ((clip.min.x * pixels_per_point).round().max(0.0) as u32).min(display_size.width);
+            let min_y = ((clip.min.y * pixels_per_point).round().max(0.0) as u32).min(display_size.height);
+            let max_x = ((clip.max.x * pixels_per_point).round().max(0.0) as u32).min(display_size.width);
+            let max_y = ((clip.max.y * pixels_per_point).round().max(0.0) as u32).min(display_size.height);
+            if max_x <= min_x || max_y <= min_y
+            {
+                continue;
+            }
+            self.draws.push(UiDraw {
+                texture_id: mesh.texture_id,
+                scissor: [min_x, min_y, max_x - min_x, max_y - min_y],
+                first_index: indices.len() as u32,
+                index_count: mesh.indices.len() as u32,
+                base_vertex: vertices.len() as i32,
+            });
+            vertices.extend_from_slice(&mesh.vertices);
+            indices.extend_from_slice(&mesh.indices);
+        }
+        if self.draws.is_empty()
+        {
+            return;
+        }
+
+        let vertex_bytes: &[u8] = bytemuck::cast_slice(&vertices);
+        let index_bytes: &[u8] = bytemuck::cast_slice(&indices);
+        if vertex_bytes.len() as BufferAddress > self.vertex_buffer.size()
+        {
+            self.vertex_buffer = Self::create_buffer(
+                device,
+                "Ui vertices",
+                vertex_bytes.len().next_power_of_two(),
+                wgpu::BufferUsages::VERTEX);
+        }
+        if index_bytes.len() as BufferAddress > self.index_buffer.size()
+        {
+            self.index_buffer = Self::create_buffer(
+                device,
+                "Ui indices",
+                index_bytes.len().next_power_of_two(),
+                wgpu::BufferUsages::INDEX);
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, vertex_bytes);
+        queue.write_buffer(&self.index_buffer, 0, index_bytes);
+
+        let params = UiParams
+        {
+            screen_size: [
+                display_size.width.max(1) as f32 / pixels_per_point,
+                display_size.height.max(1) as f32 / pixels_per_point,
+            ],
+            // Blending happens in sRGB, an sRGB back buffer encodes what is written.
+            decode_srgb: self.texture_format.is_srgb() as u32,
+            _padding: 0,
+        };
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[params]));
+    }
+
+    pub fn render(&mut self, encoder: &mut CommandEncoder, view: &TextureView)
+    {
+        if self.draws.is_empty()
+        {
+            return;
+        }
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor
+        {
+            label: Some("Ui"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment
+            {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations
+                {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(&self.render_pipeline);
+        render_pass.set_bind_group(0, &self.params_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        for draw in &self.draws
+        {
+            // Textures freed this frame are kept until the next update.
+            let texture = &self.textures[&draw.texture_id];
+            let [x, y, width, height] = draw.scissor;
+            render_pass.set_scissor_rect(x, y, width, height);
+            render_pass.set_bind_group(1, &texture.bind_group, &[]);
+            render_pass.draw_indexed(draw.first_index..draw.first_index + draw.index_count, draw.base_vertex, 0..1);
+        }
+    }
+}
+
+impl ShaderReloadable for TriangleSystem
+{
+    fn shader_name(&self) -> &'static str
+    {
+        return SHADER_NAME;
+    }
+
+    fn reload_shader(&mut self, device: &Device, source: &str) -> Result<(), String>
+    {
+        let (shader, render_pipeline) = shaders::validated(device, || {
+            let shader = shaders::create_shader_module(device, SHADER_NAME, source);
+            let render_pipeline = Self::create_pipeline(device, &self._pipeline_layout, &shader, self.texture_format);
+            (shader, render_pipeline)
+        })?;
+        self._shader = shader;
+        self.render_pipeline = render_pipeline;
+        return Ok(());
+    }
+}
+
+fn filter_mode(filter: TextureFilter) -> FilterMode
+{
+    return match filter
+    {
+        TextureFilter::Nearest => wgpu::FilterMode::Nearest,
+        TextureFilter::Linear => wgpu::FilterMode::Linear,
+    };
+}
@@ -16,22 +16,291 @@ impl common::System for TestA
     {
         let v2 = glam::Vec2::new(1.5f32, 2.5f32);
 
-        if game_state.input.is_down(&VirtualKeyCode::Space)
+        // Not on space, that jumps while walking.
+        if game_state.input.is_down(&VirtualKeyCode::T)
         {
-            println!("space is down");
-            println!("timestep: {}, f1: {}, f2: {}", dt, v2.x, v2.y);
-            println!("timestep: {}, f1: {}, f2: {}", dt, v2.x, v2.y);
+            game_state.debug_text(10.0, 100.0, "t is down");
+            game_state.debug_text(10.0, 120.0, &format!("timestep: {:.6}, f1: {}, f2: {}", dt, v2.x, v2.y));
+            game_state.debug_draw.axes(glam::Vec3::ZERO, glam::Quat::IDENTITY, 1.0);
+            game_state.debug_draw.sphere(glam::Vec3::ZERO, 0.5, [1.0, 1.0, 0.0, 1.0]);
         }
     }
 }
 
+// A row of spinning cube entities, every other one see-through. B shows their bounds.
+struct CubesSystem
+{
+    time: f64,
+    entities: std::ops::Range<usize>,
+}
+impl CubesSystem
+{
+    fn new(game_state: &mut common::GameState) -> Self
+    {
+        let entities = &mut game_state.scene.entities;
+        let first = entities.len();
+        for i in 0..5
+        {
+            entities.push(common::Entity {
+                transform: common::Transform {
+                    pos: glam::Vec3A::new(i as f32 * 1.5 - 3.0, 0.5, -3.0),
+                    rot: glam::Quat::IDENTITY,
+                    scale: glam::Vec3A::ONE,
+                },
+                model: Some(i % 2),
+                transparent: i % 2 == 1,
+            });
+        }
+        Self { time: 0.0, entities: first..entities.len() }
+    }
+}
+impl common::System for CubesSystem
+{
+    fn update(&mut self, dt: f64, game_state: &mut common::GameState)
+    {
+        self.time += dt;
+        for (i, entity) in game_state.scene.entities[self.entities.clone()].iter_mut().enumerate()
+        {
+            entity.transform.rot = glam::Quat::from_rotation_y(self.time as f32 + i as f32 * 0.5);
+            if game_state.input.is_down(&VirtualKeyCode::B)
+            {
+                if let Some(bounds) = entity.world_bounds(&game_state.mesh_data)
+                {
+                    game_state.debug_draw.aabb(bounds.aabb.min, bounds.aabb.max, [0.0, 1.0, 0.0, 1.0]);
+                    game_state.debug_draw.sphere(bounds.sphere.center, bounds.sphere.radius, [0.0, 0.5, 1.0, 1.0]);
+                }
+            }
+        }
+        game_state.scene.add_instances(&mut game_state.mesh_data);
+    }
+}
+
+// A slab of ground with a stack of boxes, balls and a capsule dropped on it, stepped at the
+// physics' fixed rate. F throws a ball from the camera and P draws every collider, the bodies
+// without a model are always drawn.
+struct PhysicsSystem {}
+impl PhysicsSystem
+{
+    fn new(game_state: &mut common::GameState) -> Self
+    {
+        let ground = Self::add_entity(game_state, glam::Vec3::new(0.0, -0.5, -6.0), glam::Quat::IDENTITY, Some(0));
+        game_state.scene.entities[ground].transform.scale = glam::Vec3A::new(20.0, 1.0, 20.0);
+        game_state.physics.add_body(common::RigidBody::new_static(ground, common::Collider::Mesh { model: 0 }), &game_state.scene);
+
+        let cube = common::Collider::Box { half_extents: glam::Vec3::splat(0.5) };
+        for i in 0..4
+        {
+            let position = glam::Vec3::new(4.0, 0.5 + i as f32 * 1.05, -8.0);
+            let entity = Self::add_entity(game_state, position, glam::Quat::from_rotation_y(i as f32 * 0.2), Some(0));
+            game_state.physics.add_body(common::RigidBody::new_dynamic(entity, cube, 1.0), &game_state.scene);
+        }
+        let tumbling = glam::Quat::from_euler(glam::EulerRot::XYZ, 0.5, 0.3, 0.8);
+        let glass = Self::add_entity(game_state, glam::Vec3::new(4.2, 8.0, -8.0), tumbling, Some(1));
+        game_state.physics.add_body(common::RigidBody::new_dynamic(glass, cube, 1.0), &game_state.scene);
+
+        for i in 0..3
+        {
+            let position = glam::Vec3::new(-4.0 + i as f32 * 0.3, 2.0 + i as f32 * 1.5, -8.0);
+            let entity = Self::add_entity(game_state, position, glam::Quat::IDENTITY, None);
+            let mut ball = common::RigidBody::new_dynamic(entity, common::Collider::Sphere { radius: 0.4 }, 1.0);
+            ball.restitution = 0.6;
+            game_state.physics.add_body(ball, &game_state.scene);
+        }
+        let capsule = Self::add_entity(game_state, glam::Vec3::new(0.0, 3.0, -9.0), tumbling, None);
+        let collider = common::Collider::Capsule { radius: 0.3, half_height: 0.6 };
+        game_state.physics.add_body(common::RigidBody::new_dynamic(capsule, collider, 2.0), &game_state.scene);
+        Self {}
+    }
+
+    fn add_entity(game_state: &mut common::GameState, position: glam::Vec3, rotation: glam::Quat, model: Option<usize>) -> usize
+    {
+        game_state.scene.entities.push(common::Entity {
+            transform: common::Transform { pos: position.into(), rot: rotation, scale: glam::Vec3A::ONE },
+            model,
+            transparent: model == Some(1),
+        });
+        game_state.scene.entities.len() - 1
+    }
+
+    fn draw_colliders(game_state: &mut common::GameState)
+    {
+        let show_all = game_state.input.is_down(&VirtualKeyCode::P);
+        for body in game_state.physics.bodies()
+        {
+            let entity = &game_state.scene.entities[body.entity];
+            if entity.model.is_some() && !show_all
+            {
+                continue;
+            }
+            let color = if body.is_static() { [0.5, 0.5, 0.5, 1.0] } else { [1.0, 0.5, 0.0, 1.0] };
+            let position = body.position();
+            match body.collider
+            {
+                common::Collider::Sphere { radius } => game_state.debug_draw.sphere(position, radius, color),
+                common::Collider::Box { half_extents } =>
+                    game_state.debug_draw.oriented_box(position, half_extents, body.rotation(), color),
+                common::Collider::Capsule { radius, half_height } =>
+                {
+                    let half = body.rotation() * glam::Vec3::Y * half_height;
+                    game_state.debug_draw.capsule(position - half, position + half, radius, color);
+                },
+                common::Collider::Mesh { model } =>
+                {
+                    let aabb = game_state.mesh_data.world_bounds(model, &entity.transform.matrix()).aabb;
+                    game_state.debug_draw.aabb(aabb.min, aabb.max, color);
+                },
+            }
+        }
+        if show_all
+        {
+            for contact in game_state.physics.contacts()
+            {
+                game_state.debug_draw.line(contact.point, contact.point + contact.normal * 0.3, [1.0, 0.0, 0.0, 1.0]);
+            }
+        }
+    }
+}
+impl common::System for PhysicsSystem
+{
+    fn update(&mut self, dt: f64, game_state: &mut common::GameState)
+    {
+        if !game_state.ui.wants_keyboard_input() && game_state.input.is_pressed(&VirtualKeyCode::F)
+        {
+            let camera = game_state.scene.get_current_camera();
+            let (eye, forward) = (camera.eye, camera.get_forward());
+            let entity = Self::add_entity(game_state, eye + forward, glam::Quat::IDENTITY, None);
+            let mut ball = common::RigidBody::new_dynamic(entity, common::Collider::Sphere { radius: 0.25 }, 0.5);
+            ball.restitution = 0.4;
+            let body = game_state.physics.add_body(ball, &game_state.scene);
+            game_state.physics.apply_impulse(body, forward * 8.0, eye + forward);
+        }
+        game_state.physics.update(dt, &mut game_state.scene, &game_state.mesh_data);
+        Self::draw_colliders(game_state);
+    }
+}
+
+
+// Left click picks the entity under the cursor, both by raycasting on the cpu and with the
+// renderer's gpu id pass. The picked triangle is outlined.
+struct Picking
+{
+    cpu_hit: Option<common::RayHit>,
+    gpu_pick: Option<renderer::GpuPick>,
+}
+impl Picking
+{
+    fn update(&mut self, game_state: &mut common::GameState, renderer: &mut renderer::Renderer, width: f32, height: f32)
+    {
+        if let Some(pick) = renderer.take_gpu_pick()
+        {
+            self.gpu_pick = Some(pick);
+        }
+        let clicked = game_state.input.is_mouse_pressed(&winit::event::MouseButton::Left)
+            && !game_state.ui.wants_pointer_input();
+        if let (true, Some([x, y])) = (clicked, game_state.input.mouse_position())
+        {
+            let ray = game_state.scene.get_current_camera().screen_ray(x, y, width, height);
+            self.cpu_hit = game_state.scene.raycast(&ray, &game_state.mesh_data);
+            renderer.request_gpu_pick(x as u32, y as u32);
+        }
+
+        let mut text = String::from("Click to pick");
+        if let Some(hit) = &self.cpu_hit
+        {
+            text = format!("cpu: entity {}, triangle {}, distance {:.2}", hit.entity, hit.triangle.triangle, hit.triangle.distance);
+            if let Some(triangle) = picked_triangle(game_state, hit)
+            {
+                game_state.debug_draw.depth_test = false;
+                for corner in 0..3
+                {
+                    game_state.debug_draw.line(triangle[corner], triangle[(corner + 1) % 3], [1.0, 0.2, 0.2, 1.0]);
+                }
+                game_state.debug_draw.depth_test = true;
+            }
+        }
+        if let Some(pick) = &self.gpu_pick
+        {
+            text += &format!("\ngpu: entity {:?}, instance {:?} at {}, {}", pick.entity, pick.instance, pick.x, pick.y);
+        }
+        game_state.debug_text(10.0, height - 40.0, &text);
+    }
+}
+
+// The world space corners of the hit triangle, where the entity is now.
+fn picked_triangle(game_state: &common::GameState, hit: &common::RayHit) -> Option<[glam::Vec3; 3]>
+{
+    let entity = game_state.scene.entities.get(hit.entity)?;
+    let location = game_state.mesh_data.models[entity.model?];
+    let transform = entity.transform.matrix();
+    let indices = location.indices_start_index as usize + hit.triangle.triangle * 3;
+    Some([0, 1, 2].map(|corner| {
+        let index = location.vertices_start_index as usize + game_state.mesh_data.indices[indices + corner] as usize;
+        transform.transform_point3(glam::Vec3::from_slice(&game_state.mesh_data.vertices[index].position[..3]))
+    }))
+}
+
+
+// Shows the frame rate, the camera and the cpu profiler spans of the last frame.
+struct DebugStatsSystem
+{
+    average_dt: f64,
+}
+impl common::System for DebugStatsSystem
+{
+    fn update(&mut self, dt: f64, game_state: &mut common::GameState)
+    {
+        self.average_dt += (dt - self.average_dt) * 0.05;
+        let fps = if self.average_dt > 0.0 { 1.0 / self.average_dt } else { 0.0 };
+        let camera = game_state.scene.get_current_camera();
+        let mut text = format!(
+            "{:.0} fps, {:.2} ms\ncamera {:.2}, {:.2}, {:.2}",
+            fps,
+            self.average_dt * 1000.0,
+            camera.eye.x,
+            camera.eye.y,
+            camera.eye.z);
+        for span in common::profiler::last_frame()
+        {
+            text += &format!("\n{}: {:.3} ms", span.name, span.duration.as_secs_f64() * 1000.0);
+        }
+        game_state.debug_text(10.0, 10.0, &text);
+    }
+}
+
 
-struct CameraSystem {}
+struct CameraSystem
+{
+    speed: f32,
+    // Walks the camera around with the character instead of flying, toggled with V.
+    walking: bool,
+    character: common::CharacterController,
+}
+impl CameraSystem
+{
+    fn set_walking(&mut self, walking: bool, camera: &common::Camera)
+    {
+        if walking && !self.walking
+        {
+            self.character.teleport(camera.eye - glam::Vec3::Y * self.character.eye_height);
+        }
+        self.walking = walking;
+    }
+}
 impl common::System for CameraSystem
 {
 
     fn update(&mut self, dt: f64, game_state: &mut common::GameState)
     {
+        // The keys go to the focused text field.
+        if game_state.ui.wants_keyboard_input()
+        {
+            return;
+        }
+        if game_state.input.is_pressed(&VirtualKeyCode::V)
+        {
+            let walking = !self.walking;
+            self.set_walking(walking, game_state.scene.get_current_camera());
+        }
         let camera = game_state.scene.get_current_camera_mut();
         let input = &game_state.input;
 
@@ -43,10 +312,10 @@ impl common::System for CameraSystem
         //let forward = (self.target - self.eye).normalize();
         let right = forward.cross(glam::Vec3::Y).normalize();
         let up = -forward.cross(right);
-        let multiplier = if input.is_down(&VirtualKeyCode::LShift)
-            || input.is_down(&VirtualKeyCode::RShift) { 5.0 } else { 1.0 };
+        let run = input.is_down(&VirtualKeyCode::LShift) || input.is_down(&VirtualKeyCode::RShift);
+        let multiplier = if run { 5.0 } else { 1.0 };
         let rotation_speed = (dt * 1.0 * multiplier) as f32;
-        let movement_speed = (dt * multiplier) as f32;
+        let movement_speed = (dt * multiplier) as f32 * self.speed;
 
         if input.is_down(&VirtualKeyCode::W)
         {
@@ -90,9 +359,77 @@ impl common::System for CameraSystem
         {
             camera.heading -= rotation_speed;
         }
-        camera.pitch = camera.pitch.max(-PI * 0.499f32).min(PI * 0.499f32);
-        camera.eye += movement;
+        camera.pitch = camera.pitch.clamp(-PI * 0.499f32, PI * 0.499f32);
+
+        if !self.walking
+        {
+            camera.eye += movement;
+            return;
+        }
+
+        // Walks along the ground under the look direction, Q and E don't move the character.
+        let flat_forward = glam::Vec3::new(forward.x, 0.0, forward.z).normalize_or_zero();
+        let flat_right = glam::Vec3::new(right.x, 0.0, right.z).normalize_or_zero();
+        let mut direction = glam::Vec3::ZERO;
+        if input.is_down(&VirtualKeyCode::W)
+        {
+            direction += flat_forward;
+        }
+        if input.is_down(&VirtualKeyCode::S)
+        {
+            direction -= flat_forward;
+        }
+        if input.is_down(&VirtualKeyCode::A)
+        {
+            direction -= flat_right;
+        }
+        if input.is_down(&VirtualKeyCode::D)
+        {
+            direction += flat_right;
+        }
+        let character_input = common::CharacterInput
+        {
+            direction: direction.normalize_or_zero(),
+            run,
+            jump: input.is_down(&VirtualKeyCode::Space),
+        };
+        self.character.update(dt as f32, &character_input, game_state.physics.gravity,
+            &game_state.scene, &game_state.mesh_data);
+        game_state.scene.get_current_camera_mut().eye = self.character.eye();
     }
+
+    fn ui(&mut self, ctx: &common::egui::Context, game_state: &mut common::GameState)
+    {
+        common::egui::Window::new("Camera").show(ctx, |ui| {
+            let mut walking = self.walking;
+            ui.checkbox(&mut walking, "Walk (V)");
+            self.set_walking(walking, game_state.scene.get_current_camera());
+            let camera = game_state.scene.get_current_camera_mut();
+            ui.add(common::egui::Slider::new(&mut self.speed, 0.1..=10.0).text("speed"));
+            ui.add(common::egui::Slider::new(&mut camera.fovy, 20.0..=120.0).text("fov"));
+            ui.add(common::egui::Slider::new(&mut self.character.walk_speed, 1.0..=10.0).text("walk speed"));
+            ui.add(common::egui::Slider::new(&mut self.character.run_speed, 1.0..=20.0).text("run speed"));
+            ui.label(if self.character.is_grounded() { "Grounded" } else { "In the air" });
+        });
+    }
+}
+
+
+// Toggles for the renderer passes, drawn with the system panels.
+fn renderer_ui(ctx: &common::egui::Context, renderer: &mut renderer::Renderer)
+{
+    common::egui::Window::new("Renderer").show(ctx, |ui| {
+        let mut settings = renderer.post_process_settings();
+        ui.checkbox(&mut settings.ssao.enabled, "Ssao");
+        ui.checkbox(&mut settings.fog.enabled, "Fog");
+        ui.checkbox(&mut settings.bloom.enabled, "Bloom");
+        ui.checkbox(&mut settings.fxaa.enabled, "Fxaa");
+        ui.checkbox(&mut settings.vignette.enabled, "Vignette");
+        if settings != renderer.post_process_settings()
+        {
+            renderer.set_post_process_settings(settings);
+        }
+    });
 }
 
 
@@ -110,11 +447,17 @@ async fn run()
 
 
     // Updateable systems.
-    let mut systems: Vec<Box<dyn common::System>> = Vec::new();
+    let mut systems: Vec<Box<dyn common::System>> = vec![
+        Box::new(CameraSystem{ speed: 1.0, walking: false,
+            character: common::CharacterController::new(glam::Vec3::ZERO) }),
+        Box::new(TestA{}),
+        Box::new(PhysicsSystem::new(&mut game_state)),
+        Box::new(CubesSystem::new(&mut game_state)),
+        Box::new(DebugStatsSystem{ average_dt: 0.0 }),
+    ];
 
-    systems.push(Box::new(CameraSystem{}));
-    systems.push(Box::new(TestA{}));
 
+    let mut picking = Picking { cpu_hit: None, gpu_pick: None };
 
     let event_loop = EventLoop::new();
     let window = winit::window::WindowBuilder::new()
@@ -124,9 +467,31 @@ async fn run()
 
     let size = window.inner_size();
     println!("window size: {}, {}", size.width, size.height);
-    let mut renderer =
-        renderer::Renderer::new(&window, size.width, size.height, &game_state).await;
+    game_state.ui.set_pixels_per_point(window.scale_factor() as f32);
+    let renderer =
+        renderer::Renderer::new(
+            &window,
+            size.width,
+            size.height,
+            &game_state,
+            renderer::RendererConfig::default()).await;
+    let mut renderer = match renderer
+    {
+        Ok(renderer) => renderer,
+        Err(err) =>
+        {
+            eprintln!("Failed to create the renderer: {}", err);
+            return;
+        }
+    };
+    // Load shaders from data/shaders and reload them on change while developing.
+    renderer.set_shader_hot_reload(cfg!(debug_assertions));
+    renderer.set_pass_profiling(cfg!(debug_assertions));
+    // Records a cpu profile of the frames, written as a Chrome trace to this path on exit.
+    let trace_path = std::env::var("BONKER_TRACE").ok();
+    common::profiler::set_enabled(trace_path.is_some());
     let mut now = std::time::Instant::now();
+    let mut time_since_timings_log = 0.0;
     event_loop.run(move |event, _, control_flow| {
 
         // Have the closure take ownership of the resources.
@@ -157,32 +522,119 @@ async fn run()
                                 // On macos the window needs to be redrawn manually after resizing
                                 window.request_redraw();
                             },
-                        WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                        WindowEvent::ScaleFactorChanged { scale_factor, .. } =>
+                            {
+                                game_state.ui.set_pixels_per_point(*scale_factor as f32);
+                            },
+                        WindowEvent::CloseRequested =>
+                            {
+                                if let Some(path) = &trace_path
+                                {
+                                    match common::profiler::write_chrome_trace(path)
+                                    {
+                                        Ok(()) => println!("Wrote the profiler trace to {}", path),
+                                        Err(err) => eprintln!("Failed to write the profiler trace {}: {}", path, err),
+                                    }
+                                }
+                                *control_flow = ControlFlow::Exit;
+                            },
                         _ => {}
                     }
                 },
             Event::RedrawRequested(_) =>
                 {
+                    common::profiler::begin_frame();
                     let new_now = std::time::Instant::now();
                     let dur = new_now.duration_since(now);
-                    let dt = dur.as_micros() as f64 / 1000_000.0;
+                    let dt = dur.as_micros() as f64 / 1_000_000.0;
                     now = new_now;
 
                     //update_func(&mut game_state, &input, dt);
 
-                    game_state.input.reset();
+                    game_state.debug_overlay.clear();
+                    game_state.debug_draw.clear();
+                    game_state.mesh_data.clear_instances();
+
+                    {
+                        let _scope = common::profiler::scope("Update");
+                        for system in &mut systems
+                        {
+                            let _scope = common::profiler::scope(system.name());
+                            system.as_mut().update(dt, &mut game_state);
+                        }
+                    }
+                    {
+                        let _scope = common::profiler::scope("Post update");
+                        for system in &mut systems
+                        {
+                            let _scope = common::profiler::scope(system.name());
+                            system.as_mut().post_update(dt, &mut game_state);
+                        }
+                    }
+                    {
+                        let _scope = common::profiler::scope("Scene bvh");
+                        game_state.scene.update_bvh(&game_state.mesh_data);
+                    }
+                    {
+                        let _scope = common::profiler::scope("Ui");
+                        let size = window.inner_size();
+                        let raw_input = game_state.ui.raw_input(
+                            &game_state.input,
+                            size.width as f32,
+                            size.height as f32,
+                            dt);
+                        let ctx = game_state.ui.context().clone();
+                        let output = ctx.run(raw_input, |ctx| {
+                            for system in &mut systems
+                            {
+                                system.as_mut().ui(ctx, &mut game_state);
+                            }
+                            renderer_ui(ctx, &mut renderer);
+                        });
+                        game_state.ui.end_frame(output);
+                    }
 
-                    for system in &mut systems
+                    let pass_timings: Vec<String> = renderer.pass_timings()
+                        .iter()
+                        .map(|timing| format!("{}: {:.3} ms", timing.name, timing.average))
+                        .collect();
+                    if !pass_timings.is_empty()
                     {
-                        system.as_mut().update(dt, &mut game_state);
+                        let text = format!("{:?} pass timings\n{}", renderer.pass_timing_source(), pass_timings.join("\n"));
+                        game_state.debug_overlay.text(window.inner_size().width as f32 - 300.0, 10.0, &text);
                     }
-                    for system in &mut systems
+
                     {
-                        system.as_mut().post_update(dt, &mut game_state);
+                        let _scope = common::profiler::scope("Picking");
+                        let size = window.inner_size();
+                        picking.update(&mut game_state, &mut renderer, size.width as f32, size.height as f32);
                     }
+                    {
+                        let _scope = common::profiler::scope("Culling");
+                        game_state.mesh_data.cull_instances(game_state.scene.get_current_camera());
+                    }
+                    {
+                        let _scope = common::profiler::scope("Renderer::update");
+                        renderer.upload_meshes(&mut game_state.mesh_data);
+                        renderer.update(dt, &game_state);
+                    }
+                    {
+                        let _scope = common::profiler::scope("Renderer::render");
+                        renderer.render();
+                    }
+                    // The systems have seen this frame's presses and releases.
+                    game_state.input.reset();
 
-                    renderer.update(dt, &game_state);
-                    renderer.render();
+                    time_since_timings_log += dt;
+                    if time_since_timings_log >= 5.0
+                    {
+                        time_since_timings_log = 0.0;
+                        for timing in renderer.pass_timings()
+                        {
+                            log::debug!("{:?} {}: {:.3} ms, average {:.3} ms",
+                                renderer.pass_timing_source(), timing.name, timing.last, timing.average);
+                        }
+                    }
                     //std::thread::sleep(std::time::Duration::from_millis(1));
                 },
             _ => {}